@@ -0,0 +1,522 @@
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Arc;
+
+use crate::cancel::CancellationToken;
+use crate::decoder;
+
+/// Decoded blocks buffered per sink before the decoder waits for it
+const SINK_QUEUE_DEPTH: usize = 32;
+
+/// Format of the decoded stream, known once the first packet is decoded
+#[derive(Clone, Copy, Debug)]
+pub struct StreamFormat {
+    pub sample_rate: u32,
+    pub channels: usize,
+    /// Expected number of frames (possibly estimated), 0 when unknown
+    pub total_frames: u64,
+}
+
+/// aubio objects that sinks hold inside an `AubioHandle`
+pub(crate) trait AubioObject {}
+
+impl AubioObject for aubio::Tempo {}
+impl AubioObject for aubio::Onset {}
+impl AubioObject for aubio::PVoc {}
+impl AubioObject for aubio::FFT {}
+
+/// An aubio object owned by one sink, so the sink can run on a decode thread.
+///
+/// aubio objects wrap raw pointers and so are not `Send` on their own.
+pub(crate) struct AubioHandle<T: AubioObject>(T);
+
+// SAFETY: aubio objects keep no thread-local or thread-affine state, and the
+// handle is neither `Clone` nor `Sync`, so the object is only ever used by the
+// one thread that currently owns the handle.
+unsafe impl<T: AubioObject> Send for AubioHandle<T> {}
+
+impl<T: AubioObject> AubioHandle<T> {
+    pub(crate) fn new(object: T) -> Self {
+        Self(object)
+    }
+}
+
+impl<T: AubioObject> Deref for AubioHandle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: AubioObject> DerefMut for AubioHandle<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+/// A time range of the file to decode instead of all of it
+#[derive(Clone, Copy, Debug)]
+pub struct Span {
+    pub start_secs: f64,
+    pub end_secs: f64,
+}
+
+impl Span {
+    /// Finite, starting at or after 0 and ending after it starts
+    fn is_valid(&self) -> bool {
+        self.start_secs.is_finite()
+            && self.end_secs.is_finite()
+            && self.start_secs >= 0.0
+            && self.end_secs > self.start_secs
+    }
+}
+
+/// One decoded packet of audio
+pub struct PcmBlock {
+    /// Interleaved samples, `channels` per frame
+    pub interleaved: Vec<f32>,
+    /// Average of the channels, one sample per frame
+    pub mono: Vec<f32>,
+    /// Index of the block's first frame in the stream (or span)
+    pub offset: u64,
+}
+
+/// An analysis fed by a shared decode pass.
+///
+/// Each sink runs on its own thread and sees `start` once, then every block in
+/// order, then `finish`. Returning an error stops that sink only.
+pub trait AnalysisSink: Send {
+    fn start(&mut self, format: &StreamFormat) -> Result<(), String>;
+    fn process(&mut self, block: &PcmBlock) -> Result<(), String>;
+    fn finish(&mut self) -> Result<(), String>;
+}
+
+/// Cuts a stream of samples into fixed hops, zero-padding the last one
+pub(crate) struct HopBuffer {
+    hop_size: usize,
+    pending: Vec<f32>,
+}
+
+impl HopBuffer {
+    pub(crate) fn new(hop_size: usize) -> Self {
+        Self {
+            hop_size,
+            pending: Vec::with_capacity(hop_size),
+        }
+    }
+
+    pub(crate) fn push<F>(&mut self, samples: &[f32], mut on_hop: F) -> Result<(), String>
+    where
+        F: FnMut(&[f32]) -> Result<(), String>,
+    {
+        let mut samples = samples;
+        if !self.pending.is_empty() {
+            let needed = (self.hop_size - self.pending.len()).min(samples.len());
+            self.pending.extend_from_slice(&samples[..needed]);
+            samples = &samples[needed..];
+            if self.pending.len() < self.hop_size {
+                return Ok(());
+            }
+            on_hop(&self.pending)?;
+            self.pending.clear();
+        }
+
+        let mut hops = samples.chunks_exact(self.hop_size);
+        for hop in &mut hops {
+            on_hop(hop)?;
+        }
+        self.pending.extend_from_slice(hops.remainder());
+        Ok(())
+    }
+
+    pub(crate) fn flush<F>(&mut self, mut on_hop: F) -> Result<(), String>
+    where
+        F: FnMut(&[f32]) -> Result<(), String>,
+    {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.pending.resize(self.hop_size, 0.0);
+        on_hop(&self.pending)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+enum Message {
+    Start(StreamFormat),
+    Block(Arc<PcmBlock>),
+}
+
+/// Decode `audio_path` once and feed every block to all `sinks` concurrently.
+///
+/// With a `span`, only that range is decoded and sinks see it as a stream of
+/// its own: block offsets and `total_frames` count from `span.start_secs`.
+/// `on_progress` receives the decoded fraction (0.0 - 1.0) after each block
+/// when the length is known, or -1.0 once when the container doesn't give
+/// it. Decoding stops with `cancel::CANCELLED` once `cancel` is cancelled.
+/// Returns each sink's outcome in order; the outer error means the file
+/// itself could not be decoded.
+pub fn run<F>(
+    audio_path: &Path,
+    span: Option<Span>,
+    cancel: &CancellationToken,
+    sinks: &mut [&mut dyn AnalysisSink],
+    mut on_progress: F,
+) -> Result<Vec<Result<(), String>>, String>
+where
+    F: FnMut(f64),
+{
+    std::thread::scope(|scope| {
+        let mut senders: Vec<Option<SyncSender<Message>>> = Vec::with_capacity(sinks.len());
+        let mut handles = Vec::with_capacity(sinks.len());
+
+        for sink in sinks.iter_mut() {
+            let sink: &mut dyn AnalysisSink = &mut **sink;
+            let (tx, rx) = sync_channel(SINK_QUEUE_DEPTH);
+            senders.push(Some(tx));
+            handles.push(scope.spawn(move || feed(sink, rx)));
+        }
+
+        let decoded = decode(
+            audio_path,
+            span,
+            cancel,
+            |message| {
+                for slot in senders.iter_mut() {
+                    let message = match &message {
+                        Message::Start(format) => Message::Start(*format),
+                        Message::Block(block) => Message::Block(Arc::clone(block)),
+                    };
+                    // A sink that failed has dropped its receiver; stop feeding it
+                    if let Some(tx) = slot {
+                        if tx.send(message).is_err() {
+                            *slot = None;
+                        }
+                    }
+                }
+            },
+            &mut on_progress,
+        );
+        drop(senders);
+
+        let outcomes = handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err("Analysis thread panicked".to_string()))
+            })
+            .collect();
+
+        decoded.map(|_| outcomes)
+    })
+}
+
+/// `run` without progress reporting, failing if decoding or any sink failed
+pub fn run_all(audio_path: &Path, sinks: &mut [&mut dyn AnalysisSink]) -> Result<(), String> {
+    for outcome in run(audio_path, None, &CancellationToken::new(), sinks, |_| {})? {
+        outcome?;
+    }
+    Ok(())
+}
+
+fn feed(sink: &mut dyn AnalysisSink, rx: Receiver<Message>) -> Result<(), String> {
+    let mut started = false;
+    for message in rx {
+        match message {
+            Message::Start(format) => {
+                sink.start(&format)?;
+                started = true;
+            }
+            Message::Block(block) => sink.process(&block)?,
+        }
+    }
+    if !started {
+        return Err("Audio stream ended before any audio was decoded".to_string());
+    }
+    sink.finish()
+}
+
+fn decode<S, P>(
+    audio_path: &Path,
+    span: Option<Span>,
+    cancel: &CancellationToken,
+    mut send: S,
+    on_progress: &mut P,
+) -> Result<(), String>
+where
+    S: FnMut(Message),
+    P: FnMut(f64),
+{
+    if matches!(span, Some(span) if !span.is_valid()) {
+        return Err("Invalid time range".to_string());
+    }
+
+    let mut decoder = decoder::open(audio_path)?;
+    let sample_rate = decoder.sample_rate();
+    let n_frames = decoder.n_frames();
+
+    // Frames of the file to keep, `[start, end)`
+    let range = match span {
+        Some(span) => {
+            if span.start_secs > 0.0 {
+                decoder.seek(span.start_secs)?;
+            }
+            Some((
+                (span.start_secs * sample_rate as f64).round() as u64,
+                (span.end_secs * sample_rate as f64).round() as u64,
+            ))
+        }
+        None => None,
+    };
+
+    // Without a frame count the length is unknown; finding it would mean
+    // reading the whole file an extra time, so progress is indeterminate
+    let total_frames = match (range, n_frames) {
+        (Some((start, end)), Some(n_frames)) => end.min(n_frames).saturating_sub(start),
+        (Some((start, end)), None) => end - start,
+        (None, Some(n_frames)) => n_frames,
+        (None, None) => 0,
+    };
+    let mut started = false;
+    let mut offset: u64 = 0;
+
+    while let Some(packet) = decoder.next_packet()? {
+        cancel.check()?;
+        if matches!(range, Some((_, end)) if packet.frame >= end) {
+            break;
+        }
+
+        let channels = packet.channels;
+        if !started {
+            started = true;
+            send(Message::Start(StreamFormat {
+                sample_rate,
+                channels,
+                total_frames,
+            }));
+            if total_frames == 0 {
+                on_progress(-1.0);
+            }
+        }
+
+        let mut samples = packet.samples;
+        if let Some((start, end)) = range {
+            // Trim the parts of the packet outside the span
+            let frames = (samples.len() / channels) as u64;
+            let first = start.saturating_sub(packet.frame).min(frames) as usize;
+            let last = end.saturating_sub(packet.frame).min(frames) as usize;
+            samples = &samples[first * channels..last * channels];
+        }
+        if samples.is_empty() {
+            continue;
+        }
+
+        let interleaved = samples.to_vec();
+        let mono: Vec<f32> = interleaved
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+
+        let frames = mono.len() as u64;
+        send(Message::Block(Arc::new(PcmBlock {
+            interleaved,
+            mono,
+            offset,
+        })));
+        offset += frames;
+
+        if total_frames > 0 {
+            on_progress((offset as f64 / total_frames as f64).min(1.0));
+        }
+    }
+
+    if range.is_some() && offset == 0 {
+        return Err("No audio decoded in the requested range".to_string());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio;
+
+    /// Counts frames and sums the mono signal
+    #[derive(Default)]
+    struct Tally {
+        format: Option<StreamFormat>,
+        frames: u64,
+        sum: f64,
+        finished: bool,
+    }
+
+    impl AnalysisSink for Tally {
+        fn start(&mut self, format: &StreamFormat) -> Result<(), String> {
+            self.format = Some(*format);
+            Ok(())
+        }
+
+        fn process(&mut self, block: &PcmBlock) -> Result<(), String> {
+            assert_eq!(block.offset, self.frames);
+            self.frames += block.mono.len() as u64;
+            self.sum += block.mono.iter().map(|&s| s as f64).sum::<f64>();
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), String> {
+            self.finished = true;
+            Ok(())
+        }
+    }
+
+    struct Failing;
+
+    impl AnalysisSink for Failing {
+        fn start(&mut self, _format: &StreamFormat) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn process(&mut self, _block: &PcmBlock) -> Result<(), String> {
+            Err("boom".to_string())
+        }
+
+        fn finish(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_hop_buffer_regroups_blocks() {
+        let mut hops = HopBuffer::new(4);
+        let mut seen: Vec<Vec<f32>> = Vec::new();
+        let mut collect = |hop: &[f32]| {
+            seen.push(hop.to_vec());
+            Ok(())
+        };
+
+        hops.push(&[1.0, 2.0, 3.0], &mut collect).unwrap();
+        hops.push(&[4.0, 5.0, 6.0, 7.0, 8.0, 9.0], &mut collect)
+            .unwrap();
+        hops.flush(&mut collect).unwrap();
+
+        assert_eq!(
+            seen,
+            vec![
+                vec![1.0, 2.0, 3.0, 4.0],
+                vec![5.0, 6.0, 7.0, 8.0],
+                vec![9.0, 0.0, 0.0, 0.0],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_feeds_every_sink_the_same_stream() {
+        let path = audio::test_file_path("fan_out.wav");
+
+        let sample_rate = 8000;
+        let left = vec![0.5f32; 10_000];
+        let right = vec![-0.25f32; 10_000];
+        audio::write_wav(&path, &[left, right], sample_rate).unwrap();
+
+        let mut first = Tally::default();
+        let mut second = Tally::default();
+        let mut failing = Failing;
+        let mut fractions = Vec::new();
+        let outcomes = run(
+            &path,
+            None,
+            &CancellationToken::new(),
+            &mut [&mut first, &mut failing, &mut second],
+            |fraction| fractions.push(fraction),
+        )
+        .unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert!(outcomes[0].is_ok());
+        assert_eq!(outcomes[1], Err("boom".to_string()));
+        assert!(outcomes[2].is_ok());
+
+        for tally in [&first, &second] {
+            let format = tally.format.unwrap();
+            assert_eq!(format.sample_rate, sample_rate);
+            assert_eq!(format.channels, 2);
+            assert_eq!(format.total_frames, 10_000);
+            assert_eq!(tally.frames, 10_000);
+            assert!((tally.sum - 1250.0).abs() < 1.0);
+            assert!(tally.finished);
+        }
+        assert_eq!(fractions.last(), Some(&1.0));
+    }
+
+    #[test]
+    fn test_run_span_decodes_only_the_range() {
+        let path = audio::test_file_path("span.wav");
+
+        // One second per level so the span's contents are easy to check
+        let sample_rate = 8000;
+        let signal: Vec<f32> = (0..4 * sample_rate)
+            .map(|i| (i / sample_rate) as f32 * 0.25)
+            .collect();
+        audio::write_wav(&path, &[signal], sample_rate).unwrap();
+
+        let mut tally = Tally::default();
+        let span = Span {
+            start_secs: 1.0,
+            end_secs: 2.5,
+        };
+        let cancel = CancellationToken::new();
+        let outcomes = run(&path, Some(span), &cancel, &mut [&mut tally], |_| {}).unwrap();
+        assert!(outcomes[0].is_ok());
+
+        assert_eq!(tally.format.unwrap().total_frames, 12_000);
+        assert_eq!(tally.frames, 12_000);
+        // 8000 frames at 0.25 and 4000 at 0.5
+        assert!((tally.sum - 4000.0).abs() < 1.0);
+
+        let past_end = Span {
+            start_secs: 10.0,
+            end_secs: 11.0,
+        };
+        let mut tally = Tally::default();
+        assert!(run(&path, Some(past_end), &cancel, &mut [&mut tally], |_| {}).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_run_rejects_invalid_span() {
+        let path = audio::test_file_path("never_written.wav");
+        let cancel = CancellationToken::new();
+        for (start_secs, end_secs) in [(f64::NAN, 1.0), (0.0, f64::NAN), (2.0, 1.0), (-1.0, 1.0)] {
+            let span = Span {
+                start_secs,
+                end_secs,
+            };
+            let mut tally = Tally::default();
+            let err = run(&path, Some(span), &cancel, &mut [&mut tally], |_| {}).unwrap_err();
+            assert_eq!(err, "Invalid time range");
+        }
+    }
+
+    #[test]
+    fn test_run_stops_when_cancelled() {
+        let path = audio::test_file_path("cancel.wav");
+        audio::write_wav(&path, &[vec![0.5f32; 80_000]], 8000).unwrap();
+
+        let cancel = CancellationToken::new();
+        let mut tally = Tally::default();
+        let result = run(&path, None, &cancel, &mut [&mut tally], |fraction| {
+            if fraction > 0.25 {
+                cancel.cancel();
+            }
+        });
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(result.err(), Some(crate::cancel::CANCELLED.to_string()));
+        assert!(tally.frames < 80_000);
+    }
+}
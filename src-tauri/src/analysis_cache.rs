@@ -0,0 +1,159 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::audio::WaveformOptions;
+use crate::beat_detection::{BeatDetectionConfig, BeatInfo};
+use crate::waveform_cache;
+use crate::WaveformData;
+
+/// Bumped whenever beat detection changes its results so stale sidecars are ignored.
+/// Peak generation has its own version in `waveform_cache`.
+const ANALYZER_VERSION: u32 = 1;
+
+/// Finished analysis of a stored track, as returned by `check_cached_audio`
+#[derive(Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedAnalysis {
+    pub beat_info: BeatInfo,
+    /// Peaks in the requested options, including duration and sample rate
+    pub waveform: WaveformData,
+}
+
+/// Contents of the `<id>.analysis.json` sidecar next to the audio file.
+///
+/// Peaks are not repeated here: they live in the waveform cache, which is
+/// keyed by the audio contents and channel mode.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AnalysisRecord {
+    analyzer_version: u32,
+    duration_secs: f64,
+    sample_rate: u32,
+    /// Carries the beat detection config it ran with
+    beat_info: BeatInfo,
+}
+
+/// Load the saved analysis of `audio_path` if it was made by this analyzer
+/// version with `beat_config`, and its peaks are cached for `options`.
+/// Blocks while the waveform cache hashes the audio file.
+pub fn load(
+    audio_path: &Path,
+    options: &WaveformOptions,
+    beat_config: &BeatDetectionConfig,
+) -> Option<CachedAnalysis> {
+    let path = sidecar_path(audio_path);
+    let json = std::fs::read_to_string(&path).ok()?;
+    let record = match decode(&json, beat_config) {
+        Ok(record) => record?,
+        Err(e) => {
+            eprintln!(
+                "[analysis] Ignoring unreadable analysis cache {:?}: {}",
+                path, e
+            );
+            return None;
+        }
+    };
+
+    let sidecar = waveform_cache::Sidecar::locate(audio_path, options).ok()?;
+    let mut waveform = sidecar.load(options, &mut |_, _, _, _| {})?;
+    waveform.duration_secs = record.duration_secs;
+    waveform.sample_rate = record.sample_rate;
+
+    Some(CachedAnalysis {
+        beat_info: record.beat_info,
+        waveform,
+    })
+}
+
+/// Save a finished analysis of `audio_path`, replacing any older one
+pub fn store(audio_path: &Path, waveform: &WaveformData, beat_info: &BeatInfo) {
+    let record = AnalysisRecord {
+        analyzer_version: ANALYZER_VERSION,
+        duration_secs: waveform.duration_secs,
+        sample_rate: waveform.sample_rate,
+        beat_info: beat_info.clone(),
+    };
+    let path = sidecar_path(audio_path);
+    let result = serde_json::to_string(&record)
+        .map_err(|e| e.to_string())
+        .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        eprintln!(
+            "[analysis] Failed to write analysis cache {:?}: {}",
+            path, e
+        );
+    }
+}
+
+/// Parse a sidecar, giving `None` when it is stale for `beat_config`
+fn decode(json: &str, beat_config: &BeatDetectionConfig) -> Result<Option<AnalysisRecord>, String> {
+    let record: AnalysisRecord =
+        serde_json::from_str(json).map_err(|e| format!("Failed to parse analysis: {}", e))?;
+    let current =
+        record.analyzer_version == ANALYZER_VERSION && record.beat_info.config == *beat_config;
+    Ok(current.then_some(record))
+}
+
+fn sidecar_path(audio_path: &Path) -> PathBuf {
+    audio_path.with_extension("analysis.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beat_detection::OnsetMethod;
+
+    fn record(version: u32, config: BeatDetectionConfig) -> String {
+        serde_json::to_string(&AnalysisRecord {
+            analyzer_version: version,
+            duration_secs: 12.5,
+            sample_rate: 44100,
+            beat_info: BeatInfo {
+                bpm: 120.0,
+                bpm_confidence: 0.8,
+                beats: vec![0.5, 1.0],
+                onsets: vec![0.49],
+                config,
+            },
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_with_same_config() {
+        let config = BeatDetectionConfig::default();
+        let decoded = decode(&record(ANALYZER_VERSION, config.clone()), &config)
+            .unwrap()
+            .unwrap();
+        assert_eq!(decoded.sample_rate, 44100);
+        assert_eq!(decoded.beat_info.beats, vec![0.5, 1.0]);
+    }
+
+    #[test]
+    fn test_stale_version_or_config_is_ignored() {
+        let config = BeatDetectionConfig::default();
+        let other = BeatDetectionConfig {
+            onset_method: OnsetMethod::Hfc,
+            ..config.clone()
+        };
+        assert!(
+            decode(&record(ANALYZER_VERSION + 1, config.clone()), &config)
+                .unwrap()
+                .is_none()
+        );
+        assert!(decode(&record(ANALYZER_VERSION, other), &config)
+            .unwrap()
+            .is_none());
+        assert!(decode("{", &config).is_err());
+    }
+
+    #[test]
+    fn test_sidecar_sits_next_to_audio() {
+        assert_eq!(
+            sidecar_path(Path::new("/data/audio/abc123.aac")),
+            PathBuf::from("/data/audio/abc123.analysis.json")
+        );
+    }
+}
@@ -1,22 +1,48 @@
+use std::io::Write;
 use std::path::Path;
-use symphonia::core::audio::SampleBuffer;
-use symphonia::core::codecs::DecoderOptions;
-use symphonia::core::formats::FormatOptions;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use symphonia::core::formats::{FormatOptions, FormatReader};
 use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision};
 use symphonia::core::probe::Hint;
+use symphonia::core::units::TimeBase;
 
-use crate::WaveformData;
+use crate::analysis::{self, AnalysisSink, PcmBlock, StreamFormat};
+use crate::file_stamp::{FileStamp, StampedCache};
+use crate::{decoder, ffmpeg_runtime, WaveformData};
 
-const SAMPLES_PER_PEAK: usize = 256;
-const CHUNK_SIZE: usize = 1000;
+pub const SAMPLES_PER_PEAK: usize = 256;
+pub const CHUNK_SIZE: usize = 1000;
 
 pub fn estimate_peak_count(duration_secs: f64, sample_rate: u32) -> usize {
     let total_samples = (duration_secs * sample_rate as f64) as usize;
     (total_samples / SAMPLES_PER_PEAK) + 1 // +1 for rounding
 }
 
-pub fn get_audio_info(audio_path: &Path) -> Result<(f64, u32), String> {
+/// Basic facts about an audio file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioInfo {
+    pub duration_secs: f64,
+    pub sample_rate: u32,
+    /// False when the duration comes from tags or the container bitrate
+    pub duration_exact: bool,
+}
+
+/// Durations found by packet scans, so repeated lookups don't rescan the file
+static SCANNED_DURATIONS: Mutex<StampedCache<f64>> = Mutex::new(StampedCache::new(256));
+
+/// Read the sample rate and duration of an audio file.
+///
+/// The duration comes from the first source that has it: the track's frame
+/// count (which Symphonia fills from Xing/Info/VBRI headers for MP3), a scan
+/// summing packet durations, the ID3 `TLEN` tag, then FFmpeg's container
+/// duration. Only the first two are reported as exact. A duration of 0.0
+/// means none of them knew. Files Symphonia cannot read at all are described
+/// by FFmpeg alone.
+pub fn get_audio_info(audio_path: &Path) -> Result<AudioInfo, String> {
     let file =
         std::fs::File::open(audio_path).map_err(|e| format!("Failed to open audio file: {}", e))?;
 
@@ -30,11 +56,24 @@ pub fn get_audio_info(audio_path: &Path) -> Result<(f64, u32), String> {
     let format_opts = FormatOptions::default();
     let metadata_opts = MetadataOptions::default();
 
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &format_opts, &metadata_opts)
-        .map_err(|e| format!("Failed to probe audio format: {}", e))?;
+    let mut probed =
+        match symphonia::default::get_probe().format(&hint, mss, &format_opts, &metadata_opts) {
+            Ok(probed) => probed,
+            Err(e) => {
+                return ffmpeg_audio_info(audio_path).map_err(|ffmpeg_err| {
+                    format!(
+                        "Failed to probe audio format: {} (FFmpeg: {})",
+                        e, ffmpeg_err
+                    )
+                })
+            }
+        };
 
-    let format = probed.format;
+    let probed_tlen = probed
+        .metadata
+        .get()
+        .and_then(|m| m.current().and_then(tlen_secs));
+    let mut format = probed.format;
 
     let track = format
         .tracks()
@@ -47,138 +86,1118 @@ pub fn get_audio_info(audio_path: &Path) -> Result<(f64, u32), String> {
         .sample_rate
         .ok_or_else(|| "Unknown sample rate".to_string())?;
 
-    let duration_secs = if let Some(n_frames) = track.codec_params.n_frames {
-        n_frames as f64 / sample_rate as f64
-    } else {
-        0.0
+    let exact = |duration_secs: f64| AudioInfo {
+        duration_secs,
+        sample_rate,
+        duration_exact: true,
+    };
+    let estimated = |duration_secs: f64| AudioInfo {
+        duration_secs,
+        sample_rate,
+        duration_exact: false,
     };
 
-    Ok((duration_secs, sample_rate))
-}
+    if let Some(n_frames) = track.codec_params.n_frames {
+        return Ok(exact(n_frames as f64 / sample_rate as f64));
+    }
 
-pub fn generate_waveform_peaks<F>(
-    audio_path: &Path,
-    mut on_chunk: F,
-) -> Result<WaveformData, String>
-where
-    F: FnMut(&[f32], usize),
-{
-    let file =
-        std::fs::File::open(audio_path).map_err(|e| format!("Failed to open audio file: {}", e))?;
+    let track_id = track.id;
+    let time_base = track
+        .codec_params
+        .time_base
+        .unwrap_or_else(|| TimeBase::new(1, sample_rate));
+    let format_tlen = format.metadata().current().and_then(tlen_secs);
 
-    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    if let Some(duration_secs) = scanned_duration(audio_path, format.as_mut(), track_id, time_base)
+    {
+        return Ok(exact(duration_secs));
+    }
 
-    let mut hint = Hint::new();
-    if let Some(ext) = audio_path.extension().and_then(|e| e.to_str()) {
-        hint.with_extension(ext);
+    if let Some(duration_secs) = probed_tlen.or(format_tlen) {
+        return Ok(estimated(duration_secs));
     }
 
-    let format_opts = FormatOptions::default();
-    let metadata_opts = MetadataOptions::default();
+    let container_secs = ffmpeg_runtime::AudioFile::open(audio_path)
+        .map(|f| f.duration_secs)
+        .unwrap_or(0.0);
+    if container_secs.is_finite() && container_secs > 0.0 {
+        return Ok(estimated(container_secs));
+    }
 
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &format_opts, &metadata_opts)
-        .map_err(|e| format!("Failed to probe audio format: {}", e))?;
+    Ok(estimated(0.0))
+}
 
-    let mut format = probed.format;
+/// Sample rate and container duration of a file only FFmpeg can open
+fn ffmpeg_audio_info(audio_path: &Path) -> Result<AudioInfo, String> {
+    let file = ffmpeg_runtime::AudioFile::open(audio_path)?;
+    if file.sample_rate <= 0 {
+        return Err("Unknown sample rate".to_string());
+    }
+    let duration_secs = file.duration_secs;
+    Ok(AudioInfo {
+        duration_secs: if duration_secs.is_finite() && duration_secs > 0.0 {
+            duration_secs
+        } else {
+            0.0
+        },
+        sample_rate: file.sample_rate as u32,
+        duration_exact: false,
+    })
+}
 
-    let track = format
-        .tracks()
+/// Duration from an ID3 `TLEN` tag (milliseconds)
+fn tlen_secs(revision: &MetadataRevision) -> Option<f64> {
+    revision
+        .tags()
         .iter()
-        .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
-        .ok_or_else(|| "No audio track found".to_string())?;
-
-    let sample_rate = track
-        .codec_params
-        .sample_rate
-        .ok_or_else(|| "Unknown sample rate".to_string())?;
-
-    let decoder_opts = DecoderOptions::default();
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &decoder_opts)
-        .map_err(|e| format!("Failed to create decoder: {}", e))?;
-
-    let track_id = track.id;
+        .find(|tag| tag.key.eq_ignore_ascii_case("TLEN"))
+        .and_then(|tag| tag.value.to_string().trim().parse::<f64>().ok())
+        .filter(|ms| *ms > 0.0)
+        .map(|ms| ms / 1000.0)
+}
 
-    let mut all_peaks: Vec<f32> = Vec::new();
-    let mut sample_buffer: Option<SampleBuffer<f32>> = None;
-    let mut accumulator: Vec<f32> = Vec::new();
-    let mut total_samples: u64 = 0;
-    let mut chunk_buffer: Vec<f32> = Vec::new();
+/// Sum the durations of every packet of a track without decoding, remembering the result
+fn scanned_duration(
+    audio_path: &Path,
+    format: &mut dyn FormatReader,
+    track_id: u32,
+    time_base: TimeBase,
+) -> Option<f64> {
+    let stamp = FileStamp::of(audio_path).ok();
+    let cached = stamp.and_then(|stamp| {
+        SCANNED_DURATIONS
+            .lock()
+            .ok()
+            .and_then(|cache| cache.get(audio_path, stamp))
+    });
+    if cached.is_some() {
+        return cached;
+    }
 
+    let mut total: u64 = 0;
     loop {
-        let packet = match format.next_packet() {
-            Ok(packet) => packet,
+        match format.next_packet() {
+            Ok(packet) if packet.track_id() == track_id => total += packet.dur,
+            Ok(_) => {}
             Err(symphonia::core::errors::Error::IoError(ref e))
                 if e.kind() == std::io::ErrorKind::UnexpectedEof =>
             {
                 break
             }
-            Err(e) => return Err(format!("Failed to read packet: {}", e)),
+            // A broken stream can't give an exact duration
+            Err(_) => return None,
+        }
+    }
+    if total == 0 {
+        return None;
+    }
+
+    let time = time_base.calc_time(total);
+    let secs = time.seconds as f64 + time.frac;
+    if let (Some(stamp), Ok(mut cache)) = (stamp, SCANNED_DURATIONS.lock()) {
+        cache.insert(audio_path.to_path_buf(), stamp, secs);
+    }
+    Some(secs)
+}
+
+/// How source channels are combined before peak picking
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum ChannelMode {
+    /// Only the averaged mono mix
+    #[default]
+    Mono,
+    /// One set of peaks per source channel, in source order
+    PerChannel,
+    /// Mid (L+R)/2 and side (L-R)/2 peaks, in that order
+    MidSide,
+}
+
+impl ChannelMode {
+    /// Number of per-channel series produced for a source with `channels` channels
+    fn output_channels(self, channels: usize) -> usize {
+        match self {
+            ChannelMode::Mono => 0,
+            ChannelMode::PerChannel => channels,
+            ChannelMode::MidSide => 2,
+        }
+    }
+
+    /// Route one interleaved frame into the per-channel series
+    fn split_frame(self, frame: &[f32], series: &mut [PeakSeries]) {
+        match self {
+            ChannelMode::Mono => {}
+            ChannelMode::PerChannel => {
+                for (s, &sample) in series.iter_mut().zip(frame) {
+                    s.push(sample);
+                }
+            }
+            ChannelMode::MidSide => {
+                // A mono source has no side signal
+                let left = frame[0];
+                let right = frame.get(1).copied().unwrap_or(left);
+                series[0].push((left + right) / 2.0);
+                series[1].push((left - right) / 2.0);
+            }
+        }
+    }
+}
+
+/// Lowest level reported in `Dbfs` scale, used for silence
+pub const DBFS_FLOOR: f32 = -96.0;
+
+/// Units of the waveform values
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum WaveformScale {
+    /// Divided by the loudest peak so it reaches 1.0
+    #[default]
+    Normalized,
+    /// Absolute sample values, full scale is 1.0
+    Linear,
+    /// Level of each value's magnitude in dBFS, floored at `DBFS_FLOOR`
+    Dbfs,
+}
+
+pub(crate) fn linear_to_dbfs(value: f32) -> f32 {
+    let magnitude = value.abs();
+    if magnitude > 0.0 {
+        (20.0 * magnitude.log10()).max(DBFS_FLOOR)
+    } else {
+        DBFS_FLOOR
+    }
+}
+
+/// Options for waveform peak generation
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WaveformOptions {
+    pub channel_mode: ChannelMode,
+    /// Also compute low/mid/high band energies of the mono mix
+    pub band_energies: bool,
+    pub scale: WaveformScale,
+}
+
+/// Per-block waveform summaries, one entry per `SAMPLES_PER_PEAK` samples
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PeakBuffers {
+    /// Absolute peak (`max(|min|, |max|)`)
+    pub peaks: Vec<f32>,
+    /// Lowest signed sample
+    pub min_peaks: Vec<f32>,
+    /// Highest signed sample
+    pub max_peaks: Vec<f32>,
+    /// Root-mean-square level
+    pub rms_peaks: Vec<f32>,
+}
+
+impl PeakBuffers {
+    pub fn len(&self) -> usize {
+        self.peaks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peaks.is_empty()
+    }
+
+    /// Fold one block of mono samples into a new entry
+    fn push_block(&mut self, block: &[f32]) {
+        let (min, max, sum_sq) = block.iter().fold(
+            (f32::INFINITY, f32::NEG_INFINITY, 0.0f32),
+            |(min, max, sum_sq), &s| (min.min(s), max.max(s), sum_sq + s * s),
+        );
+        let (min, max) = if block.is_empty() {
+            (0.0, 0.0)
+        } else {
+            (min, max)
         };
 
-        if packet.track_id() != track_id {
-            continue;
+        self.peaks.push(min.abs().max(max.abs()));
+        self.min_peaks.push(min);
+        self.max_peaks.push(max);
+        self.rms_peaks
+            .push((sum_sq / block.len().max(1) as f32).sqrt());
+    }
+
+    fn max_peak(&self) -> f32 {
+        self.peaks.iter().cloned().fold(0.0f32, f32::max)
+    }
+
+    /// Scale every series by the same factor so the loudest absolute peak is 1.0.
+    ///
+    /// Returns the factor the values were divided by (1.0 for silence).
+    fn normalize(&mut self) -> f32 {
+        let max_peak = self.max_peak();
+        self.normalize_to(max_peak);
+        normalization_factor(max_peak)
+    }
+
+    /// Divide every series by `max_peak`, leaving silence untouched
+    fn normalize_to(&mut self, max_peak: f32) {
+        if max_peak > 0.0 {
+            self.map_values(|v| v / max_peak);
+        }
+    }
+
+    fn map_values(&mut self, f: impl Fn(f32) -> f32) {
+        for series in [
+            &mut self.peaks,
+            &mut self.min_peaks,
+            &mut self.max_peaks,
+            &mut self.rms_peaks,
+        ] {
+            for value in series.iter_mut() {
+                *value = f(*value);
+            }
+        }
+    }
+}
+
+/// Divisor used when normalizing to `max_peak`
+fn normalization_factor(max_peak: f32) -> f32 {
+    if max_peak > 0.0 {
+        max_peak
+    } else {
+        1.0
+    }
+}
+
+/// Upper edge of the low band in Hz
+pub const LOW_CROSSOVER_HZ: f32 = 200.0;
+/// Lower edge of the high band in Hz
+pub const HIGH_CROSSOVER_HZ: f32 = 2000.0;
+
+/// Per-block RMS energy in three crossover bands, aligned with the mono peaks
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct BandEnergies {
+    /// Below `LOW_CROSSOVER_HZ` (kicks, bass)
+    pub low: Vec<f32>,
+    /// Between the crossovers (snares, vocals)
+    pub mid: Vec<f32>,
+    /// Above `HIGH_CROSSOVER_HZ` (hats, cymbals)
+    pub high: Vec<f32>,
+}
+
+impl BandEnergies {
+    pub fn len(&self) -> usize {
+        self.low.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.low.is_empty()
+    }
+
+    fn push(&mut self, [low, mid, high]: [f32; 3]) {
+        self.low.push(low);
+        self.mid.push(mid);
+        self.high.push(high);
+    }
+
+    /// Divide every band by `max_peak`, leaving silence untouched
+    fn normalize_to(&mut self, max_peak: f32) {
+        if max_peak > 0.0 {
+            self.map_values(|v| v / max_peak);
+        }
+    }
+
+    fn map_values(&mut self, f: impl Fn(f32) -> f32) {
+        for band in [&mut self.low, &mut self.mid, &mut self.high] {
+            for value in band.iter_mut() {
+                *value = f(*value);
+            }
+        }
+    }
+}
+
+/// Second-order Butterworth section (RBJ cookbook, transposed direct form II)
+#[derive(Clone, Copy)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    fn lowpass(sample_rate: u32, freq: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(sample_rate, freq);
+        let b = (1.0 - cos) / 2.0;
+        Self::normalized(b, 1.0 - cos, b, cos, alpha)
+    }
+
+    fn highpass(sample_rate: u32, freq: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(sample_rate, freq);
+        let b = (1.0 + cos) / 2.0;
+        Self::normalized(b, -(1.0 + cos), b, cos, alpha)
+    }
+
+    fn prewarp(sample_rate: u32, freq: f32) -> (f32, f32) {
+        // Keep the corner below Nyquist for low sample rates
+        let freq = freq.min(sample_rate as f32 * 0.45);
+        let w0 = 2.0 * std::f32::consts::PI * freq / sample_rate as f32;
+        (w0.cos(), w0.sin() / std::f32::consts::SQRT_2)
+    }
+
+    fn normalized(b0: f32, b1: f32, b2: f32, cos: f32, alpha: f32) -> Self {
+        let a0 = 1.0 + alpha;
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha) / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+/// Splits the mono mix into three bands and folds their energy into blocks
+struct BandSeries {
+    low: Biquad,
+    mid_high_pass: Biquad,
+    mid_low_pass: Biquad,
+    high: Biquad,
+    all: BandEnergies,
+    chunk: BandEnergies,
+    sum_sq: [f32; 3],
+    count: usize,
+}
+
+impl BandSeries {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            low: Biquad::lowpass(sample_rate, LOW_CROSSOVER_HZ),
+            mid_high_pass: Biquad::highpass(sample_rate, LOW_CROSSOVER_HZ),
+            mid_low_pass: Biquad::lowpass(sample_rate, HIGH_CROSSOVER_HZ),
+            high: Biquad::highpass(sample_rate, HIGH_CROSSOVER_HZ),
+            all: BandEnergies::default(),
+            chunk: BandEnergies::default(),
+            sum_sq: [0.0; 3],
+            count: 0,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        let bands = [
+            self.low.process(sample),
+            self.mid_low_pass
+                .process(self.mid_high_pass.process(sample)),
+            self.high.process(sample),
+        ];
+        for (sum, band) in self.sum_sq.iter_mut().zip(bands) {
+            *sum += band * band;
+        }
+        self.count += 1;
+        if self.count >= SAMPLES_PER_PEAK {
+            self.finish_block();
+        }
+    }
+
+    /// Fold the (possibly partial) current block into the band buffers
+    fn finish_block(&mut self) {
+        if self.count > 0 {
+            let rms = self.sum_sq.map(|sum| (sum / self.count as f32).sqrt());
+            self.all.push(rms);
+            self.chunk.push(rms);
+            self.sum_sq = [0.0; 3];
+            self.count = 0;
+        }
+    }
+}
+
+/// One signal being folded into peaks, both for the full result and the pending chunk
+#[derive(Default)]
+struct PeakSeries {
+    all: PeakBuffers,
+    chunk: PeakBuffers,
+    block: Vec<f32>,
+}
+
+impl PeakSeries {
+    fn push(&mut self, sample: f32) {
+        self.block.push(sample);
+        if self.block.len() >= SAMPLES_PER_PEAK {
+            self.finish_block();
+        }
+    }
+
+    /// Fold the (possibly partial) current block into the peak buffers
+    fn finish_block(&mut self) {
+        if !self.block.is_empty() {
+            self.all.push_block(&self.block);
+            self.chunk.push_block(&self.block);
+            self.block.clear();
+        }
+    }
+}
+
+/// Decode an audio file into per-block peaks in the scale chosen by `options`.
+///
+/// `on_chunk` receives the mono chunk, the matching per-channel chunks (empty in
+/// `Mono` mode), the matching band energies (when requested) and the offset of
+/// the chunk's first block. Chunk values are absolute levels, in dBFS for the
+/// `Dbfs` scale and linear otherwise, since the loudest block is not known yet.
+pub fn generate_waveform_peaks<F>(
+    audio_path: &Path,
+    options: &WaveformOptions,
+    on_chunk: F,
+) -> Result<WaveformData, String>
+where
+    F: FnMut(&PeakBuffers, &[PeakBuffers], Option<&BandEnergies>, usize) + Send,
+{
+    let mut waveform = generate_normalized_peaks(audio_path, options, on_chunk)?;
+    apply_scale(&mut waveform, options.scale);
+    Ok(waveform)
+}
+
+/// Convert normalized waveform data to `scale` using its normalization factors
+pub fn apply_scale(waveform: &mut WaveformData, scale: WaveformScale) {
+    if waveform.scale != WaveformScale::Normalized || scale == WaveformScale::Normalized {
+        return;
+    }
+
+    let mono_factor = waveform.normalization_factor;
+    let channel_factor = waveform.channel_normalization_factor;
+    let to_scale = |factor: f32| {
+        move |v: f32| match scale {
+            WaveformScale::Dbfs => linear_to_dbfs(v * factor),
+            _ => v * factor,
+        }
+    };
+
+    for series in [
+        &mut waveform.peaks,
+        &mut waveform.min_peaks,
+        &mut waveform.max_peaks,
+        &mut waveform.rms_peaks,
+    ] {
+        for value in series.iter_mut() {
+            *value = to_scale(mono_factor)(*value);
+        }
+    }
+    if let Some(bands) = &mut waveform.bands {
+        bands.map_values(to_scale(mono_factor));
+    }
+    for channel in &mut waveform.channels {
+        channel.map_values(to_scale(channel_factor));
+    }
+    waveform.scale = scale;
+}
+
+/// Decode an audio file into peaks normalized to the loudest block, whatever `options.scale` is.
+///
+/// Chunks are still reported in `options.scale`. The peak cache stores this form
+/// and converts it with `apply_scale`.
+pub fn generate_normalized_peaks<F>(
+    audio_path: &Path,
+    options: &WaveformOptions,
+    on_chunk: F,
+) -> Result<WaveformData, String>
+where
+    F: FnMut(&PeakBuffers, &[PeakBuffers], Option<&BandEnergies>, usize) + Send,
+{
+    let mut sink = WaveformSink::new(options, on_chunk);
+    analysis::run_all(audio_path, &mut [&mut sink])?;
+    Ok(sink.into_waveform())
+}
+
+/// Waveform peak generation as a sink of the shared decode pass.
+///
+/// Chunks go to `on_chunk` while decoding (see `generate_waveform_peaks`);
+/// `into_waveform` returns the normalized result afterwards.
+pub struct WaveformSink<F> {
+    options: WaveformOptions,
+    on_chunk: F,
+    sample_rate: u32,
+    channels: usize,
+    mono: PeakSeries,
+    channel_series: Vec<PeakSeries>,
+    bands: Option<BandSeries>,
+    total_samples: u64,
+}
+
+impl<F> WaveformSink<F>
+where
+    F: FnMut(&PeakBuffers, &[PeakBuffers], Option<&BandEnergies>, usize) + Send,
+{
+    pub fn new(options: &WaveformOptions, on_chunk: F) -> Self {
+        Self {
+            options: options.clone(),
+            on_chunk,
+            sample_rate: 0,
+            channels: 1,
+            mono: PeakSeries::default(),
+            channel_series: Vec::new(),
+            bands: None,
+            total_samples: 0,
+        }
+    }
+
+    fn emit_chunk(&mut self) {
+        let mut mono_chunk = std::mem::take(&mut self.mono.chunk);
+        let mut channel_chunks: Vec<PeakBuffers> = self
+            .channel_series
+            .iter_mut()
+            .map(|s| std::mem::take(&mut s.chunk))
+            .collect();
+        let mut band_chunk = self.bands.as_mut().map(|b| std::mem::take(&mut b.chunk));
+
+        if self.options.scale == WaveformScale::Dbfs {
+            mono_chunk.map_values(linear_to_dbfs);
+            for chunk in &mut channel_chunks {
+                chunk.map_values(linear_to_dbfs);
+            }
+            if let Some(chunk) = &mut band_chunk {
+                chunk.map_values(linear_to_dbfs);
+            }
+        }
+
+        (self.on_chunk)(
+            &mono_chunk,
+            &channel_chunks,
+            band_chunk.as_ref(),
+            self.mono.all.len() - mono_chunk.len(),
+        );
+    }
+
+    /// Peaks normalized to the loudest block, once the decode pass has finished
+    pub fn into_waveform(self) -> WaveformData {
+        // Bands share the mono scale so they stay comparable with the RMS peaks
+        let mut all_peaks = self.mono.all;
+        let mono_factor = all_peaks.normalize();
+        let all_bands = self.bands.map(|b| {
+            let mut energies = b.all;
+            energies.normalize_to(mono_factor);
+            energies
+        });
+
+        // Channels share one scale so their relative loudness stays visible
+        let mut channel_peaks: Vec<PeakBuffers> =
+            self.channel_series.into_iter().map(|s| s.all).collect();
+        let channel_max = channel_peaks
+            .iter()
+            .map(PeakBuffers::max_peak)
+            .fold(0.0f32, f32::max);
+        for peaks in &mut channel_peaks {
+            peaks.normalize_to(channel_max);
         }
 
-        let decoded = match decoder.decode(&packet) {
-            Ok(decoded) => decoded,
-            Err(_) => continue,
+        let duration_secs = if self.sample_rate > 0 {
+            self.total_samples as f64 / self.sample_rate as f64
+        } else {
+            0.0
         };
 
-        if sample_buffer.is_none() {
-            let spec = *decoded.spec();
-            let duration = decoded.capacity() as u64;
-            sample_buffer = Some(SampleBuffer::new(duration, spec));
+        WaveformData {
+            peaks: all_peaks.peaks,
+            min_peaks: all_peaks.min_peaks,
+            max_peaks: all_peaks.max_peaks,
+            rms_peaks: all_peaks.rms_peaks,
+            channel_mode: self.options.channel_mode,
+            channels: channel_peaks,
+            bands: all_bands,
+            scale: WaveformScale::Normalized,
+            normalization_factor: mono_factor,
+            channel_normalization_factor: normalization_factor(channel_max),
+            duration_secs,
+            sample_rate: self.sample_rate,
         }
+    }
+}
 
-        if let Some(ref mut buf) = sample_buffer {
-            let channels = decoded.spec().channels.count();
-            buf.copy_interleaved_ref(decoded);
-            let samples = buf.samples();
+impl<F> AnalysisSink for WaveformSink<F>
+where
+    F: FnMut(&PeakBuffers, &[PeakBuffers], Option<&BandEnergies>, usize) + Send,
+{
+    fn start(&mut self, format: &StreamFormat) -> Result<(), String> {
+        self.sample_rate = format.sample_rate;
+        self.channels = format.channels.max(1);
+        let output_channels = self.options.channel_mode.output_channels(format.channels);
+        self.channel_series = (0..output_channels)
+            .map(|_| PeakSeries::default())
+            .collect();
+        self.bands = self
+            .options
+            .band_energies
+            .then(|| BandSeries::new(format.sample_rate));
+        Ok(())
+    }
 
-            for chunk in samples.chunks(channels) {
-                let mono: f32 = chunk.iter().sum::<f32>() / channels as f32;
-                accumulator.push(mono.abs());
-                total_samples += 1;
+    fn process(&mut self, block: &PcmBlock) -> Result<(), String> {
+        let channel_mode = self.options.channel_mode;
 
-                if accumulator.len() >= SAMPLES_PER_PEAK {
-                    let peak = accumulator.iter().cloned().fold(0.0f32, f32::max);
-                    all_peaks.push(peak);
-                    chunk_buffer.push(peak);
-                    accumulator.clear();
+        for (frame, &mix) in block.interleaved.chunks(self.channels).zip(&block.mono) {
+            self.mono.push(mix);
+            channel_mode.split_frame(frame, &mut self.channel_series);
+            if let Some(ref mut bands) = self.bands {
+                bands.push(mix);
+            }
+            self.total_samples += 1;
 
-                    if chunk_buffer.len() >= CHUNK_SIZE {
-                        on_chunk(&chunk_buffer, all_peaks.len() - chunk_buffer.len());
-                        chunk_buffer.clear();
-                    }
-                }
+            if self.mono.chunk.len() >= CHUNK_SIZE {
+                self.emit_chunk();
             }
         }
+        Ok(())
     }
 
-    if !accumulator.is_empty() {
-        let peak = accumulator.iter().cloned().fold(0.0f32, f32::max);
-        all_peaks.push(peak);
-        chunk_buffer.push(peak);
+    fn finish(&mut self) -> Result<(), String> {
+        self.mono.finish_block();
+        for series in &mut self.channel_series {
+            series.finish_block();
+        }
+        if let Some(ref mut bands) = self.bands {
+            bands.finish_block();
+        }
+
+        if !self.mono.chunk.is_empty() {
+            self.emit_chunk();
+        }
+        Ok(())
     }
+}
+
+/// Decode a whole file packet by packet, passing each packet's mono mix to `on_samples`.
+///
+/// Returns the sample rate. This never holds more than one packet of audio,
+/// so it is safe for hour-long mixes.
+pub fn stream_mono_samples<F>(audio_path: &Path, mut on_samples: F) -> Result<u32, String>
+where
+    F: FnMut(&[f32]),
+{
+    let mut decoder = decoder::open(audio_path)?;
+    let mut mono: Vec<f32> = Vec::new();
 
-    if !chunk_buffer.is_empty() {
-        on_chunk(&chunk_buffer, all_peaks.len() - chunk_buffer.len());
+    while let Some(packet) = decoder.next_packet()? {
+        mono.clear();
+        mono.extend(
+            packet
+                .samples
+                .chunks(packet.channels)
+                .map(|frame| frame.iter().sum::<f32>() / packet.channels as f32),
+        );
+        on_samples(&mono);
     }
 
-    let max_peak = all_peaks.iter().cloned().fold(0.0f32, f32::max);
-    if max_peak > 0.0 {
-        for peak in &mut all_peaks {
-            *peak /= max_peak;
+    Ok(decoder.sample_rate())
+}
+
+/// Decoded PCM audio with one sample buffer per channel
+pub struct DecodedAudio {
+    pub channels: Vec<Vec<f32>>,
+    pub sample_rate: u32,
+}
+
+impl DecodedAudio {
+    /// Number of sample frames (samples per channel)
+    pub fn frames(&self) -> usize {
+        self.channels.first().map(|c| c.len()).unwrap_or(0)
+    }
+
+    /// Average all channels into a single mono buffer
+    pub fn to_mono(&self) -> Vec<f32> {
+        let scale = 1.0 / self.channels.len().max(1) as f32;
+        (0..self.frames())
+            .map(|i| self.channels.iter().map(|c| c[i]).sum::<f32>() * scale)
+            .collect()
+    }
+}
+
+/// Frame index of a packet timestamp.
+///
+/// Packet timestamps are in the track's time base, which is not always 1/sample_rate.
+pub(crate) fn packet_frame(time_base: Option<TimeBase>, ts: u64, sample_rate: u32) -> u64 {
+    match time_base {
+        Some(tb) => {
+            let time = tb.calc_time(ts);
+            ((time.seconds as f64 + time.frac) * sample_rate as f64).round() as u64
         }
+        None => ts,
     }
+}
 
-    let duration_secs = total_samples as f64 / sample_rate as f64;
+/// Decode only the `[start_secs, end_secs)` span of an audio file.
+///
+/// Seeks close to the start and discards the samples before it, so the
+/// returned buffers begin exactly at `start_secs`.
+pub fn decode_region(
+    audio_path: &Path,
+    start_secs: f64,
+    end_secs: f64,
+) -> Result<DecodedAudio, String> {
+    if end_secs <= start_secs || start_secs < 0.0 {
+        return Err("Invalid time range".to_string());
+    }
 
-    Ok(WaveformData {
-        peaks: all_peaks,
-        duration_secs,
+    let mut decoder = decoder::open(audio_path)?;
+    let sample_rate = decoder.sample_rate();
+
+    if start_secs > 0.0 {
+        decoder.seek(start_secs)?;
+    }
+
+    let start_frame = (start_secs * sample_rate as f64).round() as u64;
+    let end_frame = (end_secs * sample_rate as f64).round() as u64;
+
+    let mut channels: Vec<Vec<f32>> = Vec::new();
+
+    while let Some(packet) = decoder.next_packet()? {
+        if packet.frame >= end_frame {
+            break;
+        }
+
+        if channels.is_empty() {
+            channels = vec![Vec::new(); packet.channels];
+        }
+
+        for (i, frame) in packet.samples.chunks(packet.channels).enumerate() {
+            let position = packet.frame + i as u64;
+            if position < start_frame {
+                continue;
+            }
+            if position >= end_frame {
+                break;
+            }
+            for (channel, &sample) in channels.iter_mut().zip(frame) {
+                channel.push(sample);
+            }
+        }
+    }
+
+    if channels.is_empty() {
+        return Err("No audio decoded in the requested range".to_string());
+    }
+
+    Ok(DecodedAudio {
+        channels,
         sample_rate,
     })
 }
+
+/// Write planar channels to a 32-bit float WAV file.
+pub fn write_wav(path: &Path, channels: &[Vec<f32>], sample_rate: u32) -> Result<(), String> {
+    const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+    let channel_count = channels.len() as u16;
+    if channel_count == 0 {
+        return Err("No channels to write".to_string());
+    }
+    let frames = channels[0].len();
+    let block_align = channel_count as u32 * 4;
+    // The RIFF size field covers the data plus 50 bytes of header
+    let data_len = u32::try_from(frames)
+        .ok()
+        .and_then(|frames| frames.checked_mul(block_align))
+        .filter(|&len| len <= u32::MAX - 50)
+        .ok_or_else(|| "Audio is too long for a WAV file".to_string())?;
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(58 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(50 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+
+    bytes.extend_from_slice(b"fmt ");
+    bytes.extend_from_slice(&18u32.to_le_bytes());
+    bytes.extend_from_slice(&WAVE_FORMAT_IEEE_FLOAT.to_le_bytes());
+    bytes.extend_from_slice(&channel_count.to_le_bytes());
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * block_align).to_le_bytes());
+    bytes.extend_from_slice(&(block_align as u16).to_le_bytes());
+    bytes.extend_from_slice(&32u16.to_le_bytes());
+    bytes.extend_from_slice(&0u16.to_le_bytes());
+
+    // Non-PCM formats require a fact chunk with the frame count
+    bytes.extend_from_slice(b"fact");
+    bytes.extend_from_slice(&4u32.to_le_bytes());
+    bytes.extend_from_slice(&(frames as u32).to_le_bytes());
+
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    for i in 0..frames {
+        for channel in channels {
+            let sample = channel.get(i).copied().unwrap_or(0.0);
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+    }
+
+    let mut file =
+        std::fs::File::create(path).map_err(|e| format!("Failed to create WAV file: {}", e))?;
+    file.write_all(&bytes)
+        .map_err(|e| format!("Failed to write WAV file: {}", e))
+}
+
+/// Path of a scratch file named `name` in the directory shared by unit tests
+#[cfg(test)]
+pub(crate) fn test_file_path(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join("crate_tests");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_block_keeps_polarity() {
+        let mut buffers = PeakBuffers::default();
+        buffers.push_block(&[0.1, 0.5, -0.2, 0.3]);
+
+        assert_eq!(buffers.min_peaks, vec![-0.2]);
+        assert_eq!(buffers.max_peaks, vec![0.5]);
+        assert_eq!(buffers.peaks, vec![0.5]);
+        let expected_rms = ((0.01 + 0.25 + 0.04 + 0.09) / 4.0f32).sqrt();
+        assert!((buffers.rms_peaks[0] - expected_rms).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_dc_offset_is_visible() {
+        let mut buffers = PeakBuffers::default();
+        buffers.push_block(&[0.4, 0.6, 0.5, 0.5]);
+
+        assert!(buffers.min_peaks[0] > 0.0);
+        assert!((buffers.rms_peaks[0] - 0.5).abs() < 0.01);
+    }
+
+    fn series_for(mode: ChannelMode, frames: &[[f32; 2]]) -> Vec<PeakBuffers> {
+        let mut series: Vec<PeakSeries> = (0..mode.output_channels(2))
+            .map(|_| PeakSeries::default())
+            .collect();
+        for frame in frames {
+            mode.split_frame(frame, &mut series);
+        }
+        series
+            .into_iter()
+            .map(|mut s| {
+                s.finish_block();
+                s.all
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_per_channel_keeps_sides_apart() {
+        let frames: Vec<[f32; 2]> = (0..SAMPLES_PER_PEAK).map(|_| [0.8, 0.2]).collect();
+        let channels = series_for(ChannelMode::PerChannel, &frames);
+
+        assert_eq!(channels.len(), 2);
+        assert!((channels[0].peaks[0] - 0.8).abs() < 1e-6);
+        assert!((channels[1].peaks[0] - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_mid_side_shows_out_of_phase_content() {
+        // Fully out-of-phase stereo cancels in the mono mix but not in the side channel
+        let frames: Vec<[f32; 2]> = (0..SAMPLES_PER_PEAK)
+            .map(|i| {
+                let s = (i as f32 * 0.1).sin() * 0.5;
+                [s, -s]
+            })
+            .collect();
+        let channels = series_for(ChannelMode::MidSide, &frames);
+
+        assert_eq!(channels.len(), 2);
+        assert!(channels[0].peaks[0] < 1e-6);
+        assert!(channels[1].peaks[0] > 0.4);
+    }
+
+    #[test]
+    fn test_mono_mode_has_no_channels() {
+        assert!(series_for(ChannelMode::Mono, &[[0.5, 0.5]]).is_empty());
+    }
+
+    #[test]
+    fn test_normalize_scales_all_series() {
+        let mut buffers = PeakBuffers::default();
+        buffers.push_block(&[-0.5, 0.25]);
+        buffers.push_block(&[0.1, -0.1]);
+        buffers.normalize();
+
+        assert_eq!(buffers.peaks, vec![1.0, 0.2]);
+        assert_eq!(buffers.min_peaks, vec![-1.0, -0.2]);
+        assert_eq!(buffers.max_peaks, vec![0.5, 0.2]);
+        assert!(buffers.rms_peaks.iter().all(|&r| r <= 1.0));
+    }
+
+    fn band_energy(freq: f32) -> [f32; 3] {
+        let sample_rate = 44100;
+        let mut bands = BandSeries::new(sample_rate);
+        for i in 0..sample_rate {
+            bands.push((2.0 * std::f32::consts::PI * freq * i as f32 / sample_rate as f32).sin());
+        }
+        bands.finish_block();
+        // Skip the filter settling time at the start
+        let settled = bands.all.len() / 2;
+        let mean =
+            |band: &[f32]| band[settled..].iter().sum::<f32>() / (band.len() - settled) as f32;
+        [
+            mean(&bands.all.low),
+            mean(&bands.all.mid),
+            mean(&bands.all.high),
+        ]
+    }
+
+    #[test]
+    fn test_bands_separate_low_mid_and_high() {
+        let [low, mid, high] = band_energy(60.0);
+        assert!(
+            low > 0.6 && mid < 0.3 * low && high < 0.01,
+            "{} {} {}",
+            low,
+            mid,
+            high
+        );
+
+        let [low, mid, high] = band_energy(700.0);
+        assert!(
+            mid > 0.5 && low < 0.3 * mid && high < 0.3 * mid,
+            "{} {} {}",
+            low,
+            mid,
+            high
+        );
+
+        let [low, mid, high] = band_energy(8000.0);
+        assert!(
+            high > 0.6 && mid < 0.3 * high && low < 0.01,
+            "{} {} {}",
+            low,
+            mid,
+            high
+        );
+    }
+
+    #[test]
+    fn test_bands_align_with_mono_blocks() {
+        let mut mono = PeakSeries::default();
+        let mut bands = BandSeries::new(44100);
+        for i in 0..SAMPLES_PER_PEAK * 3 + 17 {
+            let s = (i as f32 * 0.05).sin();
+            mono.push(s);
+            bands.push(s);
+        }
+        mono.finish_block();
+        bands.finish_block();
+
+        assert_eq!(bands.all.len(), mono.all.len());
+        assert_eq!(bands.chunk.high.len(), mono.chunk.len());
+    }
+
+    fn normalized_waveform() -> WaveformData {
+        let mut mono = PeakBuffers::default();
+        mono.push_block(&[-0.25, 0.125]);
+        mono.push_block(&[0.0, 0.0]);
+        let factor = mono.normalize();
+        let mut channel = PeakBuffers::default();
+        channel.push_block(&[0.5, -0.5]);
+        channel.push_block(&[0.0, 0.1]);
+        channel.normalize_to(0.5);
+
+        WaveformData {
+            peaks: mono.peaks,
+            min_peaks: mono.min_peaks,
+            max_peaks: mono.max_peaks,
+            rms_peaks: mono.rms_peaks,
+            channel_mode: ChannelMode::PerChannel,
+            channels: vec![channel],
+            bands: None,
+            scale: WaveformScale::Normalized,
+            normalization_factor: factor,
+            channel_normalization_factor: 0.5,
+            duration_secs: 512.0 / 44100.0,
+            sample_rate: 44100,
+        }
+    }
+
+    #[test]
+    fn test_linear_scale_restores_absolute_levels() {
+        let mut waveform = normalized_waveform();
+        assert_eq!(waveform.normalization_factor, 0.25);
+        apply_scale(&mut waveform, WaveformScale::Linear);
+
+        assert_eq!(waveform.scale, WaveformScale::Linear);
+        assert_eq!(waveform.peaks, vec![0.25, 0.0]);
+        assert_eq!(waveform.min_peaks, vec![-0.25, 0.0]);
+        assert_eq!(waveform.channels[0].max_peaks, vec![0.5, 0.1]);
+    }
+
+    #[test]
+    fn test_dbfs_scale_floors_silence() {
+        let mut waveform = normalized_waveform();
+        apply_scale(&mut waveform, WaveformScale::Dbfs);
+
+        assert!((waveform.peaks[0] - 20.0 * 0.25f32.log10()).abs() < 1e-4);
+        assert!((waveform.min_peaks[0] - waveform.peaks[0]).abs() < 1e-4);
+        assert_eq!(waveform.peaks[1], DBFS_FLOOR);
+        assert!((waveform.channels[0].peaks[0] - 20.0 * 0.5f32.log10()).abs() < 1e-4);
+
+        // Already converted data is left alone
+        let converted = waveform.peaks.clone();
+        apply_scale(&mut waveform, WaveformScale::Linear);
+        assert_eq!(waveform.peaks, converted);
+    }
+
+    #[test]
+    fn test_tlen_tag_is_milliseconds() {
+        use symphonia::core::meta::{MetadataBuilder, Tag, Value};
+
+        let mut builder = MetadataBuilder::new();
+        builder.add_tag(Tag::new(None, "TLEN", Value::from("183500")));
+        assert_eq!(tlen_secs(&builder.metadata()), Some(183.5));
+
+        let mut builder = MetadataBuilder::new();
+        builder.add_tag(Tag::new(None, "TLEN", Value::from("unknown")));
+        assert_eq!(tlen_secs(&builder.metadata()), None);
+    }
+
+    #[test]
+    fn test_packet_scan_matches_frame_count() {
+        let path = test_file_path("packet_scan.wav");
+        let frames = 44100 + 123;
+        write_wav(&path, &[vec![0.25; frames]], 44100).unwrap();
+
+        let file = std::fs::File::open(&path).unwrap();
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut format = symphonia::default::get_probe()
+            .format(
+                &Hint::new(),
+                mss,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .unwrap()
+            .format;
+        let track_id = format.default_track().unwrap().id;
+
+        let secs =
+            scanned_duration(&path, format.as_mut(), track_id, TimeBase::new(1, 44100)).unwrap();
+        assert!((secs - frames as f64 / 44100.0).abs() < 1e-9);
+        assert!(get_audio_info(&path).unwrap().duration_exact);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_write_wav_rejects_data_over_4_gib() {
+        let path = test_file_path("oversized.wav");
+        // 16000 channels of 70000 frames need about 4.5 GB of sample data
+        let mut channels = vec![Vec::new(); 16000];
+        channels[0] = vec![0.0; 70000];
+
+        assert!(write_wav(&path, &channels, 44100).is_err());
+        assert!(!path.exists());
+    }
+}
@@ -3,12 +3,8 @@ use std::path::Path;
 use aubio::{Onset, OnsetMode, Tempo};
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use symphonia::core::audio::SampleBuffer;
-use symphonia::core::codecs::DecoderOptions;
-use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
-use symphonia::core::probe::Hint;
+
+use crate::analysis::{self, AnalysisSink, AubioHandle, HopBuffer, PcmBlock, StreamFormat};
 
 /// Beat and tempo information extracted from audio
 #[derive(Clone, Debug, Serialize, Deserialize, Type)]
@@ -22,16 +18,63 @@ pub struct BeatInfo {
     pub beats: Vec<f64>,
     /// Onset positions in seconds (transients/attacks)
     pub onsets: Vec<f64>,
+    /// Configuration the detection ran with
+    pub config: BeatDetectionConfig,
+}
+
+/// Onset detection function, named as in aubio
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "lowercase")]
+pub enum OnsetMethod {
+    /// Local energy of the spectral frame
+    Energy,
+    /// High frequency content, good for percussive onsets
+    Hfc,
+    /// Complex domain deviation
+    Complex,
+    /// Phase deviation
+    Phase,
+    /// Weighted phase deviation
+    WPhase,
+    /// Spectral difference
+    SpecDiff,
+    /// Kullback-Leibler divergence
+    Kl,
+    /// Modified Kullback-Leibler divergence
+    Mkl,
+    /// Spectral flux
+    SpecFlux,
+}
+
+impl From<OnsetMethod> for OnsetMode {
+    fn from(method: OnsetMethod) -> Self {
+        match method {
+            OnsetMethod::Energy => OnsetMode::Energy,
+            OnsetMethod::Hfc => OnsetMode::Hfc,
+            OnsetMethod::Complex => OnsetMode::Complex,
+            OnsetMethod::Phase => OnsetMode::Phase,
+            OnsetMethod::WPhase => OnsetMode::WPhase,
+            OnsetMethod::SpecDiff => OnsetMode::SpecDiff,
+            OnsetMethod::Kl => OnsetMode::Kl,
+            OnsetMethod::Mkl => OnsetMode::Mkl,
+            OnsetMethod::SpecFlux => OnsetMode::SpecFlux,
+        }
+    }
 }
 
+/// Largest accepted FFT buffer for beat detection
+const MAX_BUF_SIZE: usize = 16384;
+
 /// Configuration for beat detection
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase", default)]
 pub struct BeatDetectionConfig {
     /// FFT buffer size (power of 2, e.g., 1024, 2048)
     pub buf_size: usize,
     /// Hop size between frames
     pub hop_size: usize,
     /// Onset detection method
-    pub onset_method: OnsetMode,
+    pub onset_method: OnsetMethod,
     /// Silence threshold in dB
     pub silence_threshold: f32,
     /// Onset detection threshold
@@ -43,13 +86,32 @@ impl Default for BeatDetectionConfig {
         Self {
             buf_size: 1024,
             hop_size: 512,
-            onset_method: OnsetMode::SpecDiff,
+            onset_method: OnsetMethod::SpecDiff,
             silence_threshold: -70.0,
             onset_threshold: 0.3,
         }
     }
 }
 
+impl BeatDetectionConfig {
+    /// Check the sizes and thresholds before they reach aubio
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.buf_size.is_power_of_two() || !(64..=MAX_BUF_SIZE).contains(&self.buf_size) {
+            return Err(format!(
+                "Beat detection buffer size must be a power of 2 between 64 and {}",
+                MAX_BUF_SIZE
+            ));
+        }
+        if self.hop_size == 0 || self.hop_size > self.buf_size {
+            return Err("Hop size must be between 1 and the buffer size".to_string());
+        }
+        if !self.silence_threshold.is_finite() || !self.onset_threshold.is_finite() {
+            return Err("Beat detection thresholds must be finite".to_string());
+        }
+        Ok(())
+    }
+}
+
 /// Analyze audio file for beats and tempo
 pub fn analyze_beats(audio_path: &Path) -> Result<BeatInfo, String> {
     analyze_beats_with_config(audio_path, &BeatDetectionConfig::default())
@@ -60,235 +122,325 @@ pub fn analyze_beats_with_config(
     audio_path: &Path,
     config: &BeatDetectionConfig,
 ) -> Result<BeatInfo, String> {
-    // First, decode audio to f32 samples using Symphonia
-    let samples = decode_audio_to_mono(audio_path)?;
-    let sample_rate = get_sample_rate(audio_path)?;
-
-    // Run tempo detection
-    let (bpm, bpm_confidence, beats) =
-        detect_tempo(&samples, sample_rate, config)?;
+    // Tempo and onsets are tracked concurrently from a single decode
+    let mut tempo = TempoSink::new(config);
+    let mut onsets = OnsetSink::new(config);
+    analysis::run_all(audio_path, &mut [&mut tempo, &mut onsets])?;
 
-    // Run onset detection
-    let onsets = detect_onsets(&samples, sample_rate, config)?;
+    Ok(beat_info(tempo, onsets))
+}
 
-    Ok(BeatInfo {
+/// Combine the results of finished tempo and onset sinks
+pub fn beat_info<F>(tempo: TempoSink<F>, onsets: OnsetSink) -> BeatInfo
+where
+    F: FnMut(&[f64], f32) + Send,
+{
+    let config = tempo.config.clone();
+    let (bpm, bpm_confidence, beats) = tempo.into_result();
+    BeatInfo {
         bpm,
         bpm_confidence,
         beats,
-        onsets,
-    })
+        onsets: onsets.into_onsets(),
+        config,
+    }
 }
 
-/// Decode audio file to mono f32 samples
-fn decode_audio_to_mono(audio_path: &Path) -> Result<Vec<f32>, String> {
-    let file = std::fs::File::open(audio_path)
-        .map_err(|e| format!("Failed to open audio file: {}", e))?;
-
-    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+/// Streaming tempo detection; samples may arrive in blocks of any size
+pub(crate) struct TempoTracker {
+    tempo: AubioHandle<Tempo>,
+    hops: HopBuffer,
+    beats: Vec<f64>,
+    bpm: f32,
+    confidence: f32,
+}
 
-    let mut hint = Hint::new();
-    if let Some(ext) = audio_path.extension().and_then(|e| e.to_str()) {
-        hint.with_extension(ext);
+impl TempoTracker {
+    pub(crate) fn new(sample_rate: u32, config: &BeatDetectionConfig) -> Result<Self, String> {
+        config.validate()?;
+        let mut tempo = Tempo::new(
+            config.onset_method.into(),
+            config.buf_size,
+            config.hop_size,
+            sample_rate,
+        )
+        .map_err(|e| format!("Failed to create tempo detector: {:?}", e))?;
+
+        tempo.set_silence(config.silence_threshold);
+        tempo.set_threshold(config.onset_threshold);
+
+        Ok(Self {
+            tempo: AubioHandle::new(tempo),
+            hops: HopBuffer::new(config.hop_size),
+            beats: Vec::new(),
+            bpm: 0.0,
+            confidence: 0.0,
+        })
     }
 
-    let format_opts = FormatOptions::default();
-    let metadata_opts = MetadataOptions::default();
-
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &format_opts, &metadata_opts)
-        .map_err(|e| format!("Failed to probe audio format: {}", e))?;
+    pub(crate) fn push(&mut self, samples: &[f32]) -> Result<(), String> {
+        let Self {
+            tempo,
+            hops,
+            beats,
+            bpm,
+            confidence,
+        } = self;
+        hops.push(samples, |hop| {
+            Self::process_hop(tempo, hop, beats, bpm, confidence)
+        })
+    }
 
-    let mut format = probed.format;
+    /// Returns the BPM, its confidence and the beat positions in seconds
+    pub(crate) fn finish(mut self) -> Result<(f32, f32, Vec<f64>), String> {
+        let Self {
+            tempo,
+            hops,
+            beats,
+            bpm,
+            confidence,
+        } = &mut self;
+        hops.flush(|hop| Self::process_hop(tempo, hop, beats, bpm, confidence))?;
+        Ok((self.bpm, self.confidence, self.beats))
+    }
 
-    let track = format
-        .tracks()
-        .iter()
-        .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
-        .ok_or_else(|| "No audio track found".to_string())?;
+    fn process_hop(
+        tempo: &mut Tempo,
+        hop: &[f32],
+        beats: &mut Vec<f64>,
+        bpm: &mut f32,
+        confidence: &mut f32,
+    ) -> Result<(), String> {
+        let beat_detected = tempo
+            .do_result(hop)
+            .map_err(|e| format!("Tempo detection error: {:?}", e))?;
 
-    let decoder_opts = DecoderOptions::default();
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &decoder_opts)
-        .map_err(|e| format!("Failed to create decoder: {}", e))?;
+        if beat_detected > 0.0 {
+            beats.push(tempo.get_last_s() as f64);
+        }
 
-    let track_id = track.id;
-    let mut samples: Vec<f32> = Vec::new();
-    let mut sample_buffer: Option<SampleBuffer<f32>> = None;
+        // Update BPM estimate
+        let current_bpm = tempo.get_bpm();
+        if current_bpm > 0.0 {
+            *bpm = current_bpm;
+            *confidence = tempo.get_confidence();
+        }
+        Ok(())
+    }
+}
 
-    loop {
-        let packet = match format.next_packet() {
-            Ok(packet) => packet,
-            Err(symphonia::core::errors::Error::IoError(ref e))
-                if e.kind() == std::io::ErrorKind::UnexpectedEof =>
-            {
-                break;
-            }
-            Err(e) => return Err(format!("Failed to read packet: {}", e)),
-        };
+/// Streaming onset detection; samples may arrive in blocks of any size
+pub(crate) struct OnsetTracker {
+    onset: AubioHandle<Onset>,
+    hops: HopBuffer,
+    onsets: Vec<f64>,
+}
 
-        if packet.track_id() != track_id {
-            continue;
-        }
+impl OnsetTracker {
+    pub(crate) fn new(sample_rate: u32, config: &BeatDetectionConfig) -> Result<Self, String> {
+        config.validate()?;
+        let mut onset = Onset::new(
+            config.onset_method.into(),
+            config.buf_size,
+            config.hop_size,
+            sample_rate,
+        )
+        .map_err(|e| format!("Failed to create onset detector: {:?}", e))?;
+
+        onset.set_silence(config.silence_threshold);
+        onset.set_threshold(config.onset_threshold);
+
+        Ok(Self {
+            onset: AubioHandle::new(onset),
+            hops: HopBuffer::new(config.hop_size),
+            onsets: Vec::new(),
+        })
+    }
 
-        let decoded = match decoder.decode(&packet) {
-            Ok(decoded) => decoded,
-            Err(_) => continue,
-        };
+    pub(crate) fn push(&mut self, samples: &[f32]) -> Result<(), String> {
+        let Self {
+            onset,
+            hops,
+            onsets,
+        } = self;
+        hops.push(samples, |hop| Self::process_hop(onset, hop, onsets))
+    }
 
-        if sample_buffer.is_none() {
-            let spec = *decoded.spec();
-            let duration = decoded.capacity() as u64;
-            sample_buffer = Some(SampleBuffer::new(duration, spec));
-        }
+    /// Returns the onset positions in seconds
+    pub(crate) fn finish(mut self) -> Result<Vec<f64>, String> {
+        let Self {
+            onset,
+            hops,
+            onsets,
+        } = &mut self;
+        hops.flush(|hop| Self::process_hop(onset, hop, onsets))?;
+        Ok(self.onsets)
+    }
 
-        if let Some(ref mut buf) = sample_buffer {
-            let channels = decoded.spec().channels.count();
-            buf.copy_interleaved_ref(decoded);
-            let buf_samples = buf.samples();
+    fn process_hop(onset: &mut Onset, hop: &[f32], onsets: &mut Vec<f64>) -> Result<(), String> {
+        let onset_detected = onset
+            .do_result(hop)
+            .map_err(|e| format!("Onset detection error: {:?}", e))?;
 
-            // Convert to mono by averaging channels
-            for chunk in buf_samples.chunks(channels) {
-                let mono: f32 = chunk.iter().sum::<f32>() / channels as f32;
-                samples.push(mono);
-            }
+        if onset_detected > 0.0 {
+            onsets.push(onset.get_last_s() as f64);
         }
+        Ok(())
     }
-
-    Ok(samples)
 }
 
-/// Get sample rate from audio file
-fn get_sample_rate(audio_path: &Path) -> Result<u32, String> {
-    let file = std::fs::File::open(audio_path)
-        .map_err(|e| format!("Failed to open audio file: {}", e))?;
-
-    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+/// Tempo and beat tracking as a sink of the shared decode pass
+pub struct TempoSink<'a, F = fn(&[f64], f32)> {
+    config: &'a BeatDetectionConfig,
+    tracker: Option<TempoTracker>,
+    on_beats: F,
+    /// Number of beats already passed to `on_beats`
+    reported: usize,
+    result: (f32, f32, Vec<f64>),
+}
 
-    let mut hint = Hint::new();
-    if let Some(ext) = audio_path.extension().and_then(|e| e.to_str()) {
-        hint.with_extension(ext);
+impl<'a> TempoSink<'a> {
+    pub fn new(config: &'a BeatDetectionConfig) -> Self {
+        Self::with_partial_beats(config, |_, _| {})
     }
+}
 
-    let format_opts = FormatOptions::default();
-    let metadata_opts = MetadataOptions::default();
+impl<'a, F> TempoSink<'a, F>
+where
+    F: FnMut(&[f64], f32) + Send,
+{
+    /// Like `new`, but passes each batch of newly detected beats and the
+    /// current BPM estimate to `on_beats` while the audio is still decoding
+    pub fn with_partial_beats(config: &'a BeatDetectionConfig, on_beats: F) -> Self {
+        Self {
+            config,
+            tracker: None,
+            on_beats,
+            reported: 0,
+            result: (0.0, 0.0, Vec::new()),
+        }
+    }
 
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &format_opts, &metadata_opts)
-        .map_err(|e| format!("Failed to probe audio format: {}", e))?;
+    /// BPM, confidence and beat positions once the decode pass has finished
+    pub fn into_result(self) -> (f32, f32, Vec<f64>) {
+        self.result
+    }
+}
 
-    let format = probed.format;
+impl<F> AnalysisSink for TempoSink<'_, F>
+where
+    F: FnMut(&[f64], f32) + Send,
+{
+    fn start(&mut self, format: &StreamFormat) -> Result<(), String> {
+        self.tracker = Some(TempoTracker::new(format.sample_rate, self.config)?);
+        self.reported = 0;
+        Ok(())
+    }
 
-    let track = format
-        .tracks()
-        .iter()
-        .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
-        .ok_or_else(|| "No audio track found".to_string())?;
+    fn process(&mut self, block: &PcmBlock) -> Result<(), String> {
+        let Some(tracker) = self.tracker.as_mut() else {
+            return Ok(());
+        };
+        tracker.push(&block.mono)?;
+        report_new_beats(
+            &mut self.on_beats,
+            &mut self.reported,
+            &tracker.beats,
+            tracker.bpm,
+        );
+        Ok(())
+    }
 
-    track
-        .codec_params
-        .sample_rate
-        .ok_or_else(|| "Unknown sample rate".to_string())
+    fn finish(&mut self) -> Result<(), String> {
+        if let Some(tracker) = self.tracker.take() {
+            let (bpm, confidence, beats) = tracker.finish()?;
+            report_new_beats(&mut self.on_beats, &mut self.reported, &beats, bpm);
+            self.result = (bpm, confidence, beats);
+        }
+        Ok(())
+    }
 }
 
-/// Detect tempo (BPM) and beat positions
-fn detect_tempo(
-    samples: &[f32],
-    sample_rate: u32,
-    config: &BeatDetectionConfig,
-) -> Result<(f32, f32, Vec<f64>), String> {
-    let mut tempo = Tempo::new(
-        config.onset_method,
-        config.buf_size,
-        config.hop_size,
-        sample_rate,
-    )
-    .map_err(|e| format!("Failed to create tempo detector: {:?}", e))?;
-
-    tempo.set_silence(config.silence_threshold);
-    tempo.set_threshold(config.onset_threshold);
-
-    let mut beats: Vec<f64> = Vec::new();
-    let mut last_bpm: f32 = 0.0;
-    let mut last_confidence: f32 = 0.0;
-
-    // Process audio in chunks
-    for chunk in samples.chunks(config.hop_size) {
-        // Pad if necessary
-        let input: Vec<f32> = if chunk.len() < config.hop_size {
-            let mut padded = chunk.to_vec();
-            padded.resize(config.hop_size, 0.0);
-            padded
-        } else {
-            chunk.to_vec()
-        };
-
-        let beat_detected = tempo
-            .do_result(&input)
-            .map_err(|e| format!("Tempo detection error: {:?}", e))?;
+/// Pass the beats after the first `reported` to `on_beats`, if there are any
+fn report_new_beats<F>(on_beats: &mut F, reported: &mut usize, beats: &[f64], bpm: f32)
+where
+    F: FnMut(&[f64], f32),
+{
+    if beats.len() > *reported {
+        on_beats(&beats[*reported..], bpm);
+        *reported = beats.len();
+    }
+}
 
-        if beat_detected > 0.0 {
-            let beat_time = tempo.get_last_s();
-            beats.push(beat_time as f64);
-        }
+/// Onset detection as a sink of the shared decode pass
+pub struct OnsetSink<'a> {
+    config: &'a BeatDetectionConfig,
+    tracker: Option<OnsetTracker>,
+    onsets: Vec<f64>,
+}
 
-        // Update BPM estimate
-        let current_bpm = tempo.get_bpm();
-        if current_bpm > 0.0 {
-            last_bpm = current_bpm;
-            last_confidence = tempo.get_confidence();
+impl<'a> OnsetSink<'a> {
+    pub fn new(config: &'a BeatDetectionConfig) -> Self {
+        Self {
+            config,
+            tracker: None,
+            onsets: Vec::new(),
         }
     }
 
-    Ok((last_bpm, last_confidence, beats))
+    /// Onset positions once the decode pass has finished
+    pub fn into_onsets(self) -> Vec<f64> {
+        self.onsets
+    }
 }
 
-/// Detect onsets (transients/attacks)
-fn detect_onsets(
-    samples: &[f32],
-    sample_rate: u32,
-    config: &BeatDetectionConfig,
-) -> Result<Vec<f64>, String> {
-    let mut onset = Onset::new(
-        config.onset_method,
-        config.buf_size,
-        config.hop_size,
-        sample_rate,
-    )
-    .map_err(|e| format!("Failed to create onset detector: {:?}", e))?;
-
-    onset.set_silence(config.silence_threshold);
-    onset.set_threshold(config.onset_threshold);
-
-    let mut onsets: Vec<f64> = Vec::new();
-
-    // Process audio in chunks
-    for chunk in samples.chunks(config.hop_size) {
-        // Pad if necessary
-        let input: Vec<f32> = if chunk.len() < config.hop_size {
-            let mut padded = chunk.to_vec();
-            padded.resize(config.hop_size, 0.0);
-            padded
-        } else {
-            chunk.to_vec()
-        };
-
-        let onset_detected = onset
-            .do_result(&input)
-            .map_err(|e| format!("Onset detection error: {:?}", e))?;
+impl AnalysisSink for OnsetSink<'_> {
+    fn start(&mut self, format: &StreamFormat) -> Result<(), String> {
+        self.tracker = Some(OnsetTracker::new(format.sample_rate, self.config)?);
+        Ok(())
+    }
 
-        if onset_detected > 0.0 {
-            let onset_time = onset.get_last_s();
-            onsets.push(onset_time as f64);
+    fn process(&mut self, block: &PcmBlock) -> Result<(), String> {
+        match self.tracker.as_mut() {
+            Some(tracker) => tracker.push(&block.mono),
+            None => Ok(()),
         }
     }
 
-    Ok(onsets)
+    fn finish(&mut self) -> Result<(), String> {
+        if let Some(tracker) = self.tracker.take() {
+            self.onsets = tracker.finish()?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Detect tempo (BPM) and beat positions
+    fn detect_tempo(
+        samples: &[f32],
+        sample_rate: u32,
+        config: &BeatDetectionConfig,
+    ) -> Result<(f32, f32, Vec<f64>), String> {
+        let mut tracker = TempoTracker::new(sample_rate, config)?;
+        tracker.push(samples)?;
+        tracker.finish()
+    }
+
+    /// Detect onsets (transients/attacks)
+    fn detect_onsets(
+        samples: &[f32],
+        sample_rate: u32,
+        config: &BeatDetectionConfig,
+    ) -> Result<Vec<f64>, String> {
+        let mut tracker = OnsetTracker::new(sample_rate, config)?;
+        tracker.push(samples)?;
+        tracker.finish()
+    }
+
     // Helper to generate a click track (impulses at regular intervals)
     fn generate_click_track(bpm: f32, duration_secs: f32, sample_rate: u32) -> Vec<f32> {
         let num_samples = (duration_secs * sample_rate as f32) as usize;
@@ -316,6 +468,34 @@ mod tests {
         vec![0.0f32; num_samples]
     }
 
+    #[test]
+    fn test_validate_rejects_bad_sizes() {
+        assert!(BeatDetectionConfig::default().validate().is_ok());
+
+        let bad = [
+            BeatDetectionConfig {
+                hop_size: 0,
+                ..Default::default()
+            },
+            BeatDetectionConfig {
+                buf_size: 1000,
+                ..Default::default()
+            },
+            BeatDetectionConfig {
+                buf_size: 512,
+                hop_size: 1024,
+                ..Default::default()
+            },
+            BeatDetectionConfig {
+                onset_threshold: f32::NAN,
+                ..Default::default()
+            },
+        ];
+        for config in bad {
+            assert!(config.validate().is_err(), "{:?}", config);
+        }
+    }
+
     #[test]
     fn test_default_config() {
         let config = BeatDetectionConfig::default();
@@ -325,6 +505,17 @@ mod tests {
         assert_eq!(config.onset_threshold, 0.3);
     }
 
+    #[test]
+    fn test_config_deserialization() {
+        let config: BeatDetectionConfig =
+            serde_json::from_str(r#"{"onsetMethod":"wphase","hopSize":256}"#).unwrap();
+        assert_eq!(config.onset_method, OnsetMethod::WPhase);
+        assert_eq!(config.hop_size, 256);
+        assert_eq!(config.buf_size, 1024);
+
+        assert!(serde_json::from_str::<BeatDetectionConfig>(r#"{"onsetMethod":"bogus"}"#).is_err());
+    }
+
     #[test]
     fn test_beat_info_serialization() {
         let beat_info = BeatInfo {
@@ -332,11 +523,13 @@ mod tests {
             bpm_confidence: 0.85,
             beats: vec![0.5, 1.0, 1.5, 2.0],
             onsets: vec![0.5, 1.0, 1.5, 2.0, 2.25],
+            config: BeatDetectionConfig::default(),
         };
 
         let json = serde_json::to_string(&beat_info).unwrap();
         assert!(json.contains("\"bpm\":120.0"));
         assert!(json.contains("\"bpmConfidence\":0.85")); // camelCase due to serde rename
+        assert!(json.contains("\"onsetMethod\":\"specdiff\""));
 
         let deserialized: BeatInfo = serde_json::from_str(&json).unwrap();
         assert_eq!(deserialized.bpm, 120.0);
@@ -502,7 +695,7 @@ mod tests {
         let config = BeatDetectionConfig {
             buf_size: 2048,
             hop_size: 1024,
-            onset_method: OnsetMode::Energy,
+            onset_method: OnsetMethod::Energy,
             silence_threshold: -60.0,
             onset_threshold: 0.5,
         };
@@ -518,14 +711,15 @@ mod tests {
         let samples = generate_click_track(120.0, duration, sample_rate);
 
         let methods = [
-            OnsetMode::Energy,
-            OnsetMode::Hfc,
-            OnsetMode::Complex,
-            OnsetMode::Phase,
-            OnsetMode::SpecDiff,
-            OnsetMode::Kl,
-            OnsetMode::Mkl,
-            OnsetMode::SpecFlux,
+            OnsetMethod::Energy,
+            OnsetMethod::Hfc,
+            OnsetMethod::Complex,
+            OnsetMethod::Phase,
+            OnsetMethod::WPhase,
+            OnsetMethod::SpecDiff,
+            OnsetMethod::Kl,
+            OnsetMethod::Mkl,
+            OnsetMethod::SpecFlux,
         ];
 
         for method in methods {
@@ -681,7 +875,7 @@ mod tests {
         let config = BeatDetectionConfig {
             buf_size: 2048,
             hop_size: 512,
-            onset_method: OnsetMode::SpecFlux,
+            onset_method: OnsetMethod::SpecFlux,
             silence_threshold: -80.0,
             onset_threshold: 0.2,
         };
@@ -695,6 +889,7 @@ mod tests {
             bpm_confidence: confidence,
             beats,
             onsets,
+            config,
         };
 
         assert!(beat_info.bpm >= 0.0);
@@ -705,4 +900,42 @@ mod tests {
             beat_info.onsets.len()
         );
     }
+
+    #[test]
+    fn test_tempo_sink_reports_partial_beats() {
+        let sample_rate = 44100;
+        let samples = generate_click_track(120.0, 10.0, sample_rate);
+        let config = BeatDetectionConfig::default();
+
+        let mut partial: Vec<f64> = Vec::new();
+        let mut batches = 0;
+        let mut sink = TempoSink::with_partial_beats(&config, |beats: &[f64], _bpm: f32| {
+            partial.extend_from_slice(beats);
+            batches += 1;
+        });
+
+        sink.start(&StreamFormat {
+            sample_rate,
+            channels: 1,
+            total_frames: samples.len() as u64,
+        })
+        .unwrap();
+        // Odd block size so hops straddle block boundaries
+        for (i, block) in samples.chunks(1000).enumerate() {
+            sink.process(&PcmBlock {
+                interleaved: block.to_vec(),
+                mono: block.to_vec(),
+                offset: i as u64 * 1000,
+            })
+            .unwrap();
+        }
+        sink.finish().unwrap();
+        let (_, _, beats) = sink.into_result();
+
+        assert!(batches > 1, "beats should arrive in several batches");
+        assert_eq!(partial, beats);
+
+        let (_, _, whole) = detect_tempo(&samples, sample_rate, &config).unwrap();
+        assert_eq!(beats, whole);
+    }
 }
@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Error message of work stopped by a `CancellationToken`
+pub const CANCELLED: &str = "Cancelled";
+
+/// Shared flag asking long-running work to stop at its next check.
+///
+/// Clones share the flag, so one clone can be handed to each stage and
+/// cancelled from anywhere.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// `Err(CANCELLED)` once cancelled, for use with `?` between steps
+    pub fn check(&self) -> Result<(), String> {
+        if self.is_cancelled() {
+            Err(CANCELLED.to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Resolves once cancelled, for racing against other waits
+    pub async fn cancelled(&self) {
+        let notified = self.notify.notified();
+        if !self.is_cancelled() {
+            notified.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_cancellation() {
+        let token = CancellationToken::new();
+        let stage = token.clone();
+        assert!(stage.check().is_ok());
+
+        token.cancel();
+        assert!(stage.is_cancelled());
+        assert_eq!(stage.check(), Err(CANCELLED.to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_wakes_waiter() {
+        let token = CancellationToken::new();
+        let waiter = tokio::spawn({
+            let token = token.clone();
+            async move { token.cancelled().await }
+        });
+        tokio::task::yield_now().await;
+
+        token.cancel();
+        waiter.await.unwrap();
+        token.cancelled().await;
+    }
+}
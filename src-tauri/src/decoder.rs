@@ -0,0 +1,256 @@
+use std::path::Path;
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions};
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::{Time, TimeBase};
+
+use crate::audio;
+use crate::ffmpeg_runtime::AudioFile;
+
+/// One decoded packet of audio
+pub struct Packet<'a> {
+    /// Position of the packet's first frame in the stream
+    pub frame: u64,
+    pub channels: usize,
+    /// Interleaved samples, `channels` per frame
+    pub samples: &'a [f32],
+}
+
+/// A stream of decoded float PCM, whichever library does the decoding
+pub trait PcmDecoder {
+    fn sample_rate(&self) -> u32;
+
+    /// Length of the stream in frames as the container reports it, if it does
+    fn n_frames(&self) -> Option<u64>;
+
+    /// Move to `secs`; the next packet starts at or before it
+    fn seek(&mut self, secs: f64) -> Result<(), String>;
+
+    /// The next decoded packet, or `None` at the end of the stream
+    fn next_packet(&mut self) -> Result<Option<Packet<'_>>, String>;
+}
+
+/// Open `audio_path` with the first backend that can decode it.
+///
+/// Symphonia is tried first. Containers or codecs it does not support (Opus or
+/// Vorbis in WebM, ALAC, ...) fall back to the bundled FFmpeg.
+pub fn open(audio_path: &Path) -> Result<Box<dyn PcmDecoder>, String> {
+    let file =
+        std::fs::File::open(audio_path).map_err(|e| format!("Failed to open audio file: {}", e))?;
+
+    match SymphoniaDecoder::new(audio_path, file) {
+        Ok(decoder) => Ok(Box::new(decoder)),
+        Err(symphonia_err) => match FfmpegDecoder::open(audio_path) {
+            Ok(decoder) => Ok(Box::new(decoder)),
+            Err(ffmpeg_err) => Err(format!("{} (FFmpeg: {})", symphonia_err, ffmpeg_err)),
+        },
+    }
+}
+
+struct SymphoniaDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    sample_rate: u32,
+    time_base: Option<TimeBase>,
+    n_frames: Option<u64>,
+    sample_buffer: Option<SampleBuffer<f32>>,
+}
+
+impl SymphoniaDecoder {
+    fn new(audio_path: &Path, file: std::fs::File) -> Result<Self, String> {
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+        let mut hint = Hint::new();
+        if let Some(ext) = audio_path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(ext);
+        }
+
+        let format_opts = FormatOptions::default();
+        let metadata_opts = MetadataOptions::default();
+
+        let probed = symphonia::default::get_probe()
+            .format(&hint, mss, &format_opts, &metadata_opts)
+            .map_err(|e| format!("Failed to probe audio format: {}", e))?;
+
+        let format = probed.format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
+            .ok_or_else(|| "No audio track found".to_string())?;
+
+        let sample_rate = track
+            .codec_params
+            .sample_rate
+            .ok_or_else(|| "Unknown sample rate".to_string())?;
+
+        let decoder_opts = DecoderOptions::default();
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &decoder_opts)
+            .map_err(|e| format!("Failed to create decoder: {}", e))?;
+
+        Ok(Self {
+            track_id: track.id,
+            sample_rate,
+            time_base: track.codec_params.time_base,
+            n_frames: track.codec_params.n_frames,
+            format,
+            decoder,
+            sample_buffer: None,
+        })
+    }
+}
+
+impl PcmDecoder for SymphoniaDecoder {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn n_frames(&self) -> Option<u64> {
+        self.n_frames
+    }
+
+    fn seek(&mut self, secs: f64) -> Result<(), String> {
+        self.format
+            .seek(
+                SeekMode::Accurate,
+                SeekTo::Time {
+                    time: Time::from(secs),
+                    track_id: Some(self.track_id),
+                },
+            )
+            .map_err(|e| format!("Failed to seek: {}", e))?;
+        self.decoder.reset();
+        Ok(())
+    }
+
+    fn next_packet(&mut self) -> Result<Option<Packet<'_>>, String> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(symphonia::core::errors::Error::IoError(ref e))
+                    if e.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    return Ok(None)
+                }
+                Err(e) => return Err(format!("Failed to read packet: {}", e)),
+            };
+
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let frame = audio::packet_frame(self.time_base, packet.ts(), self.sample_rate);
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(_) => continue,
+            };
+
+            let spec = *decoded.spec();
+            let buf = self
+                .sample_buffer
+                .get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, spec));
+            buf.copy_interleaved_ref(decoded);
+
+            return Ok(Some(Packet {
+                frame,
+                channels: spec.channels.count(),
+                samples: buf.samples(),
+            }));
+        }
+    }
+}
+
+/// Decodes through the bundled FFmpeg libraries
+struct FfmpegDecoder {
+    file: AudioFile,
+    samples: Vec<f32>,
+}
+
+impl FfmpegDecoder {
+    fn open(audio_path: &Path) -> Result<Self, String> {
+        let file = AudioFile::open(audio_path)?;
+        if file.sample_rate <= 0 || file.channels <= 0 {
+            return Err("Unknown sample rate".to_string());
+        }
+        Ok(Self {
+            file,
+            samples: Vec::new(),
+        })
+    }
+}
+
+impl PcmDecoder for FfmpegDecoder {
+    fn sample_rate(&self) -> u32 {
+        self.file.sample_rate as u32
+    }
+
+    fn n_frames(&self) -> Option<u64> {
+        let secs = self.file.duration_secs;
+        (secs.is_finite() && secs > 0.0).then_some((secs * self.file.sample_rate as f64) as u64)
+    }
+
+    fn seek(&mut self, secs: f64) -> Result<(), String> {
+        self.file.seek(secs)
+    }
+
+    fn next_packet(&mut self) -> Result<Option<Packet<'_>>, String> {
+        let Some(position) = self.file.read_f32(&mut self.samples)? else {
+            return Ok(None);
+        };
+        Ok(Some(Packet {
+            // Encoder priming can put the first frames slightly before zero
+            frame: position.max(0) as u64,
+            channels: self.file.channels as usize,
+            samples: &self.samples,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_decodes_wav_with_symphonia() {
+        let path = audio::test_file_path("stereo.wav");
+
+        let left: Vec<f32> = (0..4410).map(|i| (i as f32 * 0.01).sin() * 0.5).collect();
+        let right = vec![0.25f32; 4410];
+        audio::write_wav(&path, &[left, right], 44100).unwrap();
+
+        let mut decoder = open(&path).unwrap();
+        assert_eq!(decoder.sample_rate(), 44100);
+        assert_eq!(decoder.n_frames(), Some(4410));
+
+        let mut frames = 0;
+        while let Some(packet) = decoder.next_packet().unwrap() {
+            assert_eq!(packet.channels, 2);
+            assert_eq!(packet.frame, frames);
+            assert!(packet.samples.chunks(2).all(|f| (f[1] - 0.25).abs() < 1e-3));
+            frames += (packet.samples.len() / 2) as u64;
+        }
+        assert_eq!(frames, 4410);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_open_reports_both_backends_when_neither_can_decode() {
+        let path = audio::test_file_path("not_audio.webm");
+        std::fs::write(&path, b"definitely not an audio file").unwrap();
+
+        let err = open(&path).err().unwrap();
+        assert!(err.contains("Failed to probe audio format"), "{}", err);
+        assert!(err.contains("FFmpeg"), "{}", err);
+
+        std::fs::remove_file(&path).ok();
+    }
+}
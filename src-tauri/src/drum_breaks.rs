@@ -0,0 +1,369 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::analysis::{self, AnalysisSink, HopBuffer, PcmBlock, StreamFormat};
+use crate::beat_detection::{self, BeatDetectionConfig, BeatInfo, OnsetSink, TempoSink};
+use crate::hpss::{Hpss, HpssConfig, HpssFrame};
+
+/// Beats per bar assumed when grouping the beat grid into windows
+const BEATS_PER_BAR: usize = 4;
+/// Windows this far (dB) below the loudest window are treated as silence
+const SILENCE_FLOOR_DB: f32 = -50.0;
+/// Weight of the percussive-to-harmonic ratio in the final score
+const PERCUSSIVE_WEIGHT: f32 = 0.7;
+/// Weight of the onset density in the final score
+const DENSITY_WEIGHT: f32 = 0.3;
+/// Onsets per beat at which the density score saturates (straight 8ths)
+const SATURATING_ONSETS_PER_BEAT: f32 = 2.0;
+
+/// Options for the drum-break finder
+#[derive(Clone, Debug, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DrumBreakOptions {
+    /// Number of bars per scored window when a beat grid is available
+    pub bars_per_window: u32,
+    /// Window length in seconds used when no beat grid could be detected
+    pub window_secs: f64,
+    /// Maximum number of ranked candidates to return
+    pub max_results: u32,
+}
+
+impl Default for DrumBreakOptions {
+    fn default() -> Self {
+        Self {
+            bars_per_window: 1,
+            window_secs: 2.0,
+            max_results: 20,
+        }
+    }
+}
+
+/// A window of audio scored for how likely it is to be an exposed drum break
+#[derive(Clone, Debug, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct DrumBreakCandidate {
+    /// Window start in seconds
+    pub start: f64,
+    /// Window end in seconds
+    pub end: f64,
+    /// Combined score (0.0 - 1.0, higher is more likely a break)
+    pub score: f32,
+    /// Share of spectral energy in the percussive component (0.0 - 1.0)
+    pub percussive_ratio: f32,
+    /// Detected onsets per second within the window
+    pub onset_density: f32,
+}
+
+/// Per-frame energies of the separated components
+struct FrameEnergy {
+    harmonic: f32,
+    percussive: f32,
+}
+
+/// Summed component energies over one candidate window
+struct WindowEnergy {
+    start: f64,
+    end: f64,
+    harmonic: f32,
+    percussive: f32,
+    mean_power: f32,
+}
+
+/// Find the windows of an audio file most likely to be isolated drum breaks.
+///
+/// The beat grid groups the track into bars; each window is scored by its
+/// percussive-to-harmonic energy ratio and onset density. Falls back to
+/// fixed-length windows when no tempo can be detected.
+pub fn find_drum_breaks(
+    audio_path: &Path,
+    options: &DrumBreakOptions,
+) -> Result<Vec<DrumBreakCandidate>, String> {
+    let beat_config = BeatDetectionConfig::default();
+    let mut tempo = TempoSink::new(&beat_config);
+    let mut onsets = OnsetSink::new(&beat_config);
+    let mut separation = EnergySink::new();
+    analysis::run_all(audio_path, &mut [&mut tempo, &mut onsets, &mut separation])?;
+
+    let beat_info = beat_detection::beat_info(tempo, onsets);
+    Ok(separation.rank(&beat_info, options))
+}
+
+/// Runs HPSS over the decoded stream, keeping only per-frame energies
+pub struct EnergySink {
+    config: HpssConfig,
+    hpss: Option<Hpss>,
+    hops: HopBuffer,
+    energies: Vec<FrameEnergy>,
+    sample_rate: u32,
+    total_frames: u64,
+}
+
+impl EnergySink {
+    pub fn new() -> Self {
+        // A short window keeps drum hits sharp
+        let config = HpssConfig {
+            fft_size: 1024,
+            ..Default::default()
+        };
+        Self {
+            hops: HopBuffer::new(config.hop_size),
+            config,
+            hpss: None,
+            energies: Vec::new(),
+            sample_rate: 0,
+            total_frames: 0,
+        }
+    }
+
+    /// Windows of the separated audio most likely to be drum breaks, laid
+    /// out on `beat_info`'s grid
+    pub fn rank(
+        &self,
+        beat_info: &BeatInfo,
+        options: &DrumBreakOptions,
+    ) -> Vec<DrumBreakCandidate> {
+        let sample_rate = self.sample_rate as f64;
+        let frame_secs = self.config.hop_size as f64 / sample_rate;
+        let duration_secs = self.total_frames as f64 / sample_rate;
+
+        let windows = build_windows(&beat_info.beats, duration_secs, options);
+        let beat_secs = if beat_info.bpm > 0.0 {
+            60.0 / beat_info.bpm as f64
+        } else {
+            0.0
+        };
+
+        rank_windows(
+            &windows,
+            &self.energies,
+            frame_secs,
+            &beat_info.onsets,
+            beat_secs,
+            options.max_results as usize,
+        )
+    }
+}
+
+impl FrameEnergy {
+    fn of(frame: &HpssFrame) -> Self {
+        Self {
+            harmonic: frame.harmonic_energy(),
+            percussive: frame.percussive_energy(),
+        }
+    }
+}
+
+impl Default for EnergySink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnalysisSink for EnergySink {
+    fn start(&mut self, format: &StreamFormat) -> Result<(), String> {
+        self.hpss = Some(Hpss::new(&self.config)?);
+        self.sample_rate = format.sample_rate;
+        self.energies
+            .reserve(format.total_frames as usize / self.config.hop_size + 1);
+        Ok(())
+    }
+
+    fn process(&mut self, block: &PcmBlock) -> Result<(), String> {
+        self.total_frames += block.mono.len() as u64;
+        let Some(hpss) = self.hpss.as_mut() else {
+            return Ok(());
+        };
+        let energies = &mut self.energies;
+        self.hops
+            .push(&block.mono, |hop| separate_hop(hpss, hop, energies))
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        let Some(mut hpss) = self.hpss.take() else {
+            return Ok(());
+        };
+        let energies = &mut self.energies;
+        self.hops
+            .flush(|hop| separate_hop(&mut hpss, hop, energies))?;
+        energies.extend(hpss.flush().iter().map(FrameEnergy::of));
+        Ok(())
+    }
+}
+
+fn separate_hop(
+    hpss: &mut Hpss,
+    hop: &[f32],
+    energies: &mut Vec<FrameEnergy>,
+) -> Result<(), String> {
+    if let Some(frame) = hpss.process(hop)? {
+        energies.push(FrameEnergy::of(&frame));
+    }
+    Ok(())
+}
+
+/// Split the track into windows of whole bars, or fixed lengths without a beat grid.
+fn build_windows(beats: &[f64], duration_secs: f64, options: &DrumBreakOptions) -> Vec<(f64, f64)> {
+    let beats_per_window = BEATS_PER_BAR * options.bars_per_window.max(1) as usize;
+
+    if beats.len() > beats_per_window {
+        return beats
+            .windows(beats_per_window + 1)
+            .step_by(beats_per_window)
+            .map(|w| (w[0], w[beats_per_window]))
+            .collect();
+    }
+
+    let step = options.window_secs.max(0.1);
+    let mut windows = Vec::new();
+    let mut start = 0.0;
+    while start + step <= duration_secs {
+        windows.push((start, start + step));
+        start += step;
+    }
+    windows
+}
+
+/// Score every window and return the best candidates, highest score first.
+fn rank_windows(
+    windows: &[(f64, f64)],
+    energies: &[FrameEnergy],
+    frame_secs: f64,
+    onsets: &[f64],
+    beat_secs: f64,
+    max_results: usize,
+) -> Vec<DrumBreakCandidate> {
+    let measured: Vec<WindowEnergy> = windows
+        .iter()
+        .filter_map(|&(start, end)| {
+            let first = (start / frame_secs) as usize;
+            let last = ((end / frame_secs) as usize).min(energies.len());
+            if first >= last {
+                return None;
+            }
+
+            let frames = &energies[first..last];
+            let harmonic: f32 = frames.iter().map(|f| f.harmonic).sum();
+            let percussive: f32 = frames.iter().map(|f| f.percussive).sum();
+            Some(WindowEnergy {
+                start,
+                end,
+                harmonic,
+                percussive,
+                mean_power: (harmonic + percussive) / frames.len() as f32,
+            })
+        })
+        .collect();
+
+    let loudest = measured.iter().map(|w| w.mean_power).fold(0.0f32, f32::max);
+    if loudest <= 0.0 {
+        return Vec::new();
+    }
+
+    let mut candidates: Vec<DrumBreakCandidate> = measured
+        .into_iter()
+        .filter(|w| {
+            w.mean_power > 0.0 && 10.0 * (w.mean_power / loudest).log10() >= SILENCE_FLOOR_DB
+        })
+        .map(|w| {
+            let (start, end) = (w.start, w.end);
+            let percussive_ratio = w.percussive / (w.harmonic + w.percussive);
+
+            let length = end - start;
+            let onset_count = onsets.iter().filter(|&&t| t >= start && t < end).count();
+            let onset_density = (onset_count as f64 / length) as f32;
+
+            // Normalise density against the beat rate so slow and fast breaks compare fairly
+            let density_score = if beat_secs > 0.0 {
+                let onsets_per_beat = onset_count as f64 / (length / beat_secs);
+                (onsets_per_beat as f32 / SATURATING_ONSETS_PER_BEAT).min(1.0)
+            } else {
+                (onset_density / (SATURATING_ONSETS_PER_BEAT * 2.0)).min(1.0)
+            };
+
+            DrumBreakCandidate {
+                start,
+                end,
+                score: PERCUSSIVE_WEIGHT * percussive_ratio + DENSITY_WEIGHT * density_score,
+                percussive_ratio,
+                onset_density,
+            }
+        })
+        .collect();
+
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    candidates.truncate(max_results);
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(harmonic: f32, percussive: f32, count: usize) -> Vec<FrameEnergy> {
+        (0..count)
+            .map(|_| FrameEnergy {
+                harmonic,
+                percussive,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_build_windows_from_beats() {
+        let beats: Vec<f64> = (0..17).map(|i| i as f64 * 0.5).collect();
+        let windows = build_windows(&beats, 8.5, &DrumBreakOptions::default());
+
+        assert_eq!(windows.len(), 4);
+        assert_eq!(windows[0], (0.0, 2.0));
+        assert_eq!(windows[3], (6.0, 8.0));
+    }
+
+    #[test]
+    fn test_build_windows_without_beats() {
+        let options = DrumBreakOptions {
+            window_secs: 2.5,
+            ..Default::default()
+        };
+        let windows = build_windows(&[], 10.0, &options);
+
+        assert_eq!(windows.len(), 4);
+        assert_eq!(windows[1], (2.5, 5.0));
+    }
+
+    #[test]
+    fn test_percussive_window_ranks_first() {
+        // 100 frames of 10ms: first second harmonic, second second percussive
+        let mut energies = frames(1.0, 0.05, 100);
+        energies.extend(frames(0.05, 1.0, 100));
+        let onsets = vec![1.0, 1.25, 1.5, 1.75];
+
+        let ranked = rank_windows(&[(0.0, 1.0), (1.0, 2.0)], &energies, 0.01, &onsets, 0.5, 10);
+
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].start, 1.0);
+        assert!(ranked[0].percussive_ratio > 0.9);
+        assert_eq!(ranked[0].onset_density, 4.0);
+        assert!(ranked[0].score > ranked[1].score);
+    }
+
+    #[test]
+    fn test_silent_windows_are_skipped() {
+        let mut energies = frames(1.0, 1.0, 100);
+        energies.extend(frames(0.0, 1e-7, 100));
+
+        let ranked = rank_windows(&[(0.0, 1.0), (1.0, 2.0)], &energies, 0.01, &[], 0.5, 10);
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].start, 0.0);
+    }
+
+    #[test]
+    fn test_max_results_limit() {
+        let energies = frames(0.5, 0.5, 1000);
+        let windows: Vec<(f64, f64)> = (0..10).map(|i| (i as f64, i as f64 + 1.0)).collect();
+
+        let ranked = rank_windows(&windows, &energies, 0.01, &[], 0.0, 3);
+        assert_eq!(ranked.len(), 3);
+    }
+}
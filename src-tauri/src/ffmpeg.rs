@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
+use crate::cancel::CancellationToken;
+
 #[derive(Debug, Serialize, Deserialize, Clone, Type)]
 #[serde(rename_all = "camelCase")]
 pub struct FFmpegResult {
@@ -13,10 +15,15 @@ pub struct FFmpegResult {
 
 /// Internal FFmpeg execution (not a Tauri command).
 /// Used by the pipeline executor to run FFmpeg commands synchronously.
-pub fn dlopen_ffmpeg_internal(command: &str, args: Vec<String>) -> Result<FFmpegResult, String> {
+/// An ffmpeg command stops early, with `was_aborted` set, once `cancel` is cancelled.
+pub fn dlopen_ffmpeg_internal(
+    command: &str,
+    args: Vec<String>,
+    cancel: &CancellationToken,
+) -> Result<FFmpegResult, String> {
     let result = match command {
         "ffprobe" => crate::ffmpeg_shim::execute_ffprobe(&args),
-        "ffmpeg" => crate::ffmpeg_shim::execute_ffmpeg(&args),
+        "ffmpeg" => crate::ffmpeg_shim::execute_ffmpeg(&args, cancel),
         _ => return Err(format!("Unknown command: {}", command)),
     };
 
@@ -28,7 +35,7 @@ pub fn dlopen_ffmpeg_internal(command: &str, args: Vec<String>) -> Result<FFmpeg
 
     Ok(FFmpegResult {
         exit_code: result.exit_code,
-        was_aborted: false,
+        was_aborted: result.exit_code != 0 && cancel.is_cancelled(),
         stdout: result.stdout,
         stderr: result.stderr,
         error,
@@ -57,7 +64,7 @@ pub async fn dlopen_ffmpeg(
 ) -> Result<FFmpegResult, String> {
     let result = match command.as_str() {
         "ffprobe" => crate::ffmpeg_shim::execute_ffprobe(&args),
-        "ffmpeg" => crate::ffmpeg_shim::execute_ffmpeg(&args),
+        "ffmpeg" => crate::ffmpeg_shim::execute_ffmpeg(&args, &CancellationToken::new()),
         _ => return Err(format!("Unknown command: {}", command)),
     };
 
@@ -12,10 +12,14 @@ use std::os::raw::{c_char, c_int, c_uint, c_void};
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
+use crate::cancel::CancellationToken;
+
 include!(concat!(env!("OUT_DIR"), "/ffmpeg_bindings.rs"));
 
 pub const AVMEDIA_TYPE_AUDIO: c_int = 1;
 pub const AV_NOPTS_VALUE: i64 = 0x8000000000000000u64 as i64;
+/// `AVERROR_EOF` is a function-like macro, so bindgen does not generate it
+pub const AVERROR_EOF: c_int = -0x2046_4F45;
 
 type FnAvformatOpenInput = unsafe extern "C" fn(
     *mut *mut AVFormatContext,
@@ -462,9 +466,19 @@ pub struct AudioFile {
     format_ctx: *mut AVFormatContext,
     codec_ctx: *mut AVCodecContext,
     stream_index: c_int,
+    time_base: AVRational,
+    packet: *mut AVPacket,
+    frame: *mut AVFrame,
+    swr_ctx: *mut SwrContext,
+    /// Position of the next frame when the decoder gives no timestamp
+    next_position: i64,
+    /// The demuxer hit the end of the file and the decoder is being flushed
+    draining: bool,
     pub sample_rate: i32,
     pub channels: i32,
     pub duration_secs: f64,
+    /// FFmpeg name of the audio stream's decoder, e.g. `aac` or `flac`
+    pub codec_name: String,
 }
 
 impl AudioFile {
@@ -552,6 +566,11 @@ impl AudioFile {
 
             let sample_rate = (*codec_ctx).sample_rate;
             let channels = (*codec_ctx).ch_layout.nb_channels;
+            let codec_name = if (*decoder).name.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr((*decoder).name).to_string_lossy().into_owned()
+            };
             let time_base = (*stream).time_base;
             let duration = (*stream).duration;
             let duration_secs = if duration != AV_NOPTS_VALUE {
@@ -564,9 +583,16 @@ impl AudioFile {
                 format_ctx,
                 codec_ctx,
                 stream_index,
+                time_base,
+                packet: std::ptr::null_mut(),
+                frame: std::ptr::null_mut(),
+                swr_ctx: std::ptr::null_mut(),
+                next_position: 0,
+                draining: false,
                 sample_rate,
                 channels,
                 duration_secs,
+                codec_name,
             })
         }
     }
@@ -591,15 +617,154 @@ impl AudioFile {
                 return Err(format!("Failed to seek: {}", av_error_string(ret)));
             }
             (ff.avcodec_flush_buffers)(self.codec_ctx);
+            self.next_position = (timestamp_secs * self.sample_rate as f64) as i64;
+            self.draining = false;
             Ok(())
         }
     }
+
+    /// Decode the next frame of audio as interleaved f32 samples.
+    ///
+    /// The samples keep the stream's sample rate and channel count and replace
+    /// the contents of `out`. Returns the position of the frame's first sample
+    /// in the stream, or `None` once the stream is exhausted.
+    pub fn read_f32(&mut self, out: &mut Vec<f32>) -> Result<Option<i64>, String> {
+        let ff = get_ffmpeg()?;
+        if self.format_ctx.is_null() {
+            return Err("Format context is null".to_string());
+        }
+        if self.codec_ctx.is_null() {
+            return Err("Codec context is null".to_string());
+        }
+        unsafe {
+            if self.packet.is_null() {
+                self.packet = (ff.av_packet_alloc)();
+                if self.packet.is_null() {
+                    return Err("Failed to allocate packet".to_string());
+                }
+            }
+            if self.frame.is_null() {
+                self.frame = (ff.av_frame_alloc)();
+                if self.frame.is_null() {
+                    return Err("Failed to allocate frame".to_string());
+                }
+            }
+
+            loop {
+                let ret = (ff.avcodec_receive_frame)(self.codec_ctx, self.frame);
+                if ret >= 0 {
+                    let position = self.convert_frame(ff, out);
+                    (ff.av_frame_unref)(self.frame);
+                    return position.map(Some);
+                }
+                if self.draining {
+                    return Ok(None);
+                }
+
+                let ret = (ff.av_read_frame)(self.format_ctx, self.packet);
+                if ret == AVERROR_EOF {
+                    // Flush the frames the decoder is still holding back
+                    (ff.avcodec_send_packet)(self.codec_ctx, std::ptr::null());
+                    self.draining = true;
+                    continue;
+                }
+                if ret < 0 {
+                    return Err(format!("Failed to read packet: {}", av_error_string(ret)));
+                }
+
+                if (*self.packet).stream_index == self.stream_index {
+                    // A packet the decoder rejects is skipped rather than ending the stream
+                    (ff.avcodec_send_packet)(self.codec_ctx, self.packet);
+                }
+                (ff.av_packet_unref)(self.packet);
+            }
+        }
+    }
+
+    /// Convert the decoded frame to packed f32 in `out`, returning its position
+    unsafe fn convert_frame(
+        &mut self,
+        ff: &FFmpegFunctions,
+        out: &mut Vec<f32>,
+    ) -> Result<i64, String> {
+        let frame = self.frame;
+
+        if self.swr_ctx.is_null() {
+            let mut out_layout: AVChannelLayout = std::mem::zeroed();
+            (ff.av_channel_layout_default)(&mut out_layout, self.channels);
+            let ret = (ff.swr_alloc_set_opts2)(
+                &mut self.swr_ctx,
+                &out_layout,
+                AVSampleFormat_AV_SAMPLE_FMT_FLT as c_int,
+                self.sample_rate,
+                &(*frame).ch_layout,
+                (*frame).format,
+                (*frame).sample_rate,
+                0,
+                std::ptr::null_mut(),
+            );
+            (ff.av_channel_layout_uninit)(&mut out_layout);
+            if ret < 0 || self.swr_ctx.is_null() {
+                return Err("Failed to allocate resampler".to_string());
+            }
+            let ret = (ff.swr_init)(self.swr_ctx);
+            if ret < 0 {
+                (ff.swr_free)(&mut self.swr_ctx);
+                return Err(format!(
+                    "Failed to initialize resampler: {}",
+                    av_error_string(ret)
+                ));
+            }
+        }
+
+        let channels = self.channels.max(1) as usize;
+        let nb_samples = (*frame).nb_samples;
+        // Same rate in and out, so the output never exceeds the input
+        out.resize(nb_samples.max(0) as usize * channels, 0.0);
+        let mut out_ptr = out.as_mut_ptr() as *mut u8;
+        let converted = (ff.swr_convert)(
+            self.swr_ctx,
+            &mut out_ptr,
+            nb_samples,
+            (*frame).extended_data as *const *const u8,
+            nb_samples,
+        );
+        if converted < 0 {
+            return Err(format!(
+                "Failed to convert samples: {}",
+                av_error_string(converted)
+            ));
+        }
+        out.truncate(converted as usize * channels);
+
+        let pts = (*frame).best_effort_timestamp;
+        let position = if pts != AV_NOPTS_VALUE {
+            let sample_time_base = AVRational {
+                num: 1,
+                den: self.sample_rate,
+            };
+            (ff.av_rescale_q)(pts, self.time_base, sample_time_base)
+        } else {
+            self.next_position
+        };
+        self.next_position = position + converted as i64;
+        Ok(position)
+    }
 }
 
 impl Drop for AudioFile {
     fn drop(&mut self) {
         if let Ok(ff) = get_ffmpeg() {
             unsafe {
+                if !self.swr_ctx.is_null() {
+                    (ff.swr_free)(&mut self.swr_ctx);
+                }
+                if !self.frame.is_null() {
+                    (ff.av_frame_free)(&mut self.frame);
+                }
+                if !self.packet.is_null() {
+                    (ff.av_packet_free)(&mut self.packet);
+                }
                 if !self.codec_ctx.is_null() {
                     (ff.avcodec_free_context)(&mut self.codec_ctx);
                 }
@@ -654,6 +819,7 @@ pub fn export_sample(
     output_path: &Path,
     start_secs: f64,
     end_secs: f64,
+    cancel: &CancellationToken,
 ) -> Result<(), String> {
     let ff = get_ffmpeg()?;
     let duration = end_secs - start_secs;
@@ -1004,6 +1170,10 @@ pub fn export_sample(
             };
 
         'decode: while (ff.av_read_frame)(input_ctx, packet) >= 0 {
+            if cancel.is_cancelled() {
+                (ff.av_packet_unref)(packet);
+                break 'decode;
+            }
             if (*packet).stream_index != audio_stream_idx {
                 (ff.av_packet_unref)(packet);
                 continue;
@@ -1155,7 +1325,7 @@ pub fn export_sample(
         (ff.avformat_close_input)(&mut input_ctx);
     }
 
-    Ok(())
+    cancel.check()
 }
 
 #[cfg(test)]
@@ -1216,6 +1386,43 @@ mod tests {
         assert!(v.contains("avformat"));
     }
 
+    #[test]
+    fn test_read_f32_decodes_whole_stream() {
+        if !setup_lib_dir() {
+            eprintln!("Skipping: FFmpeg libraries not found");
+            return;
+        }
+
+        let input_path = match get_test_wav_path() {
+            Some(p) => p,
+            None => {
+                eprintln!("Skipping: testcase.wav not found");
+                return;
+            }
+        };
+
+        let mut file = AudioFile::open(&input_path).unwrap();
+        let channels = file.channels as usize;
+        let mut samples = Vec::new();
+        let mut expected_position = 0;
+        let mut frames = 0;
+        while let Some(position) = file.read_f32(&mut samples).unwrap() {
+            assert_eq!(position, expected_position);
+            assert_eq!(samples.len() % channels, 0);
+            assert!(samples.iter().all(|s| s.abs() <= 1.0));
+            frames += samples.len() / channels;
+            expected_position = position + (samples.len() / channels) as i64;
+        }
+
+        let decoded_secs = frames as f64 / file.sample_rate as f64;
+        assert!(
+            (decoded_secs - file.duration_secs).abs() < 0.05,
+            "Decoded {} seconds of a {} second file",
+            decoded_secs,
+            file.duration_secs
+        );
+    }
+
     #[test]
     fn test_export_sample_mp3() {
         if !setup_lib_dir() {
@@ -1240,6 +1447,7 @@ mod tests {
             &output_path,
             0.0,
             0.5,
+            &CancellationToken::new(),
         );
 
         assert!(result.is_ok(), "Export failed: {:?}", result.err());
@@ -1302,6 +1510,7 @@ mod tests {
             &output_path,
             0.1,
             0.4,
+            &CancellationToken::new(),
         );
 
         assert!(result.is_ok(), "Export with offset failed: {:?}", result.err());
@@ -1342,6 +1551,7 @@ mod tests {
             &output_path,
             0.0,
             0.5,
+            &CancellationToken::new(),
         );
 
         assert!(result.is_ok(), "WAV export failed: {:?}", result.err());
@@ -1384,6 +1594,7 @@ mod tests {
             &output_path,
             0.0,
             0.5,
+            &CancellationToken::new(),
         );
 
         assert!(result.is_ok(), "FLAC export failed: {:?}", result.err());
@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 
+use crate::cancel::CancellationToken;
 use crate::ffmpeg_runtime::{self, get_ffmpeg};
 use std::ffi::CString;
 use std::os::raw::c_int;
@@ -62,9 +63,10 @@ pub fn execute_ffprobe(args: &[String]) -> ShimResult {
     }
 }
 
-pub fn execute_ffmpeg(args: &[String]) -> ShimResult {
+/// Run an ffmpeg command line, stopping early if `cancel` is cancelled
+pub fn execute_ffmpeg(args: &[String], cancel: &CancellationToken) -> ShimResult {
     match parse_ffmpeg_args(args) {
-        Ok(cmd) => run_ffmpeg_remux(cmd),
+        Ok(cmd) => run_ffmpeg_remux(cmd, cancel),
         Err(e) => ShimResult {
             exit_code: 1,
             stdout: String::new(),
@@ -190,7 +192,7 @@ duration={}
     }
 }
 
-fn run_ffmpeg_remux(cmd: FfmpegRemux) -> ShimResult {
+fn run_ffmpeg_remux(cmd: FfmpegRemux, cancel: &CancellationToken) -> ShimResult {
     let mut stderr = ffmpeg_banner();
     stderr.push_str(&format!("Input #0, from '{}':\n", cmd.input));
 
@@ -228,8 +230,8 @@ fn run_ffmpeg_remux(cmd: FfmpegRemux) -> ShimResult {
     };
 
     let result = match cmd.audio_codec {
-        AudioCodec::Copy => remux_audio_copy(ff, &cmd, &mut stderr),
-        _ => transcode_audio(ff, &cmd, &mut stderr),
+        AudioCodec::Copy => remux_audio_copy(ff, &cmd, &mut stderr, cancel),
+        _ => transcode_audio(ff, &cmd, &mut stderr, cancel),
     };
 
     match result {
@@ -251,6 +253,16 @@ fn run_ffmpeg_remux(cmd: FfmpegRemux) -> ShimResult {
                 stderr,
             }
         }
+        Err(_) if cancel.is_cancelled() => {
+            // Like an interrupted ffmpeg, but without leaving a truncated output behind
+            let _ = std::fs::remove_file(output_path);
+            stderr.push_str("Exiting normally, received signal 2.\n");
+            ShimResult {
+                exit_code: 255,
+                stdout: String::new(),
+                stderr,
+            }
+        }
         Err(e) => ShimResult {
             exit_code: 1,
             stdout: String::new(),
@@ -270,6 +282,7 @@ fn remux_audio_copy(
     ff: &ffmpeg_runtime::FFmpegFunctions,
     cmd: &FfmpegRemux,
     stderr: &mut String,
+    cancel: &CancellationToken,
 ) -> Result<RemuxStats, String> {
     use ffmpeg_runtime::*;
 
@@ -424,6 +437,10 @@ fn remux_audio_copy(
         let mut total_size: u64 = 0;
 
         while (ff.av_read_frame)(input_ctx, packet) >= 0 {
+            if cancel.is_cancelled() {
+                (ff.av_packet_unref)(packet);
+                break;
+            }
             if (*packet).stream_index != audio_idx {
                 (ff.av_packet_unref)(packet);
                 continue;
@@ -446,6 +463,7 @@ fn remux_audio_copy(
         (ff.avio_closep)(&mut (*output_ctx).pb);
         (ff.avformat_free_context)(output_ctx);
         (ff.avformat_close_input)(&mut input_ctx);
+        cancel.check()?;
 
         let elapsed = start_time.elapsed().as_secs_f64();
         let size_kb = total_size / 1024;
@@ -473,6 +491,7 @@ fn transcode_audio(
     _ff: &ffmpeg_runtime::FFmpegFunctions,
     cmd: &FfmpegRemux,
     stderr: &mut String,
+    cancel: &CancellationToken,
 ) -> Result<RemuxStats, String> {
     stderr.push_str("Transcoding via ffmpeg_runtime::export_sample\n");
 
@@ -483,7 +502,7 @@ fn transcode_audio(
     let duration = file.duration_secs;
     drop(file);
 
-    ffmpeg_runtime::export_sample(input_path, output_path, 0.0, duration)?;
+    ffmpeg_runtime::export_sample(input_path, output_path, 0.0, duration, cancel)?;
 
     let size_kb = std::fs::metadata(&cmd.output)
         .map(|m| m.len() / 1024)
@@ -669,7 +688,7 @@ mod tests {
             test_output.to_string_lossy().to_string(),
         ];
 
-        let result = execute_ffmpeg(&args);
+        let result = execute_ffmpeg(&args, &CancellationToken::new());
 
         eprintln!("Exit code: {}", result.exit_code);
         eprintln!("Stderr:\n{}", result.stderr);
@@ -0,0 +1,92 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Length and modification time of a file, to tell when it has changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FileStamp {
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> Result<Self, String> {
+        let metadata = std::fs::metadata(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Ok(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// Values worked out from files, reused while a file's stamp is unchanged.
+///
+/// Holds at most `capacity` files; storing another evicts the one stored longest ago.
+pub struct StampedCache<V> {
+    capacity: usize,
+    entries: BTreeMap<PathBuf, (FileStamp, V)>,
+    order: VecDeque<PathBuf>,
+}
+
+impl<V: Clone> StampedCache<V> {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: BTreeMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// The value stored for `path`, unless the file has changed since
+    pub fn get(&self, path: &Path, stamp: FileStamp) -> Option<V> {
+        self.entries
+            .get(path)
+            .filter(|(stored, _)| *stored == stamp)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn insert(&mut self, path: PathBuf, stamp: FileStamp, value: V) {
+        if self.entries.insert(path.clone(), (stamp, value)).is_none() {
+            self.order.push_back(path);
+        }
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stamp(len: u64) -> FileStamp {
+        FileStamp {
+            len,
+            modified: None,
+        }
+    }
+
+    #[test]
+    fn test_changed_stamp_misses() {
+        let mut cache = StampedCache::new(4);
+        cache.insert(PathBuf::from("a"), stamp(1), 10u64);
+
+        assert_eq!(cache.get(Path::new("a"), stamp(1)), Some(10));
+        assert_eq!(cache.get(Path::new("a"), stamp(2)), None);
+    }
+
+    #[test]
+    fn test_evicts_oldest_past_capacity() {
+        let mut cache = StampedCache::new(2);
+        for (i, name) in ["a", "b", "c"].iter().enumerate() {
+            cache.insert(PathBuf::from(name), stamp(0), i);
+        }
+
+        assert_eq!(cache.entries.len(), 2);
+        assert_eq!(cache.get(Path::new("a"), stamp(0)), None);
+        assert_eq!(cache.get(Path::new("c"), stamp(0)), Some(2));
+    }
+}
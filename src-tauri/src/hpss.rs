@@ -0,0 +1,481 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use aubio::vec::{CVec, CVecMut};
+use aubio::PVoc;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::analysis::AubioHandle;
+use crate::audio;
+
+/// Longest region `render_stems` separates; every channel is held in memory
+/// as samples and as two stems
+const MAX_STEM_SECS: f64 = 600.0;
+
+/// Configuration for median-filtered harmonic/percussive source separation
+#[derive(Clone, Debug)]
+pub struct HpssConfig {
+    /// FFT window size (power of 2)
+    pub fft_size: usize,
+    /// Hop size between analysis frames
+    pub hop_size: usize,
+    /// Length of the time-direction median filter in frames (odd)
+    pub harmonic_kernel: usize,
+    /// Length of the frequency-direction median filter in bins (odd)
+    pub percussive_kernel: usize,
+    /// Exponent used for the soft (Wiener) masks
+    pub mask_power: f32,
+}
+
+impl Default for HpssConfig {
+    fn default() -> Self {
+        Self {
+            fft_size: 2048,
+            hop_size: 512,
+            harmonic_kernel: 17,
+            percussive_kernel: 17,
+            mask_power: 2.0,
+        }
+    }
+}
+
+/// A single separated spectral frame
+pub struct HpssFrame {
+    /// Magnitudes of the harmonic (sustained) component
+    pub harmonic: Vec<f32>,
+    /// Magnitudes of the percussive (transient) component
+    pub percussive: Vec<f32>,
+    /// Phase of the original frame, shared by both components
+    pub phase: Vec<f32>,
+}
+
+impl HpssFrame {
+    pub fn harmonic_energy(&self) -> f32 {
+        self.harmonic.iter().map(|m| m * m).sum()
+    }
+
+    pub fn percussive_energy(&self) -> f32 {
+        self.percussive.iter().map(|m| m * m).sum()
+    }
+}
+
+/// Streaming harmonic/percussive separator.
+///
+/// Samples are fed one hop at a time. Horizontal (time) median filtering needs
+/// `harmonic_kernel / 2` frames of look-ahead, so frames come out delayed by
+/// that many hops; `flush` drains the remaining frames at end of stream. Only
+/// `harmonic_kernel` spectra are ever held in memory.
+pub struct Hpss {
+    config: HpssConfig,
+    pvoc: AubioHandle<PVoc>,
+    norm: Vec<f32>,
+    phas: Vec<f32>,
+    /// Sliding window of magnitude spectra centred on the frame being separated
+    magnitudes: VecDeque<Vec<f32>>,
+    /// Frequency-median-filtered spectra, aligned with `magnitudes`
+    percussive_enhanced: VecDeque<Vec<f32>>,
+    phases: VecDeque<Vec<f32>>,
+    /// Number of real (non-padding) frames still inside the window
+    pending: usize,
+    scratch: Vec<f32>,
+}
+
+impl Hpss {
+    pub fn new(config: &HpssConfig) -> Result<Self, String> {
+        if config.harmonic_kernel == 0 || config.percussive_kernel == 0 {
+            return Err("HPSS kernel sizes must be non-zero".to_string());
+        }
+
+        let pvoc = PVoc::new(config.fft_size, config.hop_size)
+            .map_err(|e| format!("Failed to create phase vocoder: {:?}", e))?;
+
+        let bins = config.fft_size / 2 + 1;
+        let mut hpss = Self {
+            config: config.clone(),
+            pvoc: AubioHandle::new(pvoc),
+            norm: vec![0.0; bins],
+            phas: vec![0.0; bins],
+            magnitudes: VecDeque::with_capacity(config.harmonic_kernel),
+            percussive_enhanced: VecDeque::with_capacity(config.harmonic_kernel),
+            phases: VecDeque::with_capacity(config.harmonic_kernel),
+            pending: 0,
+            scratch: Vec::with_capacity(config.harmonic_kernel.max(config.percussive_kernel)),
+        };
+
+        // Pre-roll with silent frames so the first real frame sits at the centre
+        for _ in 0..hpss.latency_frames() {
+            hpss.push_spectrum(vec![0.0; bins], vec![0.0; bins]);
+        }
+
+        Ok(hpss)
+    }
+
+    /// Number of hops between an input frame and its separated output
+    pub fn latency_frames(&self) -> usize {
+        self.config.harmonic_kernel / 2
+    }
+
+    /// Feed one hop of samples; returns the separated frame that became ready, if any.
+    pub fn process(&mut self, hop: &[f32]) -> Result<Option<HpssFrame>, String> {
+        let grain = CVecMut::from_parts(self.norm.as_mut_slice(), self.phas.as_mut_slice())
+            .map_err(|e| format!("Invalid spectral frame: {:?}", e))?;
+        self.pvoc
+            .do_(hop, grain)
+            .map_err(|e| format!("Phase vocoder error: {:?}", e))?;
+
+        self.push_spectrum(self.norm.clone(), self.phas.clone());
+        self.pending += 1;
+
+        Ok(self.separate_ready())
+    }
+
+    /// Drain the frames still waiting for look-ahead at end of stream.
+    pub fn flush(&mut self) -> Vec<HpssFrame> {
+        let bins = self.norm.len();
+        let mut frames = Vec::with_capacity(self.pending);
+
+        while self.pending > 0 {
+            self.push_spectrum(vec![0.0; bins], vec![0.0; bins]);
+            if let Some(frame) = self.separate_ready() {
+                frames.push(frame);
+            }
+        }
+
+        frames
+    }
+
+    fn push_spectrum(&mut self, magnitude: Vec<f32>, phase: Vec<f32>) {
+        let enhanced =
+            median_filter_bins(&magnitude, self.config.percussive_kernel, &mut self.scratch);
+        self.magnitudes.push_back(magnitude);
+        self.percussive_enhanced.push_back(enhanced);
+        self.phases.push_back(phase);
+    }
+
+    /// Separate the centre frame once the window is full and slide it forward.
+    fn separate_ready(&mut self) -> Option<HpssFrame> {
+        if self.magnitudes.len() < self.config.harmonic_kernel {
+            return None;
+        }
+
+        let centre = self.latency_frames();
+        let power = self.config.mask_power;
+        let bins = self.magnitudes[centre].len();
+
+        let mut harmonic = vec![0.0f32; bins];
+        let mut percussive = vec![0.0f32; bins];
+
+        for bin in 0..bins {
+            self.scratch.clear();
+            self.scratch
+                .extend(self.magnitudes.iter().map(|frame| frame[bin]));
+            let h = median(&mut self.scratch);
+            let p = self.percussive_enhanced[centre][bin];
+
+            let hp = h.powf(power);
+            let pp = p.powf(power);
+            let total = hp + pp;
+            let magnitude = self.magnitudes[centre][bin];
+
+            if total > f32::EPSILON {
+                harmonic[bin] = magnitude * hp / total;
+                percussive[bin] = magnitude * pp / total;
+            } else {
+                harmonic[bin] = magnitude * 0.5;
+                percussive[bin] = magnitude * 0.5;
+            }
+        }
+
+        let phase = self.phases[centre].clone();
+
+        self.magnitudes.pop_front();
+        self.percussive_enhanced.pop_front();
+        self.phases.pop_front();
+        self.pending = self.pending.saturating_sub(1);
+
+        Some(HpssFrame {
+            harmonic,
+            percussive,
+            phase,
+        })
+    }
+}
+
+/// Time-domain harmonic and percussive components of a signal
+pub struct Stems {
+    pub harmonic: Vec<f32>,
+    pub percussive: Vec<f32>,
+}
+
+/// Separate a mono signal into harmonic and percussive time-domain stems.
+///
+/// Each separated frame is resynthesised with the original phase, so the two
+/// stems sum back to (approximately) the input signal.
+pub fn separate_signal(samples: &[f32], config: &HpssConfig) -> Result<Stems, String> {
+    let hop_size = config.hop_size;
+    let mut hpss = Hpss::new(config)?;
+    let mut harmonic_synth = PVoc::new(config.fft_size, hop_size)
+        .map_err(|e| format!("Failed to create phase vocoder: {:?}", e))?;
+    let mut percussive_synth = PVoc::new(config.fft_size, hop_size)
+        .map_err(|e| format!("Failed to create phase vocoder: {:?}", e))?;
+
+    // Analysis plus overlap-add resynthesis delays the output by one window minus one hop
+    let delay = config.fft_size - hop_size;
+    let padded_len = samples.len() + delay;
+    let hops = padded_len.div_ceil(hop_size);
+
+    let mut stems = Stems {
+        harmonic: Vec::with_capacity(hops * hop_size),
+        percussive: Vec::with_capacity(hops * hop_size),
+    };
+    let mut hop = vec![0.0f32; hop_size];
+    let mut out = vec![0.0f32; hop_size];
+
+    let mut synthesize = |frame: HpssFrame, stems: &mut Stems| -> Result<(), String> {
+        for (magnitudes, synth, stem) in [
+            (&frame.harmonic, &mut harmonic_synth, &mut stems.harmonic),
+            (
+                &frame.percussive,
+                &mut percussive_synth,
+                &mut stems.percussive,
+            ),
+        ] {
+            let grain = CVec::from_parts(magnitudes, &frame.phase)
+                .map_err(|e| format!("Invalid spectral frame: {:?}", e))?;
+            synth
+                .rdo(grain, out.as_mut_slice())
+                .map_err(|e| format!("Phase vocoder error: {:?}", e))?;
+            stem.extend_from_slice(&out);
+        }
+        Ok(())
+    };
+
+    for i in 0..hops {
+        let start = (i * hop_size).min(samples.len());
+        let end = (start + hop_size).min(samples.len());
+        let chunk = &samples[start..end];
+        hop[..chunk.len()].copy_from_slice(chunk);
+        hop[chunk.len()..].fill(0.0);
+
+        if let Some(frame) = hpss.process(&hop)? {
+            synthesize(frame, &mut stems)?;
+        }
+    }
+    for frame in hpss.flush() {
+        synthesize(frame, &mut stems)?;
+    }
+
+    for stem in [&mut stems.harmonic, &mut stems.percussive] {
+        stem.drain(..delay.min(stem.len()));
+        stem.truncate(samples.len());
+    }
+
+    Ok(stems)
+}
+
+/// Paths of the rendered harmonic and percussive stem files
+#[derive(Clone, Debug, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct HpssStems {
+    /// WAV file containing the harmonic (sustained) component
+    pub harmonic_path: String,
+    /// WAV file containing the percussive (transient) component
+    pub percussive_path: String,
+    /// Length of the rendered region in seconds
+    pub duration_secs: f64,
+    pub sample_rate: u32,
+}
+
+/// Render a region of an audio file into harmonic and percussive WAV stems.
+///
+/// Each channel is separated independently so stereo sources keep their image.
+pub fn render_stems(
+    audio_path: &Path,
+    start_time: f64,
+    end_time: f64,
+    output_dir: &Path,
+) -> Result<HpssStems, String> {
+    let span = end_time - start_time;
+    if !span.is_finite() || span > MAX_STEM_SECS {
+        return Err(format!(
+            "Stems can be rendered for at most {} seconds; select a shorter region",
+            MAX_STEM_SECS
+        ));
+    }
+
+    let region = audio::decode_region(audio_path, start_time, end_time)?;
+
+    let config = HpssConfig::default();
+    let mut harmonic_channels = Vec::with_capacity(region.channels.len());
+    let mut percussive_channels = Vec::with_capacity(region.channels.len());
+
+    for channel in &region.channels {
+        let stems = separate_signal(channel, &config)?;
+        harmonic_channels.push(stems.harmonic);
+        percussive_channels.push(stems.percussive);
+    }
+
+    std::fs::create_dir_all(output_dir)
+        .map_err(|e| format!("Failed to create stems directory: {}", e))?;
+
+    let stem = audio_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("audio");
+    let base = format!(
+        "{}_{}-{}",
+        stem,
+        (start_time * 1000.0).round() as u64,
+        (end_time * 1000.0).round() as u64
+    );
+    let harmonic_path: PathBuf = output_dir.join(format!("{}_harmonic.wav", base));
+    let percussive_path: PathBuf = output_dir.join(format!("{}_percussive.wav", base));
+
+    audio::write_wav(&harmonic_path, &harmonic_channels, region.sample_rate)?;
+    audio::write_wav(&percussive_path, &percussive_channels, region.sample_rate)?;
+
+    Ok(HpssStems {
+        harmonic_path: harmonic_path.to_string_lossy().to_string(),
+        percussive_path: percussive_path.to_string_lossy().to_string(),
+        duration_secs: region.frames() as f64 / region.sample_rate as f64,
+        sample_rate: region.sample_rate,
+    })
+}
+
+/// Median filter across frequency bins, with the window clamped at the edges
+fn median_filter_bins(spectrum: &[f32], kernel: usize, scratch: &mut Vec<f32>) -> Vec<f32> {
+    let half = kernel / 2;
+    let len = spectrum.len();
+
+    (0..len)
+        .map(|bin| {
+            let start = bin.saturating_sub(half);
+            let end = (bin + half + 1).min(len);
+            scratch.clear();
+            scratch.extend_from_slice(&spectrum[start..end]);
+            median(scratch)
+        })
+        .collect()
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mid = values.len() / 2;
+    let (_, m, _) = values.select_nth_unstable_by(mid, |a, b| a.total_cmp(b));
+    *m
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn separate_all(samples: &[f32], config: &HpssConfig) -> Vec<HpssFrame> {
+        let mut hpss = Hpss::new(config).unwrap();
+        let mut frames = Vec::new();
+
+        for chunk in samples.chunks(config.hop_size) {
+            let mut hop = chunk.to_vec();
+            hop.resize(config.hop_size, 0.0);
+            if let Some(frame) = hpss.process(&hop).unwrap() {
+                frames.push(frame);
+            }
+        }
+        frames.extend(hpss.flush());
+        frames
+    }
+
+    fn energy_ratio(frames: &[HpssFrame]) -> f32 {
+        let h: f32 = frames.iter().map(|f| f.harmonic_energy()).sum();
+        let p: f32 = frames.iter().map(|f| f.percussive_energy()).sum();
+        p / (h + p).max(f32::EPSILON)
+    }
+
+    #[test]
+    fn test_stems_sum_to_input() {
+        let sample_rate = 44100.0;
+        let mut samples: Vec<f32> = (0..22050)
+            .map(|i| (2.0 * std::f32::consts::PI * 220.0 * i as f32 / sample_rate).sin() * 0.4)
+            .collect();
+        for pos in (0..samples.len()).step_by(5512) {
+            samples[pos] += 0.5;
+        }
+
+        let stems = separate_signal(&samples, &HpssConfig::default()).unwrap();
+        assert_eq!(stems.harmonic.len(), samples.len());
+        assert_eq!(stems.percussive.len(), samples.len());
+
+        // Ignore the edges where the analysis window is only partially filled
+        let margin = 2048;
+        let error = samples[margin..samples.len() - margin]
+            .iter()
+            .enumerate()
+            .map(|(i, &s)| {
+                let i = i + margin;
+                (stems.harmonic[i] + stems.percussive[i] - s).abs()
+            })
+            .fold(0.0f32, f32::max);
+        assert!(
+            error < 0.05,
+            "Stems should sum to the input, max error {}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_long_regions_are_rejected() {
+        let dir = std::env::temp_dir();
+        let error = render_stems(Path::new("missing.wav"), 0.0, 3600.0, &dir).unwrap_err();
+        assert!(error.contains("shorter region"), "{}", error);
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&mut [3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(&mut [5.0]), 5.0);
+        assert_eq!(median(&mut []), 0.0);
+    }
+
+    #[test]
+    fn test_frame_count_matches_input() {
+        let config = HpssConfig::default();
+        let samples = vec![0.0f32; config.hop_size * 40];
+
+        let frames = separate_all(&samples, &config);
+        assert_eq!(frames.len(), 40);
+    }
+
+    #[test]
+    fn test_sustained_tone_is_harmonic() {
+        let sample_rate = 44100.0;
+        let samples: Vec<f32> = (0..44100)
+            .map(|i| (2.0 * std::f32::consts::PI * 440.0 * i as f32 / sample_rate).sin() * 0.5)
+            .collect();
+
+        let frames = separate_all(&samples, &HpssConfig::default());
+        let ratio = energy_ratio(&frames);
+        assert!(
+            ratio < 0.2,
+            "Tone should be mostly harmonic, percussive ratio {}",
+            ratio
+        );
+    }
+
+    #[test]
+    fn test_clicks_are_percussive() {
+        let mut samples = vec![0.0f32; 44100];
+        for pos in (0..samples.len()).step_by(11025) {
+            samples[pos] = 1.0;
+        }
+
+        let frames = separate_all(&samples, &HpssConfig::default());
+        let ratio = energy_ratio(&frames);
+        assert!(
+            ratio > 0.8,
+            "Clicks should be mostly percussive, percussive ratio {}",
+            ratio
+        );
+    }
+}
@@ -1,14 +1,21 @@
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 use std::time::Instant;
 use tauri::ipc::Channel;
 use tokio::io::AsyncWriteExt;
 
+use crate::cancel::{CancellationToken, CANCELLED};
 use crate::pipeline::DownloadProgress;
 
+/// Downloads in progress by output path, so a cancelled extraction can stop them
+static ACTIVE_DOWNLOADS: Mutex<BTreeMap<String, DownloadEntry>> = Mutex::new(BTreeMap::new());
+static NEXT_DOWNLOAD: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Debug, Serialize, Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
 pub struct HttpResponse {
@@ -109,47 +116,28 @@ pub async fn download_to_file(
         .build()
         .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
 
-    let mut request = client.get(&url);
-
-    for (key, value) in headers {
-        request = request.header(&key, &value);
-    }
-
     eprintln!("[http] Starting download from: {}...", &url[..url.len().min(80)]);
 
-    let response = request
-        .send()
-        .await
-        .map_err(|e| {
-            eprintln!("[http] Download connection failed: {}", e);
-            format!("Download request failed: {}", e)
-        })?;
-
-    eprintln!("[http] Got response status: {}", response.status());
-
-    if !response.status().is_success() {
-        return Err(format!("Download failed with status: {}", response.status()));
-    }
-
-    let total_size = response.content_length();
-    eprintln!("[http] Content-Length: {:?}", total_size);
-
-    let output = Path::new(&output_path);
-    if let Some(parent) = output.parent() {
-        tokio::fs::create_dir_all(parent)
-            .await
-            .map_err(|e| format!("Failed to create output directory: {}", e))?;
-    }
-
-    let mut file = tokio::fs::File::create(&output_path)
-        .await
-        .map_err(|e| format!("Failed to create output file: {}", e))?;
-
-    let mut downloaded: u64 = 0;
-    let mut stream = response.bytes_stream();
-
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(|e| format!("Failed to read chunk: {}", e))?;
+    let active = ActiveDownload::register(&output_path);
+    let download = start_download(&client, &url, &headers, &output_path).await?;
+    let mut file = download.file;
+    let mut downloaded = download.offset;
+    let mut stream = download.response.bytes_stream();
+
+    loop {
+        // Raced against the next chunk, so a stalled download still stops
+        let chunk = tokio::select! {
+            biased;
+            _ = active.entry.cancel.cancelled() => {
+                drop(file);
+                discard_download(&output_path).await;
+                return Err(CANCELLED.to_string());
+            }
+            chunk = stream.next() => match chunk {
+                Some(chunk) => chunk.map_err(|e| format!("Failed to read chunk: {}", e))?,
+                None => break,
+            },
+        };
 
         file.write_all(&chunk)
             .await
@@ -161,6 +149,7 @@ pub async fn download_to_file(
     file.flush()
         .await
         .map_err(|e| format!("Failed to flush file: {}", e))?;
+    finish_download(&output_path).await;
 
     eprintln!("[http] Downloaded {} bytes to {}", downloaded, output_path);
 
@@ -169,6 +158,7 @@ pub async fn download_to_file(
 
 /// Download a file with progress reporting via channel.
 /// Progress is throttled to avoid overwhelming the channel (every 100KB or 250ms).
+/// Like `download_to_file`, continues a partial file left by an interrupted run.
 #[tauri::command]
 #[specta::specta]
 pub async fn download_to_file_with_progress(
@@ -184,63 +174,48 @@ pub async fn download_to_file_with_progress(
         .build()
         .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
 
-    let mut request = client.get(&url);
-
-    for (key, value) in headers {
-        request = request.header(&key, &value);
-    }
-
     eprintln!(
         "[http] Starting download with progress from: {}...",
         &url[..url.len().min(80)]
     );
 
-    let response = request.send().await.map_err(|e| {
-        eprintln!("[http] Download connection failed: {}", e);
-        format!("Download request failed: {}", e)
-    })?;
-
-    eprintln!("[http] Got response status: {}", response.status());
-
-    if !response.status().is_success() {
-        return Err(format!(
-            "Download failed with status: {}",
-            response.status()
-        ));
-    }
-
-    let total_bytes = response.content_length();
-    eprintln!("[http] Content-Length: {:?}", total_bytes);
-
-    let output = Path::new(&output_path);
-    if let Some(parent) = output.parent() {
-        tokio::fs::create_dir_all(parent)
-            .await
-            .map_err(|e| format!("Failed to create output directory: {}", e))?;
-    }
-
-    let mut file = tokio::fs::File::create(&output_path)
-        .await
-        .map_err(|e| format!("Failed to create output file: {}", e))?;
-
-    let mut downloaded: u64 = 0;
-    let mut stream = response.bytes_stream();
+    let active = ActiveDownload::register(&output_path);
+    let download = start_download(&client, &url, &headers, &output_path).await?;
+    let total_bytes = download.total_bytes;
+    let mut file = download.file;
+    let mut downloaded = download.offset;
+    let mut stream = download.response.bytes_stream();
 
     // Throttle progress updates to avoid overwhelming the channel
     let mut last_progress_time = Instant::now();
-    let mut last_progress_bytes: u64 = 0;
+    let mut last_progress_bytes = downloaded;
     const PROGRESS_BYTE_THRESHOLD: u64 = 100_000; // 100KB
     const PROGRESS_TIME_THRESHOLD_MS: u128 = 250; // 250ms
 
     // Send initial progress
     let _ = on_progress.send(DownloadProgress {
-        bytes_downloaded: 0,
+        bytes_downloaded: downloaded,
         total_bytes,
-        percent: 0.0,
+        percent: total_bytes
+            .map(|t| (downloaded as f64 / t as f64) * 100.0)
+            .unwrap_or(0.0),
+        output_path: output_path.clone(),
     });
 
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(|e| format!("Failed to read chunk: {}", e))?;
+    loop {
+        // Raced against the next chunk, so a stalled download still stops
+        let chunk = tokio::select! {
+            biased;
+            _ = active.entry.cancel.cancelled() => {
+                drop(file);
+                discard_download(&output_path).await;
+                return Err(CANCELLED.to_string());
+            }
+            chunk = stream.next() => match chunk {
+                Some(chunk) => chunk.map_err(|e| format!("Failed to read chunk: {}", e))?,
+                None => break,
+            },
+        };
 
         file.write_all(&chunk)
             .await
@@ -264,6 +239,7 @@ pub async fn download_to_file_with_progress(
                 bytes_downloaded: downloaded,
                 total_bytes,
                 percent,
+                output_path: output_path.clone(),
             });
 
             last_progress_time = now;
@@ -274,12 +250,14 @@ pub async fn download_to_file_with_progress(
     file.flush()
         .await
         .map_err(|e| format!("Failed to flush file: {}", e))?;
+    finish_download(&output_path).await;
 
     // Send final progress (100%)
     let _ = on_progress.send(DownloadProgress {
         bytes_downloaded: downloaded,
         total_bytes: Some(downloaded), // Use actual downloaded as total for final
         percent: 100.0,
+        output_path: output_path.clone(),
     });
 
     eprintln!(
@@ -289,3 +267,242 @@ pub async fn download_to_file_with_progress(
 
     Ok(())
 }
+
+/// Stop the download to `output_path`, if one is running, and delete what it wrote.
+/// Used when the extraction that started it is aborted; returns once the
+/// download has stopped, so a new one to the same path can't race its cleanup.
+#[tauri::command]
+#[specta::specta]
+pub async fn cancel_download(output_path: String) {
+    let entry = ACTIVE_DOWNLOADS
+        .lock()
+        .ok()
+        .and_then(|downloads| downloads.get(&output_path).cloned());
+    if let Some(entry) = entry {
+        eprintln!("[http] Cancelling download to {}", output_path);
+        entry.cancel.cancel();
+        entry.finished.cancelled().await;
+    }
+}
+
+#[derive(Clone)]
+struct DownloadEntry {
+    /// Tells this download apart from a newer one to the same path
+    id: u64,
+    cancel: CancellationToken,
+    /// Set once the download has stopped and cleaned up after itself
+    finished: CancellationToken,
+}
+
+/// Registration in `ACTIVE_DOWNLOADS` for as long as its download runs
+struct ActiveDownload {
+    output_path: String,
+    entry: DownloadEntry,
+}
+
+impl ActiveDownload {
+    fn register(output_path: &str) -> Self {
+        let entry = DownloadEntry {
+            id: NEXT_DOWNLOAD.fetch_add(1, Ordering::Relaxed),
+            cancel: CancellationToken::new(),
+            finished: CancellationToken::new(),
+        };
+        if let Ok(mut downloads) = ACTIVE_DOWNLOADS.lock() {
+            downloads.insert(output_path.to_string(), entry.clone());
+        }
+        Self {
+            output_path: output_path.to_string(),
+            entry,
+        }
+    }
+}
+
+impl Drop for ActiveDownload {
+    fn drop(&mut self) {
+        if let Ok(mut downloads) = ACTIVE_DOWNLOADS.lock() {
+            if downloads.get(&self.output_path).map(|entry| entry.id) == Some(self.entry.id) {
+                downloads.remove(&self.output_path);
+            }
+        }
+        self.entry.finished.cancel();
+    }
+}
+
+/// Written next to a download while it is in progress, so an interrupted
+/// download can later be continued instead of started over
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PartialDownload {
+    total_bytes: u64,
+}
+
+/// A download response together with the file its body goes to
+struct Download {
+    response: reqwest::Response,
+    file: tokio::fs::File,
+    /// Bytes already in `file` from an earlier attempt
+    offset: u64,
+    total_bytes: Option<u64>,
+}
+
+fn partial_marker_path(output_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.partial", output_path))
+}
+
+/// Request `url`, continuing where an interrupted download to `output_path`
+/// stopped when the server supports ranges and the file is unchanged.
+/// Anything else starts over from the first byte.
+async fn start_download(
+    client: &reqwest::Client,
+    url: &str,
+    headers: &HashMap<String, String>,
+    output_path: &str,
+) -> Result<Download, String> {
+    let request = || {
+        headers
+            .iter()
+            .fold(client.get(url), |request, (key, value)| {
+                request.header(key, value)
+            })
+    };
+
+    let output = Path::new(output_path);
+    if let Some(parent) = output.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    }
+
+    let marker_path = partial_marker_path(output_path);
+    if let Some((offset, total_bytes)) = resumable_bytes(output, &marker_path).await {
+        eprintln!(
+            "[http] Resuming download at byte {} of {}",
+            offset, total_bytes
+        );
+        let response = request()
+            .header(reqwest::header::RANGE, format!("bytes={}-", offset))
+            .send()
+            .await
+            .map_err(|e| format!("Download request failed: {}", e))?;
+
+        let range = response
+            .headers()
+            .get(reqwest::header::CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(content_range);
+        if response.status() == reqwest::StatusCode::PARTIAL_CONTENT
+            && range == Some((offset, total_bytes))
+        {
+            let file = tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(output)
+                .await
+                .map_err(|e| format!("Failed to open partial file: {}", e))?;
+            return Ok(Download {
+                response,
+                file,
+                offset,
+                total_bytes: Some(total_bytes),
+            });
+        }
+        eprintln!(
+            "[http] Server did not resume (status {}), starting over",
+            response.status()
+        );
+    }
+
+    let response = request().send().await.map_err(|e| {
+        eprintln!("[http] Download connection failed: {}", e);
+        format!("Download request failed: {}", e)
+    })?;
+
+    eprintln!("[http] Got response status: {}", response.status());
+
+    if !response.status().is_success() {
+        return Err(format!(
+            "Download failed with status: {}",
+            response.status()
+        ));
+    }
+
+    let total_bytes = response.content_length();
+    eprintln!("[http] Content-Length: {:?}", total_bytes);
+
+    let file = tokio::fs::File::create(output)
+        .await
+        .map_err(|e| format!("Failed to create output file: {}", e))?;
+
+    // Without a known size a partial file can't be checked, so it isn't resumable
+    let _ = tokio::fs::remove_file(&marker_path).await;
+    if let Some(total_bytes) = total_bytes {
+        let marker = serde_json::to_string(&PartialDownload { total_bytes })
+            .map_err(|e| format!("Failed to serialize download marker: {}", e))?;
+        tokio::fs::write(&marker_path, marker)
+            .await
+            .map_err(|e| format!("Failed to write download marker: {}", e))?;
+    }
+
+    Ok(Download {
+        response,
+        file,
+        offset: 0,
+        total_bytes,
+    })
+}
+
+/// Size of the partial file at `output` and the total it was downloading
+/// towards, if it was left by an interrupted download
+async fn resumable_bytes(output: &Path, marker_path: &Path) -> Option<(u64, u64)> {
+    let marker = tokio::fs::read_to_string(marker_path).await.ok()?;
+    let marker: PartialDownload = serde_json::from_str(&marker).ok()?;
+    let offset = tokio::fs::metadata(output).await.ok()?.len();
+    (offset > 0 && offset < marker.total_bytes).then_some((offset, marker.total_bytes))
+}
+
+/// Mark the download to `output_path` as complete
+async fn finish_download(output_path: &str) {
+    let _ = tokio::fs::remove_file(partial_marker_path(output_path)).await;
+}
+
+/// Delete a cancelled download's file and its marker
+async fn discard_download(output_path: &str) {
+    let _ = tokio::fs::remove_file(output_path).await;
+    let _ = tokio::fs::remove_file(partial_marker_path(output_path)).await;
+}
+
+/// First byte and complete length from a `Content-Range: bytes first-last/length` header
+fn content_range(header: &str) -> Option<(u64, u64)> {
+    let range = header.trim().strip_prefix("bytes ")?;
+    let (span, total) = range.split_once('/')?;
+    let (first, _) = span.split_once('-')?;
+    Some((first.trim().parse().ok()?, total.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_range() {
+        assert_eq!(content_range("bytes 1000-4999/5000"), Some((1000, 5000)));
+        assert_eq!(content_range("bytes 0-99/*"), None);
+        assert_eq!(content_range("bytes */5000"), None);
+        assert_eq!(content_range("items 0-1/2"), None);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_download_waits_for_latest_registration() {
+        let path = "/tmp/test_cancel_download.m4a";
+        let first = ActiveDownload::register(path);
+        let second = ActiveDownload::register(path);
+        drop(first);
+
+        let cancelling = tokio::spawn(cancel_download(path.to_string()));
+        second.entry.cancel.cancelled().await;
+        assert!(!cancelling.is_finished());
+
+        drop(second);
+        cancelling.await.unwrap();
+        assert!(!ACTIVE_DOWNLOADS.lock().unwrap().contains_key(path));
+    }
+}
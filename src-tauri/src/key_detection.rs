@@ -0,0 +1,292 @@
+use aubio::vec::CVecMut;
+use aubio::FFT;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::analysis::{AnalysisSink, AubioHandle, HopBuffer, PcmBlock, StreamFormat};
+
+/// FFT frame length; long enough to resolve semitones in the bass
+const FRAME_SIZE: usize = 8192;
+/// Frames overlap by half
+const HOP_SIZE: usize = FRAME_SIZE / 2;
+/// Lowest frequency folded into the chroma (C2)
+const MIN_FREQ_HZ: f32 = 65.4;
+/// Highest frequency folded into the chroma (C7)
+const MAX_FREQ_HZ: f32 = 2093.0;
+
+const PITCH_CLASSES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Krumhansl-Kessler key profiles, tonic first
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyMode {
+    Major,
+    Minor,
+}
+
+/// Musical key estimated from the pitch-class content of the audio
+#[derive(Clone, Debug, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyInfo {
+    /// Tonic pitch class, e.g. "F#"
+    pub tonic: String,
+    pub mode: KeyMode,
+    /// Display name, e.g. "F# minor"
+    pub name: String,
+    /// Correlation of the chroma with the winning key profile (-1.0 - 1.0)
+    pub confidence: f32,
+    /// Energy per pitch class starting at C, scaled so the strongest is 1.0
+    pub chroma: Vec<f32>,
+}
+
+/// Pick the key whose Krumhansl-Kessler profile best correlates with `chroma`.
+///
+/// Returns `None` for a silent chroma.
+pub fn estimate_key(chroma: &[f32; 12]) -> Option<KeyInfo> {
+    let max = chroma.iter().copied().fold(0.0f32, f32::max);
+    if max <= 0.0 {
+        return None;
+    }
+
+    let mut best = (f32::MIN, 0, KeyMode::Major);
+    for tonic in 0..12 {
+        let rotated: Vec<f32> = (0..12).map(|i| chroma[(tonic + i) % 12]).collect();
+        for (mode, profile) in [
+            (KeyMode::Major, &MAJOR_PROFILE),
+            (KeyMode::Minor, &MINOR_PROFILE),
+        ] {
+            let score = correlation(&rotated, profile);
+            if score > best.0 {
+                best = (score, tonic, mode);
+            }
+        }
+    }
+
+    let (confidence, tonic, mode) = best;
+    let tonic = PITCH_CLASSES[tonic].to_string();
+    let mode_name = match mode {
+        KeyMode::Major => "major",
+        KeyMode::Minor => "minor",
+    };
+
+    Some(KeyInfo {
+        name: format!("{} {}", tonic, mode_name),
+        tonic,
+        mode,
+        confidence,
+        chroma: chroma.iter().map(|v| v / max).collect(),
+    })
+}
+
+/// Pearson correlation of two equally long series
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let n = a.len() as f32;
+    let mean_a = a.iter().sum::<f32>() / n;
+    let mean_b = b.iter().sum::<f32>() / n;
+
+    let mut cov = 0.0;
+    let mut var_a = 0.0;
+    let mut var_b = 0.0;
+    for (x, y) in a.iter().zip(b) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+
+    if var_a <= 0.0 || var_b <= 0.0 {
+        return 0.0;
+    }
+    cov / (var_a * var_b).sqrt()
+}
+
+/// Folds windowed spectra of the mono mix into a 12-bin chroma
+struct Chromagram {
+    fft: AubioHandle<FFT>,
+    window: Vec<f32>,
+    /// The most recent `FRAME_SIZE` samples
+    frame: Vec<f32>,
+    windowed: Vec<f32>,
+    norm: Vec<f32>,
+    phas: Vec<f32>,
+    /// Pitch class of each FFT bin, `None` outside the analysed range
+    bin_classes: Vec<Option<usize>>,
+    chroma: [f32; 12],
+}
+
+impl Chromagram {
+    fn new(sample_rate: u32) -> Result<Self, String> {
+        let fft = FFT::new(FRAME_SIZE).map_err(|e| format!("Failed to create FFT: {:?}", e))?;
+        let bins = FRAME_SIZE / 2 + 1;
+
+        let window = (0..FRAME_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FRAME_SIZE as f32).cos())
+            .collect();
+
+        let bin_classes = (0..bins)
+            .map(|bin| {
+                let freq = bin as f32 * sample_rate as f32 / FRAME_SIZE as f32;
+                if !(MIN_FREQ_HZ..=MAX_FREQ_HZ).contains(&freq) {
+                    return None;
+                }
+                // MIDI note number; A4 = 69 = pitch class 9
+                let note = (12.0 * (freq / 440.0).log2()).round() as i32 + 69;
+                Some(note.rem_euclid(12) as usize)
+            })
+            .collect();
+
+        Ok(Self {
+            fft: AubioHandle::new(fft),
+            window,
+            frame: vec![0.0; FRAME_SIZE],
+            windowed: vec![0.0; FRAME_SIZE],
+            norm: vec![0.0; bins],
+            phas: vec![0.0; bins],
+            bin_classes,
+            chroma: [0.0; 12],
+        })
+    }
+
+    fn process_hop(&mut self, hop: &[f32]) -> Result<(), String> {
+        self.frame.copy_within(HOP_SIZE.., 0);
+        self.frame[FRAME_SIZE - HOP_SIZE..].copy_from_slice(hop);
+
+        for ((out, sample), w) in self.windowed.iter_mut().zip(&self.frame).zip(&self.window) {
+            *out = sample * w;
+        }
+
+        let spectrum = CVecMut::from_parts(self.norm.as_mut_slice(), self.phas.as_mut_slice())
+            .map_err(|e| format!("Invalid spectral frame: {:?}", e))?;
+        self.fft
+            .do_(self.windowed.as_slice(), spectrum)
+            .map_err(|e| format!("FFT error: {:?}", e))?;
+
+        for (magnitude, class) in self.norm.iter().zip(&self.bin_classes) {
+            if let Some(class) = class {
+                self.chroma[*class] += magnitude;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Key detection as a sink of the shared decode pass
+pub struct KeySink {
+    chromagram: Option<Chromagram>,
+    hops: HopBuffer,
+    key: Option<KeyInfo>,
+}
+
+impl KeySink {
+    pub fn new() -> Self {
+        Self {
+            chromagram: None,
+            hops: HopBuffer::new(HOP_SIZE),
+            key: None,
+        }
+    }
+
+    /// The estimated key once the decode pass has finished, `None` for silence
+    pub fn into_key(self) -> Option<KeyInfo> {
+        self.key
+    }
+}
+
+impl Default for KeySink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnalysisSink for KeySink {
+    fn start(&mut self, format: &StreamFormat) -> Result<(), String> {
+        self.chromagram = Some(Chromagram::new(format.sample_rate)?);
+        Ok(())
+    }
+
+    fn process(&mut self, block: &PcmBlock) -> Result<(), String> {
+        match self.chromagram.as_mut() {
+            Some(chromagram) => self
+                .hops
+                .push(&block.mono, |hop| chromagram.process_hop(hop)),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        if let Some(mut chromagram) = self.chromagram.take() {
+            self.hops.flush(|hop| chromagram.process_hop(hop))?;
+            self.key = estimate_key(&chromagram.chroma);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(freqs: &[f32], sample_rate: u32, secs: f32) -> Vec<f32> {
+        (0..(sample_rate as f32 * secs) as usize)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                freqs
+                    .iter()
+                    .map(|f| (2.0 * std::f32::consts::PI * f * t).sin())
+                    .sum::<f32>()
+                    * 0.2
+            })
+            .collect()
+    }
+
+    fn detect(samples: &[f32], sample_rate: u32) -> Option<KeyInfo> {
+        let mut sink = KeySink::new();
+        sink.start(&StreamFormat {
+            sample_rate,
+            channels: 1,
+            total_frames: samples.len() as u64,
+        })
+        .unwrap();
+        for (i, block) in samples.chunks(1152).enumerate() {
+            sink.process(&PcmBlock {
+                interleaved: block.to_vec(),
+                mono: block.to_vec(),
+                offset: i as u64 * 1152,
+            })
+            .unwrap();
+        }
+        sink.finish().unwrap();
+        sink.into_key()
+    }
+
+    #[test]
+    fn test_major_triad() {
+        // C4, E4, G4
+        let samples = chord(&[261.63, 329.63, 392.0], 44100, 3.0);
+        let key = detect(&samples, 44100).unwrap();
+        assert_eq!(key.name, "C major");
+        assert_eq!(key.chroma.len(), 12);
+    }
+
+    #[test]
+    fn test_minor_triad() {
+        // A3, C4, E4
+        let samples = chord(&[220.0, 261.63, 329.63], 44100, 3.0);
+        let key = detect(&samples, 44100).unwrap();
+        assert_eq!(key.tonic, "A");
+        assert_eq!(key.mode, KeyMode::Minor);
+    }
+
+    #[test]
+    fn test_silence_has_no_key() {
+        assert!(detect(&vec![0.0; 44100], 44100).is_none());
+    }
+}
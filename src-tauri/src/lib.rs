@@ -1,13 +1,30 @@
+mod analysis;
+mod analysis_cache;
 mod audio;
 mod beat_detection;
 mod binary;
+mod cancel;
+mod decoder;
+mod drum_breaks;
 mod ffmpeg;
 mod ffmpeg_runtime;
 mod ffmpeg_shim;
+mod file_stamp;
+mod hpss;
 mod http;
+mod key_detection;
+mod loudness;
+mod peak_pyramid;
 mod pipeline;
+mod region;
+mod spectrogram;
+mod waveform_cache;
 mod youtube;
 
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use tauri::{ipc::Channel, Manager};
@@ -61,6 +78,12 @@ pub enum ExtractionEvent {
     },
     WaveformChunk {
         peaks: Vec<f32>,
+        #[serde(rename = "minPeaks")]
+        min_peaks: Vec<f32>,
+        #[serde(rename = "maxPeaks")]
+        max_peaks: Vec<f32>,
+        #[serde(rename = "rmsPeaks")]
+        rms_peaks: Vec<f32>,
         offset: usize,
     },
     BeatInfo {
@@ -84,7 +107,25 @@ pub enum ExtractionEvent {
 #[derive(Clone, Serialize, Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
 pub struct WaveformData {
+    /// Absolute peak per block, normalized to the loudest block
     pub peaks: Vec<f32>,
+    /// Signed minimum per block, on the same scale as `peaks`
+    pub min_peaks: Vec<f32>,
+    /// Signed maximum per block, on the same scale as `peaks`
+    pub max_peaks: Vec<f32>,
+    /// RMS level per block, on the same scale as `peaks`
+    pub rms_peaks: Vec<f32>,
+    pub channel_mode: audio::ChannelMode,
+    /// Per-channel peaks as selected by `channel_mode`, sharing one scale
+    pub channels: Vec<audio::PeakBuffers>,
+    /// Low/mid/high band energy per block, on the same scale as `peaks`
+    pub bands: Option<audio::BandEnergies>,
+    /// Units of every peak, band and channel value above
+    pub scale: audio::WaveformScale,
+    /// Loudest mono peak (1.0 for silence); normalized mono and band values were divided by it
+    pub normalization_factor: f32,
+    /// Loudest channel peak; normalized channel values were divided by it
+    pub channel_normalization_factor: f32,
     pub duration_secs: f64,
     pub sample_rate: u32,
 }
@@ -153,7 +194,11 @@ async fn extract_audio(
         output_path.exists()
     );
 
-    let (duration_secs, sample_rate) = audio::get_audio_info(&output_path)?;
+    let audio::AudioInfo {
+        duration_secs,
+        sample_rate,
+        ..
+    } = audio::get_audio_info(&output_path)?;
     let expected_peaks = audio::estimate_peak_count(duration_secs, sample_rate);
     
     println!(
@@ -172,14 +217,21 @@ async fn extract_audio(
     });
 
     let on_event_clone = on_event.clone();
-    let waveform = match audio::generate_waveform_peaks(&output_path, move |peaks, offset| {
-        if let Err(e) = on_event_clone.send(ExtractionEvent::WaveformChunk {
-            peaks: peaks.to_vec(),
-            offset,
-        }) {
-            eprintln!("[tubetape] Failed to send waveform chunk event: {}", e);
-        }
-    }) {
+    let waveform = match audio::generate_waveform_peaks(
+        &output_path,
+        &audio::WaveformOptions::default(),
+        move |peaks, _, _, offset| {
+            if let Err(e) = on_event_clone.send(ExtractionEvent::WaveformChunk {
+                peaks: peaks.peaks.clone(),
+                min_peaks: peaks.min_peaks.clone(),
+                max_peaks: peaks.max_peaks.clone(),
+                rms_peaks: peaks.rms_peaks.clone(),
+                offset,
+            }) {
+                eprintln!("[tubetape] Failed to send waveform chunk event: {}", e);
+            }
+        },
+    ) {
         Ok(w) => {
             println!(
                 "[tubetape] Waveform generated: {} peaks, {} seconds",
@@ -241,9 +293,29 @@ async fn extract_audio(
 
 #[tauri::command]
 #[specta::specta]
-async fn get_waveform(audio_path: String) -> Result<WaveformData, String> {
+async fn get_waveform(
+    audio_path: String,
+    options: Option<audio::WaveformOptions>,
+) -> Result<WaveformData, String> {
+    let path = std::path::PathBuf::from(&audio_path);
+    audio::generate_waveform_peaks(&path, &options.unwrap_or_default(), |_, _, _, _| {})
+}
+
+/// Peaks for a visible time range at exactly the resolution needed for `pixels` columns.
+/// The peak pyramid for a file is built on first use and kept in memory until
+/// the file changes. At most `MAX_RANGE_PIXELS` (32768) columns may be requested.
+#[tauri::command]
+#[specta::specta]
+async fn get_waveform_range(
+    cache: tauri::State<'_, peak_pyramid::PeakPyramidCache>,
+    audio_path: String,
+    start_time: f64,
+    end_time: f64,
+    pixels: u32,
+) -> Result<peak_pyramid::WaveformRange, String> {
     let path = std::path::PathBuf::from(&audio_path);
-    audio::generate_waveform_peaks(&path, |_, _| {})
+    let pyramid = cache.get_or_build(&path)?;
+    peak_pyramid::get_range(&path, &pyramid, start_time, end_time, pixels)
 }
 
 #[derive(Clone, Serialize, Type)]
@@ -258,6 +330,8 @@ pub enum WaveformEvent {
         sample_rate: u32,
         #[serde(rename = "durationSecs")]
         duration_secs: f64,
+        #[serde(rename = "durationExact")]
+        duration_exact: bool,
     },
     Progress {
         #[serde(rename = "totalPeaks")]
@@ -265,10 +339,31 @@ pub enum WaveformEvent {
     },
     Chunk {
         peaks: Vec<f32>,
+        #[serde(rename = "minPeaks")]
+        min_peaks: Vec<f32>,
+        #[serde(rename = "maxPeaks")]
+        max_peaks: Vec<f32>,
+        #[serde(rename = "rmsPeaks")]
+        rms_peaks: Vec<f32>,
+        channels: Vec<audio::PeakBuffers>,
+        bands: Option<audio::BandEnergies>,
         offset: usize,
     },
     Completed {
         peaks: Vec<f32>,
+        #[serde(rename = "minPeaks")]
+        min_peaks: Vec<f32>,
+        #[serde(rename = "maxPeaks")]
+        max_peaks: Vec<f32>,
+        #[serde(rename = "rmsPeaks")]
+        rms_peaks: Vec<f32>,
+        channels: Vec<audio::PeakBuffers>,
+        bands: Option<audio::BandEnergies>,
+        scale: audio::WaveformScale,
+        #[serde(rename = "normalizationFactor")]
+        normalization_factor: f32,
+        #[serde(rename = "channelNormalizationFactor")]
+        channel_normalization_factor: f32,
         #[serde(rename = "durationSecs")]
         duration_secs: f64,
     },
@@ -281,6 +376,7 @@ pub enum WaveformEvent {
 #[specta::specta]
 async fn generate_waveform_stream(
     audio_path: String,
+    options: Option<audio::WaveformOptions>,
     on_event: Channel<WaveformEvent>,
 ) -> Result<WaveformData, String> {
     let path = std::path::PathBuf::from(&audio_path);
@@ -289,12 +385,13 @@ async fn generate_waveform_stream(
         audio_path: audio_path.clone(),
     });
 
-    let (duration_secs, sample_rate) = audio::get_audio_info(&path)?;
-    let expected_peaks = audio::estimate_peak_count(duration_secs, sample_rate);
+    let info = audio::get_audio_info(&path)?;
+    let expected_peaks = audio::estimate_peak_count(info.duration_secs, info.sample_rate);
 
     let _ = on_event.send(WaveformEvent::AudioInfo {
-        sample_rate,
-        duration_secs,
+        sample_rate: info.sample_rate,
+        duration_secs: info.duration_secs,
+        duration_exact: info.duration_exact,
     });
     
     let _ = on_event.send(WaveformEvent::Progress {
@@ -302,37 +399,101 @@ async fn generate_waveform_stream(
     });
 
     let on_event_clone = on_event.clone();
-    let waveform = audio::generate_waveform_peaks(&path, move |peaks, offset| {
-        let _ = on_event_clone.send(WaveformEvent::Chunk {
-            peaks: peaks.to_vec(),
-            offset,
-        });
-    })?;
+    let options = options.unwrap_or_default();
+    let waveform =
+        audio::generate_waveform_peaks(&path, &options, move |peaks, channels, bands, offset| {
+            let _ = on_event_clone.send(WaveformEvent::Chunk {
+                peaks: peaks.peaks.clone(),
+                min_peaks: peaks.min_peaks.clone(),
+                max_peaks: peaks.max_peaks.clone(),
+                rms_peaks: peaks.rms_peaks.clone(),
+                channels: channels.to_vec(),
+                bands: bands.cloned(),
+                offset,
+            });
+        })?;
 
     let _ = on_event.send(WaveformEvent::Completed {
         peaks: waveform.peaks.clone(),
+        min_peaks: waveform.min_peaks.clone(),
+        max_peaks: waveform.max_peaks.clone(),
+        rms_peaks: waveform.rms_peaks.clone(),
+        channels: waveform.channels.clone(),
+        bands: waveform.bands.clone(),
+        scale: waveform.scale,
+        normalization_factor: waveform.normalization_factor,
+        channel_normalization_factor: waveform.channel_normalization_factor,
         duration_secs: waveform.duration_secs,
     });
 
     Ok(waveform)
 }
 
+/// Stream spectrogram tiles covering a time range.
+/// Tiles are cached per source file and zoom level, so repeated requests are served from memory.
+#[tauri::command]
+#[specta::specta]
+async fn generate_spectrogram(
+    cache: tauri::State<'_, spectrogram::SpectrogramCache>,
+    audio_path: String,
+    start_time: f64,
+    end_time: f64,
+    options: Option<spectrogram::SpectrogramOptions>,
+    on_event: Channel<spectrogram::SpectrogramEvent>,
+) -> Result<(), String> {
+    let path = std::path::PathBuf::from(&audio_path);
+    let options = options.unwrap_or_default();
+
+    let on_event_clone = on_event.clone();
+    let result =
+        spectrogram::generate_tiles(&path, start_time, end_time, &options, &cache, |event| {
+            let _ = on_event_clone.send(event);
+        });
+
+    match result {
+        Ok(total_tiles) => {
+            let _ = on_event.send(spectrogram::SpectrogramEvent::Completed { total_tiles });
+            Ok(())
+        }
+        Err(e) => {
+            let _ = on_event.send(spectrogram::SpectrogramEvent::Error { message: e.clone() });
+            Err(e)
+        }
+    }
+}
+
+/// Look up stored audio for a video ID, along with its saved analysis when
+/// that is still valid for `waveform_options` and `beat_config`.
 #[tauri::command]
 #[specta::specta]
 async fn check_cached_audio(
     app: tauri::AppHandle,
     video_id: String,
+    waveform_options: Option<audio::WaveformOptions>,
+    beat_config: Option<beat_detection::BeatDetectionConfig>,
 ) -> Result<Option<CachedAudioInfo>, String> {
     let output_dir = get_audio_output_dir(&app)?;
     
-    for ext in ["aac", "m4a", "mp3"] {
+    for ext in ["aac", "m4a", "mp3", "flac"] {
         let audio_path = output_dir.join(format!("{}.{}", video_id, ext));
         if audio_path.exists() {
-            let (duration_secs, sample_rate) = audio::get_audio_info(&audio_path)?;
+            let info = audio::get_audio_info(&audio_path)?;
+            let analysis_path = audio_path.clone();
+            let analysis = tokio::task::spawn_blocking(move || {
+                analysis_cache::load(
+                    &analysis_path,
+                    &waveform_options.unwrap_or_default(),
+                    &beat_config.unwrap_or_default(),
+                )
+            })
+            .await
+            .map_err(|e| format!("Analysis cache task panicked: {}", e))?;
             return Ok(Some(CachedAudioInfo {
                 audio_path: audio_path.to_string_lossy().to_string(),
-                duration_secs,
-                sample_rate,
+                duration_secs: info.duration_secs,
+                sample_rate: info.sample_rate,
+                duration_exact: info.duration_exact,
+                analysis,
             }));
         }
     }
@@ -346,6 +507,11 @@ pub struct CachedAudioInfo {
     pub audio_path: String,
     pub duration_secs: f64,
     pub sample_rate: u32,
+    /// False when `duration_secs` is an estimate
+    pub duration_exact: bool,
+    /// Saved waveform and beats, if still valid for the requested options,
+    /// so the track can be shown without running `process_audio`
+    pub analysis: Option<analysis_cache::CachedAnalysis>,
 }
 
 #[tauri::command]
@@ -359,7 +525,8 @@ async fn export_sample(
 ) -> Result<String, String> {
     let source = std::path::PathBuf::from(&source_path);
     let output = std::path::PathBuf::from(&output_path);
-    ffmpeg_runtime::export_sample(&source, &output, start_time, end_time)?;
+    let cancel = cancel::CancellationToken::new();
+    ffmpeg_runtime::export_sample(&source, &output, start_time, end_time, &cancel)?;
     Ok(output_path)
 }
 
@@ -370,11 +537,68 @@ fn get_audio_output_dir(app: &tauri::AppHandle) -> Result<std::path::PathBuf, St
         .map_err(|e| format!("Failed to get app data directory: {}", e))
 }
 
+/// Directory holding the saved state of unfinished pipeline runs
+fn get_pipeline_journal_dir(app: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map(|p| p.join("pipelines"))
+        .map_err(|e| format!("Failed to get app data directory: {}", e))
+}
+
 #[tauri::command]
 #[specta::specta]
-async fn analyze_audio_beats(audio_path: String) -> Result<beat_detection::BeatInfo, String> {
+async fn analyze_audio_beats(
+    audio_path: String,
+    config: Option<beat_detection::BeatDetectionConfig>,
+) -> Result<beat_detection::BeatInfo, String> {
+    let config = config.unwrap_or_default();
+    config.validate()?;
+    let path = std::path::PathBuf::from(&audio_path);
+    beat_detection::analyze_beats_with_config(&path, &config)
+}
+
+/// Run the requested analyses over just the `[start, end)` selection of a file.
+/// Only that span is decoded, and all analyses share the one pass.
+#[tauri::command]
+#[specta::specta]
+async fn analyze_region(
+    audio_path: String,
+    start: f64,
+    end: f64,
+    analyses: Vec<region::RegionAnalysis>,
+    beat_config: Option<beat_detection::BeatDetectionConfig>,
+) -> Result<region::RegionAnalysisResult, String> {
+    let beat_config = beat_config.unwrap_or_default();
+    beat_config.validate()?;
     let path = std::path::PathBuf::from(&audio_path);
-    beat_detection::analyze_beats(&path)
+    region::analyze_region(&path, start, end, &analyses, &beat_config)
+}
+
+/// Rank the bars of a track by how likely they are to be exposed drum breaks.
+#[tauri::command]
+#[specta::specta]
+async fn find_drum_breaks(
+    audio_path: String,
+    options: Option<drum_breaks::DrumBreakOptions>,
+) -> Result<Vec<drum_breaks::DrumBreakCandidate>, String> {
+    let path = std::path::PathBuf::from(&audio_path);
+    drum_breaks::find_drum_breaks(&path, &options.unwrap_or_default())
+}
+
+/// Render a region of up to ten minutes into separate harmonic and
+/// percussive WAV stems.
+/// The stem files can be passed to `export_sample` like any other source.
+#[tauri::command]
+#[specta::specta]
+async fn render_hpss_stems(
+    app: tauri::AppHandle,
+    source_path: String,
+    start_time: f64,
+    end_time: f64,
+) -> Result<hpss::HpssStems, String> {
+    let source = std::path::PathBuf::from(&source_path);
+    let output_dir = get_audio_output_dir(&app)?.join("stems");
+    hpss::render_stems(&source, start_time, end_time, &output_dir)
 }
 
 /// Process an existing audio file (waveform + beat detection).
@@ -383,10 +607,19 @@ async fn analyze_audio_beats(audio_path: String) -> Result<beat_detection::BeatI
 #[specta::specta]
 async fn process_audio(
     audio_path: String,
+    waveform_options: Option<audio::WaveformOptions>,
+    beat_config: Option<beat_detection::BeatDetectionConfig>,
     on_event: Channel<pipeline::PipelineEvent>,
 ) -> Result<pipeline::PipelineResult, String> {
+    let beat_config = beat_config.unwrap_or_default();
+    beat_config.validate()?;
     let path = std::path::PathBuf::from(&audio_path);
-    let executor = pipeline::PipelineExecutor::for_existing_audio(path, on_event);
+    let executor = pipeline::PipelineExecutor::for_existing_audio(
+        path,
+        on_event,
+        waveform_options.unwrap_or_default(),
+        beat_config,
+    );
     executor.execute().await
 }
 
@@ -394,18 +627,124 @@ async fn process_audio(
 // Unified Pipeline Commands
 // ============================================================================
 
-/// State wrapper for the pipeline command sender.
-/// This allows the frontend to send commands to a running pipeline.
+/// State wrapper for the running pipelines, keyed by pipeline ID.
+/// This allows the frontend to send commands to a specific run.
 struct PipelineCommandSender {
-    sender: TokioMutex<Option<mpsc::Sender<pipeline::PipelineCommand>>>,
+    pipelines: TokioMutex<HashMap<String, RunningPipeline>>,
+    next_id: AtomicU64,
+}
+
+/// Handles for talking to a running pipeline
+struct RunningPipeline {
+    commands: mpsc::Sender<pipeline::PipelineCommand>,
+    cancel: cancel::CancellationToken,
+    /// File the run writes, which no other run may share
+    output_path: std::path::PathBuf,
 }
 
 impl PipelineCommandSender {
     fn new() -> Self {
         Self {
-            sender: TokioMutex::new(None),
+            pipelines: TokioMutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
         }
     }
+
+    /// A fresh ID for a run of `video_id`, unique for the app's lifetime
+    fn next_pipeline_id(&self, video_id: &str) -> String {
+        let n = self.next_id.fetch_add(1, Ordering::Relaxed);
+        format!("{}-{}", video_id, n)
+    }
+
+    /// Keep new IDs clear of those in `saved`, which were handed out by an
+    /// earlier launch of the app
+    fn skip_saved_ids(&self, saved: &[pipeline::SavedPipeline]) {
+        let last = saved
+            .iter()
+            .filter_map(|s| s.pipeline_id.rsplit_once('-')?.1.parse::<u64>().ok())
+            .max();
+        if let Some(last) = last {
+            self.next_id.fetch_max(last + 1, Ordering::Relaxed);
+        }
+    }
+
+    /// A fresh ID for a batch of queued jobs
+    fn next_batch_id(&self) -> String {
+        let n = self.next_id.fetch_add(1, Ordering::Relaxed);
+        format!("batch-{}", n)
+    }
+
+    /// Register a run so `pipeline_notify` can reach it, unless another run
+    /// is already writing the same file, such as a second run of one video
+    async fn register(&self, pipeline_id: String, pipeline: RunningPipeline) -> Result<(), String> {
+        let mut pipelines = self.pipelines.lock().await;
+        let same_output = pipelines
+            .iter()
+            .find(|(_, running)| running.output_path == pipeline.output_path);
+        if let Some((other_id, _)) = same_output {
+            return Err(format!(
+                "Pipeline {} is already writing {}",
+                other_id,
+                pipeline.output_path.display()
+            ));
+        }
+        pipelines.insert(pipeline_id, pipeline);
+        Ok(())
+    }
+}
+
+/// Build the executor for `url` and register it under `pipeline_id` so
+/// `pipeline_notify` can reach it. The caller unregisters it once it has run.
+/// Fails if another run of the same video is still going.
+async fn prepare_pipeline(
+    app: &tauri::AppHandle,
+    pipelines: &PipelineCommandSender,
+    pipeline_id: String,
+    launch: pipeline::JobLaunch,
+) -> Result<pipeline::PipelineExecutor, String> {
+    let video_id =
+        youtube::extract_video_id(&launch.url).ok_or_else(|| "Invalid YouTube URL".to_string())?;
+
+    let output_dir = get_audio_output_dir(app)?;
+    std::fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+
+    let output_path = output_dir.join(format!("{}.aac", video_id));
+
+    println!(
+        "[pipeline] Starting pipeline {} -> {:?}",
+        pipeline_id, output_path
+    );
+
+    // Create command channel for frontend → pipeline communication
+    let (command_tx, command_rx) = mpsc::channel::<pipeline::PipelineCommand>(32);
+
+    // Create the pipeline
+    let executor = pipeline::PipelineExecutor::new(
+        pipeline_id.clone(),
+        launch.url,
+        output_path.clone(),
+        launch.on_event,
+        command_rx,
+        launch.waveform_options,
+        launch.beat_config,
+    )
+    .with_config(launch.config)
+    .persist_to(get_pipeline_journal_dir(app)?);
+
+    // Register the run so frontend can send commands via pipeline_notify
+    pipelines
+        .register(
+            pipeline_id,
+            RunningPipeline {
+                commands: command_tx,
+                cancel: executor.cancel_token(),
+                output_path,
+            },
+        )
+        .await?;
+
+    Ok(executor)
 }
 
 /// Start the unified pipeline for fetching and processing audio.
@@ -414,75 +753,451 @@ impl PipelineCommandSender {
 /// 1. Emits `RequestExtraction` for the frontend to run Pyodide/yt-dlp
 /// 2. Waits for extraction progress/completion via `pipeline_notify`
 /// 3. Runs FFmpeg commands for audio conversion
-/// 4. Runs waveform + beat detection in parallel
+/// 4. Runs the configured analysis stages, by default waveform + beat detection
 /// 5. Reports unified progress throughout
+///
+/// Each run gets its own pipeline ID, sent with every event, so several
+/// pipelines can run at once; a second run of a video that is still running
+/// is rejected, as both would write the same file. Runs join the job queue
+/// ahead of queued jobs and wait for its download and analysis slots like them.
+/// Recoverable failures are retried by `config.retry_policy`, or the default
+/// policy, with a `Retrying` event before each new attempt. Extraction that
+/// stalls or runs past `config.extraction_timeouts` fails with a recoverable error.
 #[tauri::command]
 #[specta::specta]
 async fn run_pipeline(
     app: tauri::AppHandle,
     url: String,
+    waveform_options: Option<audio::WaveformOptions>,
+    beat_config: Option<beat_detection::BeatDetectionConfig>,
+    config: Option<pipeline::PipelineConfig>,
     on_event: Channel<pipeline::PipelineEvent>,
     state: tauri::State<'_, PipelineCommandSender>,
 ) -> Result<pipeline::PipelineResult, String> {
     let video_id =
         youtube::extract_video_id(&url).ok_or_else(|| "Invalid YouTube URL".to_string())?;
+    let beat_config = beat_config.unwrap_or_default();
+    beat_config.validate()?;
+    let pipeline_id = state.next_pipeline_id(&video_id);
+    let queue = app.state::<Arc<pipeline::JobQueue>>().inner().clone();
+
+    let executor = prepare_pipeline(
+        &app,
+        &state,
+        pipeline_id.clone(),
+        pipeline::JobLaunch {
+            url: url.clone(),
+            waveform_options: waveform_options.unwrap_or_default(),
+            beat_config,
+            config: config.unwrap_or_default(),
+            on_event,
+        },
+    )
+    .await?;
+
+    queue.add_direct(&pipeline_id, &url);
+    let cancel = executor.cancel_token();
+    let result = executor.through_queue(queue.clone()).run().await;
+
+    // Unregister only this run; others may still be going
+    state.pipelines.lock().await.remove(&pipeline_id);
+    queue.finish(&pipeline_id, &result, cancel.is_cancelled());
 
-    let output_dir = get_audio_output_dir(&app)?;
-    std::fs::create_dir_all(&output_dir)
-        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    result
+}
 
-    let output_path = output_dir.join(format!("{}.aac", video_id));
+/// Pipeline runs that were interrupted before finishing, such as by the app
+/// quitting, and can be continued with `resume_pipeline`
+#[tauri::command]
+#[specta::specta]
+async fn list_interrupted_pipelines(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, PipelineCommandSender>,
+) -> Result<Vec<pipeline::SavedPipeline>, String> {
+    let journal_dir = get_pipeline_journal_dir(&app)?;
+    let saved = tokio::task::spawn_blocking(move || pipeline::journal::load_all(&journal_dir))
+        .await
+        .map_err(|e| format!("Failed to read pipeline state: {}", e))?;
+
+    // Running pipelines keep a record too, but they aren't interrupted, and
+    // a resumed run's old record stays until the run has written its own
+    let running = state.pipelines.lock().await;
+    Ok(saved
+        .into_iter()
+        .filter(|s| {
+            let output_path = std::path::Path::new(&s.output_path);
+            !running.contains_key(&s.pipeline_id)
+                && !running.values().any(|r| r.output_path == output_path)
+        })
+        .collect())
+}
 
-    println!(
-        "[pipeline] Starting pipeline for {} -> {:?}",
-        video_id, output_path
-    );
+/// Continue an interrupted pipeline run from its last saved stage.
+///
+/// The run gets a new pipeline ID, sent in its `Started` event. If it was
+/// still extracting, `RequestExtraction` is emitted again and the download
+/// picks up where it stopped; otherwise the conversion and analysis stages
+/// that had not finished are run on the extracted audio, with the config the
+/// interrupted run was started with. Like `run_pipeline`, it waits for the
+/// job queue's slots.
+#[tauri::command]
+#[specta::specta]
+async fn resume_pipeline(
+    app: tauri::AppHandle,
+    pipeline_id: String,
+    on_event: Channel<pipeline::PipelineEvent>,
+    state: tauri::State<'_, PipelineCommandSender>,
+) -> Result<pipeline::PipelineResult, String> {
+    let journal_dir = get_pipeline_journal_dir(&app)?;
+    let saved = pipeline::journal::load(&journal_dir, &pipeline_id)?;
+    let video_id =
+        youtube::extract_video_id(&saved.url).ok_or_else(|| "Invalid YouTube URL".to_string())?;
+    let new_id = state.next_pipeline_id(&video_id);
 
-    // Create command channel for frontend → pipeline communication
-    let (command_tx, command_rx) = mpsc::channel::<pipeline::PipelineCommand>(32);
+    println!("[pipeline] Resuming pipeline {} as {}", pipeline_id, new_id);
 
-    // Store sender in state so frontend can send commands via pipeline_notify
-    {
-        let mut sender_guard = state.sender.lock().await;
-        *sender_guard = Some(command_tx);
-    }
+    let queue = app.state::<Arc<pipeline::JobQueue>>().inner().clone();
+    let output_path = std::path::PathBuf::from(&saved.output_path);
+    let url = saved.url.clone();
 
-    // Create and run the pipeline
-    let executor = pipeline::PipelineExecutor::new(url, output_path, on_event, command_rx);
+    let (command_tx, command_rx) = mpsc::channel::<pipeline::PipelineCommand>(32);
+    // The old record is replaced once the resumed run has written its own
+    let executor = pipeline::PipelineExecutor::resume(new_id.clone(), saved, on_event, command_rx)
+        .persist_to(journal_dir)
+        .through_queue(queue.clone());
+
+    state
+        .register(
+            new_id.clone(),
+            RunningPipeline {
+                commands: command_tx,
+                cancel: executor.cancel_token(),
+                output_path,
+            },
+        )
+        .await?;
+    queue.add_direct(&new_id, &url);
 
+    let cancel = executor.cancel_token();
     let result = executor.run().await;
+    state.pipelines.lock().await.remove(&new_id);
+    queue.finish(&new_id, &result, cancel.is_cancelled());
+
+    result
+}
 
-    // Clear the sender when done
+/// Forget an interrupted pipeline run, deleting its partial download.
+/// Fails while a running pipeline writes the same file.
+#[tauri::command]
+#[specta::specta]
+async fn discard_pipeline(
+    app: tauri::AppHandle,
+    pipeline_id: String,
+    state: tauri::State<'_, PipelineCommandSender>,
+) -> Result<(), String> {
+    let journal_dir = get_pipeline_journal_dir(&app)?;
+    let saved = pipeline::journal::load(&journal_dir, &pipeline_id)?;
+
+    // Held until the files are gone, so the run can't be resumed meanwhile
+    let running = state.pipelines.lock().await;
+    let output_path = std::path::Path::new(&saved.output_path);
+    if running.contains_key(&saved.pipeline_id)
+        || running.values().any(|r| r.output_path == output_path)
     {
-        let mut sender_guard = state.sender.lock().await;
-        *sender_guard = None;
+        return Err("Cannot discard a pipeline that is still running".to_string());
+    }
+
+    if let Some(partial_path) = &saved.partial_path {
+        // Only ever delete downloads inside the audio store, resolving links
+        // and `..` so a crafted record can't point outside it
+        let output_dir = get_audio_output_dir(&app)?;
+        if let Ok(output_dir) = output_dir.canonicalize() {
+            let partial_file = format!("{}.partial", partial_path);
+            for path in [partial_path.as_str(), partial_file.as_str()] {
+                let inside = std::path::Path::new(path)
+                    .canonicalize()
+                    .is_ok_and(|path| path.starts_with(&output_dir));
+                if inside {
+                    let _ = std::fs::remove_file(path);
+                }
+            }
+        }
     }
 
+    pipeline::journal::remove(&journal_dir, &pipeline_id);
+    drop(running);
+    Ok(())
+}
+
+/// Import a local audio or video file into the audio store and analyze it.
+///
+/// The file is probed with FFmpeg and its audio stream copied or transcoded
+/// into the store under a content hash ID, so importing the same file twice
+/// reuses the first copy. The ID works like a video ID with `check_cached_audio`.
+/// Like other runs, the import can be cancelled through `pipeline_notify`.
+#[tauri::command]
+#[specta::specta]
+async fn import_local_file(
+    app: tauri::AppHandle,
+    path: String,
+    waveform_options: Option<audio::WaveformOptions>,
+    beat_config: Option<beat_detection::BeatDetectionConfig>,
+    on_event: Channel<pipeline::PipelineEvent>,
+    state: tauri::State<'_, PipelineCommandSender>,
+) -> Result<pipeline::ImportedFile, String> {
+    let beat_config = beat_config.unwrap_or_default();
+    beat_config.validate()?;
+    let source = tokio::task::spawn_blocking(move || {
+        pipeline::LocalSource::probe(std::path::Path::new(&path))
+    })
+    .await
+    .map_err(|e| format!("Import task panicked: {}", e))??;
+
+    let output_dir = get_audio_output_dir(&app)?;
+    std::fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    let output_path = output_dir.join(source.stored_file_name());
+
+    let pipeline_id = state.next_pipeline_id(&source.source_id);
+    let executor = pipeline::ImportExecutor::new(
+        pipeline_id.clone(),
+        source,
+        output_path.clone(),
+        on_event,
+        waveform_options.unwrap_or_default(),
+        beat_config,
+    );
+
+    // Imports only take `Cancel`; other commands fail to send
+    let (command_tx, _) = mpsc::channel::<pipeline::PipelineCommand>(1);
+    state
+        .register(
+            pipeline_id.clone(),
+            RunningPipeline {
+                commands: command_tx,
+                cancel: executor.cancel_token(),
+                output_path,
+            },
+        )
+        .await?;
+
+    let result = executor.run().await;
+    state.pipelines.lock().await.remove(&pipeline_id);
+
     result
 }
 
-/// Send a command to the running pipeline.
+/// Send a command to the pipeline with the given ID.
 ///
 /// Used by the frontend to:
 /// - Report extraction progress (`DownloadProgress`)
 /// - Signal extraction completion (`ExtractionComplete`)
 /// - Signal extraction failure (`ExtractionFailed`)
+/// - Stop the pipeline (`Cancel`)
 #[tauri::command]
 #[specta::specta]
 async fn pipeline_notify(
     state: tauri::State<'_, PipelineCommandSender>,
+    pipeline_id: String,
     command: pipeline::PipelineCommand,
 ) -> Result<(), String> {
-    let sender_guard = state.sender.lock().await;
+    // Clone the handles so a blocked send doesn't hold up other pipelines
+    let (commands, cancel) = {
+        let pipelines = state.pipelines.lock().await;
+        let Some(pipeline) = pipelines.get(&pipeline_id) else {
+            return Err(format!("No running pipeline with ID {}", pipeline_id));
+        };
+        (pipeline.commands.clone(), pipeline.cancel.clone())
+    };
 
-    if let Some(sender) = sender_guard.as_ref() {
-        sender
-            .send(command)
-            .await
-            .map_err(|e| format!("Failed to send pipeline command: {}", e))
-    } else {
-        Err("No pipeline is currently running".to_string())
+    // Every stage watches the token, including the wait for extraction, and
+    // later stages no longer read commands, so a cancel is never queued
+    if let pipeline::PipelineCommand::Cancel = command {
+        cancel.cancel();
+        return Ok(());
     }
+
+    commands
+        .send(command)
+        .await
+        .map_err(|e| format!("Failed to send pipeline command: {}", e))
+}
+
+// ============================================================================
+// Job Queue Commands
+// ============================================================================
+
+/// Queue a pipeline for `url` to run in the background.
+///
+/// Returns the job ID, which is also the pipeline ID once the job starts.
+/// Events for the job arrive on `on_event` exactly as for `run_pipeline`.
+#[tauri::command]
+#[specta::specta]
+async fn enqueue_pipeline(
+    url: String,
+    priority: Option<i32>,
+    waveform_options: Option<audio::WaveformOptions>,
+    beat_config: Option<beat_detection::BeatDetectionConfig>,
+    on_event: Channel<pipeline::PipelineEvent>,
+    pipelines: tauri::State<'_, PipelineCommandSender>,
+    queue: tauri::State<'_, Arc<pipeline::JobQueue>>,
+) -> Result<String, String> {
+    let video_id =
+        youtube::extract_video_id(&url).ok_or_else(|| "Invalid YouTube URL".to_string())?;
+    let beat_config = beat_config.unwrap_or_default();
+    beat_config.validate()?;
+    let job_id = pipelines.next_pipeline_id(&video_id);
+
+    queue.enqueue(pipeline::NewJob {
+        id: job_id.clone(),
+        title: None,
+        priority: priority.unwrap_or(0),
+        launch: pipeline::JobLaunch {
+            url,
+            waveform_options: waveform_options.unwrap_or_default(),
+            beat_config,
+            config: pipeline::PipelineConfig::default(),
+            on_event,
+        },
+    });
+    Ok(job_id)
+}
+
+/// List the videos of a playlist or channel URL so the user can pick which to queue
+#[tauri::command]
+#[specta::specta]
+async fn expand_playlist(
+    app: tauri::AppHandle,
+    url: String,
+) -> Result<youtube::PlaylistInfo, String> {
+    let qjs_path = binary::ensure_qjs_binary(app).await.ok();
+    youtube::expand_playlist(&url, qjs_path).await
+}
+
+/// Queue the chosen videos of a playlist or channel as one batch.
+///
+/// Events for every job in the batch arrive on `on_event`; the pipeline ID
+/// each event carries tells them apart. Aggregate progress is part of each
+/// `QueueSnapshot`.
+#[tauri::command]
+#[specta::specta]
+async fn enqueue_batch(
+    title: String,
+    entries: Vec<youtube::PlaylistEntry>,
+    waveform_options: Option<audio::WaveformOptions>,
+    beat_config: Option<beat_detection::BeatDetectionConfig>,
+    on_event: Channel<pipeline::PipelineEvent>,
+    pipelines: tauri::State<'_, PipelineCommandSender>,
+    queue: tauri::State<'_, Arc<pipeline::JobQueue>>,
+) -> Result<pipeline::EnqueuedBatch, String> {
+    if entries.is_empty() {
+        return Err("No videos selected".to_string());
+    }
+
+    let waveform_options = waveform_options.unwrap_or_default();
+    let beat_config = beat_config.unwrap_or_default();
+    beat_config.validate()?;
+    let batch_id = pipelines.next_batch_id();
+
+    let jobs: Vec<pipeline::NewJob> = entries
+        .into_iter()
+        .map(|entry| pipeline::NewJob {
+            id: pipelines.next_pipeline_id(&entry.video_id),
+            title: Some(entry.title),
+            priority: 0,
+            launch: pipeline::JobLaunch {
+                url: entry.url,
+                waveform_options: waveform_options.clone(),
+                beat_config: beat_config.clone(),
+                config: pipeline::PipelineConfig::default(),
+                on_event: on_event.clone(),
+            },
+        })
+        .collect();
+    let job_ids = jobs.iter().map(|job| job.id.clone()).collect();
+
+    queue.enqueue_batch(&batch_id, title, jobs);
+    Ok(pipeline::EnqueuedBatch { batch_id, job_ids })
+}
+
+/// Current jobs and limits; later changes arrive as `QueueSnapshot` events
+#[tauri::command]
+#[specta::specta]
+async fn get_queue(
+    queue: tauri::State<'_, Arc<pipeline::JobQueue>>,
+) -> Result<pipeline::QueueSnapshot, String> {
+    Ok(queue.snapshot())
+}
+
+/// Change how many jobs may download and analyse at once. Downloads are
+/// capped at one, since extractions run one at a time.
+#[tauri::command]
+#[specta::specta]
+async fn set_queue_config(
+    config: pipeline::QueueConfig,
+    queue: tauri::State<'_, Arc<pipeline::JobQueue>>,
+) -> Result<(), String> {
+    queue.set_config(config);
+    Ok(())
+}
+
+/// Move a job ahead of every other job in the queue
+#[tauri::command]
+#[specta::specta]
+async fn bump_job(
+    job_id: String,
+    queue: tauri::State<'_, Arc<pipeline::JobQueue>>,
+) -> Result<(), String> {
+    queue.bump(&job_id)
+}
+
+/// Hold a queued job back until it is resumed
+#[tauri::command]
+#[specta::specta]
+async fn pause_job(
+    job_id: String,
+    queue: tauri::State<'_, Arc<pipeline::JobQueue>>,
+) -> Result<(), String> {
+    queue.pause(&job_id)
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn resume_job(
+    job_id: String,
+    queue: tauri::State<'_, Arc<pipeline::JobQueue>>,
+) -> Result<(), String> {
+    queue.resume(&job_id)
+}
+
+/// Remove a job that is not running. Running jobs are stopped with `pipeline_notify`.
+#[tauri::command]
+#[specta::specta]
+async fn remove_job(
+    job_id: String,
+    queue: tauri::State<'_, Arc<pipeline::JobQueue>>,
+) -> Result<(), String> {
+    queue.remove(&job_id)
+}
+
+/// Run a job the queue has given a download slot, then report how it ended
+async fn run_queued_pipeline(app: tauri::AppHandle, job_id: String, launch: pipeline::JobLaunch) {
+    let queue = app.state::<Arc<pipeline::JobQueue>>().inner().clone();
+    let pipelines = app.state::<PipelineCommandSender>();
+
+    let executor = prepare_pipeline(&app, &pipelines, job_id.clone(), launch).await;
+
+    let (result, cancelled) = match executor {
+        Ok(executor) => {
+            let cancel = executor.cancel_token();
+            let result = executor.in_queue(queue.clone()).run().await;
+            pipelines.pipelines.lock().await.remove(&job_id);
+            (result, cancel.is_cancelled())
+        }
+        Err(e) => (Err(e), false),
+    };
+
+    queue.finish(&job_id, &result, cancelled);
 }
 
 #[derive(Clone, Serialize, Type)]
@@ -549,23 +1264,42 @@ pub fn run() {
             fetch_video_metadata,
             extract_audio,
             get_waveform,
+            get_waveform_range,
             generate_waveform_stream,
+            generate_spectrogram,
             export_sample,
             check_cached_audio,
             get_app_stats,
             analyze_audio_beats,
+            analyze_region,
+            find_drum_breaks,
+            render_hpss_stems,
             process_audio,
             run_pipeline,
+            list_interrupted_pipelines,
+            resume_pipeline,
+            discard_pipeline,
+            import_local_file,
             pipeline_notify,
+            enqueue_pipeline,
+            expand_playlist,
+            enqueue_batch,
+            get_queue,
+            set_queue_config,
+            bump_job,
+            pause_job,
+            resume_job,
+            remove_job,
             binary::get_qjs_status,
             binary::get_ffmpeg_status,
             http::http_request,
             http::download_to_file,
             http::download_to_file_with_progress,
+            http::cancel_download,
             ffmpeg::dlopen_ffmpeg,
             ffmpeg::ffprobe_capabilities,
         ])
-        .events(collect_events![AppNotification]);
+        .events(collect_events![AppNotification, pipeline::QueueSnapshot]);
 
     #[cfg(debug_assertions)]
     builder
@@ -581,10 +1315,33 @@ pub fn run() {
             system: Mutex::new(System::new_all()),
         })
         .manage(PipelineCommandSender::new())
+        .manage(spectrogram::SpectrogramCache::default())
+        .manage(peak_pyramid::PeakPyramidCache::default())
         .invoke_handler(builder.invoke_handler())
         .setup(move |app| {
             builder.mount_events(app);
 
+            if let Ok(journal_dir) = get_pipeline_journal_dir(app.handle()) {
+                app.state::<PipelineCommandSender>()
+                    .skip_saved_ids(&pipeline::journal::load_all(&journal_dir));
+            }
+
+            let launch_handle = app.handle().clone();
+            let events_handle = app.handle().clone();
+            app.manage(Arc::new(pipeline::JobQueue::new(
+                pipeline::QueueConfig::default(),
+                move |job_id, launch| {
+                    tauri::async_runtime::spawn(run_queued_pipeline(
+                        launch_handle.clone(),
+                        job_id,
+                        launch,
+                    ));
+                },
+                move |snapshot| {
+                    let _ = snapshot.emit(&events_handle);
+                },
+            )));
+
             if let Some(lib_path) = binary::get_ffmpeg_library_path(app.handle()) {
                 println!("[tubetape] FFmpeg library found: {:?}", lib_path);
                 if let Some(lib_dir) = lib_path.parent() {
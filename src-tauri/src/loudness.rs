@@ -0,0 +1,302 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::analysis::{AnalysisSink, PcmBlock, StreamFormat};
+use crate::audio;
+
+/// Gating block length (BS.1770: 400 ms)
+const BLOCK_SECS: f64 = 0.4;
+/// Gating blocks overlap by 75%, so a new one starts every 100 ms
+const STEPS_PER_BLOCK: usize = 4;
+/// Blocks quieter than this are ignored, and the lowest loudness reported
+pub const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+/// Blocks this far below the ungated loudness are ignored
+const RELATIVE_GATE_LU: f64 = -10.0;
+/// Surround channel weight for 5.1 input (BS.1770 table 3)
+const SURROUND_WEIGHT: f64 = 1.41;
+
+/// Loudness measured per ITU-R BS.1770-4
+#[derive(Clone, Debug, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct LoudnessInfo {
+    /// Gated integrated loudness in LUFS, floored at `ABSOLUTE_GATE_LUFS`
+    pub integrated_lufs: f64,
+    /// Highest absolute sample value in dBFS
+    pub sample_peak_dbfs: f32,
+}
+
+fn lufs(mean_square: f64) -> f64 {
+    if mean_square > 0.0 {
+        -0.691 + 10.0 * mean_square.log10()
+    } else {
+        f64::NEG_INFINITY
+    }
+}
+
+/// Two-stage K-weighting filter (high shelf then high pass) for one channel
+#[derive(Clone, Copy)]
+struct KWeighting {
+    stages: [Stage; 2],
+}
+
+/// Second-order section in transposed direct form II, in f64 for the 38 Hz high pass
+#[derive(Clone, Copy)]
+struct Stage {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Stage {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+impl KWeighting {
+    /// Filter coefficients for any sample rate, matching the 48 kHz ones in BS.1770
+    fn new(sample_rate: u32) -> Self {
+        let rate = sample_rate as f64;
+
+        // Stage 1: high shelf modelling the acoustic effect of the head
+        let f0 = 1681.974450955533;
+        let gain_db = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (std::f64::consts::PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Stage {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        };
+
+        // Stage 2: high pass (RLB weighting)
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (std::f64::consts::PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Stage {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        };
+
+        Self {
+            stages: [shelf, high_pass],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        self.stages.iter_mut().fold(x, |x, stage| stage.process(x))
+    }
+}
+
+/// Integrated loudness and sample peak as a sink of the shared decode pass
+pub struct LoudnessSink {
+    filters: Vec<KWeighting>,
+    weights: Vec<f64>,
+    step_frames: usize,
+    /// Weighted sum of squares in the step being filled
+    step_energy: f64,
+    step_filled: usize,
+    /// The last `STEPS_PER_BLOCK` completed steps
+    recent_steps: VecDeque<f64>,
+    /// Mean square of every gating block
+    blocks: Vec<f64>,
+    peak: f32,
+    loudness: Option<LoudnessInfo>,
+}
+
+impl LoudnessSink {
+    pub fn new() -> Self {
+        Self {
+            filters: Vec::new(),
+            weights: Vec::new(),
+            step_frames: 0,
+            step_energy: 0.0,
+            step_filled: 0,
+            recent_steps: VecDeque::with_capacity(STEPS_PER_BLOCK),
+            blocks: Vec::new(),
+            peak: 0.0,
+            loudness: None,
+        }
+    }
+
+    /// The measurement once the decode pass has finished
+    pub fn into_loudness(self) -> Option<LoudnessInfo> {
+        self.loudness
+    }
+
+    fn finish_step(&mut self) {
+        if self.recent_steps.len() == STEPS_PER_BLOCK {
+            self.recent_steps.pop_front();
+        }
+        self.recent_steps.push_back(self.step_energy);
+        self.step_energy = 0.0;
+        self.step_filled = 0;
+
+        if self.recent_steps.len() == STEPS_PER_BLOCK {
+            let block_frames = (self.step_frames * STEPS_PER_BLOCK) as f64;
+            self.blocks
+                .push(self.recent_steps.iter().sum::<f64>() / block_frames);
+        }
+    }
+
+    /// Two-stage gated mean of the block loudnesses
+    fn integrated_lufs(&self) -> f64 {
+        let gated_mean = |threshold: f64| {
+            let gated: Vec<f64> = self
+                .blocks
+                .iter()
+                .copied()
+                .filter(|&block| lufs(block) > threshold)
+                .collect();
+            (!gated.is_empty()).then(|| gated.iter().sum::<f64>() / gated.len() as f64)
+        };
+
+        let Some(ungated) = gated_mean(ABSOLUTE_GATE_LUFS) else {
+            return ABSOLUTE_GATE_LUFS;
+        };
+        let relative_gate = lufs(ungated) + RELATIVE_GATE_LU;
+        gated_mean(relative_gate.max(ABSOLUTE_GATE_LUFS))
+            .map(lufs)
+            .unwrap_or(ABSOLUTE_GATE_LUFS)
+            .max(ABSOLUTE_GATE_LUFS)
+    }
+}
+
+impl Default for LoudnessSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnalysisSink for LoudnessSink {
+    fn start(&mut self, format: &StreamFormat) -> Result<(), String> {
+        let channels = format.channels.max(1);
+        self.filters = vec![KWeighting::new(format.sample_rate); channels];
+        // 5.1 in the usual L R C LFE Ls Rs order: LFE is excluded, surrounds boosted
+        self.weights = if channels == 6 {
+            vec![1.0, 1.0, 1.0, 0.0, SURROUND_WEIGHT, SURROUND_WEIGHT]
+        } else {
+            vec![1.0; channels]
+        };
+        self.step_frames =
+            ((format.sample_rate as f64 * BLOCK_SECS) as usize / STEPS_PER_BLOCK).max(1);
+        Ok(())
+    }
+
+    fn process(&mut self, block: &PcmBlock) -> Result<(), String> {
+        let channels = self.filters.len();
+        if channels == 0 {
+            return Ok(());
+        }
+
+        for frame in block.interleaved.chunks_exact(channels) {
+            for ((filter, weight), &sample) in self.filters.iter_mut().zip(&self.weights).zip(frame)
+            {
+                let filtered = filter.process(sample as f64);
+                self.step_energy += weight * filtered * filtered;
+                self.peak = self.peak.max(sample.abs());
+            }
+
+            self.step_filled += 1;
+            if self.step_filled == self.step_frames {
+                self.finish_step();
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        // A trailing partial step cannot complete a gating block and is dropped
+        self.loudness = Some(LoudnessInfo {
+            integrated_lufs: self.integrated_lufs(),
+            sample_peak_dbfs: audio::linear_to_dbfs(self.peak),
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measure(channels: &[Vec<f32>], sample_rate: u32) -> LoudnessInfo {
+        let frames = channels[0].len();
+        let interleaved: Vec<f32> = (0..frames)
+            .flat_map(|i| channels.iter().map(move |c| c[i]))
+            .collect();
+
+        let mut sink = LoudnessSink::new();
+        sink.start(&StreamFormat {
+            sample_rate,
+            channels: channels.len(),
+            total_frames: frames as u64,
+        })
+        .unwrap();
+        for (i, block) in interleaved.chunks(1024 * channels.len()).enumerate() {
+            sink.process(&PcmBlock {
+                interleaved: block.to_vec(),
+                mono: Vec::new(),
+                offset: (i * 1024) as u64,
+            })
+            .unwrap();
+        }
+        sink.finish().unwrap();
+        sink.into_loudness().unwrap()
+    }
+
+    fn sine(freq: f32, amplitude: f32, sample_rate: u32, secs: f32) -> Vec<f32> {
+        (0..(sample_rate as f32 * secs) as usize)
+            .map(|i| {
+                amplitude
+                    * (2.0 * std::f32::consts::PI * freq * i as f32 / sample_rate as f32).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reference_tone() {
+        // EBU Tech 3341: a stereo 997 Hz sine at -23 dBFS reads -23 LUFS
+        for sample_rate in [44100, 48000] {
+            let tone = sine(997.0, 10f32.powf(-23.0 / 20.0), sample_rate, 5.0);
+            let info = measure(&[tone.clone(), tone], sample_rate);
+            assert!(
+                (info.integrated_lufs + 23.0).abs() < 0.1,
+                "{} Hz: {}",
+                sample_rate,
+                info.integrated_lufs
+            );
+            assert!((info.sample_peak_dbfs + 23.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn test_quiet_passage_is_gated() {
+        // A loud tone followed by a much quieter one: the relative gate drops the quiet part
+        let sample_rate = 48000;
+        let mut tone = sine(997.0, 10f32.powf(-23.0 / 20.0), sample_rate, 5.0);
+        tone.extend(sine(997.0, 10f32.powf(-50.0 / 20.0), sample_rate, 5.0));
+        let info = measure(&[tone.clone(), tone], sample_rate);
+        assert!((info.integrated_lufs + 23.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn test_silence_reads_absolute_gate() {
+        let info = measure(&[vec![0.0; 48000]], 48000);
+        assert_eq!(info.integrated_lufs, ABSOLUTE_GATE_LUFS);
+        assert_eq!(info.sample_peak_dbfs, audio::DBFS_FLOOR);
+    }
+}
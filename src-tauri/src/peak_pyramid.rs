@@ -0,0 +1,496 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::audio;
+use crate::file_stamp::FileStamp;
+
+/// Samples per peak at each pyramid level, finest first; each level is 4x the previous
+pub const LEVEL_SAMPLES_PER_PEAK: [usize; 5] = [32, 128, 512, 2048, 8192];
+/// Number of pyramids kept in memory
+const MAX_CACHED_PYRAMIDS: usize = 4;
+/// Memory the cached pyramids may use together; the newest one is always kept
+const MAX_CACHED_BYTES: usize = 256 * 1024 * 1024;
+/// Most columns a single range request may ask for
+pub const MAX_RANGE_PIXELS: u32 = 32768;
+
+/// Peaks for one view of the waveform at the resolution the view needs
+#[derive(Clone, Debug, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct WaveformRange {
+    pub start_time: f64,
+    pub end_time: f64,
+    /// Source samples covered by each entry; 1.0 means the entries are raw samples
+    pub samples_per_peak: f64,
+    /// Signed minimum per entry (full scale = 1.0)
+    pub min_peaks: Vec<f32>,
+    /// Signed maximum per entry (full scale = 1.0)
+    pub max_peaks: Vec<f32>,
+    /// RMS level per entry
+    pub rms_peaks: Vec<f32>,
+}
+
+/// Min/max/RMS summaries at a single resolution
+#[derive(Clone, Debug, Default)]
+struct PyramidLevel {
+    samples_per_peak: usize,
+    /// Number of source frames covered by the level
+    frames: usize,
+    min: Vec<f32>,
+    max: Vec<f32>,
+    rms: Vec<f32>,
+}
+
+impl PyramidLevel {
+    fn with_capacity(samples_per_peak: usize, capacity: usize) -> Self {
+        Self {
+            samples_per_peak,
+            frames: 0,
+            min: Vec::with_capacity(capacity),
+            max: Vec::with_capacity(capacity),
+            rms: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// A level with one entry per sample, used below the finest pyramid level
+    fn from_samples(samples: &[f32]) -> Self {
+        Self {
+            samples_per_peak: 1,
+            frames: samples.len(),
+            min: samples.to_vec(),
+            max: samples.to_vec(),
+            rms: samples.iter().map(|s| s.abs()).collect(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.min.len()
+    }
+
+    fn size_bytes(&self) -> usize {
+        (self.min.len() + self.max.len() + self.rms.len()) * std::mem::size_of::<f32>()
+    }
+
+    /// Frames covered by entry `index` (only the last entry can be short)
+    fn entry_frames(&self, index: usize) -> usize {
+        (self.frames - index * self.samples_per_peak).min(self.samples_per_peak)
+    }
+
+    /// Merge groups of `factor` entries into a coarser level
+    fn downsample(&self, factor: usize) -> Self {
+        let mut level =
+            Self::with_capacity(self.samples_per_peak * factor, self.len().div_ceil(factor));
+        level.frames = self.frames;
+
+        for start in (0..self.len()).step_by(factor) {
+            let end = (start + factor).min(self.len());
+            let (min, max, rms) = self.combine(start, end);
+            level.min.push(min);
+            level.max.push(max);
+            level.rms.push(rms);
+        }
+        level
+    }
+
+    /// Min, max and frame-weighted RMS over entries `[start, end)`
+    fn combine(&self, start: usize, end: usize) -> (f32, f32, f32) {
+        let mut min = f32::INFINITY;
+        let mut max = f32::NEG_INFINITY;
+        let mut sum_sq = 0.0f64;
+        let mut frames = 0usize;
+
+        for i in start..end {
+            min = min.min(self.min[i]);
+            max = max.max(self.max[i]);
+            let n = self.entry_frames(i);
+            sum_sq += (self.rms[i] as f64).powi(2) * n as f64;
+            frames += n;
+        }
+
+        let rms = if frames > 0 {
+            (sum_sq / frames as f64).sqrt() as f32
+        } else {
+            0.0
+        };
+        (min, max, rms)
+    }
+
+    /// Resample into `buckets` entries of `bucket_frames` frames starting at `first_frame`.
+    ///
+    /// Stops early at the end of the level, so the result may be shorter than `buckets`.
+    fn resample(&self, first_frame: f64, bucket_frames: f64, buckets: usize) -> WaveformPeaks {
+        let mut peaks = WaveformPeaks::default();
+        let spp = self.samples_per_peak as f64;
+
+        for bucket in 0..buckets {
+            let start_frame = first_frame + bucket as f64 * bucket_frames;
+            let start = (start_frame / spp).floor() as usize;
+            if start >= self.len() {
+                break;
+            }
+            let end = (((start_frame + bucket_frames) / spp).ceil() as usize)
+                .clamp(start + 1, self.len());
+
+            let (min, max, rms) = self.combine(start, end);
+            peaks.min.push(min);
+            peaks.max.push(max);
+            peaks.rms.push(rms);
+        }
+        peaks
+    }
+}
+
+#[derive(Default)]
+struct WaveformPeaks {
+    min: Vec<f32>,
+    max: Vec<f32>,
+    rms: Vec<f32>,
+}
+
+/// Mipmapped min/max/RMS peaks for a whole file
+pub struct PeakPyramid {
+    sample_rate: u32,
+    levels: Vec<PyramidLevel>,
+}
+
+impl PeakPyramid {
+    /// Decode a file once and build every pyramid level from it.
+    pub fn build(audio_path: &Path) -> Result<Self, String> {
+        let mut builder = LevelBuilder::new(LEVEL_SAMPLES_PER_PEAK[0]);
+        let sample_rate = audio::stream_mono_samples(audio_path, |samples| {
+            for &sample in samples {
+                builder.push(sample);
+            }
+        })?;
+        Ok(Self::from_finest(builder.finish(), sample_rate))
+    }
+
+    #[cfg(test)]
+    fn from_samples(samples: &[f32], sample_rate: u32) -> Self {
+        let mut builder = LevelBuilder::new(LEVEL_SAMPLES_PER_PEAK[0]);
+        for &sample in samples {
+            builder.push(sample);
+        }
+        Self::from_finest(builder.finish(), sample_rate)
+    }
+
+    fn from_finest(finest: PyramidLevel, sample_rate: u32) -> Self {
+        let mut levels = vec![finest];
+        for pair in LEVEL_SAMPLES_PER_PEAK.windows(2) {
+            let coarser = levels[levels.len() - 1].downsample(pair[1] / pair[0]);
+            levels.push(coarser);
+        }
+        Self {
+            sample_rate,
+            levels,
+        }
+    }
+
+    pub fn duration_secs(&self) -> f64 {
+        self.levels[0].frames as f64 / self.sample_rate as f64
+    }
+
+    /// Memory held by the peaks of every level
+    fn size_bytes(&self) -> usize {
+        self.levels.iter().map(PyramidLevel::size_bytes).sum()
+    }
+
+    /// Coarsest level that still has at least one entry per bucket, if any
+    fn level_for(&self, bucket_frames: f64) -> Option<&PyramidLevel> {
+        self.levels
+            .iter()
+            .rev()
+            .find(|level| level.samples_per_peak as f64 <= bucket_frames)
+    }
+}
+
+/// Folds a stream of samples into the finest pyramid level
+struct LevelBuilder {
+    level: PyramidLevel,
+    block: Vec<f32>,
+}
+
+impl LevelBuilder {
+    fn new(samples_per_peak: usize) -> Self {
+        Self {
+            level: PyramidLevel::with_capacity(samples_per_peak, 0),
+            block: Vec::with_capacity(samples_per_peak),
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        self.block.push(sample);
+        if self.block.len() == self.level.samples_per_peak {
+            self.flush_block();
+        }
+    }
+
+    fn flush_block(&mut self) {
+        if self.block.is_empty() {
+            return;
+        }
+        let (min, max, sum_sq) = self.block.iter().fold(
+            (f32::INFINITY, f32::NEG_INFINITY, 0.0f32),
+            |(min, max, sum_sq), &s| (min.min(s), max.max(s), sum_sq + s * s),
+        );
+        self.level.min.push(min);
+        self.level.max.push(max);
+        self.level
+            .rms
+            .push((sum_sq / self.block.len() as f32).sqrt());
+        self.level.frames += self.block.len();
+        self.block.clear();
+    }
+
+    fn finish(mut self) -> PyramidLevel {
+        self.flush_block();
+        self.level
+    }
+}
+
+/// Return `pixels` peaks covering `[start_time, end_time)`.
+///
+/// Uses the coarsest pyramid level that still resolves one entry per pixel.
+/// Below the finest level the region is decoded directly, and once a pixel
+/// spans less than one sample the raw samples themselves are returned.
+pub fn get_range(
+    audio_path: &Path,
+    pyramid: &PeakPyramid,
+    start_time: f64,
+    end_time: f64,
+    pixels: u32,
+) -> Result<WaveformRange, String> {
+    let start_time = start_time.max(0.0);
+    let end_time = end_time.min(pyramid.duration_secs());
+    if end_time <= start_time || pixels == 0 {
+        return Err("Invalid waveform range".to_string());
+    }
+    if pixels > MAX_RANGE_PIXELS {
+        return Err(format!(
+            "Waveform range can have at most {} pixels, got {}",
+            MAX_RANGE_PIXELS, pixels
+        ));
+    }
+
+    let sample_rate = pyramid.sample_rate as f64;
+    let first_frame = start_time * sample_rate;
+    let bucket_frames = (end_time - start_time) * sample_rate / pixels as f64;
+
+    let (peaks, samples_per_peak) = match pyramid.level_for(bucket_frames) {
+        Some(level) => (
+            level.resample(first_frame, bucket_frames, pixels as usize),
+            bucket_frames,
+        ),
+        None => {
+            let region = audio::decode_region(audio_path, start_time, end_time)?;
+            let level = PyramidLevel::from_samples(&region.to_mono());
+            if bucket_frames <= 1.0 {
+                (level.resample(0.0, 1.0, level.len()), 1.0)
+            } else {
+                (
+                    level.resample(0.0, bucket_frames, pixels as usize),
+                    bucket_frames,
+                )
+            }
+        }
+    };
+
+    Ok(WaveformRange {
+        start_time,
+        end_time,
+        samples_per_peak,
+        min_peaks: peaks.min,
+        max_peaks: peaks.max,
+        rms_peaks: peaks.rms,
+    })
+}
+
+/// Built pyramids keyed by source path, dropped once the file changes
+#[derive(Default)]
+pub struct PeakPyramidCache {
+    inner: Mutex<PyramidCacheInner>,
+}
+
+#[derive(Default)]
+struct PyramidCacheInner {
+    pyramids: HashMap<String, CachedPyramid>,
+    order: VecDeque<String>,
+}
+
+struct CachedPyramid {
+    stamp: FileStamp,
+    pyramid: Arc<PeakPyramid>,
+}
+
+impl PyramidCacheInner {
+    fn get(&self, key: &str, stamp: FileStamp) -> Option<Arc<PeakPyramid>> {
+        self.pyramids
+            .get(key)
+            .filter(|cached| cached.stamp == stamp)
+            .map(|cached| Arc::clone(&cached.pyramid))
+    }
+
+    fn insert(&mut self, key: String, stamp: FileStamp, pyramid: Arc<PeakPyramid>) {
+        self.order.retain(|k| *k != key);
+        self.order.push_back(key.clone());
+        self.pyramids.insert(key, CachedPyramid { stamp, pyramid });
+
+        while self.order.len() > 1
+            && (self.order.len() > MAX_CACHED_PYRAMIDS || self.size_bytes() > MAX_CACHED_BYTES)
+        {
+            if let Some(oldest) = self.order.pop_front() {
+                self.pyramids.remove(&oldest);
+            }
+        }
+    }
+
+    fn size_bytes(&self) -> usize {
+        self.pyramids
+            .values()
+            .map(|cached| cached.pyramid.size_bytes())
+            .sum()
+    }
+}
+
+impl PeakPyramidCache {
+    /// Return the cached pyramid for a file, building it on first use or
+    /// after the file was modified.
+    pub fn get_or_build(&self, audio_path: &Path) -> Result<Arc<PeakPyramid>, String> {
+        let key = audio_path.to_string_lossy().to_string();
+        let stamp = FileStamp::of(audio_path)?;
+
+        if let Some(pyramid) = self
+            .inner
+            .lock()
+            .map_err(|e| e.to_string())?
+            .get(&key, stamp)
+        {
+            return Ok(pyramid);
+        }
+
+        // Build without holding the lock so other files can still be queried
+        let pyramid = Arc::new(PeakPyramid::build(audio_path)?);
+
+        self.inner
+            .lock()
+            .map_err(|e| e.to_string())?
+            .insert(key, stamp, Arc::clone(&pyramid));
+
+        Ok(pyramid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp(len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (i as f32 / len as f32) * 2.0 - 1.0)
+            .collect()
+    }
+
+    #[test]
+    fn test_levels_have_expected_sizes() {
+        let pyramid = PeakPyramid::from_samples(&ramp(100_000), 44100);
+
+        assert_eq!(pyramid.levels.len(), LEVEL_SAMPLES_PER_PEAK.len());
+        for (level, &spp) in pyramid.levels.iter().zip(&LEVEL_SAMPLES_PER_PEAK) {
+            assert_eq!(level.samples_per_peak, spp);
+            assert_eq!(level.len(), 100_000usize.div_ceil(spp));
+            assert_eq!(level.frames, 100_000);
+        }
+    }
+
+    #[test]
+    fn test_downsampled_levels_match_direct_computation() {
+        let samples: Vec<f32> = (0..50_000).map(|i| (i as f32 * 0.013).sin()).collect();
+        let pyramid = PeakPyramid::from_samples(&samples, 44100);
+        let coarse = &pyramid.levels[2];
+
+        for (i, block) in samples.chunks(coarse.samples_per_peak).enumerate() {
+            let min = block.iter().cloned().fold(f32::INFINITY, f32::min);
+            let max = block.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+            let rms = (block.iter().map(|s| s * s).sum::<f32>() / block.len() as f32).sqrt();
+
+            assert_eq!(coarse.min[i], min);
+            assert_eq!(coarse.max[i], max);
+            assert!((coarse.rms[i] - rms).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_level_selection() {
+        let pyramid = PeakPyramid::from_samples(&ramp(1_000_000), 44100);
+
+        assert_eq!(pyramid.level_for(10_000.0).unwrap().samples_per_peak, 8192);
+        assert_eq!(pyramid.level_for(600.0).unwrap().samples_per_peak, 512);
+        assert_eq!(pyramid.level_for(32.0).unwrap().samples_per_peak, 32);
+        assert!(pyramid.level_for(31.9).is_none());
+    }
+
+    #[test]
+    fn test_resample_returns_requested_pixels() {
+        let pyramid = PeakPyramid::from_samples(&ramp(441_000), 44100);
+        let level = pyramid.level_for(441.0).unwrap();
+
+        let peaks = level.resample(0.0, 441.0, 1000);
+        assert_eq!(peaks.min.len(), 1000);
+        // The ramp rises, so every bucket's max is above its min and later buckets are higher
+        assert!(peaks.min.iter().zip(&peaks.max).all(|(lo, hi)| lo <= hi));
+        assert!(peaks.max[999] > peaks.max[0]);
+    }
+
+    #[test]
+    fn test_resample_stops_at_end() {
+        let level = PyramidLevel::from_samples(&[0.1, -0.2, 0.3]);
+        let peaks = level.resample(0.0, 1.0, 10);
+
+        assert_eq!(peaks.min, vec![0.1, -0.2, 0.3]);
+        assert_eq!(peaks.rms, vec![0.1, 0.2, 0.3]);
+    }
+
+    #[test]
+    fn test_range_rejects_too_many_pixels() {
+        let pyramid = PeakPyramid::from_samples(&ramp(44100), 44100);
+        let path = Path::new("unused.wav");
+
+        assert!(get_range(path, &pyramid, 0.0, 1.0, MAX_RANGE_PIXELS + 1).is_err());
+        assert!(get_range(path, &pyramid, 0.0, 1.0, 100).is_ok());
+    }
+
+    #[test]
+    fn test_cache_drops_pyramids_of_changed_files() {
+        let mut inner = PyramidCacheInner::default();
+        let stamp = FileStamp {
+            len: 10,
+            modified: None,
+        };
+        let pyramid = Arc::new(PeakPyramid::from_samples(&ramp(1000), 44100));
+        inner.insert("a".to_string(), stamp, pyramid);
+
+        assert!(inner.get("a", stamp).is_some());
+        let changed = FileStamp { len: 11, ..stamp };
+        assert!(inner.get("a", changed).is_none());
+    }
+
+    #[test]
+    fn test_cache_evicts_oldest_pyramids() {
+        let mut inner = PyramidCacheInner::default();
+        let stamp = FileStamp {
+            len: 0,
+            modified: None,
+        };
+        for i in 0..=MAX_CACHED_PYRAMIDS {
+            let pyramid = Arc::new(PeakPyramid::from_samples(&ramp(1000), 44100));
+            inner.insert(i.to_string(), stamp, pyramid);
+        }
+
+        assert_eq!(inner.pyramids.len(), MAX_CACHED_PYRAMIDS);
+        assert!(inner.get("0", stamp).is_none());
+        assert!(inner.get(&MAX_CACHED_PYRAMIDS.to_string(), stamp).is_some());
+    }
+}
@@ -1,19 +1,25 @@
-use std::collections::HashMap;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use specta::Type;
 use tauri::ipc::Channel;
 use tokio::sync::mpsc;
 
 use crate::audio;
-use crate::beat_detection::{self, BeatInfo};
+use crate::beat_detection::BeatDetectionConfig;
+use crate::cancel::CancellationToken;
 use crate::ffmpeg;
 use crate::WaveformData;
 
-use super::{FFmpegCommand, PipelineCommand, PipelineEvent, StageName, StageProgress};
+use super::journal::Journal;
+use super::retry::{self, RetryPolicy};
+use super::stage::{self, AnalysisRun, ProgressTracker};
+use super::{
+    FFmpegCommand, JobQueue, PipelineCommand, PipelineEvent, SavedPipeline, StageName,
+    StageProgress,
+};
 
 /// Result of the complete pipeline execution
 #[derive(Clone, Debug, Serialize, Type)]
@@ -24,56 +30,113 @@ pub struct PipelineResult {
     pub sample_rate: u32,
 }
 
-/// Internal state of the pipeline state machine
-#[derive(Debug)]
-enum PipelineState {
+/// State of the pipeline state machine, saved so interrupted runs can resume
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum PipelineState {
     /// Initial state - about to request extraction
     Initial,
     /// Waiting for frontend to complete extraction via Pyodide
     AwaitingExtraction,
     /// Extraction done, running FFmpeg commands
     RunningFFmpeg,
-    /// FFmpeg done, processing audio (waveform + beats in parallel)
+    /// FFmpeg done, running the analysis stages
     ProcessingAudio,
     /// All done
     Completed,
     /// Failed
     #[allow(dead_code)]
     Failed,
+    /// Stopped by a `Cancel` command
+    Cancelled,
 }
 
-/// Shared progress state for thread-safe updates during parallel processing
-struct SharedProgress {
-    /// Progress as fixed-point integer (0-10000 = 0.00% - 100.00%)
-    waveform_progress: AtomicU64,
-    beat_progress: AtomicU64,
+/// How long a pipeline waits on the frontend's extraction before giving up on it.
+///
+/// The inactivity timeout restarts with every `DownloadProgress` or
+/// `InitializingProgress`, so it catches a hung worker or a reloaded webview
+/// long before the overall timeout would.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractionTimeouts {
+    /// Longest the whole extraction may take, in seconds
+    pub overall_secs: u64,
+    /// Longest the frontend may go without reporting progress, in seconds
+    pub inactivity_secs: u64,
 }
 
-impl SharedProgress {
-    fn new() -> Self {
+impl Default for ExtractionTimeouts {
+    fn default() -> Self {
         Self {
-            waveform_progress: AtomicU64::new(0),
-            beat_progress: AtomicU64::new(0),
+            overall_secs: 30 * 60,
+            inactivity_secs: 2 * 60,
         }
     }
+}
 
-    fn set_waveform(&self, percent: f64) {
-        let value = (percent * 100.0).clamp(0.0, 10000.0) as u64;
-        self.waveform_progress.store(value, Ordering::Relaxed);
+impl ExtractionTimeouts {
+    /// When waiting must stop given when extraction started and when the
+    /// frontend last reported progress, and the error to fail with then.
+    ///
+    /// A timeout too long to represent as an instant never fires, so `None`
+    /// means waiting without a deadline.
+    fn deadline(
+        &self,
+        started: tokio::time::Instant,
+        last_activity: tokio::time::Instant,
+    ) -> Option<(tokio::time::Instant, String)> {
+        let overall = started
+            .checked_add(Duration::from_secs(self.overall_secs))
+            .map(|at| {
+                let message = format!("Extraction timed out after {}s", self.overall_secs);
+                (at, message)
+            });
+        let idle = last_activity
+            .checked_add(Duration::from_secs(self.inactivity_secs))
+            .map(|at| {
+                let message = format!(
+                    "Extraction stopped responding: no progress for {}s",
+                    self.inactivity_secs
+                );
+                (at, message)
+            });
+        match (idle, overall) {
+            (Some(idle), Some(overall)) => Some(if idle.0 < overall.0 { idle } else { overall }),
+            (idle, overall) => idle.or(overall),
+        }
     }
+}
 
-    fn set_beat(&self, percent: f64) {
-        let value = (percent * 100.0).clamp(0.0, 10000.0) as u64;
-        self.beat_progress.store(value, Ordering::Relaxed);
-    }
+/// Per-run settings for which analyses a pipeline runs and how it handles
+/// failures and stalls
+#[derive(Clone, Debug, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PipelineConfig {
+    pub retry_policy: RetryPolicy,
+    pub extraction_timeouts: ExtractionTimeouts,
+    /// Analysis stages to run once the audio is converted; the waveform always runs
+    pub analysis_stages: Vec<StageName>,
+}
 
-    fn get_waveform(&self) -> f64 {
-        self.waveform_progress.load(Ordering::Relaxed) as f64 / 100.0
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            retry_policy: RetryPolicy::default(),
+            extraction_timeouts: ExtractionTimeouts::default(),
+            analysis_stages: StageName::default_analysis(),
+        }
     }
+}
 
-    fn get_beat(&self) -> f64 {
-        self.beat_progress.load(Ordering::Relaxed) as f64 / 100.0
-    }
+/// Why a stage stopped, before the failure is reported so it can still be retried
+enum StageError {
+    /// Stopped by a `Cancel` command, already reported with a `Cancelled` event
+    Cancelled(String),
+    Failed {
+        stage: StageName,
+        message: String,
+        recoverable: bool,
+    },
 }
 
 /// Pipeline executor that orchestrates the entire fetch-convert-process flow.
@@ -82,90 +145,295 @@ impl SharedProgress {
 /// 1. Requests extraction from frontend (which runs Pyodide/yt-dlp)
 /// 2. Receives progress updates and completion signals
 /// 3. Runs FFmpeg commands for audio conversion
-/// 4. Runs waveform generation and beat detection in parallel
-/// 5. Reports unified progress throughout
+/// 4. Runs the configured analysis stages, sharing decode passes between them
+/// 5. Reports unified progress throughout, weighted over the stages it has
+///
+/// A `Cancel` command or the executor's cancellation token stops it at the
+/// next check, removing whatever the interrupted stage had half-written.
 pub struct PipelineExecutor {
+    pipeline_id: String,
     url: String,
     output_path: PathBuf,
     event_channel: Channel<PipelineEvent>,
     command_rx: mpsc::Receiver<PipelineCommand>,
+    waveform_options: audio::WaveformOptions,
+    beat_config: BeatDetectionConfig,
+    cancel: CancellationToken,
+    /// Fired once an event fails to reach the frontend, which then can no
+    /// longer answer `RequestExtraction`
+    channel_lost: CancellationToken,
+    /// Queue this run was started by, whose analysis slots it must wait for
+    queue: Option<Arc<JobQueue>>,
+    /// Whether the run also waits for a download slot, not being launched by `queue`
+    awaits_download_slot: bool,
+    /// On-disk record of this run's progress, for resuming after a restart
+    journal: Option<Journal>,
+    /// Extraction results of the run this one resumes, if it got that far
+    resumed: Option<(String, Vec<FFmpegCommand>)>,
+    /// ID of the run this one resumes, whose record this run's replaces
+    resumed_from: Option<String>,
+    retry_policy: RetryPolicy,
+    extraction_timeouts: ExtractionTimeouts,
+    analysis_stages: Vec<StageName>,
     state: PipelineState,
-    stage_progress: HashMap<StageName, f64>,
+    /// Set up for the run's stages once it starts
+    progress: Arc<ProgressTracker>,
 }
 
 impl PipelineExecutor {
     /// Create a new pipeline executor.
     ///
     /// # Arguments
+    /// * `pipeline_id` - ID the frontend uses to address this run
     /// * `url` - YouTube URL to extract audio from
     /// * `output_path` - Path where the final audio file should be saved
     /// * `event_channel` - Channel to send events to frontend
     /// * `command_rx` - Channel to receive commands from frontend
+    /// * `waveform_options` - Channel layout for the waveform stage
+    /// * `beat_config` - Onset method and thresholds for beat detection
     pub fn new(
+        pipeline_id: String,
         url: String,
         output_path: PathBuf,
         event_channel: Channel<PipelineEvent>,
         command_rx: mpsc::Receiver<PipelineCommand>,
+        waveform_options: audio::WaveformOptions,
+        beat_config: BeatDetectionConfig,
     ) -> Self {
         Self {
+            pipeline_id,
             url,
             output_path,
             event_channel,
             command_rx,
+            waveform_options,
+            beat_config,
+            cancel: CancellationToken::new(),
+            channel_lost: CancellationToken::new(),
+            queue: None,
+            awaits_download_slot: false,
+            journal: None,
+            resumed: None,
+            resumed_from: None,
+            retry_policy: RetryPolicy::default(),
+            extraction_timeouts: ExtractionTimeouts::default(),
+            analysis_stages: StageName::default_analysis(),
             state: PipelineState::Initial,
-            stage_progress: HashMap::new(),
+            progress: Arc::default(),
         }
     }
 
+    /// Token that cancels this pipeline from outside, e.g. while a stage is running
+    pub fn cancel_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Run as a job of `queue`, waiting for one of its analysis slots before
+    /// processing the audio
+    pub fn in_queue(mut self, queue: Arc<JobQueue>) -> Self {
+        self.queue = Some(queue);
+        self
+    }
+
+    /// Run under `queue`'s limits as a job added with `JobQueue::add_direct`,
+    /// waiting for a download slot once started and an analysis slot after
+    pub fn through_queue(mut self, queue: Arc<JobQueue>) -> Self {
+        self.queue = Some(queue);
+        self.awaits_download_slot = true;
+        self
+    }
+
+    /// Run the analyses and retry failures and time out extraction by `config`
+    /// instead of the defaults
+    pub fn with_config(mut self, config: PipelineConfig) -> Self {
+        self.retry_policy = config.retry_policy;
+        self.extraction_timeouts = config.extraction_timeouts;
+        self.analysis_stages = config.analysis_stages;
+        self
+    }
+
+    /// Continue a run saved by `persist_to` with the config it was started
+    /// with, skipping extraction when it had completed. An interrupted
+    /// download is requested again and picks up where it stopped, since
+    /// `download_to_file` resumes partial files.
+    pub fn resume(
+        pipeline_id: String,
+        saved: SavedPipeline,
+        event_channel: Channel<PipelineEvent>,
+        command_rx: mpsc::Receiver<PipelineCommand>,
+    ) -> Self {
+        let mut executor = Self::new(
+            pipeline_id,
+            saved.url.clone(),
+            PathBuf::from(&saved.output_path),
+            event_channel,
+            command_rx,
+            saved.waveform_options.clone(),
+            saved.beat_config.clone(),
+        );
+        executor.resumed = saved.extracted();
+        executor.resumed_from = Some(saved.pipeline_id);
+        executor.with_config(saved.config)
+    }
+
+    /// Save this run's state to `dir` as it progresses, so it can be resumed
+    /// if the app quits before it finishes
+    pub fn persist_to(mut self, dir: PathBuf) -> Self {
+        let (audio_path, ffmpeg_commands) = self.resumed.clone().unzip();
+        let saved = SavedPipeline {
+            pipeline_id: self.pipeline_id.clone(),
+            url: self.url.clone(),
+            output_path: self.output_path.to_string_lossy().to_string(),
+            state: self.state,
+            partial_path: None,
+            bytes_received: 0,
+            total_bytes: None,
+            audio_path,
+            ffmpeg_commands: ffmpeg_commands.unwrap_or_default(),
+            waveform_options: self.waveform_options.clone(),
+            beat_config: self.beat_config.clone(),
+            config: PipelineConfig {
+                retry_policy: self.retry_policy.clone(),
+                extraction_timeouts: self.extraction_timeouts,
+                analysis_stages: self.analysis_stages.clone(),
+            },
+        };
+        self.journal = Some(Journal::new(dir, saved, self.resumed_from.clone()));
+        self
+    }
+
     /// Run the pipeline to completion.
     ///
     /// This is the main entry point that drives the state machine through all stages.
     pub async fn run(mut self) -> Result<PipelineResult, String> {
+        let result = self.run_stages().await;
+
+        // Finished, failed or cancelled, there is nothing left to resume
+        if let Some(journal) = self.journal.take() {
+            journal.finish();
+        }
+
+        result
+    }
+
+    async fn run_stages(&mut self) -> Result<PipelineResult, String> {
+        let progress = ProgressTracker::for_stages(
+            StageName::fetch(),
+            &self.analysis_stages,
+            &self.waveform_options,
+            &self.beat_config,
+        );
+        self.progress = match progress {
+            Ok(progress) => Arc::new(progress),
+            Err(e) => return Err(self.fail(StageName::Initializing, e, false)),
+        };
+
         // Emit started event
         self.emit(PipelineEvent::Started {
+            pipeline_id: Some(self.pipeline_id.clone()),
             url: self.url.clone(),
             output_path: self.output_path.to_string_lossy().to_string(),
-            stages: StageName::all(),
+            stages: self.progress.stages(),
         });
 
-        // === STAGE: Initializing + Downloading + Converting (via Pyodide) ===
-        self.state = PipelineState::AwaitingExtraction;
-
-        // Request extraction from frontend
-        self.emit_progress(StageName::Initializing, -1.0, "Extracting video info...");
-        self.emit(PipelineEvent::RequestExtraction {
-            url: self.url.clone(),
-            output_path: self.output_path.to_string_lossy().to_string(),
-        });
+        if let Some(queue) = self.queue.clone().filter(|_| self.awaits_download_slot) {
+            let message = "Waiting for download slot...";
+            self.emit_progress(StageName::Initializing, -1.0, message);
+            if let Err(e) = queue.enter_download(&self.pipeline_id, &self.cancel).await {
+                if self.cancel.is_cancelled() {
+                    return Err(self.cancelled(StageName::Initializing, &[]));
+                }
+                return Err(self.fail(StageName::Initializing, e, false));
+            }
+        }
 
-        // Wait for extraction to complete, processing commands as they arrive
-        let (audio_path, ffmpeg_commands) = self.await_extraction().await?;
+        // === STAGE: Initializing + Downloading + Converting (via Pyodide) ===
+        let (audio_path, ffmpeg_commands) = match self.resumed.take() {
+            Some(extracted) => extracted,
+            None => {
+                self.enter(PipelineState::AwaitingExtraction);
+
+                let mut attempt = 1;
+                loop {
+                    // Request extraction from frontend
+                    self.emit_progress(StageName::Initializing, -1.0, "Extracting video info...");
+                    self.emit(PipelineEvent::RequestExtraction {
+                        pipeline_id: self.pipeline_id.clone(),
+                        url: self.url.clone(),
+                        output_path: self.output_path.to_string_lossy().to_string(),
+                    });
+
+                    // Wait for extraction to complete, processing commands as they arrive
+                    match self.await_extraction().await {
+                        Ok(extracted) => break extracted,
+                        Err(e) => self.retry_or_fail(e, &mut attempt).await?,
+                    }
+                }
+            }
+        };
 
         // Mark extraction stages as complete
         self.mark_stage_complete(StageName::Initializing);
         self.mark_stage_complete(StageName::Downloading);
 
         // === STAGE: Converting (FFmpeg) ===
-        self.state = PipelineState::RunningFFmpeg;
+        if let Some(journal) = self.journal.as_mut() {
+            journal.extracted(&audio_path, &ffmpeg_commands);
+        }
+        self.enter(PipelineState::RunningFFmpeg);
 
         if !ffmpeg_commands.is_empty() {
             self.emit_progress(StageName::Converting, -1.0, "Converting audio...");
 
             for cmd in &ffmpeg_commands {
-                self.run_ffmpeg_command(cmd).await?;
+                let mut attempt = 1;
+                loop {
+                    let result = self.run_ffmpeg_command(cmd).await;
+                    if self.cancel.is_cancelled() {
+                        let mut partial = vec![PathBuf::from(&audio_path)];
+                        for cmd in &ffmpeg_commands {
+                            partial.extend(cmd.input_path.iter().map(PathBuf::from));
+                            partial.extend(cmd.output_path.iter().map(PathBuf::from));
+                        }
+                        return Err(self.cancelled(StageName::Converting, &partial));
+                    }
+                    match result {
+                        Ok(()) => break,
+                        // Commands overwrite their output, so a retry starts clean;
+                        // only I/O failures are retried, as bad input fails the same way again
+                        Err(message) => {
+                            let error = StageError::Failed {
+                                stage: StageName::Converting,
+                                recoverable: retry::is_transient(&message),
+                                message,
+                            };
+                            self.retry_or_fail(error, &mut attempt).await?;
+                        }
+                    }
+                }
             }
         }
 
         self.mark_stage_complete(StageName::Converting);
 
-        // === STAGE: Waveform + BeatDetection (parallel) ===
-        self.state = PipelineState::ProcessingAudio;
+        // === STAGE: Analysis (waveform, beats and whatever else is configured) ===
+        self.enter(PipelineState::ProcessingAudio);
+        if self.cancel.is_cancelled() {
+            return Err(self.cancelled(StageName::Waveform, &[]));
+        }
+
+        if let Some(queue) = self.queue.clone() {
+            self.emit_progress(StageName::Waveform, 0.0, "Waiting for analysis slot...");
+            let slot = queue.enter_analysis(&self.pipeline_id, &self.cancel).await;
+            if slot.is_err() {
+                return Err(self.cancelled(StageName::Waveform, &[]));
+            }
+        }
 
-        let audio_path_buf = PathBuf::from(&audio_path);
-        let (waveform_data, _beat_info) = self.run_processing_parallel(&audio_path_buf).await?;
+        let waveform_data = self.run_analysis_stages(&audio_path).await?;
 
         // === COMPLETE ===
-        self.state = PipelineState::Completed;
+        self.enter(PipelineState::Completed);
 
         let result = PipelineResult {
             audio_path,
@@ -173,27 +441,73 @@ impl PipelineExecutor {
             sample_rate: waveform_data.sample_rate,
         };
 
-        self.emit(PipelineEvent::Completed(result.clone()));
+        self.emit(PipelineEvent::Completed {
+            pipeline_id: Some(self.pipeline_id.clone()),
+            result: result.clone(),
+        });
 
         Ok(result)
     }
 
     /// Wait for extraction to complete, processing incoming commands.
-    async fn await_extraction(&mut self) -> Result<(String, Vec<FFmpegCommand>), String> {
+    ///
+    /// Fails with a recoverable error if the frontend goes quiet for longer
+    /// than the inactivity timeout or extraction outlasts the overall timeout.
+    async fn await_extraction(&mut self) -> Result<(String, Vec<FFmpegCommand>), StageError> {
         let mut in_download_phase = false;
+        let started = tokio::time::Instant::now();
+        let mut last_activity = started;
 
         loop {
-            match self.command_rx.recv().await {
+            let deadline = self.extraction_timeouts.deadline(started, last_activity);
+            let (cancel, channel_lost) = (&self.cancel, &self.channel_lost);
+            let commands = &mut self.command_rx;
+            let next_command = async {
+                match &deadline {
+                    Some((at, _)) => tokio::time::timeout_at(*at, commands.recv()).await.ok(),
+                    None => Some(commands.recv().await),
+                }
+            };
+            // The token is raced too, so a cancel lands even if the frontend never answers
+            let received = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => Some(Some(PipelineCommand::Cancel)),
+                // No reply can come once the frontend stops hearing from us
+                _ = channel_lost.cancelled() => Some(None),
+                received = next_command => received,
+            };
+            let Some(command) = received else {
+                let timeout_message = deadline.map(|(_, message)| message).unwrap_or_default();
+                eprintln!("[pipeline] {}: {}", self.pipeline_id, timeout_message);
+                return Err(StageError::Failed {
+                    stage: if in_download_phase {
+                        StageName::Downloading
+                    } else {
+                        StageName::Initializing
+                    },
+                    message: timeout_message,
+                    recoverable: true,
+                });
+            };
+
+            match command {
                 Some(PipelineCommand::DownloadProgress {
                     bytes_downloaded,
                     total_bytes,
+                    partial_path,
                 }) => {
+                    last_activity = tokio::time::Instant::now();
+
                     // First download progress means we've moved from init to download
                     if !in_download_phase {
                         self.mark_stage_complete(StageName::Initializing);
                         in_download_phase = true;
                     }
 
+                    if let Some(journal) = self.journal.as_mut() {
+                        journal.download_progress(bytes_downloaded, total_bytes, partial_path);
+                    }
+
                     let percent = total_bytes
                         .map(|t| (bytes_downloaded as f64 / t as f64) * 100.0)
                         .unwrap_or(-1.0);
@@ -215,6 +529,7 @@ impl PipelineExecutor {
                 }
 
                 Some(PipelineCommand::InitializingProgress { message }) => {
+                    last_activity = tokio::time::Instant::now();
                     self.emit_progress(StageName::Initializing, -1.0, &message);
                 }
 
@@ -226,6 +541,19 @@ impl PipelineExecutor {
                     return Ok((audio_path, ffmpeg_commands));
                 }
 
+                Some(PipelineCommand::Cancel) => {
+                    let stage = if in_download_phase {
+                        StageName::Downloading
+                    } else {
+                        StageName::Initializing
+                    };
+                    // yt-dlp writes to a `.part` file until the download completes
+                    let mut part_path = self.output_path.clone().into_os_string();
+                    part_path.push(".part");
+                    let partial = [self.output_path.clone(), PathBuf::from(part_path)];
+                    return Err(StageError::Cancelled(self.cancelled(stage, &partial)));
+                }
+
                 Some(PipelineCommand::ExtractionFailed { message }) => {
                     // Determine which stage failed based on whether we started downloading
                     let failed_stage = if in_download_phase {
@@ -233,14 +561,24 @@ impl PipelineExecutor {
                     } else {
                         StageName::Initializing
                     };
-                    return Err(self.fail(failed_stage, message));
+                    return Err(StageError::Failed {
+                        stage: failed_stage,
+                        recoverable: retry::is_transient(&message),
+                        message,
+                    });
                 }
 
                 None => {
-                    return Err(self.fail(
-                        StageName::Initializing,
-                        "Command channel closed unexpectedly".to_string(),
-                    ));
+                    let message = if self.channel_lost.is_cancelled() {
+                        "Lost the event channel to the frontend"
+                    } else {
+                        "Command channel closed unexpectedly"
+                    };
+                    return Err(StageError::Failed {
+                        stage: StageName::Initializing,
+                        message: message.to_string(),
+                        recoverable: false,
+                    });
                 }
             }
         }
@@ -250,7 +588,11 @@ impl PipelineExecutor {
     async fn run_ffmpeg_command(&self, cmd: &FFmpegCommand) -> Result<(), String> {
         eprintln!("[pipeline] Running FFmpeg command: {} {:?}", cmd.command, cmd.args);
 
-        let result = ffmpeg::dlopen_ffmpeg_internal(&cmd.command, cmd.args.clone())?;
+        let result = ffmpeg::dlopen_ffmpeg_internal(&cmd.command, cmd.args.clone(), &self.cancel)?;
+
+        if result.was_aborted {
+            return Err("FFmpeg command was aborted".to_string());
+        }
 
         if result.exit_code != 0 {
             return Err(format!(
@@ -263,228 +605,140 @@ impl PipelineExecutor {
         Ok(())
     }
 
-    /// Run waveform generation and beat detection in parallel.
-    async fn run_processing_parallel(
-        &mut self,
-        audio_path: &PathBuf,
-    ) -> Result<(WaveformData, BeatInfo), String> {
-        let progress = Arc::new(SharedProgress::new());
-
-        // Clone what we need for the parallel tasks
-        let waveform_path = audio_path.clone();
-        let beat_path = audio_path.clone();
-        let waveform_channel = self.event_channel.clone();
-        let beat_channel = self.event_channel.clone();
-        let waveform_progress = Arc::clone(&progress);
-        let beat_progress = Arc::clone(&progress);
-
-        // Calculate base progress (sum of completed blocking stages)
-        let base_progress = StageName::Initializing.weight()
-            + StageName::Downloading.weight()
-            + StageName::Converting.weight();
-
-        // Run both stages in parallel using spawn_blocking for CPU-bound work
-        let waveform_handle = tokio::task::spawn_blocking(move || {
-            run_waveform_stage(waveform_path, waveform_channel, waveform_progress, base_progress)
-        });
-
-        let beat_handle = tokio::task::spawn_blocking(move || {
-            run_beat_detection_stage(beat_path, beat_channel, beat_progress, base_progress)
-        });
-
-        // Wait for both to complete
-        let (waveform_result, beat_result) = tokio::join!(waveform_handle, beat_handle);
-
-        // Handle join errors
-        let waveform_data = waveform_result
-            .map_err(|e| format!("Waveform task panicked: {}", e))?
-            .map_err(|e| {
-                let _ = self.event_channel.send(PipelineEvent::Error {
-                    stage: StageName::Waveform,
-                    message: e.clone(),
-                    recoverable: false,
-                });
-                e
-            })?;
-
-        let beat_info = beat_result
-            .map_err(|e| format!("Beat detection task panicked: {}", e))?
-            .map_err(|e| {
-                let _ = self.event_channel.send(PipelineEvent::Error {
-                    stage: StageName::BeatDetection,
-                    message: e.clone(),
-                    recoverable: false,
-                });
-                e
-            })?;
-
-        // Send completion events
-        let _ = self.event_channel.send(PipelineEvent::WaveformComplete {
-            peaks: waveform_data.peaks.clone(),
-            duration_secs: waveform_data.duration_secs,
-            sample_rate: waveform_data.sample_rate,
-        });
-
-        let _ = self
-            .event_channel
-            .send(PipelineEvent::BeatDetectionComplete {
-                bpm: beat_info.bpm,
-                bpm_confidence: beat_info.bpm_confidence,
-                beats: beat_info.beats.clone(),
-                onsets: beat_info.onsets.clone(),
-            });
-
-        self.mark_stage_complete(StageName::Waveform);
-        self.mark_stage_complete(StageName::BeatDetection);
+    /// Run the configured analysis stages over the converted audio.
+    async fn run_analysis_stages(&mut self, audio_path: &str) -> Result<WaveformData, String> {
+        let outcome = stage::run_analysis(AnalysisRun {
+            audio_path: PathBuf::from(audio_path),
+            stages: self.analysis_stages.clone(),
+            waveform_options: self.waveform_options.clone(),
+            beat_config: self.beat_config.clone(),
+            cancel: self.cancel.clone(),
+            pipeline_id: Some(self.pipeline_id.clone()),
+            channel: self.event_channel.clone(),
+            progress: self.progress.clone(),
+            queue: self.queue.clone(),
+        })
+        .await;
+
+        match outcome {
+            Ok(results) => results
+                .waveform
+                .ok_or_else(|| "Waveform was not generated".to_string()),
+            // Stages only write caches once they succeed, so there is nothing to clean up
+            Err(failure) if self.cancel.is_cancelled() => Err(self.cancelled(failure.stage, &[])),
+            Err(failure) => Err(self.fail(failure.stage, failure.message, false)),
+        }
+    }
 
-        Ok((waveform_data, beat_info))
+    /// Move the state machine to `state`, saving it if this run is persisted.
+    fn enter(&mut self, state: PipelineState) {
+        self.state = state;
+        if let Some(journal) = self.journal.as_mut() {
+            journal.enter(state);
+        }
     }
 
     /// Emit a progress event with calculated overall progress.
-    fn emit_progress(&mut self, stage: StageName, stage_percent: f64, message: &str) {
-        // Update stage progress (only if determinate)
-        if stage_percent >= 0.0 {
-            self.stage_progress
-                .insert(stage.clone(), stage_percent.min(100.0));
+    fn emit_progress(&self, stage: StageName, stage_percent: f64, message: &str) {
+        let overall = self.progress.update(&stage, stage_percent);
+        if let Some(queue) = &self.queue {
+            queue.set_progress(&self.pipeline_id, overall);
         }
 
-        let overall = self.calculate_overall_progress();
-
-        let _ = self.event_channel.send(PipelineEvent::Progress(StageProgress {
-            stage,
-            stage_percent,
-            overall_percent: overall,
-            message: message.to_string(),
-        }));
+        self.emit(PipelineEvent::Progress {
+            pipeline_id: Some(self.pipeline_id.clone()),
+            progress: StageProgress {
+                stage,
+                stage_percent,
+                overall_percent: overall,
+                message: message.to_string(),
+            },
+        });
     }
 
-    /// Calculate overall progress based on completed stages and current stage progress.
-    fn calculate_overall_progress(&self) -> f64 {
-        let total_weight: f64 = StageName::all().iter().map(|s| s.weight()).sum();
-        let mut completed_weight = 0.0;
+    /// Mark a stage as 100% complete.
+    fn mark_stage_complete(&self, stage: StageName) {
+        self.progress.update(&stage, 100.0);
+    }
 
-        for (stage, &progress) in &self.stage_progress {
-            // Progress is 0-100, convert to 0-1 and multiply by weight
-            completed_weight += (progress / 100.0) * stage.weight();
+    /// Wait out the retry policy's backoff if `error` may be retried, counting
+    /// the new attempt; otherwise report it and return the message the run ends with.
+    async fn retry_or_fail(&mut self, error: StageError, attempt: &mut u32) -> Result<(), String> {
+        let (stage, message, recoverable) = match error {
+            StageError::Cancelled(message) => return Err(message),
+            StageError::Failed {
+                stage,
+                message,
+                recoverable,
+            } => (stage, message, recoverable),
+        };
+        if !recoverable || !self.retry_policy.allows(&stage, *attempt) {
+            return Err(self.fail(stage, message, recoverable));
         }
 
-        (completed_weight / total_weight) * 100.0
-    }
+        let delay = self.retry_policy.delay(*attempt);
+        *attempt += 1;
+        eprintln!(
+            "[pipeline] {:?} failed, retrying in {:?} (attempt {}): {}",
+            stage, delay, attempt, message
+        );
+        self.emit(PipelineEvent::Retrying {
+            pipeline_id: Some(self.pipeline_id.clone()),
+            stage: stage.clone(),
+            attempt: *attempt,
+            delay_ms: delay.as_millis() as u64,
+        });
 
-    /// Mark a stage as 100% complete.
-    fn mark_stage_complete(&mut self, stage: StageName) {
-        self.stage_progress.insert(stage, 100.0);
+        if !retry::wait(delay, &self.cancel).await {
+            return Err(self.cancelled(stage, &[]));
+        }
+        Ok(())
     }
 
     /// Emit an error event and return the error message.
-    fn fail(&self, stage: StageName, message: String) -> String {
-        let _ = self.event_channel.send(PipelineEvent::Error {
+    fn fail(&self, stage: StageName, message: String, recoverable: bool) -> String {
+        self.emit(PipelineEvent::Error {
+            pipeline_id: Some(self.pipeline_id.clone()),
             stage,
             message: message.clone(),
-            recoverable: false,
+            recoverable,
         });
         message
     }
 
-    /// Emit a pipeline event.
-    fn emit(&self, event: PipelineEvent) {
-        let _ = self.event_channel.send(event);
-    }
-}
+    /// Remove the interrupted stage's partial files and emit a cancelled event.
+    ///
+    /// Only files inside the output directory are removed. Returns the error
+    /// message the run ends with.
+    fn cancelled(&mut self, stage: StageName, partial_files: &[PathBuf]) -> String {
+        self.state = PipelineState::Cancelled;
+
+        let output_dir = self.output_path.parent();
+        for path in partial_files {
+            if output_dir.is_some_and(|dir| path.starts_with(dir)) && path.is_file() {
+                if let Err(e) = std::fs::remove_file(path) {
+                    eprintln!("[pipeline] Failed to remove {:?}: {}", path, e);
+                }
+            }
+        }
 
-/// Run waveform generation stage with progress reporting.
-fn run_waveform_stage(
-    audio_path: PathBuf,
-    channel: Channel<PipelineEvent>,
-    progress: Arc<SharedProgress>,
-    base_progress: f64,
-) -> Result<WaveformData, String> {
-    // Get audio info first to estimate progress
-    let (duration_secs, sample_rate) = audio::get_audio_info(&audio_path)?;
-    let expected_peaks = audio::estimate_peak_count(duration_secs, sample_rate);
-
-    let channel_clone = channel.clone();
-    let progress_clone = Arc::clone(&progress);
-
-    let mut peaks_received = 0usize;
-
-    let waveform = audio::generate_waveform_peaks(&audio_path, move |peaks, offset| {
-        // Send chunk event
-        let _ = channel_clone.send(PipelineEvent::WaveformChunk {
-            peaks: peaks.to_vec(),
-            offset,
+        self.emit(PipelineEvent::Cancelled {
+            pipeline_id: Some(self.pipeline_id.clone()),
+            stage,
         });
+        "Pipeline cancelled".to_string()
+    }
 
-        // Update progress
-        peaks_received += peaks.len();
-        let stage_percent = if expected_peaks > 0 {
-            (peaks_received as f64 / expected_peaks as f64 * 100.0).min(100.0)
-        } else {
-            0.0
-        };
-
-        progress_clone.set_waveform(stage_percent);
-
-        // Calculate overall progress
-        // Waveform and BeatDetection run in parallel, so we need to combine them
-        let waveform_contribution = (stage_percent / 100.0) * StageName::Waveform.weight();
-        let beat_contribution = (progress_clone.get_beat() / 100.0) * StageName::BeatDetection.weight();
-        let total_weight: f64 = StageName::all().iter().map(|s| s.weight()).sum();
-        let overall = ((base_progress + waveform_contribution + beat_contribution) / total_weight) * 100.0;
-
-        // Send progress event
-        let _ = channel.send(PipelineEvent::Progress(StageProgress {
-            stage: StageName::Waveform,
-            stage_percent,
-            overall_percent: overall,
-            message: format!("Generating waveform ({:.0}%)", stage_percent),
-        }));
-    })?;
-
-    // Mark as complete
-    progress.set_waveform(100.0);
-
-    Ok(waveform)
-}
-
-/// Run beat detection stage with progress reporting.
-fn run_beat_detection_stage(
-    audio_path: PathBuf,
-    channel: Channel<PipelineEvent>,
-    progress: Arc<SharedProgress>,
-    base_progress: f64,
-) -> Result<BeatInfo, String> {
-    // Report starting
-    let total_weight: f64 = StageName::all().iter().map(|s| s.weight()).sum();
-    let overall = (base_progress / total_weight) * 100.0;
-
-    let _ = channel.send(PipelineEvent::Progress(StageProgress {
-        stage: StageName::BeatDetection,
-        stage_percent: 0.0,
-        overall_percent: overall,
-        message: "Starting beat detection...".to_string(),
-    }));
-
-    progress.set_beat(10.0);
-
-    // Run beat detection
-    let beat_info = beat_detection::analyze_beats(&audio_path)?;
-
-    // Mark as complete
-    progress.set_beat(100.0);
-
-    let waveform_contribution = (progress.get_waveform() / 100.0) * StageName::Waveform.weight();
-    let beat_contribution = StageName::BeatDetection.weight();
-    let final_overall = ((base_progress + waveform_contribution + beat_contribution) / total_weight) * 100.0;
-
-    let _ = channel.send(PipelineEvent::Progress(StageProgress {
-        stage: StageName::BeatDetection,
-        stage_percent: 100.0,
-        overall_percent: final_overall,
-        message: format!("Beat detection complete: {:.1} BPM", beat_info.bpm),
-    }));
-
-    Ok(beat_info)
+    /// Emit a pipeline event, noting when the frontend can no longer receive it.
+    fn emit(&self, event: PipelineEvent) {
+        if let Err(e) = self.event_channel.send(event) {
+            if !self.channel_lost.is_cancelled() {
+                let id = &self.pipeline_id;
+                eprintln!("[pipeline] {}: Failed to send event: {}", id, e);
+            }
+            self.channel_lost.cancel();
+        }
+    }
 }
 
 // ============================================================================
@@ -497,10 +751,14 @@ impl PipelineExecutor {
     pub fn for_existing_audio(
         audio_path: PathBuf,
         event_channel: Channel<PipelineEvent>,
+        waveform_options: audio::WaveformOptions,
+        beat_config: BeatDetectionConfig,
     ) -> ProcessingOnlyExecutor {
         ProcessingOnlyExecutor {
             audio_path,
             event_channel,
+            waveform_options,
+            beat_config,
         }
     }
 }
@@ -510,83 +768,56 @@ impl PipelineExecutor {
 pub struct ProcessingOnlyExecutor {
     audio_path: PathBuf,
     event_channel: Channel<PipelineEvent>,
+    waveform_options: audio::WaveformOptions,
+    beat_config: BeatDetectionConfig,
 }
 
 impl ProcessingOnlyExecutor {
-    /// Run waveform and beat detection on an existing audio file.
+    /// Run the default analysis stages on an existing audio file.
     pub async fn execute(self) -> Result<PipelineResult, String> {
-        let stages = vec![StageName::Waveform, StageName::BeatDetection];
+        let stages = StageName::default_analysis();
+        let progress = ProgressTracker::for_stages(
+            Vec::new(),
+            &stages,
+            &self.waveform_options,
+            &self.beat_config,
+        )?;
 
         // Send started event
         let _ = self.event_channel.send(PipelineEvent::Started {
+            pipeline_id: None,
             url: String::new(),
             output_path: self.audio_path.to_string_lossy().to_string(),
-            stages: stages.clone(),
-        });
-
-        let progress = Arc::new(SharedProgress::new());
-
-        // Clone what we need for the parallel tasks
-        let waveform_path = self.audio_path.clone();
-        let beat_path = self.audio_path.clone();
-        let waveform_channel = self.event_channel.clone();
-        let beat_channel = self.event_channel.clone();
-        let waveform_progress = Arc::clone(&progress);
-        let beat_progress = Arc::clone(&progress);
-
-        // For processing-only, base progress is 0 (no fetch stages)
-        // But we need to adjust weights to only count waveform + beats
-        let _base_progress = 0.0;
-
-        // Run both stages in parallel
-        let waveform_handle = tokio::task::spawn_blocking(move || {
-            run_waveform_stage_processing_only(waveform_path, waveform_channel, waveform_progress)
+            stages: progress.stages(),
         });
 
-        let beat_handle = tokio::task::spawn_blocking(move || {
-            run_beat_detection_stage_processing_only(beat_path, beat_channel, beat_progress)
-        });
-
-        // Wait for both
-        let (waveform_result, beat_result) = tokio::join!(waveform_handle, beat_handle);
-
-        let waveform_data = waveform_result
-            .map_err(|e| format!("Waveform task panicked: {}", e))?
-            .map_err(|e| {
+        let outcome = stage::run_analysis(AnalysisRun {
+            audio_path: self.audio_path.clone(),
+            stages,
+            waveform_options: self.waveform_options,
+            beat_config: self.beat_config,
+            cancel: CancellationToken::new(),
+            pipeline_id: None,
+            channel: self.event_channel.clone(),
+            progress: Arc::new(progress),
+            queue: None,
+        })
+        .await;
+
+        let waveform_data = match outcome {
+            Ok(results) => results
+                .waveform
+                .ok_or_else(|| "Waveform was not generated".to_string())?,
+            Err(failure) => {
                 let _ = self.event_channel.send(PipelineEvent::Error {
-                    stage: StageName::Waveform,
-                    message: e.clone(),
+                    pipeline_id: None,
+                    stage: failure.stage,
+                    message: failure.message.clone(),
                     recoverable: false,
                 });
-                e
-            })?;
-
-        let beat_info = beat_result
-            .map_err(|e| format!("Beat detection task panicked: {}", e))?
-            .map_err(|e| {
-                let _ = self.event_channel.send(PipelineEvent::Error {
-                    stage: StageName::BeatDetection,
-                    message: e.clone(),
-                    recoverable: false,
-                });
-                e
-            })?;
-
-        // Send completion events
-        let _ = self.event_channel.send(PipelineEvent::WaveformComplete {
-            peaks: waveform_data.peaks.clone(),
-            duration_secs: waveform_data.duration_secs,
-            sample_rate: waveform_data.sample_rate,
-        });
-
-        let _ = self
-            .event_channel
-            .send(PipelineEvent::BeatDetectionComplete {
-                bpm: beat_info.bpm,
-                bpm_confidence: beat_info.bpm_confidence,
-                beats: beat_info.beats,
-                onsets: beat_info.onsets,
-            });
+                return Err(failure.message);
+            }
+        };
 
         let result = PipelineResult {
             audio_path: self.audio_path.to_string_lossy().to_string(),
@@ -594,127 +825,53 @@ impl ProcessingOnlyExecutor {
             sample_rate: waveform_data.sample_rate,
         };
 
-        let _ = self.event_channel.send(PipelineEvent::Completed(result.clone()));
+        let _ = self.event_channel.send(PipelineEvent::Completed {
+            pipeline_id: None,
+            result: result.clone(),
+        });
 
         Ok(result)
     }
 }
 
-/// Waveform stage for processing-only mode (50% weight with beat detection)
-fn run_waveform_stage_processing_only(
-    audio_path: PathBuf,
-    channel: Channel<PipelineEvent>,
-    progress: Arc<SharedProgress>,
-) -> Result<WaveformData, String> {
-    let (duration_secs, sample_rate) = audio::get_audio_info(&audio_path)?;
-    let expected_peaks = audio::estimate_peak_count(duration_secs, sample_rate);
-
-    let channel_clone = channel.clone();
-    let progress_clone = Arc::clone(&progress);
-
-    let mut peaks_received = 0usize;
-
-    let waveform = audio::generate_waveform_peaks(&audio_path, move |peaks, offset| {
-        let _ = channel_clone.send(PipelineEvent::WaveformChunk {
-            peaks: peaks.to_vec(),
-            offset,
-        });
-
-        peaks_received += peaks.len();
-        let stage_percent = if expected_peaks > 0 {
-            (peaks_received as f64 / expected_peaks as f64 * 100.0).min(100.0)
-        } else {
-            0.0
-        };
-
-        progress_clone.set_waveform(stage_percent);
-
-        // For processing-only, overall = (waveform + beat) / 2
-        let overall = (stage_percent + progress_clone.get_beat()) / 2.0;
-
-        let _ = channel.send(PipelineEvent::Progress(StageProgress {
-            stage: StageName::Waveform,
-            stage_percent,
-            overall_percent: overall,
-            message: format!("Generating waveform ({:.0}%)", stage_percent),
-        }));
-    })?;
-
-    progress.set_waveform(100.0);
-    Ok(waveform)
-}
-
-/// Beat detection stage for processing-only mode (50% weight with waveform)
-fn run_beat_detection_stage_processing_only(
-    audio_path: PathBuf,
-    channel: Channel<PipelineEvent>,
-    progress: Arc<SharedProgress>,
-) -> Result<BeatInfo, String> {
-    let overall = progress.get_waveform() / 2.0;
-
-    let _ = channel.send(PipelineEvent::Progress(StageProgress {
-        stage: StageName::BeatDetection,
-        stage_percent: 0.0,
-        overall_percent: overall,
-        message: "Starting beat detection...".to_string(),
-    }));
-
-    progress.set_beat(10.0);
-
-    let beat_info = beat_detection::analyze_beats(&audio_path)?;
-
-    progress.set_beat(100.0);
-
-    let final_overall = (progress.get_waveform() + 100.0) / 2.0;
-
-    let _ = channel.send(PipelineEvent::Progress(StageProgress {
-        stage: StageName::BeatDetection,
-        stage_percent: 100.0,
-        overall_percent: final_overall,
-        message: format!("Beat detection complete: {:.1} BPM", beat_info.bpm),
-    }));
-
-    Ok(beat_info)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn test_shared_progress() {
-        let progress = SharedProgress::new();
-
-        assert_eq!(progress.get_waveform(), 0.0);
-        assert_eq!(progress.get_beat(), 0.0);
-
-        progress.set_waveform(50.0);
-        assert_eq!(progress.get_waveform(), 50.0);
+    fn test_extraction_deadline_is_the_earlier_timeout() {
+        let timeouts = ExtractionTimeouts {
+            overall_secs: 600,
+            inactivity_secs: 60,
+        };
+        let started = tokio::time::Instant::now();
 
-        progress.set_beat(50.0);
-        assert_eq!(progress.get_beat(), 50.0);
+        let (deadline, message) = timeouts.deadline(started, started).unwrap();
+        assert_eq!(deadline, started + Duration::from_secs(60));
+        assert!(message.contains("no progress for 60s"));
 
-        progress.set_waveform(100.0);
-        progress.set_beat(100.0);
-        assert_eq!(progress.get_waveform(), 100.0);
-        assert_eq!(progress.get_beat(), 100.0);
+        // Progress keeps pushing the inactivity deadline back, up to the overall one
+        let last_activity = started + Duration::from_secs(580);
+        let (deadline, message) = timeouts.deadline(started, last_activity).unwrap();
+        assert_eq!(deadline, started + Duration::from_secs(600));
+        assert!(message.contains("timed out after 600s"));
     }
 
     #[test]
-    fn test_progress_clamping() {
-        let progress = SharedProgress::new();
-
-        progress.set_waveform(150.0);
-        assert_eq!(progress.get_waveform(), 100.0);
-
-        progress.set_beat(-10.0);
-        assert_eq!(progress.get_beat(), 0.0);
-    }
+    fn test_unrepresentable_timeout_never_fires() {
+        let started = tokio::time::Instant::now();
+        let timeouts = ExtractionTimeouts {
+            overall_secs: u64::MAX,
+            inactivity_secs: 60,
+        };
+        let (deadline, _) = timeouts.deadline(started, started).unwrap();
+        assert_eq!(deadline, started + Duration::from_secs(60));
 
-    #[test]
-    fn test_stage_weights_sum_to_100() {
-        let total: f64 = StageName::all().iter().map(|s| s.weight()).sum();
-        assert!((total - 100.0).abs() < 0.001, "Stage weights should sum to 100, got {}", total);
+        let timeouts = ExtractionTimeouts {
+            overall_secs: u64::MAX,
+            inactivity_secs: u64::MAX,
+        };
+        assert!(timeouts.deadline(started, started).is_none());
     }
 
     #[test]
@@ -724,6 +881,9 @@ mod tests {
         assert!(StageName::Converting.is_blocking());
         assert!(!StageName::Waveform.is_blocking());
         assert!(!StageName::BeatDetection.is_blocking());
+        assert!(!StageName::KeyDetection.is_blocking());
+        assert!(!StageName::Loudness.is_blocking());
+        assert!(!StageName::DrumBreaks.is_blocking());
     }
 
     #[test]
@@ -746,25 +906,83 @@ mod tests {
 
         // Test RequestExtraction event serialization
         let event = PipelineEvent::RequestExtraction {
+            pipeline_id: "test-1".to_string(),
             url: "https://youtube.com/watch?v=test".to_string(),
             output_path: "/path/to/output.aac".to_string(),
         };
 
         let json = serde_json::to_string(&event).unwrap();
         assert!(json.contains("\"event\":\"requestExtraction\""));
+        assert!(json.contains("\"pipelineId\":\"test-1\""));
         assert!(json.contains("\"outputPath\":\"/path/to/output.aac\""));
         assert!(json.contains("\"url\":\"https://youtube.com/watch?v=test\""));
 
         // Test WaveformComplete event serialization
         let event = PipelineEvent::WaveformComplete {
+            pipeline_id: Some("test-1".to_string()),
             peaks: vec![0.1, 0.2],
+            min_peaks: vec![-0.1, -0.05],
+            max_peaks: vec![0.05, 0.2],
+            rms_peaks: vec![0.04, 0.1],
+            channels: Vec::new(),
+            bands: None,
+            scale: audio::WaveformScale::Normalized,
+            normalization_factor: 0.5,
+            channel_normalization_factor: 1.0,
             duration_secs: 120.5,
             sample_rate: 44100,
         };
 
         let json = serde_json::to_string(&event).unwrap();
         assert!(json.contains("\"event\":\"waveformComplete\""));
+        assert!(json.contains("\"minPeaks\":[-0.1,-0.05]"));
+        assert!(json.contains("\"rmsPeaks\":[0.04,0.1]"));
+        assert!(json.contains("\"bands\":null"));
+        assert!(json.contains("\"scale\":\"normalized\""));
+        assert!(json.contains("\"normalizationFactor\":0.5"));
         assert!(json.contains("\"durationSecs\":120.5"));
         assert!(json.contains("\"sampleRate\":44100"));
+
+        // Test BeatDetectionChunk event serialization
+        let event = PipelineEvent::BeatDetectionChunk {
+            pipeline_id: None,
+            beats: vec![0.5, 1.0],
+            bpm: 120.0,
+        };
+
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(json.contains("\"event\":\"beatDetectionChunk\""));
+        assert!(json.contains("\"beats\":[0.5,1.0]"));
+        assert!(json.contains("\"bpm\":120.0"));
+
+        // Test Cancelled event serialization
+        let event = PipelineEvent::Cancelled {
+            pipeline_id: Some("test-1".to_string()),
+            stage: StageName::Downloading,
+        };
+
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            json,
+            r#"{"event":"cancelled","data":{"pipelineId":"test-1","stage":"downloading"}}"#
+        );
+
+        let event = PipelineEvent::Completed {
+            pipeline_id: Some("test-1".to_string()),
+            result: PipelineResult {
+                audio_path: "/path/to/audio.aac".to_string(),
+                duration_secs: 1.0,
+                sample_rate: 44100,
+            },
+        };
+
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(json.contains(r#""data":{"pipelineId":"test-1","result":{"audioPath""#));
+    }
+
+    #[test]
+    fn test_cancel_command_deserialization() {
+        let command: PipelineCommand = serde_json::from_str(r#"{"command":"cancel"}"#).unwrap();
+        assert!(matches!(command, PipelineCommand::Cancel));
     }
 }
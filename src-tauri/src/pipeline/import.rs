@@ -0,0 +1,299 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Serialize;
+use specta::Type;
+use tauri::ipc::Channel;
+
+use crate::audio;
+use crate::beat_detection::BeatDetectionConfig;
+use crate::cancel::CancellationToken;
+use crate::ffmpeg;
+use crate::ffmpeg_runtime;
+use crate::waveform_cache;
+
+use super::stage::{self, AnalysisRun, ProgressTracker};
+use super::{PipelineEvent, PipelineResult, StageName, StageProgress};
+
+/// Decoders whose audio is stored as FLAC rather than lossy AAC
+const LOSSLESS_CODECS: &[&str] = &["flac", "alac", "wavpack", "ape", "tta", "truehd", "mlp"];
+
+/// A local file that was probed and hashed, ready to be imported
+pub struct LocalSource {
+    path: PathBuf,
+    /// `local-` plus a hash of the file contents
+    pub source_id: String,
+    /// File name without its extension
+    pub title: String,
+    codec_name: String,
+}
+
+impl LocalSource {
+    /// Open `path` with FFmpeg to find its audio stream, then hash its contents.
+    /// Blocks for as long as reading the whole file takes.
+    pub fn probe(path: &Path) -> Result<Self, String> {
+        if !path.is_file() {
+            return Err(format!("File not found: {}", path.display()));
+        }
+
+        let file = ffmpeg_runtime::AudioFile::open(path)
+            .map_err(|e| format!("Failed to probe {}: {}", path.display(), e))?;
+        let hash = waveform_cache::file_hash(path)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            source_id: format!("local-{:016x}", hash),
+            title: path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_else(|| "Untitled".to_string()),
+            codec_name: file.codec_name.clone(),
+        })
+    }
+
+    /// Name of the imported audio in the audio store
+    pub fn stored_file_name(&self) -> String {
+        let (extension, _) = stored_format(&self.codec_name);
+        format!("{}.{}", self.source_id, extension)
+    }
+}
+
+/// Extension and ffmpeg `-c:a` argument for storing audio decoded by `codec_name`.
+///
+/// AAC and MP3 streams are copied as is; lossless audio, which is common for
+/// vinyl rips, is kept lossless as FLAC, and everything else becomes AAC.
+fn stored_format(codec_name: &str) -> (&'static str, &'static str) {
+    match codec_name {
+        "aac" | "aac_fixed" => ("aac", "copy"),
+        "mp3" | "mp3float" => ("mp3", "copy"),
+        name if name.starts_with("pcm_") || LOSSLESS_CODECS.contains(&name) => ("flac", "flac"),
+        _ => ("aac", "aac"),
+    }
+}
+
+/// A local file copied into the audio store and analyzed
+#[derive(Clone, Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedFile {
+    /// Content hash ID, usable like a video ID with `check_cached_audio`
+    pub source_id: String,
+    pub title: String,
+    pub source_path: String,
+    pub result: PipelineResult,
+}
+
+/// Pipeline that imports a local audio or video file instead of fetching one.
+///
+/// The audio stream is copied or transcoded into the audio store, then goes
+/// through the same analysis stages as a YouTube download. When
+/// the store already holds the file's audio, the copy is skipped.
+pub struct ImportExecutor {
+    pipeline_id: String,
+    source: LocalSource,
+    output_path: PathBuf,
+    event_channel: Channel<PipelineEvent>,
+    waveform_options: audio::WaveformOptions,
+    beat_config: BeatDetectionConfig,
+    cancel: CancellationToken,
+}
+
+impl ImportExecutor {
+    pub fn new(
+        pipeline_id: String,
+        source: LocalSource,
+        output_path: PathBuf,
+        event_channel: Channel<PipelineEvent>,
+        waveform_options: audio::WaveformOptions,
+        beat_config: BeatDetectionConfig,
+    ) -> Self {
+        Self {
+            pipeline_id,
+            source,
+            output_path,
+            event_channel,
+            waveform_options,
+            beat_config,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Token that cancels this import from outside
+    pub fn cancel_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Import the file, then analyze the stored copy.
+    pub async fn run(self) -> Result<ImportedFile, String> {
+        let stages = StageName::default_analysis();
+        // An import only converts the file it is given
+        let converting = StageName::fetch()
+            .into_iter()
+            .filter(|(stage, _)| *stage == StageName::Converting)
+            .collect();
+        let progress = ProgressTracker::for_stages(
+            converting,
+            &stages,
+            &self.waveform_options,
+            &self.beat_config,
+        )?;
+
+        let _ = self.event_channel.send(PipelineEvent::Started {
+            pipeline_id: Some(self.pipeline_id.clone()),
+            url: self.source.path.to_string_lossy().to_string(),
+            output_path: self.output_path.to_string_lossy().to_string(),
+            stages: progress.stages(),
+        });
+
+        // === STAGE: Converting ===
+        if self.output_path.exists() {
+            self.emit_converting(&progress, 100.0, "Already imported");
+        } else {
+            self.emit_converting(&progress, -1.0, "Importing audio...");
+            if let Err(e) = self.store_audio().await {
+                if self.cancel.is_cancelled() {
+                    return Err(self.cancelled(StageName::Converting));
+                }
+                return Err(self.fail(StageName::Converting, e));
+            }
+        }
+        progress.update(&StageName::Converting, 100.0);
+
+        // === STAGE: Analysis ===
+        if self.cancel.is_cancelled() {
+            return Err(self.cancelled(StageName::Waveform));
+        }
+
+        let outcome = stage::run_analysis(AnalysisRun {
+            audio_path: self.output_path.clone(),
+            stages,
+            waveform_options: self.waveform_options.clone(),
+            beat_config: self.beat_config.clone(),
+            cancel: self.cancel.clone(),
+            pipeline_id: Some(self.pipeline_id.clone()),
+            channel: self.event_channel.clone(),
+            progress: Arc::new(progress),
+            queue: None,
+        })
+        .await;
+
+        let waveform_data = match outcome {
+            Ok(results) => results
+                .waveform
+                .ok_or_else(|| "Waveform was not generated".to_string())?,
+            // The stored audio is complete, so a cancelled analysis leaves it in place
+            Err(failure) if self.cancel.is_cancelled() => return Err(self.cancelled(failure.stage)),
+            Err(failure) => return Err(self.fail(failure.stage, failure.message)),
+        };
+
+        let result = PipelineResult {
+            audio_path: self.output_path.to_string_lossy().to_string(),
+            duration_secs: waveform_data.duration_secs,
+            sample_rate: waveform_data.sample_rate,
+        };
+
+        let _ = self.event_channel.send(PipelineEvent::Completed {
+            pipeline_id: Some(self.pipeline_id.clone()),
+            result: result.clone(),
+        });
+
+        Ok(ImportedFile {
+            source_id: self.source.source_id,
+            title: self.source.title,
+            source_path: self.source.path.to_string_lossy().to_string(),
+            result,
+        })
+    }
+
+    /// Copy or transcode the source's audio stream into the audio store.
+    ///
+    /// FFmpeg writes to a temporary name that is renamed once complete, so an
+    /// interrupted import is never mistaken for stored audio.
+    async fn store_audio(&self) -> Result<(), String> {
+        let (extension, codec) = stored_format(&self.source.codec_name);
+        let partial_path = self
+            .output_path
+            .with_extension(format!("importing.{}", extension));
+
+        let args = vec![
+            "-i".to_string(),
+            self.source.path.to_string_lossy().to_string(),
+            "-vn".to_string(),
+            "-c:a".to_string(),
+            codec.to_string(),
+            "-y".to_string(),
+            partial_path.to_string_lossy().to_string(),
+        ];
+        let cancel = self.cancel.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            ffmpeg::dlopen_ffmpeg_internal("ffmpeg", args, &cancel)
+        })
+        .await
+        .map_err(|e| format!("Import task panicked: {}", e))??;
+
+        if result.exit_code != 0 {
+            let _ = std::fs::remove_file(&partial_path);
+            return Err(format!(
+                "FFmpeg failed to import audio: {}",
+                result.stderr.lines().last().unwrap_or("unknown error")
+            ));
+        }
+
+        std::fs::rename(&partial_path, &self.output_path)
+            .map_err(|e| format!("Failed to store imported audio: {}", e))
+    }
+
+    /// Emit a progress event for the copy into the audio store.
+    fn emit_converting(&self, progress: &ProgressTracker, stage_percent: f64, message: &str) {
+        let overall = progress.update(&StageName::Converting, stage_percent);
+
+        let _ = self.event_channel.send(PipelineEvent::Progress {
+            pipeline_id: Some(self.pipeline_id.clone()),
+            progress: StageProgress {
+                stage: StageName::Converting,
+                stage_percent,
+                overall_percent: overall,
+                message: message.to_string(),
+            },
+        });
+    }
+
+    /// Emit an error event and return the error message.
+    fn fail(&self, stage: StageName, message: String) -> String {
+        let _ = self.event_channel.send(PipelineEvent::Error {
+            pipeline_id: Some(self.pipeline_id.clone()),
+            stage,
+            message: message.clone(),
+            recoverable: false,
+        });
+        message
+    }
+
+    /// Emit a cancelled event and return the error message the run ends with.
+    fn cancelled(&self, stage: StageName) -> String {
+        let _ = self.event_channel.send(PipelineEvent::Cancelled {
+            pipeline_id: Some(self.pipeline_id.clone()),
+            stage,
+        });
+        "Pipeline cancelled".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stored_format_copies_lossy_streams() {
+        assert_eq!(stored_format("aac"), ("aac", "copy"));
+        assert_eq!(stored_format("mp3float"), ("mp3", "copy"));
+        assert_eq!(stored_format("opus"), ("aac", "aac"));
+        assert_eq!(stored_format("vorbis"), ("aac", "aac"));
+    }
+
+    #[test]
+    fn test_stored_format_keeps_lossless_audio_lossless() {
+        assert_eq!(stored_format("flac"), ("flac", "flac"));
+        assert_eq!(stored_format("pcm_s24le"), ("flac", "flac"));
+        assert_eq!(stored_format("alac"), ("flac", "flac"));
+    }
+}
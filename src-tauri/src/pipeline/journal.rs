@@ -0,0 +1,297 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::audio::WaveformOptions;
+use crate::beat_detection::BeatDetectionConfig;
+
+use super::{FFmpegCommand, PipelineConfig, PipelineState};
+
+/// On-disk record of a running pipeline, rewritten as it moves through its
+/// stages and removed once it finishes, fails or is cancelled. A record that
+/// is still there at startup belongs to a run the app did not get to finish.
+#[derive(Clone, Debug, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedPipeline {
+    pub pipeline_id: String,
+    pub url: String,
+    pub output_path: String,
+    /// Last state the run entered
+    pub state: PipelineState,
+    /// File the audio stream was being downloaded to, once downloading started
+    pub partial_path: Option<String>,
+    pub bytes_received: u64,
+    pub total_bytes: Option<u64>,
+    /// Extracted audio, once extraction completed
+    pub audio_path: Option<String>,
+    /// Conversions still to run on `audio_path`
+    pub ffmpeg_commands: Vec<FFmpegCommand>,
+    pub waveform_options: WaveformOptions,
+    pub beat_config: BeatDetectionConfig,
+    /// Records from before the config was saved resume with the defaults
+    #[serde(default)]
+    pub config: PipelineConfig,
+}
+
+impl SavedPipeline {
+    /// Audio path and remaining FFmpeg commands when extraction already
+    /// completed, so a resumed run can skip it
+    pub fn extracted(&self) -> Option<(String, Vec<FFmpegCommand>)> {
+        let audio_path = self.audio_path.clone()?;
+        match self.state {
+            PipelineState::RunningFFmpeg => Some((audio_path, self.ffmpeg_commands.clone())),
+            PipelineState::ProcessingAudio => Some((audio_path, Vec::new())),
+            _ => None,
+        }
+    }
+}
+
+/// Download progress between two writes of the record while downloading
+const SAVE_EVERY_BYTES: u64 = 1_000_000;
+
+/// Keeps the record of one run up to date as it progresses.
+/// Failing to write is logged but never fails the run.
+pub(super) struct Journal {
+    dir: PathBuf,
+    saved: SavedPipeline,
+    /// `bytes_received` as of the last write
+    written_bytes: u64,
+    /// Record of the run this one resumes, deleted once this run's own
+    /// record is written so the run can always be resumed from one of them
+    replaces: Option<String>,
+}
+
+impl Journal {
+    pub fn new(dir: PathBuf, saved: SavedPipeline, replaces: Option<String>) -> Self {
+        Self {
+            dir,
+            saved,
+            written_bytes: 0,
+            replaces,
+        }
+    }
+
+    /// Record that the run entered `state`
+    pub fn enter(&mut self, state: PipelineState) {
+        self.saved.state = state;
+        if state == PipelineState::ProcessingAudio {
+            self.saved.ffmpeg_commands.clear();
+        }
+        self.write();
+    }
+
+    /// Note download progress, writing it out every `SAVE_EVERY_BYTES`
+    pub fn download_progress(
+        &mut self,
+        bytes_received: u64,
+        total_bytes: Option<u64>,
+        partial_path: Option<String>,
+    ) {
+        let new_path = partial_path.is_some() && partial_path != self.saved.partial_path;
+        if new_path {
+            self.saved.partial_path = partial_path;
+        }
+        self.saved.bytes_received = bytes_received;
+        self.saved.total_bytes = total_bytes;
+
+        if new_path || bytes_received.abs_diff(self.written_bytes) >= SAVE_EVERY_BYTES {
+            self.write();
+        }
+    }
+
+    /// Note the extracted audio and the conversions still to run on it;
+    /// written out by the next `enter`
+    pub fn extracted(&mut self, audio_path: &str, ffmpeg_commands: &[FFmpegCommand]) {
+        self.saved.audio_path = Some(audio_path.to_string());
+        self.saved.ffmpeg_commands = ffmpeg_commands.to_vec();
+    }
+
+    /// Delete the record once the run is over
+    pub fn finish(self) {
+        remove(&self.dir, &self.saved.pipeline_id);
+        if let Some(replaced) = &self.replaces {
+            remove(&self.dir, replaced);
+        }
+    }
+
+    fn write(&mut self) {
+        self.written_bytes = self.saved.bytes_received;
+        match save(&self.dir, &self.saved) {
+            Ok(()) => {
+                if let Some(replaced) = self.replaces.take() {
+                    remove(&self.dir, &replaced);
+                }
+            }
+            Err(e) => eprintln!("[pipeline] {}", e),
+        }
+    }
+}
+
+/// Write `saved` to `dir`, replacing its previous record
+pub fn save(dir: &Path, saved: &SavedPipeline) -> Result<(), String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create pipeline journal directory: {}", e))?;
+    let json = serde_json::to_string(saved)
+        .map_err(|e| format!("Failed to serialize pipeline state: {}", e))?;
+
+    // Write then rename, so a crash mid-write never leaves a truncated record
+    let path = record_path(dir, &saved.pipeline_id)?;
+    let temp_path = path.with_extension("json.tmp");
+    std::fs::write(&temp_path, json)
+        .map_err(|e| format!("Failed to write pipeline state: {}", e))?;
+    std::fs::rename(&temp_path, &path).map_err(|e| format!("Failed to write pipeline state: {}", e))
+}
+
+/// The record of the run with `pipeline_id`
+pub fn load(dir: &Path, pipeline_id: &str) -> Result<SavedPipeline, String> {
+    let json = std::fs::read_to_string(record_path(dir, pipeline_id)?)
+        .map_err(|e| format!("No saved pipeline with ID {}: {}", pipeline_id, e))?;
+    serde_json::from_str(&json).map_err(|e| format!("Failed to parse pipeline state: {}", e))
+}
+
+/// Every record in `dir`, skipping unreadable ones
+pub fn load_all(dir: &Path) -> Vec<SavedPipeline> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut saved = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let record = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()));
+        match record {
+            Ok(record) => saved.push(record),
+            Err(e) => eprintln!("[pipeline] Ignoring unreadable state {:?}: {}", path, e),
+        }
+    }
+    saved
+}
+
+/// Delete the record of the run with `pipeline_id`, if there is one
+pub fn remove(dir: &Path, pipeline_id: &str) {
+    let Ok(path) = record_path(dir, pipeline_id) else {
+        return;
+    };
+    if path.exists() {
+        if let Err(e) = std::fs::remove_file(&path) {
+            eprintln!("[pipeline] Failed to remove state {:?}: {}", path, e);
+        }
+    }
+}
+
+/// Where the record of `pipeline_id` lives. IDs come from the frontend, so
+/// anything but letters, digits, `-` and `_` is rejected to keep it in `dir`.
+fn record_path(dir: &Path, pipeline_id: &str) -> Result<PathBuf, String> {
+    let valid = !pipeline_id.is_empty()
+        && pipeline_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(format!("Invalid pipeline ID {:?}", pipeline_id));
+    }
+    Ok(dir.join(format!("{}.json", pipeline_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved(pipeline_id: &str, state: PipelineState) -> SavedPipeline {
+        SavedPipeline {
+            pipeline_id: pipeline_id.to_string(),
+            url: "https://youtube.com/watch?v=dQw4w9WgXcQ".to_string(),
+            output_path: "/audio/dQw4w9WgXcQ.aac".to_string(),
+            state,
+            partial_path: Some("/audio/dQw4w9WgXcQ_raw.webm".to_string()),
+            bytes_received: 1_000_000,
+            total_bytes: Some(4_000_000),
+            audio_path: Some("/audio/dQw4w9WgXcQ.aac".to_string()),
+            ffmpeg_commands: vec![FFmpegCommand {
+                id: "ffmpeg_1".to_string(),
+                command: "ffmpeg".to_string(),
+                args: vec!["-i".to_string()],
+                input_path: None,
+                output_path: None,
+                status: "queued".to_string(),
+            }],
+            waveform_options: WaveformOptions::default(),
+            beat_config: BeatDetectionConfig::default(),
+            config: PipelineConfig::default(),
+        }
+    }
+
+    #[test]
+    fn test_resume_skips_only_finished_stages() {
+        assert!(saved("a-1", PipelineState::AwaitingExtraction)
+            .extracted()
+            .is_none());
+
+        let (_, commands) = saved("a-1", PipelineState::RunningFFmpeg)
+            .extracted()
+            .unwrap();
+        assert_eq!(commands.len(), 1);
+
+        let (audio_path, commands) = saved("a-1", PipelineState::ProcessingAudio)
+            .extracted()
+            .unwrap();
+        assert_eq!(audio_path, "/audio/dQw4w9WgXcQ.aac");
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn test_save_load_and_remove() {
+        let dir = std::env::temp_dir().join(format!("journal-test-{}", std::process::id()));
+        save(&dir, &saved("a-1", PipelineState::RunningFFmpeg)).unwrap();
+        save(&dir, &saved("b-2", PipelineState::AwaitingExtraction)).unwrap();
+        std::fs::write(dir.join("junk.json"), "{").unwrap();
+
+        let loaded = load(&dir, "a-1").unwrap();
+        assert_eq!(loaded.bytes_received, 1_000_000);
+        assert_eq!(loaded.ffmpeg_commands.len(), 1);
+
+        let mut ids: Vec<_> = load_all(&dir).into_iter().map(|s| s.pipeline_id).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["a-1", "b-2"]);
+
+        remove(&dir, "a-1");
+        assert!(load(&dir, "a-1").is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_ids_that_leave_the_directory_are_rejected() {
+        let dir = Path::new("/journal");
+        assert!(record_path(dir, "dQw4w9WgXcQ-12").is_ok());
+        assert!(record_path(dir, "local-00ff_1").is_ok());
+        for id in ["", "../a-1", "a/b", "a.b", "..", "a-1\\b"] {
+            assert!(record_path(dir, id).is_err(), "{:?} should be rejected", id);
+        }
+    }
+
+    #[test]
+    fn test_replaced_record_is_removed_once_the_new_one_is_written() {
+        let dir = std::env::temp_dir().join(format!("journal-replace-{}", std::process::id()));
+        save(&dir, &saved("a-1", PipelineState::AwaitingExtraction)).unwrap();
+
+        let mut journal = Journal::new(
+            dir.clone(),
+            saved("a-2", PipelineState::Initial),
+            Some("a-1".to_string()),
+        );
+        assert!(load(&dir, "a-1").is_ok());
+
+        journal.enter(PipelineState::AwaitingExtraction);
+        assert!(load(&dir, "a-1").is_err());
+        assert!(load(&dir, "a-2").is_ok());
+
+        journal.finish();
+        assert!(load_all(&dir).is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,11 +1,28 @@
 mod executor;
+mod import;
+pub mod journal;
+mod queue;
+mod retry;
+mod stage;
 
-pub use executor::{PipelineExecutor, PipelineResult};
+pub use executor::{
+    ExtractionTimeouts, PipelineConfig, PipelineExecutor, PipelineResult, PipelineState,
+};
+pub use import::{ImportExecutor, ImportedFile, LocalSource};
+pub use journal::SavedPipeline;
+pub use queue::{EnqueuedBatch, JobLaunch, JobQueue, NewJob, QueueConfig, QueueSnapshot};
+pub use retry::RetryPolicy;
 
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
-/// Names of processing stages in the pipeline with associated weights
+use crate::audio::{BandEnergies, PeakBuffers, WaveformScale};
+use crate::beat_detection::BeatDetectionConfig;
+use crate::drum_breaks::DrumBreakCandidate;
+use crate::key_detection::KeyInfo;
+use crate::loudness::LoudnessInfo;
+
+/// Names of processing stages in the pipeline
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Type)]
 #[serde(rename_all = "camelCase")]
 pub enum StageName {
@@ -19,20 +36,15 @@ pub enum StageName {
     Waveform,
     /// Beat/tempo analysis (indeterminate progress)
     BeatDetection,
+    /// Musical key estimation (chunk progress)
+    KeyDetection,
+    /// Integrated loudness measurement (chunk progress)
+    Loudness,
+    /// Drum-break search over the beat grid (chunk progress)
+    DrumBreaks,
 }
 
 impl StageName {
-    /// Weight of this stage in overall progress calculation (should sum to 100)
-    pub fn weight(&self) -> f64 {
-        match self {
-            StageName::Initializing => 5.0,
-            StageName::Downloading => 40.0,
-            StageName::Converting => 10.0,
-            StageName::Waveform => 25.0,
-            StageName::BeatDetection => 20.0,
-        }
-    }
-
     /// Whether this stage must complete before the next stage starts
     #[allow(dead_code)]
     pub fn is_blocking(&self) -> bool {
@@ -42,16 +54,21 @@ impl StageName {
         )
     }
 
-    /// All stages in execution order
-    pub fn all() -> Vec<StageName> {
+    /// Stages that fetch and convert the audio, in execution order, with their
+    /// weights in overall progress; analysis stages set their own. Progress is
+    /// normalized over the stages a run has, so only the default stages sum to 100.
+    pub fn fetch() -> Vec<(StageName, f64)> {
         vec![
-            StageName::Initializing,
-            StageName::Downloading,
-            StageName::Converting,
-            StageName::Waveform,
-            StageName::BeatDetection,
+            (StageName::Initializing, 5.0),
+            (StageName::Downloading, 40.0),
+            (StageName::Converting, 10.0),
         ]
     }
+
+    /// Analysis stages a run has unless configured otherwise
+    pub fn default_analysis() -> Vec<StageName> {
+        vec![StageName::Waveform, StageName::BeatDetection]
+    }
 }
 
 impl std::fmt::Display for StageName {
@@ -62,6 +79,9 @@ impl std::fmt::Display for StageName {
             StageName::Converting => write!(f, "Converting"),
             StageName::Waveform => write!(f, "Waveform"),
             StageName::BeatDetection => write!(f, "Beat Detection"),
+            StageName::KeyDetection => write!(f, "Key Detection"),
+            StageName::Loudness => write!(f, "Loudness"),
+            StageName::DrumBreaks => write!(f, "Drum Breaks"),
         }
     }
 }
@@ -90,12 +110,18 @@ pub struct FFmpegCommand {
     pub status: String,
 }
 
-/// Unified pipeline event enum for streaming progress and results
+/// Unified pipeline event enum for streaming progress and results.
+///
+/// Every event names the run it belongs to, so one channel can serve several.
 #[derive(Clone, Serialize, Type)]
 #[serde(rename_all = "camelCase", tag = "event", content = "data")]
 pub enum PipelineEvent {
     /// Pipeline has started processing
     Started {
+        /// ID to address this run in `pipeline_notify`; `None` for processing-only
+        /// runs, which take no commands
+        #[serde(rename = "pipelineId")]
+        pipeline_id: Option<String>,
         url: String,
         #[serde(rename = "outputPath")]
         output_path: String,
@@ -103,47 +129,144 @@ pub enum PipelineEvent {
     },
 
     /// Progress update from any stage
-    Progress(StageProgress),
+    Progress {
+        #[serde(rename = "pipelineId")]
+        pipeline_id: Option<String>,
+        progress: StageProgress,
+    },
 
     /// Request frontend to run extraction via Pyodide worker
     /// Pipeline BLOCKS until it receives ExtractionComplete or ExtractionFailed
     RequestExtraction {
+        #[serde(rename = "pipelineId")]
+        pipeline_id: String,
         url: String,
         #[serde(rename = "outputPath")]
         output_path: String,
     },
 
     /// Waveform chunk for progressive rendering
-    WaveformChunk { peaks: Vec<f32>, offset: usize },
+    WaveformChunk {
+        #[serde(rename = "pipelineId")]
+        pipeline_id: Option<String>,
+        peaks: Vec<f32>,
+        #[serde(rename = "minPeaks")]
+        min_peaks: Vec<f32>,
+        #[serde(rename = "maxPeaks")]
+        max_peaks: Vec<f32>,
+        #[serde(rename = "rmsPeaks")]
+        rms_peaks: Vec<f32>,
+        channels: Vec<PeakBuffers>,
+        bands: Option<BandEnergies>,
+        offset: usize,
+    },
 
     /// Waveform generation completed
     WaveformComplete {
+        #[serde(rename = "pipelineId")]
+        pipeline_id: Option<String>,
         peaks: Vec<f32>,
+        #[serde(rename = "minPeaks")]
+        min_peaks: Vec<f32>,
+        #[serde(rename = "maxPeaks")]
+        max_peaks: Vec<f32>,
+        #[serde(rename = "rmsPeaks")]
+        rms_peaks: Vec<f32>,
+        channels: Vec<PeakBuffers>,
+        bands: Option<BandEnergies>,
+        scale: WaveformScale,
+        #[serde(rename = "normalizationFactor")]
+        normalization_factor: f32,
+        #[serde(rename = "channelNormalizationFactor")]
+        channel_normalization_factor: f32,
         #[serde(rename = "durationSecs")]
         duration_secs: f64,
         #[serde(rename = "sampleRate")]
         sample_rate: u32,
     },
 
+    /// Beats found so far, sent while beat detection is still running
+    BeatDetectionChunk {
+        #[serde(rename = "pipelineId")]
+        pipeline_id: Option<String>,
+        /// Beat positions (seconds) detected since the previous chunk
+        beats: Vec<f64>,
+        /// Current tempo estimate
+        bpm: f32,
+    },
+
     /// Beat detection completed
     BeatDetectionComplete {
+        #[serde(rename = "pipelineId")]
+        pipeline_id: Option<String>,
         bpm: f32,
         #[serde(rename = "bpmConfidence")]
         bpm_confidence: f32,
         beats: Vec<f64>,
         onsets: Vec<f64>,
+        /// Configuration the detection ran with
+        config: BeatDetectionConfig,
+    },
+
+    /// Key detection completed
+    KeyDetectionComplete {
+        #[serde(rename = "pipelineId")]
+        pipeline_id: Option<String>,
+        /// `None` when the audio is silent
+        key: Option<KeyInfo>,
+    },
+
+    /// Loudness measurement completed
+    LoudnessComplete {
+        #[serde(rename = "pipelineId")]
+        pipeline_id: Option<String>,
+        /// `None` when the audio is silent
+        loudness: Option<LoudnessInfo>,
+    },
+
+    /// Drum-break search completed
+    DrumBreaksComplete {
+        #[serde(rename = "pipelineId")]
+        pipeline_id: Option<String>,
+        /// Candidates, most likely first
+        breaks: Vec<DrumBreakCandidate>,
     },
 
     /// All stages completed successfully
-    Completed(PipelineResult),
+    Completed {
+        #[serde(rename = "pipelineId")]
+        pipeline_id: Option<String>,
+        result: PipelineResult,
+    },
 
-    /// A stage failed (fail-fast)
+    /// A stage failed with a recoverable error and will run again after `delay_ms`
+    Retrying {
+        #[serde(rename = "pipelineId")]
+        pipeline_id: Option<String>,
+        stage: StageName,
+        /// Number of the attempt about to start, 2 for the first retry
+        attempt: u32,
+        #[serde(rename = "delayMs")]
+        delay_ms: u64,
+    },
+
+    /// A stage failed and will not be retried
     Error {
+        #[serde(rename = "pipelineId")]
+        pipeline_id: Option<String>,
         stage: StageName,
         message: String,
         /// Whether this error might be recoverable with retry
         recoverable: bool,
     },
+
+    /// The pipeline stopped after a `Cancel` command; partial files were removed
+    Cancelled {
+        #[serde(rename = "pipelineId")]
+        pipeline_id: Option<String>,
+        /// Stage that was running when the cancellation took effect
+        stage: StageName,
+    },
 }
 
 /// Commands sent TO the pipeline FROM the frontend/worker
@@ -167,10 +290,16 @@ pub enum PipelineCommand {
         bytes_downloaded: u64,
         #[serde(rename = "totalBytes")]
         total_bytes: Option<u64>,
+        /// File being downloaded to, saved so the download can be resumed
+        #[serde(rename = "partialPath")]
+        partial_path: Option<String>,
     },
 
     /// Initializing progress update (yt-dlp is working but no download yet)
     InitializingProgress { message: String },
+
+    /// Stop the pipeline at the next cancellation check
+    Cancel,
 }
 
 /// Download progress information from http.rs
@@ -180,4 +309,6 @@ pub struct DownloadProgress {
     pub bytes_downloaded: u64,
     pub total_bytes: Option<u64>,
     pub percent: f64,
+    /// File being downloaded to
+    pub output_path: String,
 }
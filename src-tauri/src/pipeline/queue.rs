@@ -0,0 +1,766 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::ipc::Channel;
+use tauri_specta::Event;
+use tokio::sync::Notify;
+
+use crate::audio;
+use crate::beat_detection::BeatDetectionConfig;
+use crate::cancel::CancellationToken;
+
+use super::{PipelineConfig, PipelineEvent, PipelineResult};
+
+/// Finished jobs kept for the frontend to show; older ones are dropped
+const MAX_FINISHED_JOBS: usize = 50;
+
+/// The Pyodide worker runs one extraction at a time. A second download slot
+/// would only park a pipeline behind it in the frontend while its extraction
+/// timeouts run down, so the download limit is capped here.
+const MAX_CONCURRENT_DOWNLOADS: u32 = 1;
+
+/// How many queued pipelines may be in each phase at once
+#[derive(Clone, Debug, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueConfig {
+    /// Pipelines extracting, downloading or converting; at most 1, since
+    /// extractions run one at a time
+    pub max_concurrent_downloads: u32,
+    /// Pipelines running waveform generation and beat detection
+    pub max_concurrent_analysis: u32,
+}
+
+impl QueueConfig {
+    /// This config with the download limit capped to what extraction supports
+    fn clamped(self) -> Self {
+        Self {
+            max_concurrent_downloads: self.max_concurrent_downloads.min(MAX_CONCURRENT_DOWNLOADS),
+            ..self
+        }
+    }
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_downloads: 1,
+            max_concurrent_analysis: 1,
+        }
+    }
+}
+
+/// Where a queued job is in its life
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum JobStatus {
+    /// Waiting for a download slot
+    Queued,
+    /// Held back by the user until resumed
+    Paused,
+    /// Extracting, downloading or converting
+    Downloading,
+    /// Audio is ready; waiting for an analysis slot
+    WaitingForAnalysis,
+    /// Running waveform generation and beat detection
+    Analyzing,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    /// Whether the job's pipeline has started and not yet finished
+    pub fn is_running(self) -> bool {
+        matches!(
+            self,
+            Self::Downloading | Self::WaitingForAnalysis | Self::Analyzing
+        )
+    }
+
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// A job as shown to the frontend
+#[derive(Clone, Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedJob {
+    /// Also the pipeline ID for `pipeline_notify` once the job is running
+    pub id: String,
+    pub url: String,
+    /// Video title, when it was known at queue time
+    pub title: Option<String>,
+    /// Batch the job was queued with, e.g. from a playlist
+    pub batch_id: Option<String>,
+    /// Higher runs first; equal priorities run in the order they were queued
+    pub priority: i32,
+    pub status: JobStatus,
+    /// Overall pipeline progress (0-100)
+    pub progress: f64,
+    /// Why the job failed, once `status` is `Failed`
+    pub error: Option<String>,
+}
+
+/// Aggregate progress of the jobs queued together as one batch
+#[derive(Clone, Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchProgress {
+    pub batch_id: String,
+    pub title: String,
+    pub total: u32,
+    pub completed: u32,
+    /// Jobs that failed or were cancelled
+    pub failed: u32,
+    /// Mean progress of the batch's jobs, counting finished ones as 100
+    pub percent: f64,
+}
+
+/// Queue contents, emitted whenever a job or the config changes
+#[derive(Clone, Debug, Serialize, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueSnapshot {
+    pub jobs: Vec<QueuedJob>,
+    pub batches: Vec<BatchProgress>,
+    pub config: QueueConfig,
+}
+
+/// IDs handed out for a batch of jobs
+#[derive(Clone, Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct EnqueuedBatch {
+    pub batch_id: String,
+    /// In the order the jobs were given
+    pub job_ids: Vec<String>,
+}
+
+/// A job to add to the queue
+pub struct NewJob {
+    /// Also the pipeline ID once the job starts
+    pub id: String,
+    pub title: Option<String>,
+    pub priority: i32,
+    pub launch: JobLaunch,
+}
+
+/// What the launcher needs to start a job's pipeline
+pub struct JobLaunch {
+    pub url: String,
+    pub waveform_options: audio::WaveformOptions,
+    pub beat_config: BeatDetectionConfig,
+    pub config: PipelineConfig,
+    pub on_event: Channel<PipelineEvent>,
+}
+
+/// Scheduling bookkeeping, kept apart from the launch payloads
+#[derive(Debug, Default)]
+struct Jobs {
+    /// In the order they were queued
+    entries: Vec<QueuedJob>,
+    batch_titles: HashMap<String, String>,
+}
+
+impl Jobs {
+    fn get_mut(&mut self, id: &str) -> Result<&mut QueuedJob, String> {
+        self.entries
+            .iter_mut()
+            .find(|job| job.id == id)
+            .ok_or_else(|| format!("No queued job with ID {}", id))
+    }
+
+    fn count(&self, status: JobStatus) -> u32 {
+        self.entries
+            .iter()
+            .filter(|job| job.status == status)
+            .count() as u32
+    }
+
+    /// Highest-priority job in `status`, earliest queued on ties
+    fn best(&self, status: JobStatus) -> Option<&QueuedJob> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|job| job.status == status)
+            .max_by_key(|job| job.priority)
+    }
+
+    /// Move the best queued job to `Downloading` if a download slot is free
+    fn next_download(&mut self, config: &QueueConfig) -> Option<String> {
+        if self.count(JobStatus::Downloading) >= config.max_concurrent_downloads.max(1) {
+            return None;
+        }
+        let id = self.best(JobStatus::Queued)?.id.clone();
+        self.get_mut(&id).ok()?.status = JobStatus::Downloading;
+        Some(id)
+    }
+
+    /// Move `id` to `Analyzing` if a slot is free and no better job is waiting
+    fn start_analysis(&mut self, id: &str, config: &QueueConfig) -> bool {
+        if self.count(JobStatus::Analyzing) >= config.max_concurrent_analysis.max(1) {
+            return false;
+        }
+        let best = self.best(JobStatus::WaitingForAnalysis);
+        if best.map(|job| job.id.as_str()) != Some(id) {
+            return false;
+        }
+        if let Ok(job) = self.get_mut(id) {
+            job.status = JobStatus::Analyzing;
+        }
+        true
+    }
+
+    /// Put `id` ahead of every other job
+    fn bump(&mut self, id: &str) -> Result<(), String> {
+        let top = self
+            .entries
+            .iter()
+            .filter(|job| job.id != id)
+            .map(|job| job.priority)
+            .max();
+        let job = self.get_mut(id)?;
+        if let Some(top) = top {
+            job.priority = job.priority.max(top.saturating_add(1));
+        }
+        Ok(())
+    }
+
+    fn pause(&mut self, id: &str) -> Result<(), String> {
+        let job = self.get_mut(id)?;
+        match job.status {
+            JobStatus::Queued => {
+                job.status = JobStatus::Paused;
+                Ok(())
+            }
+            JobStatus::Paused => Ok(()),
+            _ => Err("Only queued jobs can be paused".to_string()),
+        }
+    }
+
+    fn resume(&mut self, id: &str) -> Result<(), String> {
+        let job = self.get_mut(id)?;
+        if job.status == JobStatus::Paused {
+            job.status = JobStatus::Queued;
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, id: &str) -> Result<(), String> {
+        if self.get_mut(id)?.status.is_running() {
+            return Err("Cancel the running pipeline before removing its job".to_string());
+        }
+        self.entries.retain(|job| job.id != id);
+        self.forget_empty_batches();
+        Ok(())
+    }
+
+    /// Drop the oldest finished jobs past `MAX_FINISHED_JOBS`. Jobs of a batch
+    /// that is still running are kept so its totals stay right.
+    fn prune(&mut self) {
+        let running_batches: HashSet<String> = self
+            .entries
+            .iter()
+            .filter(|job| !job.status.is_finished())
+            .filter_map(|job| job.batch_id.clone())
+            .collect();
+        let finished = self
+            .entries
+            .iter()
+            .filter(|job| job.status.is_finished())
+            .count();
+        let mut excess = finished.saturating_sub(MAX_FINISHED_JOBS);
+
+        self.entries.retain(|job| {
+            let prunable = job.status.is_finished()
+                && !job
+                    .batch_id
+                    .as_ref()
+                    .is_some_and(|batch_id| running_batches.contains(batch_id));
+            if excess > 0 && prunable {
+                excess -= 1;
+                return false;
+            }
+            true
+        });
+        self.forget_empty_batches();
+    }
+
+    fn forget_empty_batches(&mut self) {
+        self.batch_titles.retain(|batch_id, _| {
+            self.entries
+                .iter()
+                .any(|job| job.batch_id.as_ref() == Some(batch_id))
+        });
+    }
+
+    fn batches(&self) -> Vec<BatchProgress> {
+        let mut batches: Vec<BatchProgress> = Vec::new();
+        for job in &self.entries {
+            let Some(batch_id) = &job.batch_id else {
+                continue;
+            };
+            let index = match batches.iter().position(|b| &b.batch_id == batch_id) {
+                Some(index) => index,
+                None => {
+                    batches.push(BatchProgress {
+                        batch_id: batch_id.clone(),
+                        title: self.batch_titles.get(batch_id).cloned().unwrap_or_default(),
+                        total: 0,
+                        completed: 0,
+                        failed: 0,
+                        percent: 0.0,
+                    });
+                    batches.len() - 1
+                }
+            };
+
+            let batch = &mut batches[index];
+            batch.total += 1;
+            match job.status {
+                JobStatus::Completed => batch.completed += 1,
+                JobStatus::Failed | JobStatus::Cancelled => batch.failed += 1,
+                _ => {}
+            }
+            batch.percent += if job.status.is_finished() {
+                100.0
+            } else {
+                job.progress
+            };
+        }
+
+        for batch in &mut batches {
+            batch.percent /= batch.total as f64;
+        }
+        batches
+    }
+}
+
+struct QueueState {
+    config: QueueConfig,
+    jobs: Jobs,
+    launches: HashMap<String, JobLaunch>,
+}
+
+impl QueueState {
+    fn push(&mut self, job: NewJob, batch_id: Option<&str>) {
+        self.push_entry(&job.id, &job.launch.url, job.title, batch_id, job.priority);
+        self.launches.insert(job.id, job.launch);
+    }
+
+    fn push_entry(
+        &mut self,
+        id: &str,
+        url: &str,
+        title: Option<String>,
+        batch_id: Option<&str>,
+        priority: i32,
+    ) {
+        self.jobs.entries.push(QueuedJob {
+            id: id.to_string(),
+            url: url.to_string(),
+            title,
+            batch_id: batch_id.map(str::to_string),
+            priority,
+            status: JobStatus::Queued,
+            progress: 0.0,
+            error: None,
+        });
+    }
+}
+
+type Launcher = Box<dyn Fn(String, JobLaunch) + Send + Sync>;
+type ChangeListener = Box<dyn Fn(QueueSnapshot) + Send + Sync>;
+
+/// Background queue in front of `PipelineExecutor`.
+///
+/// Jobs take a download slot when they start and trade it for an analysis slot
+/// once their audio is ready (see `enter_analysis`), so downloads keep going
+/// while earlier jobs are analysed. Starting a pipeline is left to `launch`,
+/// which must call `finish` when the pipeline ends. Pipelines the frontend
+/// runs directly join with `add_direct` and share the same slots.
+pub struct JobQueue {
+    state: Mutex<QueueState>,
+    /// Woken whenever jobs change, so waits for a slot re-check
+    slots_changed: Notify,
+    launch: Launcher,
+    on_change: ChangeListener,
+}
+
+impl JobQueue {
+    pub fn new(
+        config: QueueConfig,
+        launch: impl Fn(String, JobLaunch) + Send + Sync + 'static,
+        on_change: impl Fn(QueueSnapshot) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                config: config.clamped(),
+                jobs: Jobs::default(),
+                launches: HashMap::new(),
+            }),
+            slots_changed: Notify::new(),
+            launch: Box::new(launch),
+            on_change: Box::new(on_change),
+        }
+    }
+
+    pub fn snapshot(&self) -> QueueSnapshot {
+        let state = self.lock();
+        QueueSnapshot {
+            jobs: state.jobs.entries.clone(),
+            batches: state.jobs.batches(),
+            config: state.config.clone(),
+        }
+    }
+
+    /// Add a job and start it if a slot is free
+    pub fn enqueue(&self, job: NewJob) {
+        self.lock().push(job, None);
+        self.changed();
+    }
+
+    /// Add several jobs whose progress is also reported together under `batch_id`
+    pub fn enqueue_batch(&self, batch_id: &str, title: String, jobs: Vec<NewJob>) {
+        {
+            let mut state = self.lock();
+            state.jobs.batch_titles.insert(batch_id.to_string(), title);
+            for job in jobs {
+                state.push(job, Some(batch_id));
+            }
+        }
+        self.changed();
+    }
+
+    /// Add a job its caller runs itself, ahead of every queued job. The caller
+    /// waits for a download slot with `enter_download` and calls `finish`.
+    pub fn add_direct(&self, id: &str, url: &str) {
+        {
+            let mut state = self.lock();
+            state.push_entry(id, url, None, None, 0);
+            let _ = state.jobs.bump(id);
+        }
+        self.changed();
+    }
+
+    /// Replace the limits, capping downloads to `MAX_CONCURRENT_DOWNLOADS`
+    pub fn set_config(&self, config: QueueConfig) {
+        self.lock().config = config.clamped();
+        self.changed();
+    }
+
+    pub fn bump(&self, id: &str) -> Result<(), String> {
+        self.lock().jobs.bump(id)?;
+        self.changed();
+        Ok(())
+    }
+
+    pub fn pause(&self, id: &str) -> Result<(), String> {
+        self.lock().jobs.pause(id)?;
+        self.changed();
+        Ok(())
+    }
+
+    pub fn resume(&self, id: &str) -> Result<(), String> {
+        self.lock().jobs.resume(id)?;
+        self.changed();
+        Ok(())
+    }
+
+    /// Drop a job that is not running, finished ones included
+    pub fn remove(&self, id: &str) -> Result<(), String> {
+        {
+            let mut state = self.lock();
+            state.jobs.remove(id)?;
+            state.launches.remove(id);
+        }
+        self.changed();
+        Ok(())
+    }
+
+    /// Record `id`'s overall pipeline progress (0-100).
+    ///
+    /// Only whole-percent changes are reported, to keep the event stream light.
+    pub fn set_progress(&self, id: &str, percent: f64) {
+        let moved = match self.lock().jobs.get_mut(id) {
+            Ok(job) => {
+                let percent = percent.clamp(0.0, 100.0);
+                let moved = percent.floor() != job.progress.floor();
+                job.progress = percent;
+                moved
+            }
+            Err(_) => false,
+        };
+        if moved {
+            self.changed();
+        }
+    }
+
+    /// Wait until the queue gives the job added with `add_direct` a download slot.
+    ///
+    /// Fails if `cancel` fires or the job is removed while waiting.
+    pub async fn enter_download(&self, id: &str, cancel: &CancellationToken) -> Result<(), String> {
+        self.wait_until(cancel, |state| {
+            Ok(state.jobs.get_mut(id)?.status == JobStatus::Downloading)
+        })
+        .await
+    }
+
+    /// Give up `id`'s download slot and wait for an analysis slot.
+    ///
+    /// Returns `Err` only if `cancel` fires while waiting.
+    pub async fn enter_analysis(&self, id: &str, cancel: &CancellationToken) -> Result<(), String> {
+        if let Ok(job) = self.lock().jobs.get_mut(id) {
+            job.status = JobStatus::WaitingForAnalysis;
+        }
+        self.changed();
+
+        self.wait_until(cancel, |state| {
+            Ok(state.jobs.start_analysis(id, &state.config))
+        })
+        .await?;
+        self.changed();
+        Ok(())
+    }
+
+    /// Wait until `ready` holds, re-checking it whenever the jobs change
+    async fn wait_until(
+        &self,
+        cancel: &CancellationToken,
+        mut ready: impl FnMut(&mut QueueState) -> Result<bool, String>,
+    ) -> Result<(), String> {
+        loop {
+            // Created before checking so a change in between still wakes us
+            let changed = self.slots_changed.notified();
+            if ready(&mut self.lock())? {
+                return Ok(());
+            }
+            cancel.check()?;
+            tokio::select! {
+                _ = changed => {}
+                _ = cancel.cancelled() => {}
+            }
+        }
+    }
+
+    /// Record how `id`'s pipeline ended and start whatever its slot frees up
+    pub fn finish(&self, id: &str, result: &Result<PipelineResult, String>, cancelled: bool) {
+        {
+            let mut state = self.lock();
+            if let Ok(job) = state.jobs.get_mut(id) {
+                (job.status, job.error) = match result {
+                    Ok(_) => {
+                        job.progress = 100.0;
+                        (JobStatus::Completed, None)
+                    }
+                    Err(_) if cancelled => (JobStatus::Cancelled, None),
+                    Err(e) => (JobStatus::Failed, Some(e.clone())),
+                };
+            }
+            state.jobs.prune();
+        }
+        self.changed();
+    }
+
+    /// Start every job a slot is free for, then report the new state
+    fn changed(&self) {
+        let ready: Vec<(String, JobLaunch)> = {
+            let mut state = self.lock();
+            let state = &mut *state;
+            let mut ready = Vec::new();
+            while let Some(id) = state.jobs.next_download(&state.config) {
+                if let Some(launch) = state.launches.remove(&id) {
+                    ready.push((id, launch));
+                }
+            }
+            ready
+        };
+
+        self.slots_changed.notify_waiters();
+        (self.on_change)(self.snapshot());
+        for (id, launch) in ready {
+            (self.launch)(id, launch);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn jobs(priorities: &[i32]) -> Jobs {
+        Jobs {
+            entries: priorities
+                .iter()
+                .enumerate()
+                .map(|(i, &priority)| QueuedJob {
+                    id: format!("job-{}", i),
+                    url: String::new(),
+                    title: None,
+                    batch_id: None,
+                    priority,
+                    status: JobStatus::Queued,
+                    progress: 0.0,
+                    error: None,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn config(downloads: u32, analysis: u32) -> QueueConfig {
+        QueueConfig {
+            max_concurrent_downloads: downloads,
+            max_concurrent_analysis: analysis,
+        }
+    }
+
+    #[test]
+    fn test_downloads_start_by_priority_up_to_the_limit() {
+        let mut jobs = jobs(&[0, 5, 0, 5]);
+        let config = config(2, 1);
+
+        assert_eq!(jobs.next_download(&config).as_deref(), Some("job-1"));
+        assert_eq!(jobs.next_download(&config).as_deref(), Some("job-3"));
+        assert_eq!(jobs.next_download(&config), None);
+
+        jobs.get_mut("job-1").unwrap().status = JobStatus::Completed;
+        assert_eq!(jobs.next_download(&config).as_deref(), Some("job-0"));
+    }
+
+    #[test]
+    fn test_paused_jobs_are_skipped_until_resumed() {
+        let mut jobs = jobs(&[0, 0]);
+        let config = config(1, 1);
+
+        jobs.pause("job-0").unwrap();
+        assert_eq!(jobs.next_download(&config).as_deref(), Some("job-1"));
+        assert!(jobs.pause("job-1").is_err());
+
+        jobs.get_mut("job-1").unwrap().status = JobStatus::Completed;
+        assert_eq!(jobs.next_download(&config), None);
+        jobs.resume("job-0").unwrap();
+        assert_eq!(jobs.next_download(&config).as_deref(), Some("job-0"));
+    }
+
+    #[test]
+    fn test_bump_moves_job_to_the_front() {
+        let mut jobs = jobs(&[3, 1, 0]);
+        jobs.bump("job-2").unwrap();
+        assert_eq!(jobs.entries[2].priority, 4);
+        assert_eq!(jobs.next_download(&config(1, 1)).as_deref(), Some("job-2"));
+    }
+
+    #[test]
+    fn test_analysis_slot_goes_to_the_best_waiting_job() {
+        let mut jobs = jobs(&[0, 1]);
+        let config = config(2, 1);
+        for job in &mut jobs.entries {
+            job.status = JobStatus::WaitingForAnalysis;
+        }
+
+        assert!(!jobs.start_analysis("job-0", &config));
+        assert!(jobs.start_analysis("job-1", &config));
+        assert!(!jobs.start_analysis("job-0", &config));
+
+        jobs.get_mut("job-1").unwrap().status = JobStatus::Completed;
+        assert!(jobs.start_analysis("job-0", &config));
+    }
+
+    #[test]
+    fn test_running_jobs_cannot_be_removed() {
+        let mut jobs = jobs(&[0, 0]);
+        jobs.next_download(&config(1, 1));
+
+        assert!(jobs.remove("job-0").is_err());
+        jobs.remove("job-1").unwrap();
+        assert_eq!(jobs.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_prune_keeps_recent_and_running_batch_jobs() {
+        let mut jobs = jobs(&vec![0; MAX_FINISHED_JOBS + 3]);
+        for job in &mut jobs.entries {
+            job.status = JobStatus::Completed;
+        }
+        jobs.entries[0].batch_id = Some("batch-1".to_string());
+        jobs.entries[1].batch_id = Some("batch-1".to_string());
+        jobs.entries[1].status = JobStatus::Downloading;
+
+        jobs.prune();
+        assert_eq!(jobs.count(JobStatus::Completed) as usize, MAX_FINISHED_JOBS);
+        let ids: Vec<&str> = jobs.entries.iter().map(|job| job.id.as_str()).collect();
+        assert_eq!(&ids[..3], ["job-0", "job-1", "job-4"]);
+    }
+
+    #[tokio::test]
+    async fn test_direct_job_waits_for_a_download_slot() {
+        let queue = Arc::new(JobQueue::new(config(1, 1), |_, _| {}, |_| {}));
+        queue.add_direct("job-0", "url");
+        queue.add_direct("job-1", "url");
+        let cancel = CancellationToken::new();
+
+        queue.enter_download("job-0", &cancel).await.unwrap();
+        let waiter = tokio::spawn({
+            let (queue, cancel) = (queue.clone(), cancel.clone());
+            async move { queue.enter_download("job-1", &cancel).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        queue.finish("job-0", &Err("failed".to_string()), false);
+        waiter.await.unwrap().unwrap();
+
+        queue.add_direct("job-2", "url");
+        cancel.cancel();
+        assert!(queue.enter_download("job-2", &cancel).await.is_err());
+    }
+
+    #[test]
+    fn test_download_limit_is_capped_to_one() {
+        let queue = JobQueue::new(config(3, 2), |_, _| {}, |_| {});
+        assert_eq!(queue.snapshot().config.max_concurrent_downloads, 1);
+
+        queue.set_config(config(4, 3));
+        let config = queue.snapshot().config;
+        assert_eq!(config.max_concurrent_downloads, 1);
+        assert_eq!(config.max_concurrent_analysis, 3);
+    }
+
+    #[test]
+    fn test_batch_progress_counts_finished_jobs_as_done() {
+        let mut jobs = jobs(&[0, 0, 0, 0]);
+        jobs.batch_titles
+            .insert("batch-1".to_string(), "Breaks".to_string());
+        for (job, (status, progress)) in jobs.entries.iter_mut().zip([
+            (JobStatus::Completed, 100.0),
+            (JobStatus::Failed, 10.0),
+            (JobStatus::Downloading, 40.0),
+        ]) {
+            job.batch_id = Some("batch-1".to_string());
+            job.status = status;
+            job.progress = progress;
+        }
+
+        let batches = jobs.batches();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.title, "Breaks");
+        assert_eq!((batch.total, batch.completed, batch.failed), (3, 1, 1));
+        assert!((batch.percent - 80.0).abs() < 1e-9);
+
+        for id in ["job-0", "job-1"] {
+            jobs.remove(id).unwrap();
+        }
+        jobs.get_mut("job-2").unwrap().status = JobStatus::Cancelled;
+        jobs.remove("job-2").unwrap();
+        assert!(jobs.batches().is_empty());
+        assert!(jobs.batch_titles.is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::cancel::CancellationToken;
+
+use super::StageName;
+
+/// Lower-cased fragments of error messages from network and I/O failures
+/// that may pass on their own
+const TRANSIENT_ERRORS: &[&str] = &[
+    "too many requests",
+    "timed out",
+    "connection reset",
+    "connection refused",
+    "connection aborted",
+    "connection closed",
+    "reset by peer",
+    "broken pipe",
+    "network is unreachable",
+    "temporary failure in name resolution",
+    "temporarily unavailable",
+    "input/output error",
+    "error sending request",
+    "failed to read chunk",
+];
+
+/// Where HTTP status codes appear in error messages, as yt-dlp writes them
+/// ("HTTP Error 503: ...") and as downloads do ("status: 503 Service Unavailable")
+const STATUS_PREFIXES: &[&str] = &["http error ", "status: "];
+
+/// How a pipeline retries stages that failed with a recoverable error
+#[derive(Clone, Debug, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryPolicy {
+    /// Attempts per stage, counting the first; 1 never retries
+    pub max_attempts: u32,
+    /// Wait before the first retry, in milliseconds
+    pub initial_delay_ms: u64,
+    /// Factor the wait grows by with each further retry
+    pub backoff_factor: f64,
+    /// Longest wait between attempts, in milliseconds
+    pub max_delay_ms: u64,
+    /// Stages that may be retried; extraction is retried by requesting it again
+    pub stages: Vec<StageName>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay_ms: 2_000,
+            backoff_factor: 2.0,
+            max_delay_ms: 30_000,
+            stages: vec![
+                StageName::Initializing,
+                StageName::Downloading,
+                StageName::Converting,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether `stage` may run again after failing on attempt number `attempt`
+    pub fn allows(&self, stage: &StageName, attempt: u32) -> bool {
+        attempt < self.max_attempts && self.stages.contains(stage)
+    }
+
+    /// Wait before the attempt after attempt number `attempt`
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = self
+            .backoff_factor
+            .max(1.0)
+            .powi(attempt.saturating_sub(1) as i32);
+        let delay_ms = (self.initial_delay_ms as f64 * factor).min(self.max_delay_ms as f64);
+        Duration::from_millis(delay_ms as u64)
+    }
+}
+
+/// Whether an error message looks like it may pass on a retry: an expired
+/// stream URL, rate limiting, a server error or a network or I/O blip
+pub fn is_transient(message: &str) -> bool {
+    let message = message.to_lowercase();
+    http_statuses(&message).any(|status| matches!(status, 403 | 408 | 429 | 500..=599))
+        || TRANSIENT_ERRORS
+            .iter()
+            .any(|fragment| message.contains(fragment))
+}
+
+/// Three-digit HTTP status codes following any of `STATUS_PREFIXES`
+fn http_statuses(message: &str) -> impl Iterator<Item = u16> + '_ {
+    STATUS_PREFIXES.iter().flat_map(move |prefix| {
+        message.match_indices(prefix).filter_map(move |(i, _)| {
+            let rest = &message[i + prefix.len()..];
+            let code = rest.split(|c: char| !c.is_ascii_digit()).next()?;
+            (code.len() == 3).then(|| code.parse().ok()).flatten()
+        })
+    })
+}
+
+/// Sleep for `delay`, waking early if `cancel` fires. Returns false if cancelled.
+pub async fn wait(delay: Duration, cancel: &CancellationToken) -> bool {
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => false,
+        _ = tokio::time::sleep(delay) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_wakes_when_cancelled() {
+        let cancel = CancellationToken::new();
+        let waiter = tokio::spawn({
+            let cancel = cancel.clone();
+            async move { wait(Duration::from_secs(60), &cancel).await }
+        });
+        tokio::task::yield_now().await;
+        cancel.cancel();
+        assert!(!waiter.await.unwrap());
+
+        assert!(wait(Duration::ZERO, &CancellationToken::new()).await);
+    }
+
+    #[test]
+    fn test_delay_backs_off_up_to_max() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(1), Duration::from_secs(2));
+        assert_eq!(policy.delay(2), Duration::from_secs(4));
+        assert_eq!(policy.delay(3), Duration::from_secs(8));
+        assert_eq!(policy.delay(10), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_allows_only_listed_stages_within_attempts() {
+        let policy = RetryPolicy::default();
+        assert!(policy.allows(&StageName::Downloading, 1));
+        assert!(policy.allows(&StageName::Downloading, 2));
+        assert!(!policy.allows(&StageName::Downloading, 3));
+        assert!(!policy.allows(&StageName::Waveform, 1));
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient("HTTP Error 403: Forbidden"));
+        assert!(is_transient("HTTP Error 503: Service Unavailable"));
+        assert!(is_transient(
+            "Download failed with status: 429 Too Many Requests"
+        ));
+        assert!(is_transient(
+            "Download request failed: error sending request for url (https://example.com/)"
+        ));
+        assert!(is_transient(
+            "Failed to read chunk: connection reset by peer"
+        ));
+        assert!(is_transient("FFmpeg failed: Input/output error"));
+        assert!(!is_transient("Video unavailable. This video is private"));
+    }
+
+    #[test]
+    fn test_permanent_errors_are_not_transient() {
+        assert!(!is_transient("HTTP Error 404: Not Found"));
+        assert!(!is_transient("Download failed with status: 410 Gone"));
+        assert!(!is_transient("HTTP Error 5000"));
+        assert!(!is_transient(
+            "No connection adapters were found for 'ftp://example.com'"
+        ));
+        assert!(!is_transient("Unsupported network protocol"));
+        assert!(!is_transient("Invalid timeout value"));
+        assert!(!is_transient(
+            "This video is temporarily restricted by the uploader"
+        ));
+        assert!(!is_transient(
+            "FFmpeg failed: Invalid data found when processing input"
+        ));
+    }
+}
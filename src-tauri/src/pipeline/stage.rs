@@ -0,0 +1,1048 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use tauri::ipc::Channel;
+
+use crate::analysis::{self, AnalysisSink};
+use crate::analysis_cache;
+use crate::audio::{self, BandEnergies, PeakBuffers, WaveformOptions, WaveformSink};
+use crate::beat_detection::{self, BeatDetectionConfig, BeatInfo, OnsetSink, TempoSink};
+use crate::cancel::CancellationToken;
+use crate::drum_breaks::{DrumBreakOptions, EnergySink};
+use crate::key_detection::KeySink;
+use crate::loudness::LoudnessSink;
+use crate::waveform_cache;
+use crate::WaveformData;
+
+use super::{JobQueue, PipelineEvent, StageName, StageProgress};
+
+/// A step of audio analysis the pipeline can run.
+///
+/// Stages whose dependencies are met together share one decode pass: each
+/// hands out the sinks it wants fed from `sinks`, then `run` finishes it once
+/// the audio has been decoded. Stages that need no decoding, e.g. because
+/// their result was cached, return no sinks.
+///
+/// New stages are added to `ANALYSIS_STAGES`; the executor needs no changes.
+pub trait PipelineStage: Send {
+    fn name(&self) -> StageName;
+
+    /// Share of overall progress, relative to the other stages of the run
+    fn weight(&self) -> f64;
+
+    /// Stages whose results this one reads from `StageContext::results`
+    fn dependencies(&self) -> Vec<StageName> {
+        Vec::new()
+    }
+
+    /// Stages whose results this one reads only in `run`. Unlike
+    /// dependencies they share its decode pass and just finish first.
+    fn finishes_after(&self) -> Vec<StageName> {
+        Vec::new()
+    }
+
+    /// What the stage is doing while the audio decodes, e.g. "Generating waveform"
+    fn activity(&self) -> String;
+
+    /// Prepare to run, returning the sinks to feed from the shared decode pass
+    fn sinks(
+        &mut self,
+        ctx: &StageContext,
+        progress: &StageReporter,
+    ) -> Result<Vec<&mut dyn AnalysisSink>, String>;
+
+    /// Finish the stage given how each of its sinks fared, in order
+    fn run(
+        &mut self,
+        outcomes: Vec<Result<(), String>>,
+        ctx: &mut StageContext,
+        progress: &StageReporter,
+    ) -> Result<(), String>;
+}
+
+/// Results later stages and the executor read; stages report everything
+/// else through their own events
+#[derive(Default)]
+pub struct StageResults {
+    pub waveform: Option<WaveformData>,
+    pub beat_info: Option<BeatInfo>,
+}
+
+/// What stages run on and report to
+pub struct StageContext {
+    pub audio_path: PathBuf,
+    /// Run the stage's events belong to; `None` for processing-only runs
+    pub pipeline_id: Option<String>,
+    pub channel: Channel<PipelineEvent>,
+    pub results: StageResults,
+}
+
+impl StageContext {
+    pub fn emit(&self, event: PipelineEvent) {
+        let _ = self.channel.send(event);
+    }
+}
+
+/// Overall progress of a run, weighted over the stages it actually has
+#[derive(Default)]
+pub struct ProgressTracker {
+    weights: Vec<(StageName, f64)>,
+    percents: Mutex<HashMap<StageName, f64>>,
+}
+
+impl ProgressTracker {
+    pub fn new(weights: Vec<(StageName, f64)>) -> Self {
+        Self {
+            weights,
+            percents: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Progress over the weighted `leading` stages followed by the analysis
+    /// stages `analysis`, failing if those do not form a valid graph
+    pub fn for_stages(
+        leading: Vec<(StageName, f64)>,
+        analysis: &[StageName],
+        waveform_options: &WaveformOptions,
+        beat_config: &BeatDetectionConfig,
+    ) -> Result<Self, String> {
+        let mut weights = leading;
+        weights.extend(analysis_graph(analysis, waveform_options, beat_config)?.weights());
+        Ok(Self::new(weights))
+    }
+
+    /// Stages of the run, in order
+    pub fn stages(&self) -> Vec<StageName> {
+        self.weights
+            .iter()
+            .map(|(stage, _)| stage.clone())
+            .collect()
+    }
+
+    /// Record `stage_percent` for `stage` and return the overall percent.
+    /// Indeterminate progress (negative) leaves the stage where it was.
+    pub fn update(&self, stage: &StageName, stage_percent: f64) -> f64 {
+        if stage_percent >= 0.0 {
+            if let Ok(mut percents) = self.percents.lock() {
+                percents.insert(stage.clone(), stage_percent.min(100.0));
+            }
+        }
+        self.overall()
+    }
+
+    /// Weighted progress over all stages (0-100)
+    pub fn overall(&self) -> f64 {
+        let total: f64 = self.weights.iter().map(|(_, weight)| weight).sum();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        let Ok(percents) = self.percents.lock() else {
+            return 0.0;
+        };
+        let done: f64 = self
+            .weights
+            .iter()
+            .map(|(stage, weight)| percents.get(stage).copied().unwrap_or(0.0) / 100.0 * weight)
+            .sum();
+        done / total * 100.0
+    }
+}
+
+/// Reports one stage's progress into the run's overall progress
+pub struct StageReporter<'r> {
+    stage: StageName,
+    tracker: &'r ProgressTracker,
+    emit: &'r dyn Fn(StageProgress),
+}
+
+impl StageReporter<'_> {
+    /// Update the stage's progress and send it with `message`
+    pub fn report(&self, stage_percent: f64, message: &str) {
+        let overall_percent = self.tracker.update(&self.stage, stage_percent);
+        (self.emit)(StageProgress {
+            stage: self.stage.clone(),
+            stage_percent,
+            overall_percent,
+            message: message.to_string(),
+        });
+    }
+
+    /// Update the stage's progress without sending it
+    pub fn set(&self, stage_percent: f64) {
+        self.tracker.update(&self.stage, stage_percent);
+    }
+}
+
+/// The stage that stopped a run of a `StageGraph`, and why
+pub struct StageFailure {
+    pub stage: StageName,
+    pub message: String,
+}
+
+/// Stages ordered by their dependencies into layers that run one after another
+pub struct StageGraph<'a> {
+    stages: Vec<Box<dyn PipelineStage + 'a>>,
+    /// Indices into `stages` in the order they finish; every stage's
+    /// dependencies are in earlier layers
+    layers: Vec<Vec<usize>>,
+}
+
+impl<'a> StageGraph<'a> {
+    /// Order `stages`, failing on duplicates, missing dependencies and cycles
+    pub fn new(stages: Vec<Box<dyn PipelineStage + 'a>>) -> Result<Self, String> {
+        let names: Vec<StageName> = stages.iter().map(|stage| stage.name()).collect();
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                return Err(format!("Stage {} is enabled more than once", name));
+            }
+        }
+
+        let dependencies: Vec<Vec<StageName>> =
+            stages.iter().map(|stage| stage.dependencies()).collect();
+        let finishes_after: Vec<Vec<StageName>> =
+            stages.iter().map(|stage| stage.finishes_after()).collect();
+        for (i, name) in names.iter().enumerate() {
+            let mut required = dependencies[i].iter().chain(&finishes_after[i]);
+            if let Some(missing) = required.find(|dep| !names.contains(dep)) {
+                return Err(format!(
+                    "Stage {} depends on {}, which is not enabled",
+                    name, missing
+                ));
+            }
+        }
+
+        let mut layer_of: HashMap<StageName, usize> = HashMap::new();
+        let mut layers: Vec<Vec<usize>> = Vec::new();
+        while layer_of.len() < names.len() {
+            let ready: Vec<usize> = (0..names.len())
+                .filter(|&i| !layer_of.contains_key(&names[i]))
+                .filter(|&i| {
+                    dependencies[i]
+                        .iter()
+                        .chain(&finishes_after[i])
+                        .all(|dep| layer_of.contains_key(dep))
+                })
+                .collect();
+            if ready.is_empty() {
+                let stuck: Vec<String> = names
+                    .iter()
+                    .filter(|name| !layer_of.contains_key(name))
+                    .map(|name| name.to_string())
+                    .collect();
+                return Err(format!("Stages depend on each other: {}", stuck.join(", ")));
+            }
+
+            // Ready stages come after everything already placed, so a stage
+            // lands behind those it finishes after even in the same layer
+            for &i in &ready {
+                let layer = dependencies[i]
+                    .iter()
+                    .map(|dep| layer_of[dep] + 1)
+                    .chain(finishes_after[i].iter().map(|stage| layer_of[stage]))
+                    .max()
+                    .unwrap_or(0);
+                layer_of.insert(names[i].clone(), layer);
+                if layers.len() <= layer {
+                    layers.resize(layer + 1, Vec::new());
+                }
+                layers[layer].push(i);
+            }
+        }
+
+        Ok(Self { stages, layers })
+    }
+
+    /// Each stage and its weight, in the order the stages run
+    pub fn weights(&self) -> Vec<(StageName, f64)> {
+        self.layers
+            .iter()
+            .flatten()
+            .map(|&i| (self.stages[i].name(), self.stages[i].weight()))
+            .collect()
+    }
+
+    /// Run every layer, decoding the audio once per layer.
+    ///
+    /// The first stage of a layer that feeds on the decode reports its
+    /// progress with a message; the others only update theirs.
+    pub fn run(
+        &mut self,
+        ctx: &mut StageContext,
+        cancel: &CancellationToken,
+        tracker: &ProgressTracker,
+        emit: &dyn Fn(StageProgress),
+    ) -> Result<(), StageFailure> {
+        let mut slots: Vec<Option<&mut Box<dyn PipelineStage + 'a>>> =
+            self.stages.iter_mut().map(Some).collect();
+        for layer in &self.layers {
+            let mut stages: Vec<&mut Box<dyn PipelineStage + 'a>> =
+                layer.iter().filter_map(|&i| slots[i].take()).collect();
+            let names: Vec<StageName> = stages.iter().map(|stage| stage.name()).collect();
+            let activities: Vec<String> = stages.iter().map(|stage| stage.activity()).collect();
+            let fail = |i: usize| {
+                let stage = names[i].clone();
+                move |message: String| StageFailure { stage, message }
+            };
+
+            cancel.check().map_err(fail(0))?;
+            let reporters: Vec<StageReporter> = names
+                .iter()
+                .map(|name| StageReporter {
+                    stage: name.clone(),
+                    tracker,
+                    emit,
+                })
+                .collect();
+
+            let mut sinks: Vec<&mut dyn AnalysisSink> = Vec::new();
+            let mut sink_counts = Vec::with_capacity(stages.len());
+            for (i, stage) in stages.iter_mut().enumerate() {
+                let stage_sinks = stage.sinks(ctx, &reporters[i]).map_err(fail(i))?;
+                sink_counts.push(stage_sinks.len());
+                sinks.extend(stage_sinks);
+            }
+
+            let outcomes = match sink_counts.iter().position(|&count| count > 0) {
+                Some(lead) => {
+                    // Stages advance together with the decoder; report each whole percent once
+                    let mut last_percent = -1.0;
+                    let outcomes =
+                        analysis::run(&ctx.audio_path, None, cancel, &mut sinks, |fraction| {
+                            if fraction < 0.0 {
+                                reporters[lead].report(-1.0, &activities[lead]);
+                                return;
+                            }
+                            let percent = (fraction * 100.0).floor();
+                            if percent <= last_percent {
+                                return;
+                            }
+                            last_percent = percent;
+
+                            for (i, reporter) in reporters.iter().enumerate() {
+                                if i != lead && sink_counts[i] > 0 {
+                                    reporter.set(percent);
+                                }
+                            }
+                            let message = format!("{} ({:.0}%)", activities[lead], percent);
+                            reporters[lead].report(percent, &message);
+                        });
+                    outcomes.map_err(fail(lead))?
+                }
+                None => Vec::new(),
+            };
+            drop(sinks);
+
+            let mut outcomes = outcomes.into_iter();
+            for (i, stage) in stages.iter_mut().enumerate() {
+                let stage_outcomes = outcomes.by_ref().take(sink_counts[i]).collect();
+                stage
+                    .run(stage_outcomes, ctx, &reporters[i])
+                    .map_err(fail(i))?;
+                reporters[i].set(100.0);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run settings analysis stages are built from
+pub struct StageSettings<'a> {
+    pub waveform_options: &'a WaveformOptions,
+    pub beat_config: &'a BeatDetectionConfig,
+}
+
+type StageConstructor = for<'a> fn(&StageSettings<'a>) -> Box<dyn PipelineStage + 'a>;
+
+/// Every analysis stage a run can enable. Each names itself and sets its
+/// own weight and dependencies, so a new stage only needs adding here.
+const ANALYSIS_STAGES: &[StageConstructor] = &[
+    |settings| Box::new(WaveformStage::new(settings.waveform_options)),
+    |settings| Box::new(BeatDetectionStage::new(settings.beat_config)),
+    |_| Box::<KeyDetectionStage>::default(),
+    |_| Box::<LoudnessStage>::default(),
+    |_| Box::<DrumBreaksStage>::default(),
+];
+
+/// Build the stage called `name`, or fail if it is not an analysis stage
+fn analysis_stage<'a>(
+    name: &StageName,
+    settings: &StageSettings<'a>,
+) -> Result<Box<dyn PipelineStage + 'a>, String> {
+    ANALYSIS_STAGES
+        .iter()
+        .map(|build| build(settings))
+        .find(|stage| stage.name() == *name)
+        .ok_or_else(|| format!("{} is not an analysis stage", name))
+}
+
+/// Graph of the analysis stages `names`. The waveform always runs, since
+/// the run's duration and sample rate come from it.
+pub fn analysis_graph<'a>(
+    names: &[StageName],
+    waveform_options: &'a WaveformOptions,
+    beat_config: &'a BeatDetectionConfig,
+) -> Result<StageGraph<'a>, String> {
+    let mut names = names.to_vec();
+    if !names.contains(&StageName::Waveform) {
+        names.insert(0, StageName::Waveform);
+    }
+    let settings = StageSettings {
+        waveform_options,
+        beat_config,
+    };
+    let stages = names
+        .iter()
+        .map(|name| analysis_stage(name, &settings))
+        .collect::<Result<_, _>>()?;
+    StageGraph::new(stages)
+}
+
+/// Everything `run_analysis` needs, owned so it can move to a blocking task
+pub(super) struct AnalysisRun {
+    pub audio_path: PathBuf,
+    pub stages: Vec<StageName>,
+    pub waveform_options: WaveformOptions,
+    pub beat_config: BeatDetectionConfig,
+    pub cancel: CancellationToken,
+    /// Also the job ID in `queue`
+    pub pipeline_id: Option<String>,
+    pub channel: Channel<PipelineEvent>,
+    pub progress: Arc<ProgressTracker>,
+    /// Queue to keep up to date with the overall progress
+    pub queue: Option<Arc<JobQueue>>,
+}
+
+/// Run the analysis stages of `run` on a blocking task.
+///
+/// A finished waveform and beat analysis is saved for `check_cached_audio`.
+pub(super) async fn run_analysis(run: AnalysisRun) -> Result<StageResults, StageFailure> {
+    tokio::task::spawn_blocking(move || {
+        let mut graph = analysis_graph(&run.stages, &run.waveform_options, &run.beat_config)
+            .map_err(|message| StageFailure {
+                stage: StageName::Waveform,
+                message,
+            })?;
+
+        let channel = run.channel.clone();
+        let emit = |progress: StageProgress| {
+            if let (Some(queue), Some(job_id)) = (&run.queue, &run.pipeline_id) {
+                queue.set_progress(job_id, progress.overall_percent);
+            }
+            let _ = channel.send(PipelineEvent::Progress {
+                pipeline_id: run.pipeline_id.clone(),
+                progress,
+            });
+        };
+
+        let mut ctx = StageContext {
+            audio_path: run.audio_path.clone(),
+            pipeline_id: run.pipeline_id.clone(),
+            channel: run.channel.clone(),
+            results: StageResults::default(),
+        };
+        graph.run(&mut ctx, &run.cancel, &run.progress, &emit)?;
+
+        if let (Some(waveform), Some(beat_info)) = (&ctx.results.waveform, &ctx.results.beat_info) {
+            analysis_cache::store(&run.audio_path, waveform, beat_info);
+        }
+        Ok(ctx.results)
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(StageFailure {
+            stage: StageName::Waveform,
+            message: format!("Analysis task panicked: {}", e),
+        })
+    })
+}
+
+/// Fail with the first error among a stage's sink outcomes
+fn all_ok(outcomes: Vec<Result<(), String>>) -> Result<(), String> {
+    outcomes.into_iter().collect()
+}
+
+type ChunkCallback =
+    Box<dyn FnMut(&PeakBuffers, &[PeakBuffers], Option<&BandEnergies>, usize) + Send>;
+
+/// Waveform peaks, replayed from the peak cache when the audio was seen before
+pub struct WaveformStage<'a> {
+    options: &'a WaveformOptions,
+    sidecar: Option<waveform_cache::Sidecar>,
+    sink: Option<WaveformSink<ChunkCallback>>,
+    cached: Option<WaveformData>,
+}
+
+impl<'a> WaveformStage<'a> {
+    pub fn new(options: &'a WaveformOptions) -> Self {
+        Self {
+            options,
+            sidecar: None,
+            sink: None,
+            cached: None,
+        }
+    }
+}
+
+impl PipelineStage for WaveformStage<'_> {
+    fn name(&self) -> StageName {
+        StageName::Waveform
+    }
+
+    fn weight(&self) -> f64 {
+        25.0
+    }
+
+    fn activity(&self) -> String {
+        "Generating waveform".to_string()
+    }
+
+    fn sinks(
+        &mut self,
+        ctx: &StageContext,
+        progress: &StageReporter,
+    ) -> Result<Vec<&mut dyn AnalysisSink>, String> {
+        let (channel, pipeline_id) = (ctx.channel.clone(), ctx.pipeline_id.clone());
+        let mut send_chunk: ChunkCallback = Box::new(move |peaks, channels, bands, offset| {
+            let _ = channel.send(PipelineEvent::WaveformChunk {
+                pipeline_id: pipeline_id.clone(),
+                peaks: peaks.peaks.clone(),
+                min_peaks: peaks.min_peaks.clone(),
+                max_peaks: peaks.max_peaks.clone(),
+                rms_peaks: peaks.rms_peaks.clone(),
+                channels: channels.to_vec(),
+                bands: bands.cloned(),
+                offset,
+            });
+        });
+
+        let sidecar = waveform_cache::Sidecar::locate(&ctx.audio_path, self.options)?;
+        if let Some(waveform) = sidecar.load(self.options, &mut send_chunk) {
+            self.cached = Some(waveform);
+            progress.set(100.0);
+            return Ok(Vec::new());
+        }
+
+        self.sidecar = Some(sidecar);
+        let sink = self
+            .sink
+            .insert(WaveformSink::new(self.options, send_chunk));
+        Ok(vec![sink])
+    }
+
+    fn run(
+        &mut self,
+        outcomes: Vec<Result<(), String>>,
+        ctx: &mut StageContext,
+        _progress: &StageReporter,
+    ) -> Result<(), String> {
+        let waveform = match (self.sink.take(), self.cached.take()) {
+            (Some(sink), _) => {
+                all_ok(outcomes)?;
+                let mut waveform = sink.into_waveform();
+                if let Some(sidecar) = &self.sidecar {
+                    sidecar.store(&waveform);
+                }
+                audio::apply_scale(&mut waveform, self.options.scale);
+                waveform
+            }
+            (None, Some(cached)) => cached,
+            (None, None) => return Err("Waveform was not generated".to_string()),
+        };
+
+        ctx.emit(PipelineEvent::WaveformComplete {
+            pipeline_id: ctx.pipeline_id.clone(),
+            peaks: waveform.peaks.clone(),
+            min_peaks: waveform.min_peaks.clone(),
+            max_peaks: waveform.max_peaks.clone(),
+            rms_peaks: waveform.rms_peaks.clone(),
+            channels: waveform.channels.clone(),
+            bands: waveform.bands.clone(),
+            scale: waveform.scale,
+            normalization_factor: waveform.normalization_factor,
+            channel_normalization_factor: waveform.channel_normalization_factor,
+            duration_secs: waveform.duration_secs,
+            sample_rate: waveform.sample_rate,
+        });
+        ctx.results.waveform = Some(waveform);
+        Ok(())
+    }
+}
+
+type BeatCallback = Box<dyn FnMut(&[f64], f32) + Send>;
+
+/// Tempo, beat grid and onsets, streaming beats as they are found
+pub struct BeatDetectionStage<'a> {
+    config: &'a BeatDetectionConfig,
+    sinks: Option<(TempoSink<'a, BeatCallback>, OnsetSink<'a>)>,
+}
+
+impl<'a> BeatDetectionStage<'a> {
+    pub fn new(config: &'a BeatDetectionConfig) -> Self {
+        Self {
+            config,
+            sinks: None,
+        }
+    }
+}
+
+impl PipelineStage for BeatDetectionStage<'_> {
+    fn name(&self) -> StageName {
+        StageName::BeatDetection
+    }
+
+    fn weight(&self) -> f64 {
+        20.0
+    }
+
+    fn activity(&self) -> String {
+        "Detecting beats".to_string()
+    }
+
+    fn sinks(
+        &mut self,
+        ctx: &StageContext,
+        progress: &StageReporter,
+    ) -> Result<Vec<&mut dyn AnalysisSink>, String> {
+        progress.report(0.0, "Starting beat detection...");
+
+        let (channel, pipeline_id) = (ctx.channel.clone(), ctx.pipeline_id.clone());
+        let on_beats: BeatCallback = Box::new(move |beats, bpm| {
+            let _ = channel.send(PipelineEvent::BeatDetectionChunk {
+                pipeline_id: pipeline_id.clone(),
+                beats: beats.to_vec(),
+                bpm,
+            });
+        });
+        let (tempo, onsets) = self.sinks.insert((
+            TempoSink::with_partial_beats(self.config, on_beats),
+            OnsetSink::new(self.config),
+        ));
+        Ok(vec![tempo, onsets])
+    }
+
+    fn run(
+        &mut self,
+        outcomes: Vec<Result<(), String>>,
+        ctx: &mut StageContext,
+        progress: &StageReporter,
+    ) -> Result<(), String> {
+        let (tempo, onsets) = self
+            .sinks
+            .take()
+            .ok_or_else(|| "Beat detection did not run".to_string())?;
+        all_ok(outcomes)?;
+
+        let beat_info = beat_detection::beat_info(tempo, onsets);
+        progress.report(
+            100.0,
+            &format!("Beat detection complete: {:.1} BPM", beat_info.bpm),
+        );
+        ctx.emit(PipelineEvent::BeatDetectionComplete {
+            pipeline_id: ctx.pipeline_id.clone(),
+            bpm: beat_info.bpm,
+            bpm_confidence: beat_info.bpm_confidence,
+            beats: beat_info.beats.clone(),
+            onsets: beat_info.onsets.clone(),
+            config: beat_info.config.clone(),
+        });
+        ctx.results.beat_info = Some(beat_info);
+        Ok(())
+    }
+}
+
+/// Musical key of the whole track
+#[derive(Default)]
+pub struct KeyDetectionStage {
+    sink: Option<KeySink>,
+}
+
+impl PipelineStage for KeyDetectionStage {
+    fn name(&self) -> StageName {
+        StageName::KeyDetection
+    }
+
+    fn weight(&self) -> f64 {
+        10.0
+    }
+
+    fn activity(&self) -> String {
+        "Detecting key".to_string()
+    }
+
+    fn sinks(
+        &mut self,
+        _ctx: &StageContext,
+        _progress: &StageReporter,
+    ) -> Result<Vec<&mut dyn AnalysisSink>, String> {
+        Ok(vec![self.sink.insert(KeySink::new())])
+    }
+
+    fn run(
+        &mut self,
+        outcomes: Vec<Result<(), String>>,
+        ctx: &mut StageContext,
+        progress: &StageReporter,
+    ) -> Result<(), String> {
+        let sink = self
+            .sink
+            .take()
+            .ok_or_else(|| "Key detection did not run".to_string())?;
+        all_ok(outcomes)?;
+
+        let key = sink.into_key();
+        if let Some(key) = &key {
+            progress.report(100.0, &format!("Key detection complete: {}", key.name));
+        }
+        ctx.emit(PipelineEvent::KeyDetectionComplete {
+            pipeline_id: ctx.pipeline_id.clone(),
+            key,
+        });
+        Ok(())
+    }
+}
+
+/// Integrated loudness and sample peak of the whole track
+#[derive(Default)]
+pub struct LoudnessStage {
+    sink: Option<LoudnessSink>,
+}
+
+impl PipelineStage for LoudnessStage {
+    fn name(&self) -> StageName {
+        StageName::Loudness
+    }
+
+    fn weight(&self) -> f64 {
+        5.0
+    }
+
+    fn activity(&self) -> String {
+        "Measuring loudness".to_string()
+    }
+
+    fn sinks(
+        &mut self,
+        _ctx: &StageContext,
+        _progress: &StageReporter,
+    ) -> Result<Vec<&mut dyn AnalysisSink>, String> {
+        Ok(vec![self.sink.insert(LoudnessSink::new())])
+    }
+
+    fn run(
+        &mut self,
+        outcomes: Vec<Result<(), String>>,
+        ctx: &mut StageContext,
+        progress: &StageReporter,
+    ) -> Result<(), String> {
+        let sink = self
+            .sink
+            .take()
+            .ok_or_else(|| "Loudness measurement did not run".to_string())?;
+        all_ok(outcomes)?;
+
+        let loudness = sink.into_loudness();
+        if let Some(loudness) = &loudness {
+            let message = format!("Loudness measured: {:.1} LUFS", loudness.integrated_lufs);
+            progress.report(100.0, &message);
+        }
+        ctx.emit(PipelineEvent::LoudnessComplete {
+            pipeline_id: ctx.pipeline_id.clone(),
+            loudness,
+        });
+        Ok(())
+    }
+}
+
+/// Windows most likely to be exposed drum breaks, laid out on the beat grid
+#[derive(Default)]
+pub struct DrumBreaksStage {
+    options: DrumBreakOptions,
+    sink: Option<EnergySink>,
+}
+
+impl PipelineStage for DrumBreaksStage {
+    fn name(&self) -> StageName {
+        StageName::DrumBreaks
+    }
+
+    fn weight(&self) -> f64 {
+        15.0
+    }
+
+    /// Windows are whole bars of the detected beat grid, which is only
+    /// needed to rank the energies gathered during the decode
+    fn finishes_after(&self) -> Vec<StageName> {
+        vec![StageName::BeatDetection]
+    }
+
+    fn activity(&self) -> String {
+        "Finding drum breaks".to_string()
+    }
+
+    fn sinks(
+        &mut self,
+        _ctx: &StageContext,
+        _progress: &StageReporter,
+    ) -> Result<Vec<&mut dyn AnalysisSink>, String> {
+        Ok(vec![self.sink.insert(EnergySink::new())])
+    }
+
+    fn run(
+        &mut self,
+        outcomes: Vec<Result<(), String>>,
+        ctx: &mut StageContext,
+        progress: &StageReporter,
+    ) -> Result<(), String> {
+        let sink = self
+            .sink
+            .take()
+            .ok_or_else(|| "Drum-break search did not run".to_string())?;
+        all_ok(outcomes)?;
+        let beat_info = ctx
+            .results
+            .beat_info
+            .as_ref()
+            .ok_or_else(|| "Drum-break search needs the beat grid".to_string())?;
+
+        let breaks = sink.rank(beat_info, &self.options);
+        let message = format!("Found {} drum-break candidates", breaks.len());
+        progress.report(100.0, &message);
+        ctx.emit(PipelineEvent::DrumBreaksComplete {
+            pipeline_id: ctx.pipeline_id.clone(),
+            breaks,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stage that only records when it ran
+    struct Marker {
+        name: StageName,
+        dependencies: Vec<StageName>,
+        finishes_after: Vec<StageName>,
+        log: Arc<Mutex<Vec<StageName>>>,
+    }
+
+    impl PipelineStage for Marker {
+        fn name(&self) -> StageName {
+            self.name.clone()
+        }
+
+        fn weight(&self) -> f64 {
+            1.0
+        }
+
+        fn dependencies(&self) -> Vec<StageName> {
+            self.dependencies.clone()
+        }
+
+        fn finishes_after(&self) -> Vec<StageName> {
+            self.finishes_after.clone()
+        }
+
+        fn activity(&self) -> String {
+            self.name.to_string()
+        }
+
+        fn sinks(
+            &mut self,
+            _ctx: &StageContext,
+            _progress: &StageReporter,
+        ) -> Result<Vec<&mut dyn AnalysisSink>, String> {
+            Ok(Vec::new())
+        }
+
+        fn run(
+            &mut self,
+            _outcomes: Vec<Result<(), String>>,
+            _ctx: &mut StageContext,
+            _progress: &StageReporter,
+        ) -> Result<(), String> {
+            self.log.lock().unwrap().push(self.name.clone());
+            Ok(())
+        }
+    }
+
+    fn markers(
+        stages: &[(StageName, &[StageName])],
+        log: &Arc<Mutex<Vec<StageName>>>,
+    ) -> Vec<Box<dyn PipelineStage>> {
+        stages
+            .iter()
+            .map(|(name, dependencies)| {
+                Box::new(Marker {
+                    name: name.clone(),
+                    dependencies: dependencies.to_vec(),
+                    finishes_after: Vec::new(),
+                    log: log.clone(),
+                }) as Box<dyn PipelineStage>
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_graph_runs_dependencies_first() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let stages = markers(
+            &[
+                (StageName::KeyDetection, &[StageName::BeatDetection]),
+                (StageName::BeatDetection, &[StageName::Waveform]),
+                (StageName::Waveform, &[]),
+                (StageName::Loudness, &[]),
+            ],
+            &log,
+        );
+        let mut graph = StageGraph::new(stages).unwrap();
+
+        let names: Vec<StageName> = graph.weights().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(
+            names,
+            vec![
+                StageName::Waveform,
+                StageName::Loudness,
+                StageName::BeatDetection,
+                StageName::KeyDetection,
+            ]
+        );
+
+        let tracker = ProgressTracker::new(graph.weights());
+        let mut ctx = StageContext {
+            audio_path: PathBuf::new(),
+            pipeline_id: None,
+            channel: Channel::new(|_| Ok(())),
+            results: StageResults::default(),
+        };
+        let result = graph.run(&mut ctx, &CancellationToken::new(), &tracker, &|_| {});
+        assert!(result.is_ok());
+        assert_eq!(*log.lock().unwrap(), names);
+        assert_eq!(tracker.overall(), 100.0);
+    }
+
+    #[test]
+    fn test_graph_rejects_invalid_stages() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let missing = markers(&[(StageName::BeatDetection, &[StageName::Waveform])], &log);
+        assert!(StageGraph::new(missing).is_err());
+
+        let duplicate = markers(
+            &[(StageName::Waveform, &[]), (StageName::Waveform, &[])],
+            &log,
+        );
+        assert!(StageGraph::new(duplicate).is_err());
+
+        let cycle = markers(
+            &[
+                (StageName::Waveform, &[StageName::Loudness]),
+                (StageName::Loudness, &[StageName::Waveform]),
+            ],
+            &log,
+        );
+        assert!(StageGraph::new(cycle).is_err());
+
+        let waveform_options = WaveformOptions::default();
+        let beat_config = BeatDetectionConfig::default();
+        assert!(
+            analysis_graph(&[StageName::Downloading], &waveform_options, &beat_config).is_err()
+        );
+    }
+
+    #[test]
+    fn test_progress_is_weighted_over_enabled_stages() {
+        let tracker = ProgressTracker::new(vec![
+            (StageName::Waveform, 25.0),
+            (StageName::Loudness, 5.0),
+        ]);
+        assert_eq!(
+            tracker.update(&StageName::Waveform, 100.0),
+            25.0 / 30.0 * 100.0
+        );
+
+        // Indeterminate progress leaves the stage as it was
+        assert_eq!(
+            tracker.update(&StageName::Loudness, -1.0),
+            25.0 / 30.0 * 100.0
+        );
+        assert_eq!(tracker.update(&StageName::Loudness, 150.0), 100.0);
+    }
+
+    #[test]
+    fn test_default_stage_weights_sum_to_100() {
+        let tracker = ProgressTracker::for_stages(
+            StageName::fetch(),
+            &StageName::default_analysis(),
+            &WaveformOptions::default(),
+            &BeatDetectionConfig::default(),
+        )
+        .unwrap();
+        let total: f64 = tracker.weights.iter().map(|(_, weight)| weight).sum();
+        assert!(
+            (total - 100.0).abs() < 0.001,
+            "Stage weights should sum to 100, got {}",
+            total
+        );
+    }
+
+    #[test]
+    fn test_graph_finishes_stages_in_order_within_a_layer() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut stages = markers(
+            &[
+                (StageName::DrumBreaks, &[]),
+                (StageName::BeatDetection, &[]),
+            ],
+            &log,
+        );
+        stages[0] = Box::new(Marker {
+            name: StageName::DrumBreaks,
+            dependencies: Vec::new(),
+            finishes_after: vec![StageName::BeatDetection],
+            log: log.clone(),
+        });
+        let mut graph = StageGraph::new(stages).unwrap();
+        assert_eq!(graph.layers.len(), 1);
+
+        let tracker = ProgressTracker::new(graph.weights());
+        let mut ctx = StageContext {
+            audio_path: PathBuf::new(),
+            pipeline_id: None,
+            channel: Channel::new(|_| Ok(())),
+            results: StageResults::default(),
+        };
+        let result = graph.run(&mut ctx, &CancellationToken::new(), &tracker, &|_| {});
+        assert!(result.is_ok());
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![StageName::BeatDetection, StageName::DrumBreaks]
+        );
+    }
+
+    #[test]
+    fn test_drum_breaks_share_the_beat_detection_decode() {
+        let waveform_options = WaveformOptions::default();
+        let beat_config = BeatDetectionConfig::default();
+        let stages = [StageName::DrumBreaks, StageName::BeatDetection];
+        let graph = analysis_graph(&stages, &waveform_options, &beat_config).unwrap();
+        assert_eq!(graph.layers.len(), 1);
+
+        let names: Vec<StageName> = graph.weights().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names.last(), Some(&StageName::DrumBreaks));
+
+        // Without beat detection there is no grid to search
+        assert!(analysis_graph(&[StageName::DrumBreaks], &waveform_options, &beat_config).is_err());
+    }
+
+    #[test]
+    fn test_analysis_graph_always_includes_the_waveform() {
+        let waveform_options = WaveformOptions::default();
+        let beat_config = BeatDetectionConfig::default();
+        let graph =
+            analysis_graph(&[StageName::Loudness], &waveform_options, &beat_config).unwrap();
+        let names: Vec<StageName> = graph.weights().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec![StageName::Waveform, StageName::Loudness]);
+    }
+}
@@ -0,0 +1,147 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::analysis::{self, AnalysisSink, Span};
+use crate::beat_detection::{self, BeatDetectionConfig, BeatInfo, OnsetSink, TempoSink};
+use crate::cancel::CancellationToken;
+use crate::key_detection::{KeyInfo, KeySink};
+use crate::loudness::{LoudnessInfo, LoudnessSink};
+
+/// Analyses `analyze_region` can run over a selection
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum RegionAnalysis {
+    /// Tempo (BPM), beat grid and onsets
+    Beats,
+    /// Musical key
+    Key,
+    /// Integrated loudness and sample peak
+    Loudness,
+}
+
+/// Combined result of `analyze_region`; analyses that were not requested are null
+#[derive(Clone, Debug, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RegionAnalysisResult {
+    /// Region start in seconds
+    pub start: f64,
+    /// Region end in seconds
+    pub end: f64,
+    /// Beat and onset positions are in seconds from the start of the file
+    pub beats: Option<BeatInfo>,
+    /// Null when requested but the region is silent
+    pub key: Option<KeyInfo>,
+    pub loudness: Option<LoudnessInfo>,
+}
+
+/// Decode only `[start, end)` of an audio file and run the requested analyses over it.
+///
+/// All analyses share the one decode pass.
+pub fn analyze_region(
+    audio_path: &Path,
+    start: f64,
+    end: f64,
+    analyses: &[RegionAnalysis],
+    beat_config: &BeatDetectionConfig,
+) -> Result<RegionAnalysisResult, String> {
+    let wants = |analysis| analyses.contains(&analysis);
+
+    let mut tempo = wants(RegionAnalysis::Beats).then(|| TempoSink::new(beat_config));
+    let mut onsets = wants(RegionAnalysis::Beats).then(|| OnsetSink::new(beat_config));
+    let mut key = wants(RegionAnalysis::Key).then(KeySink::new);
+    let mut loudness = wants(RegionAnalysis::Loudness).then(LoudnessSink::new);
+
+    let mut sinks: Vec<&mut dyn AnalysisSink> = Vec::new();
+    if let (Some(tempo), Some(onsets)) = (tempo.as_mut(), onsets.as_mut()) {
+        sinks.push(tempo);
+        sinks.push(onsets);
+    }
+    if let Some(key) = key.as_mut() {
+        sinks.push(key);
+    }
+    if let Some(loudness) = loudness.as_mut() {
+        sinks.push(loudness);
+    }
+
+    let span = Span {
+        start_secs: start,
+        end_secs: end,
+    };
+    let cancel = CancellationToken::new();
+    for outcome in analysis::run(audio_path, Some(span), &cancel, &mut sinks, |_| {})? {
+        outcome?;
+    }
+    drop(sinks);
+
+    // The sinks saw the region as its own stream, so shift times back onto the file
+    let beats = tempo.zip(onsets).map(|(tempo, onsets)| {
+        let mut info = beat_detection::beat_info(tempo, onsets);
+        for time in info.beats.iter_mut().chain(info.onsets.iter_mut()) {
+            *time += start;
+        }
+        info
+    });
+
+    Ok(RegionAnalysisResult {
+        start,
+        end,
+        beats,
+        key: key.and_then(KeySink::into_key),
+        loudness: loudness.and_then(LoudnessSink::into_loudness),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio;
+
+    #[test]
+    fn test_analyze_region_runs_only_requested_analyses() {
+        let path = audio::test_file_path("region.wav");
+
+        // Two seconds of silence, then a 120 BPM click track
+        let sample_rate = 44100;
+        let mut signal = vec![0.0f32; 2 * sample_rate as usize];
+        let beat = sample_rate as usize / 2;
+        signal.extend((0..8 * sample_rate as usize).map(|i| {
+            if i % beat < 400 {
+                0.8 * (i as f32 * 0.3).sin()
+            } else {
+                0.0
+            }
+        }));
+        audio::write_wav(&path, &[signal], sample_rate).unwrap();
+
+        let result = analyze_region(
+            &path,
+            2.0,
+            10.0,
+            &[RegionAnalysis::Beats, RegionAnalysis::Loudness],
+            &BeatDetectionConfig::default(),
+        )
+        .unwrap();
+
+        assert!(result.key.is_none());
+        assert!(result.loudness.is_some());
+        let beats = result.beats.unwrap();
+        assert!(!beats.onsets.is_empty());
+        // Times are reported on the file's clock, so nothing precedes the region
+        assert!(beats.onsets.iter().all(|&t| (2.0..10.0).contains(&t)));
+
+        let silent = analyze_region(
+            &path,
+            0.0,
+            1.5,
+            &[RegionAnalysis::Key],
+            &BeatDetectionConfig::default(),
+        )
+        .unwrap();
+        assert!(silent.beats.is_none());
+        assert!(silent.key.is_none());
+
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,599 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use aubio::vec::CVecMut;
+use aubio::FFT;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::audio;
+use crate::file_stamp::FileStamp;
+
+/// Number of columns (time slices) in every tile
+pub const TILE_WIDTH: u32 = 256;
+/// Lowest frequency shown when using logarithmic scaling
+const LOG_MIN_FREQ: f32 = 20.0;
+/// Maximum number of tiles kept in the in-memory cache
+const MAX_CACHED_TILES: usize = 512;
+/// Largest accepted tile height, in rows
+const MAX_HEIGHT: u32 = 4096;
+/// Largest accepted FFT window
+const MAX_FFT_SIZE: u32 = 32768;
+/// Highest accepted horizontal zoom, in columns per second
+const MAX_PIXELS_PER_SECOND: f64 = 10_000.0;
+/// Most pixels a single request may render across all its tiles
+const MAX_REQUEST_PIXELS: u64 = 64 * 1024 * 1024;
+
+/// How frequency bins are distributed over the rows of a tile
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum FrequencyScale {
+    Linear,
+    Log,
+    Mel,
+}
+
+/// Resolution and display range for spectrogram rendering
+#[derive(Clone, Debug, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SpectrogramOptions {
+    /// Horizontal zoom: columns per second of audio
+    pub pixels_per_second: f64,
+    /// Number of frequency rows per tile
+    pub height: u32,
+    /// FFT window size (power of 2)
+    pub fft_size: u32,
+    pub scale: FrequencyScale,
+    /// Level mapped to intensity 0
+    pub min_db: f32,
+    /// Level mapped to intensity 255
+    pub max_db: f32,
+}
+
+impl Default for SpectrogramOptions {
+    fn default() -> Self {
+        Self {
+            pixels_per_second: 100.0,
+            height: 256,
+            fft_size: 2048,
+            scale: FrequencyScale::Log,
+            min_db: -90.0,
+            max_db: 0.0,
+        }
+    }
+}
+
+impl SpectrogramOptions {
+    fn validate(&self) -> Result<(), String> {
+        if !self.fft_size.is_power_of_two() || !(64..=MAX_FFT_SIZE).contains(&self.fft_size) {
+            return Err(format!(
+                "FFT size must be a power of 2 between 64 and {}",
+                MAX_FFT_SIZE
+            ));
+        }
+        if self.height == 0 || self.height > MAX_HEIGHT {
+            return Err(format!(
+                "Spectrogram height must be between 1 and {}",
+                MAX_HEIGHT
+            ));
+        }
+        if !self.pixels_per_second.is_finite()
+            || self.pixels_per_second <= 0.0
+            || self.pixels_per_second > MAX_PIXELS_PER_SECOND
+        {
+            return Err(format!(
+                "Pixels per second must be positive and at most {}",
+                MAX_PIXELS_PER_SECOND
+            ));
+        }
+        if self.max_db <= self.min_db {
+            return Err("Maximum dB must be above minimum dB".to_string());
+        }
+        Ok(())
+    }
+
+    /// Identifies tiles rendered with these settings in the cache
+    fn cache_key(&self) -> String {
+        format!(
+            "{}:{}:{}:{:?}:{}:{}",
+            self.pixels_per_second,
+            self.height,
+            self.fft_size,
+            self.scale,
+            self.min_db,
+            self.max_db
+        )
+    }
+
+    fn tile_secs(&self) -> f64 {
+        TILE_WIDTH as f64 / self.pixels_per_second
+    }
+}
+
+/// An image-ready block of spectrogram columns
+#[derive(Clone, Debug, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SpectrogramTile {
+    /// Tile position at this zoom level (tile `n` starts at `n * width / pixelsPerSecond`)
+    pub index: u32,
+    pub start_time: f64,
+    pub end_time: f64,
+    pub width: u32,
+    pub height: u32,
+    /// Row-major 8-bit intensities; the first row is the highest frequency
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase", tag = "event", content = "data")]
+pub enum SpectrogramEvent {
+    Started {
+        #[serde(rename = "totalTiles")]
+        total_tiles: u32,
+        #[serde(rename = "sampleRate")]
+        sample_rate: u32,
+    },
+    Tile(SpectrogramTile),
+    Completed {
+        #[serde(rename = "totalTiles")]
+        total_tiles: u32,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct TileKey {
+    source: String,
+    /// Tiles of a file that has since changed no longer match
+    stamp: FileStamp,
+    settings: String,
+    index: u32,
+}
+
+/// Rendered tiles keyed by source file and its stamp, zoom/settings and tile index
+#[derive(Default)]
+pub struct SpectrogramCache {
+    inner: Mutex<CacheInner>,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    tiles: HashMap<TileKey, Arc<SpectrogramTile>>,
+    order: VecDeque<TileKey>,
+}
+
+impl SpectrogramCache {
+    fn get(&self, key: &TileKey) -> Option<Arc<SpectrogramTile>> {
+        self.inner.lock().ok()?.tiles.get(key).cloned()
+    }
+
+    fn insert(&self, key: TileKey, tile: Arc<SpectrogramTile>) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        if inner.tiles.insert(key.clone(), tile).is_none() {
+            inner.order.push_back(key);
+        }
+        while inner.order.len() > MAX_CACHED_TILES {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.tiles.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Compute the spectrogram tiles covering a time range and stream them over `on_event`.
+///
+/// Cached tiles are sent first; the remaining ones are rendered from a single
+/// decode of the uncovered span. Returns the number of tiles sent.
+pub fn generate_tiles<F>(
+    audio_path: &Path,
+    start_time: f64,
+    end_time: f64,
+    options: &SpectrogramOptions,
+    cache: &SpectrogramCache,
+    mut on_event: F,
+) -> Result<u32, String>
+where
+    F: FnMut(SpectrogramEvent),
+{
+    options.validate()?;
+
+    let info = audio::get_audio_info(audio_path)?;
+    let sample_rate = info.sample_rate;
+    // An estimated duration may be short, so only clamp to an exact one
+    let end_time = if info.duration_exact && info.duration_secs > 0.0 {
+        end_time.min(info.duration_secs)
+    } else {
+        end_time
+    };
+    let start_time = start_time.max(0.0);
+    if end_time <= start_time {
+        return Err("Invalid time range".to_string());
+    }
+
+    let tile_secs = options.tile_secs();
+    let first = (start_time / tile_secs).floor() as u32;
+    let last = (end_time / tile_secs).ceil() as u32;
+
+    let pixels = (last - first) as u64 * TILE_WIDTH as u64 * options.height as u64;
+    if pixels > MAX_REQUEST_PIXELS {
+        return Err(format!(
+            "Requested range needs {} pixels, more than the limit of {}; request a shorter range",
+            pixels, MAX_REQUEST_PIXELS
+        ));
+    }
+
+    let source = audio_path.to_string_lossy().to_string();
+    let stamp = FileStamp::of(audio_path)?;
+    let settings = options.cache_key();
+    let key = |index: u32| TileKey {
+        source: source.clone(),
+        stamp,
+        settings: settings.clone(),
+        index,
+    };
+
+    on_event(SpectrogramEvent::Started {
+        total_tiles: last - first,
+        sample_rate,
+    });
+
+    let mut missing = Vec::new();
+    for index in first..last {
+        match cache.get(&key(index)) {
+            Some(tile) => on_event(SpectrogramEvent::Tile((*tile).clone())),
+            None => missing.push(index),
+        }
+    }
+
+    if let (Some(&lo), Some(&hi)) = (missing.first(), missing.last()) {
+        // Pad by half a window so edge columns see real neighbouring samples
+        let pad_secs = options.fft_size as f64 / 2.0 / sample_rate as f64;
+        let region_start = lo as f64 * tile_secs;
+        let region_end = (hi + 1) as f64 * tile_secs;
+        let decode_start = (region_start - pad_secs).max(0.0);
+        let decoded = audio::decode_region(audio_path, decode_start, region_end + pad_secs)?;
+        let samples = decoded.to_mono();
+
+        let mut renderer = SpectrogramRenderer::new(options, decoded.sample_rate)?;
+        for index in missing {
+            let tile = renderer.render_tile(&samples, decode_start, index)?;
+            cache.insert(key(index), Arc::new(tile.clone()));
+            on_event(SpectrogramEvent::Tile(tile));
+        }
+    }
+
+    Ok(last - first)
+}
+
+/// Renders spectrogram columns from mono samples with a fixed set of options
+struct SpectrogramRenderer {
+    options: SpectrogramOptions,
+    sample_rate: u32,
+    fft: FFT,
+    window: Vec<f32>,
+    frame: Vec<f32>,
+    norm: Vec<f32>,
+    phas: Vec<f32>,
+    /// FFT bin range `[lo, hi)` feeding each output row, lowest frequency first
+    row_bins: Vec<(usize, usize)>,
+    /// Magnitude of a full-scale sine, used as the 0 dB reference
+    reference: f32,
+}
+
+impl SpectrogramRenderer {
+    fn new(options: &SpectrogramOptions, sample_rate: u32) -> Result<Self, String> {
+        let fft_size = options.fft_size as usize;
+        let fft = FFT::new(fft_size).map_err(|e| format!("Failed to create FFT: {:?}", e))?;
+        let bins = fft_size / 2 + 1;
+
+        let window = (0..fft_size)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / fft_size as f32).cos())
+            .collect();
+
+        Ok(Self {
+            options: options.clone(),
+            sample_rate,
+            fft,
+            window,
+            frame: vec![0.0; fft_size],
+            norm: vec![0.0; bins],
+            phas: vec![0.0; bins],
+            row_bins: row_bins(
+                options.scale,
+                options.height as usize,
+                fft_size,
+                sample_rate,
+            ),
+            // Hann coherent gain (0.5) times the unnormalised FFT gain (N / 2)
+            reference: fft_size as f32 / 4.0,
+        })
+    }
+
+    /// Render one tile; `samples_start` is the time of `samples[0]` in seconds.
+    fn render_tile(
+        &mut self,
+        samples: &[f32],
+        samples_start: f64,
+        index: u32,
+    ) -> Result<SpectrogramTile, String> {
+        let width = TILE_WIDTH as usize;
+        let height = self.options.height as usize;
+        let tile_secs = self.options.tile_secs();
+        let start_time = index as f64 * tile_secs;
+        let column_secs = 1.0 / self.options.pixels_per_second;
+        let db_range = self.options.max_db - self.options.min_db;
+
+        let mut pixels = vec![0u8; width * height];
+
+        for x in 0..width {
+            let centre_time = start_time + (x as f64 + 0.5) * column_secs;
+            let centre = ((centre_time - samples_start) * self.sample_rate as f64).round() as i64;
+            self.analyze(samples, centre)?;
+
+            for (row, &(lo, hi)) in self.row_bins.iter().enumerate() {
+                let magnitude = self.norm[lo..hi].iter().copied().fold(0.0f32, f32::max);
+                let db = 20.0 * (magnitude / self.reference).max(1e-10).log10();
+                let level = ((db - self.options.min_db) / db_range).clamp(0.0, 1.0);
+
+                // Flip vertically so the image has high frequencies at the top
+                pixels[(height - 1 - row) * width + x] = (level * 255.0).round() as u8;
+            }
+        }
+
+        Ok(SpectrogramTile {
+            index,
+            start_time,
+            end_time: start_time + tile_secs,
+            width: TILE_WIDTH,
+            height: self.options.height,
+            pixels,
+        })
+    }
+
+    /// Window the frame centred on `centre` (zero-padded at the edges) and take its spectrum.
+    fn analyze(&mut self, samples: &[f32], centre: i64) -> Result<(), String> {
+        let half = (self.frame.len() / 2) as i64;
+        for (i, (out, w)) in self.frame.iter_mut().zip(&self.window).enumerate() {
+            let pos = centre - half + i as i64;
+            let sample = if pos >= 0 {
+                samples.get(pos as usize).copied().unwrap_or(0.0)
+            } else {
+                0.0
+            };
+            *out = sample * w;
+        }
+
+        let spectrum = CVecMut::from_parts(self.norm.as_mut_slice(), self.phas.as_mut_slice())
+            .map_err(|e| format!("Invalid spectral frame: {:?}", e))?;
+        self.fft
+            .do_(self.frame.as_slice(), spectrum)
+            .map_err(|e| format!("FFT error: {:?}", e))
+    }
+}
+
+/// Map each output row to the FFT bins it covers, lowest frequency first.
+fn row_bins(
+    scale: FrequencyScale,
+    height: usize,
+    fft_size: usize,
+    sample_rate: u32,
+) -> Vec<(usize, usize)> {
+    let nyquist = sample_rate as f32 / 2.0;
+    let bin_hz = sample_rate as f32 / fft_size as f32;
+    let max_bin = fft_size / 2;
+
+    let edge = |fraction: f32| -> f32 {
+        match scale {
+            FrequencyScale::Linear => fraction * nyquist,
+            FrequencyScale::Log => LOG_MIN_FREQ * (nyquist / LOG_MIN_FREQ).powf(fraction),
+            FrequencyScale::Mel => mel_to_hz(fraction * hz_to_mel(nyquist)),
+        }
+    };
+
+    (0..height)
+        .map(|row| {
+            let lo_hz = edge(row as f32 / height as f32);
+            let hi_hz = edge((row + 1) as f32 / height as f32);
+            let lo = ((lo_hz / bin_hz).round() as usize).min(max_bin);
+            // The top row also takes the Nyquist bin
+            let hi = if row + 1 == height {
+                max_bin + 1
+            } else {
+                ((hi_hz / bin_hz).round() as usize).clamp(lo + 1, max_bin + 1)
+            };
+            (lo, hi)
+        })
+        .collect()
+}
+
+fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, sample_rate: u32, secs: f32) -> Vec<f32> {
+        (0..(sample_rate as f32 * secs) as usize)
+            .map(|i| (2.0 * std::f32::consts::PI * freq * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    fn stamp(len: u64) -> FileStamp {
+        FileStamp {
+            len,
+            modified: None,
+        }
+    }
+
+    /// Row (0 = lowest frequency) with the highest mean intensity
+    fn brightest_row(tile: &SpectrogramTile) -> usize {
+        let width = tile.width as usize;
+        let height = tile.height as usize;
+        let row_sum = |row: usize| -> u32 {
+            let y = height - 1 - row;
+            tile.pixels[y * width..(y + 1) * width]
+                .iter()
+                .map(|&p| p as u32)
+                .sum()
+        };
+        (0..height).max_by_key(|&row| row_sum(row)).unwrap()
+    }
+
+    #[test]
+    fn test_mel_round_trip() {
+        for hz in [0.0, 440.0, 8000.0] {
+            assert!((mel_to_hz(hz_to_mel(hz)) - hz).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn test_row_bins_cover_spectrum() {
+        for scale in [
+            FrequencyScale::Linear,
+            FrequencyScale::Log,
+            FrequencyScale::Mel,
+        ] {
+            let rows = row_bins(scale, 128, 2048, 44100);
+            assert_eq!(rows.len(), 128);
+            assert!(rows.iter().all(|&(lo, hi)| hi > lo && hi <= 1025));
+            assert!(rows.windows(2).all(|w| w[1].0 >= w[0].0));
+            assert_eq!(rows.last().unwrap().1, 1025);
+        }
+    }
+
+    #[test]
+    fn test_sine_lands_in_expected_row() {
+        let sample_rate = 44100;
+        let options = SpectrogramOptions {
+            scale: FrequencyScale::Linear,
+            height: 64,
+            ..Default::default()
+        };
+        let samples = sine(5512.5, sample_rate, 3.0);
+
+        let mut renderer = SpectrogramRenderer::new(&options, sample_rate).unwrap();
+        let tile = renderer.render_tile(&samples, 0.0, 0).unwrap();
+
+        assert_eq!(tile.pixels.len(), (TILE_WIDTH * 64) as usize);
+        // 5512.5 Hz is a quarter of Nyquist
+        assert_eq!(brightest_row(&tile), 16);
+
+        // A full-scale sine should read close to 0 dB
+        let peak = *tile.pixels.iter().max().unwrap();
+        assert!(peak > 240, "Full-scale sine peak intensity {}", peak);
+    }
+
+    #[test]
+    fn test_silence_renders_black() {
+        let options = SpectrogramOptions::default();
+        let samples = vec![0.0f32; 44100];
+
+        let mut renderer = SpectrogramRenderer::new(&options, 44100).unwrap();
+        let tile = renderer.render_tile(&samples, 0.0, 0).unwrap();
+        assert!(tile.pixels.iter().all(|&p| p == 0));
+    }
+
+    #[test]
+    fn test_invalid_options_rejected() {
+        let options = SpectrogramOptions {
+            fft_size: 1000,
+            ..Default::default()
+        };
+        assert!(options.validate().is_err());
+
+        let options = SpectrogramOptions {
+            min_db: 0.0,
+            max_db: -10.0,
+            ..Default::default()
+        };
+        assert!(options.validate().is_err());
+    }
+
+    #[test]
+    fn test_oversized_options_rejected() {
+        let options = SpectrogramOptions {
+            height: MAX_HEIGHT + 1,
+            ..Default::default()
+        };
+        assert!(options.validate().is_err());
+
+        let options = SpectrogramOptions {
+            fft_size: MAX_FFT_SIZE * 2,
+            ..Default::default()
+        };
+        assert!(options.validate().is_err());
+
+        let options = SpectrogramOptions {
+            pixels_per_second: f64::MAX,
+            ..Default::default()
+        };
+        assert!(options.validate().is_err());
+
+        let options = SpectrogramOptions {
+            height: MAX_HEIGHT,
+            fft_size: MAX_FFT_SIZE,
+            pixels_per_second: MAX_PIXELS_PER_SECOND,
+            ..Default::default()
+        };
+        assert!(options.validate().is_ok());
+    }
+
+    #[test]
+    fn test_cache_evicts_oldest() {
+        let cache = SpectrogramCache::default();
+        let key = |index| TileKey {
+            source: "a".to_string(),
+            stamp: stamp(1),
+            settings: "s".to_string(),
+            index,
+        };
+        let tile = Arc::new(SpectrogramTile {
+            index: 0,
+            start_time: 0.0,
+            end_time: 1.0,
+            width: 1,
+            height: 1,
+            pixels: vec![0],
+        });
+
+        for index in 0..(MAX_CACHED_TILES as u32 + 1) {
+            cache.insert(key(index), tile.clone());
+        }
+        assert!(cache.get(&key(0)).is_none());
+        assert!(cache.get(&key(MAX_CACHED_TILES as u32)).is_some());
+    }
+
+    #[test]
+    fn test_cache_misses_after_file_changes() {
+        let cache = SpectrogramCache::default();
+        let key = |len| TileKey {
+            source: "a".to_string(),
+            stamp: stamp(len),
+            settings: "s".to_string(),
+            index: 0,
+        };
+        let tile = Arc::new(SpectrogramTile {
+            index: 0,
+            start_time: 0.0,
+            end_time: 1.0,
+            width: 1,
+            height: 1,
+            pixels: vec![0],
+        });
+
+        cache.insert(key(1), tile);
+        assert!(cache.get(&key(1)).is_some());
+        assert!(cache.get(&key(2)).is_none());
+    }
+}
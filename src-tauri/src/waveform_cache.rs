@@ -0,0 +1,671 @@
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::audio::{self, BandEnergies, ChannelMode, PeakBuffers, WaveformOptions, WaveformScale};
+use crate::file_stamp::{FileStamp, StampedCache};
+use crate::WaveformData;
+
+/// Bumped whenever peak generation changes so stale sidecars are ignored
+const GENERATOR_VERSION: u32 = 2;
+/// audiowaveform `.dat` format version (v2 adds the channel count)
+const DAT_VERSION: i32 = 2;
+/// Header flag: values are 8-bit instead of 16-bit
+const FLAG_8_BIT: u32 = 1;
+const DAT_HEADER_LEN: usize = 24;
+/// Most channels a peak file may hold; more means it is corrupt
+const MAX_DAT_CHANNELS: i32 = 32;
+
+/// Extension block: generator info (version, channel mode, frame count, normalization factors)
+const TAG_INFO: &[u8; 4] = b"TTIN";
+/// Extension block: RMS per entry, laid out like the min/max data
+const TAG_RMS: &[u8; 4] = b"TTRM";
+/// Extension block: mono mix min/max/RMS when the main channels are not the mono mix
+const TAG_MONO: &[u8; 4] = b"TTMO";
+/// Extension block: low/mid/high band energy of the mono mix
+const TAG_BANDS: &[u8; 4] = b"TTBD";
+
+/// The peak sidecar of one audio file for one channel mode.
+///
+/// The sidecar is keyed by a hash of the audio file and the generator version,
+/// so it is regenerated whenever either changes, or when band energies are
+/// requested but were not saved. It holds normalized peaks plus their
+/// normalization factors, so one file serves every `WaveformScale`. Loaded
+/// peaks are replayed through `on_chunk` so streaming consumers see the same
+/// events either way.
+pub struct Sidecar {
+    audio_path: PathBuf,
+    path: PathBuf,
+}
+
+impl Sidecar {
+    /// Hash `audio_path` and work out where its sidecar lives
+    pub fn locate(audio_path: &Path, options: &WaveformOptions) -> Result<Self, String> {
+        let hash = file_hash(audio_path)?;
+        Ok(Self {
+            audio_path: audio_path.to_path_buf(),
+            path: sidecar_path(audio_path, hash, options.channel_mode),
+        })
+    }
+
+    /// Cached peaks in `options.scale`, replayed through `on_chunk`, if the sidecar is usable
+    pub fn load<F>(&self, options: &WaveformOptions, on_chunk: &mut F) -> Option<WaveformData>
+    where
+        F: FnMut(&PeakBuffers, &[PeakBuffers], Option<&BandEnergies>, usize),
+    {
+        if !self.path.exists() {
+            return None;
+        }
+
+        match read_dat(&self.path) {
+            Ok(waveform) if options.band_energies && waveform.bands.is_none() => None,
+            Ok(mut waveform) => {
+                if !options.band_energies {
+                    waveform.bands = None;
+                }
+                audio::apply_scale(&mut waveform, options.scale);
+                replay_chunks(&waveform, on_chunk);
+                Some(waveform)
+            }
+            Err(e) => {
+                eprintln!(
+                    "[waveform] Ignoring unreadable peak cache {:?}: {}",
+                    self.path, e
+                );
+                None
+            }
+        }
+    }
+
+    /// Save freshly generated, still normalized peaks, replacing older versions
+    pub fn store(&self, waveform: &WaveformData) {
+        remove_stale_sidecars(&self.audio_path, &self.path);
+        if let Err(e) = write_dat(&self.path, waveform) {
+            eprintln!(
+                "[waveform] Failed to write peak cache {:?}: {}",
+                self.path, e
+            );
+        }
+    }
+}
+
+/// Hashes of recently seen files, so reopening one doesn't read it all again
+static FILE_HASHES: Mutex<StampedCache<u64>> = Mutex::new(StampedCache::new(256));
+
+/// 64-bit FNV-1a hash of the file contents.
+///
+/// Reuses the last hash of the file while its length and modification time
+/// are unchanged.
+pub fn file_hash(path: &Path) -> Result<u64, String> {
+    let stamp = FileStamp::of(path).ok();
+    let cached = stamp.and_then(|stamp| {
+        FILE_HASHES
+            .lock()
+            .ok()
+            .and_then(|hashes| hashes.get(path, stamp))
+    });
+    if let Some(hash) = cached {
+        return Ok(hash);
+    }
+
+    let hash = hash_contents(path)?;
+    if let (Some(stamp), Ok(mut hashes)) = (stamp, FILE_HASHES.lock()) {
+        hashes.insert(path.to_path_buf(), stamp, hash);
+    }
+    Ok(hash)
+}
+
+fn hash_contents(path: &Path) -> Result<u64, String> {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let file =
+        std::fs::File::open(path).map_err(|e| format!("Failed to open audio file: {}", e))?;
+    let mut reader = BufReader::with_capacity(1 << 16, file);
+    let mut buf = [0u8; 1 << 16];
+    let mut hash = OFFSET_BASIS;
+
+    loop {
+        let n = reader
+            .read(&mut buf)
+            .map_err(|e| format!("Failed to read audio file: {}", e))?;
+        if n == 0 {
+            break;
+        }
+        for &byte in &buf[..n] {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+
+    Ok(hash)
+}
+
+fn sidecar_prefix(audio_path: &Path) -> String {
+    let stem = audio_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("audio");
+    format!("{}.peaks-", stem)
+}
+
+fn sidecar_path(audio_path: &Path, hash: u64, mode: ChannelMode) -> PathBuf {
+    let mode = match mode {
+        ChannelMode::Mono => "mono",
+        ChannelMode::PerChannel => "channels",
+        ChannelMode::MidSide => "midside",
+    };
+    audio_path.with_file_name(format!(
+        "{}{:016x}-v{}-{}.dat",
+        sidecar_prefix(audio_path),
+        hash,
+        GENERATOR_VERSION,
+        mode
+    ))
+}
+
+/// Delete sidecars for older versions of the same audio file, keeping other channel modes.
+fn remove_stale_sidecars(audio_path: &Path, current: &Path) {
+    let (Some(dir), Some(current_name)) = (
+        audio_path.parent(),
+        current.file_name().and_then(|n| n.to_str()),
+    ) else {
+        return;
+    };
+    let prefix = sidecar_prefix(audio_path);
+    let mode_suffix = &current_name[current_name.rfind('-').unwrap_or(0)..];
+
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        if name.starts_with(&prefix) && name.ends_with(mode_suffix) && name != current_name {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Send loaded peaks to a chunk callback in the same chunk sizes as live generation
+fn replay_chunks<F>(waveform: &WaveformData, on_chunk: &mut F)
+where
+    F: FnMut(&PeakBuffers, &[PeakBuffers], Option<&BandEnergies>, usize),
+{
+    let mono = PeakBuffers {
+        peaks: waveform.peaks.clone(),
+        min_peaks: waveform.min_peaks.clone(),
+        max_peaks: waveform.max_peaks.clone(),
+        rms_peaks: waveform.rms_peaks.clone(),
+    };
+
+    for start in (0..mono.len()).step_by(audio::CHUNK_SIZE) {
+        let end = (start + audio::CHUNK_SIZE).min(mono.len());
+        let channels: Vec<PeakBuffers> = waveform
+            .channels
+            .iter()
+            .map(|c| slice_peaks(c, start, end))
+            .collect();
+        let bands = waveform.bands.as_ref().map(|b| BandEnergies {
+            low: b.low[start..end].to_vec(),
+            mid: b.mid[start..end].to_vec(),
+            high: b.high[start..end].to_vec(),
+        });
+        on_chunk(
+            &slice_peaks(&mono, start, end),
+            &channels,
+            bands.as_ref(),
+            start,
+        );
+    }
+}
+
+fn slice_peaks(peaks: &PeakBuffers, start: usize, end: usize) -> PeakBuffers {
+    PeakBuffers {
+        peaks: peaks.peaks[start..end].to_vec(),
+        min_peaks: peaks.min_peaks[start..end].to_vec(),
+        max_peaks: peaks.max_peaks[start..end].to_vec(),
+        rms_peaks: peaks.rms_peaks[start..end].to_vec(),
+    }
+}
+
+fn write_dat(path: &Path, waveform: &WaveformData) -> Result<(), String> {
+    std::fs::write(path, encode_dat(waveform))
+        .map_err(|e| format!("Failed to write peak file: {}", e))
+}
+
+fn read_dat(path: &Path) -> Result<WaveformData, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read peak file: {}", e))?;
+    decode_dat(&bytes)
+}
+
+fn to_i16(value: f32) -> i16 {
+    (value.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
+
+fn from_i16(value: i16) -> f32 {
+    value as f32 / i16::MAX as f32
+}
+
+/// Encode peaks as an audiowaveform v2 `.dat` file followed by extension blocks.
+///
+/// The min/max section holds the per-channel peaks, or the mono mix in `Mono`
+/// mode, so other audiowaveform readers see a normal file. RMS, the mono mix,
+/// band energies and generator info live in tagged blocks after the data,
+/// which those readers ignore.
+fn encode_dat(waveform: &WaveformData) -> Vec<u8> {
+    let mono = PeakBuffers {
+        peaks: Vec::new(),
+        min_peaks: waveform.min_peaks.clone(),
+        max_peaks: waveform.max_peaks.clone(),
+        rms_peaks: waveform.rms_peaks.clone(),
+    };
+    let main: Vec<&PeakBuffers> = if waveform.channels.is_empty() {
+        vec![&mono]
+    } else {
+        waveform.channels.iter().collect()
+    };
+    let length = waveform.min_peaks.len();
+
+    let mut bytes = Vec::with_capacity(DAT_HEADER_LEN + length * main.len() * 6 + 64);
+    bytes.extend_from_slice(&DAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(&(waveform.sample_rate as i32).to_le_bytes());
+    bytes.extend_from_slice(&(audio::SAMPLES_PER_PEAK as i32).to_le_bytes());
+    bytes.extend_from_slice(&(length as u32).to_le_bytes());
+    bytes.extend_from_slice(&(main.len() as i32).to_le_bytes());
+
+    for i in 0..length {
+        for channel in &main {
+            bytes.extend_from_slice(&to_i16(channel.min_peaks[i]).to_le_bytes());
+            bytes.extend_from_slice(&to_i16(channel.max_peaks[i]).to_le_bytes());
+        }
+    }
+
+    let total_frames = (waveform.duration_secs * waveform.sample_rate as f64).round() as u64;
+    let mut info = Vec::with_capacity(21);
+    info.extend_from_slice(&GENERATOR_VERSION.to_le_bytes());
+    info.push(match waveform.channel_mode {
+        ChannelMode::Mono => 0,
+        ChannelMode::PerChannel => 1,
+        ChannelMode::MidSide => 2,
+    });
+    info.extend_from_slice(&total_frames.to_le_bytes());
+    info.extend_from_slice(&waveform.normalization_factor.to_le_bytes());
+    info.extend_from_slice(&waveform.channel_normalization_factor.to_le_bytes());
+    push_block(&mut bytes, TAG_INFO, &info);
+
+    let mut rms = Vec::with_capacity(length * main.len() * 2);
+    for i in 0..length {
+        for channel in &main {
+            rms.extend_from_slice(&to_i16(channel.rms_peaks[i]).to_le_bytes());
+        }
+    }
+    push_block(&mut bytes, TAG_RMS, &rms);
+
+    if !waveform.channels.is_empty() {
+        let mut mono_block = Vec::with_capacity(length * 6);
+        for i in 0..length {
+            for value in [mono.min_peaks[i], mono.max_peaks[i], mono.rms_peaks[i]] {
+                mono_block.extend_from_slice(&to_i16(value).to_le_bytes());
+            }
+        }
+        push_block(&mut bytes, TAG_MONO, &mono_block);
+    }
+
+    if let Some(bands) = &waveform.bands {
+        let mut band_block = Vec::with_capacity(length * 6);
+        for i in 0..length {
+            for value in [bands.low[i], bands.mid[i], bands.high[i]] {
+                band_block.extend_from_slice(&to_i16(value).to_le_bytes());
+            }
+        }
+        push_block(&mut bytes, TAG_BANDS, &band_block);
+    }
+
+    bytes
+}
+
+fn push_block(bytes: &mut Vec<u8>, tag: &[u8; 4], payload: &[u8]) {
+    bytes.extend_from_slice(tag);
+    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(payload);
+}
+
+/// Contents of the `TAG_INFO` block
+struct GeneratorInfo {
+    channel_mode: ChannelMode,
+    total_frames: u64,
+    normalization_factor: f32,
+    channel_normalization_factor: f32,
+}
+
+/// Little-endian reader over a byte slice
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| "Peak file is truncated".to_string())?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        Ok(i16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+}
+
+fn decode_dat(bytes: &[u8]) -> Result<WaveformData, String> {
+    let mut reader = ByteReader { bytes, pos: 0 };
+
+    let version = reader.i32()?;
+    let flags = reader.u32()?;
+    let sample_rate = reader.i32()?;
+    let samples_per_pixel = reader.i32()?;
+    let length = reader.u32()? as usize;
+    let channel_count = reader.i32()?;
+
+    if version != DAT_VERSION || flags & FLAG_8_BIT != 0 {
+        return Err("Unsupported peak file format".to_string());
+    }
+    if samples_per_pixel != audio::SAMPLES_PER_PEAK as i32 || sample_rate <= 0 {
+        return Err("Peak file resolution does not match".to_string());
+    }
+    if !(1..=MAX_DAT_CHANNELS).contains(&channel_count) {
+        return Err(format!(
+            "Invalid channel count {} in peak file",
+            channel_count
+        ));
+    }
+    let channel_count = channel_count as usize;
+
+    let mut main = vec![PeakBuffers::default(); channel_count];
+    for _ in 0..length {
+        for channel in main.iter_mut() {
+            channel.min_peaks.push(from_i16(reader.i16()?));
+            channel.max_peaks.push(from_i16(reader.i16()?));
+        }
+    }
+
+    let mut info: Option<GeneratorInfo> = None;
+    let mut has_rms = false;
+    let mut mono: Option<PeakBuffers> = None;
+    let mut bands: Option<BandEnergies> = None;
+
+    while !reader.is_empty() {
+        let tag: [u8; 4] = reader.take(4)?.try_into().unwrap();
+        let len = reader.u32()? as usize;
+        let mut block = ByteReader {
+            bytes: reader.take(len)?,
+            pos: 0,
+        };
+
+        match &tag {
+            TAG_INFO => {
+                if block.u32()? != GENERATOR_VERSION {
+                    return Err(
+                        "Peak file was written by a different generator version".to_string()
+                    );
+                }
+                let channel_mode = match block.take(1)?[0] {
+                    0 => ChannelMode::Mono,
+                    1 => ChannelMode::PerChannel,
+                    2 => ChannelMode::MidSide,
+                    other => return Err(format!("Unknown channel mode {}", other)),
+                };
+                info = Some(GeneratorInfo {
+                    channel_mode,
+                    total_frames: u64::from_le_bytes(block.take(8)?.try_into().unwrap()),
+                    normalization_factor: block.f32()?,
+                    channel_normalization_factor: block.f32()?,
+                });
+            }
+            TAG_RMS => {
+                for _ in 0..length {
+                    for channel in main.iter_mut() {
+                        channel.rms_peaks.push(from_i16(block.i16()?));
+                    }
+                }
+                has_rms = true;
+            }
+            TAG_MONO => {
+                let mut buffers = PeakBuffers::default();
+                for _ in 0..length {
+                    buffers.min_peaks.push(from_i16(block.i16()?));
+                    buffers.max_peaks.push(from_i16(block.i16()?));
+                    buffers.rms_peaks.push(from_i16(block.i16()?));
+                }
+                mono = Some(buffers);
+            }
+            TAG_BANDS => {
+                let mut energies = BandEnergies::default();
+                for _ in 0..length {
+                    energies.low.push(from_i16(block.i16()?));
+                    energies.mid.push(from_i16(block.i16()?));
+                    energies.high.push(from_i16(block.i16()?));
+                }
+                bands = Some(energies);
+            }
+            // Unknown blocks come from newer writers; skip them
+            _ => {}
+        }
+    }
+
+    let info = info.ok_or_else(|| "Peak file has no generator info".to_string())?;
+    if !has_rms {
+        return Err("Peak file has no RMS data".to_string());
+    }
+
+    for channel in main.iter_mut() {
+        channel.peaks = abs_peaks(channel);
+    }
+
+    let (mut mono, channels) = match info.channel_mode {
+        ChannelMode::Mono => (main.remove(0), Vec::new()),
+        _ => (
+            mono.ok_or_else(|| "Peak file has no mono mix".to_string())?,
+            main,
+        ),
+    };
+    mono.peaks = abs_peaks(&mono);
+
+    Ok(WaveformData {
+        peaks: mono.peaks,
+        min_peaks: mono.min_peaks,
+        max_peaks: mono.max_peaks,
+        rms_peaks: mono.rms_peaks,
+        channel_mode: info.channel_mode,
+        channels,
+        bands,
+        scale: WaveformScale::Normalized,
+        normalization_factor: info.normalization_factor,
+        channel_normalization_factor: info.channel_normalization_factor,
+        duration_secs: info.total_frames as f64 / sample_rate as f64,
+        sample_rate: sample_rate as u32,
+    })
+}
+
+fn abs_peaks(peaks: &PeakBuffers) -> Vec<f32> {
+    peaks
+        .min_peaks
+        .iter()
+        .zip(&peaks.max_peaks)
+        .map(|(min, max)| min.abs().max(max.abs()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffers(values: &[(f32, f32, f32)]) -> PeakBuffers {
+        let mut buffers = PeakBuffers::default();
+        for &(min, max, rms) in values {
+            buffers.peaks.push(min.abs().max(max.abs()));
+            buffers.min_peaks.push(min);
+            buffers.max_peaks.push(max);
+            buffers.rms_peaks.push(rms);
+        }
+        buffers
+    }
+
+    fn waveform(mode: ChannelMode, channels: Vec<PeakBuffers>) -> WaveformData {
+        let mono = buffers(&[(-0.5, 1.0, 0.4), (-0.25, 0.125, 0.1), (0.0, 0.0, 0.0)]);
+        WaveformData {
+            peaks: mono.peaks,
+            min_peaks: mono.min_peaks,
+            max_peaks: mono.max_peaks,
+            rms_peaks: mono.rms_peaks,
+            channel_mode: mode,
+            channels,
+            bands: None,
+            scale: WaveformScale::Normalized,
+            normalization_factor: 0.5,
+            channel_normalization_factor: 0.75,
+            duration_secs: 700.0 / 44100.0,
+            sample_rate: 44100,
+        }
+    }
+
+    fn assert_close(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            assert!((x - y).abs() < 1e-4, "{} != {}", x, y);
+        }
+    }
+
+    #[test]
+    fn test_header_is_audiowaveform_v2() {
+        let bytes = encode_dat(&waveform(ChannelMode::Mono, Vec::new()));
+
+        assert_eq!(i32::from_le_bytes(bytes[0..4].try_into().unwrap()), 2);
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 0);
+        assert_eq!(i32::from_le_bytes(bytes[8..12].try_into().unwrap()), 44100);
+        assert_eq!(i32::from_le_bytes(bytes[12..16].try_into().unwrap()), 256);
+        assert_eq!(u32::from_le_bytes(bytes[16..20].try_into().unwrap()), 3);
+        assert_eq!(i32::from_le_bytes(bytes[20..24].try_into().unwrap()), 1);
+        // First min/max pair
+        assert_eq!(
+            i16::from_le_bytes(bytes[24..26].try_into().unwrap()),
+            -16384
+        );
+        assert_eq!(i16::from_le_bytes(bytes[26..28].try_into().unwrap()), 32767);
+    }
+
+    #[test]
+    fn test_mono_round_trip() {
+        let original = waveform(ChannelMode::Mono, Vec::new());
+        let decoded = decode_dat(&encode_dat(&original)).unwrap();
+
+        assert_close(&decoded.peaks, &original.peaks);
+        assert_close(&decoded.min_peaks, &original.min_peaks);
+        assert_close(&decoded.max_peaks, &original.max_peaks);
+        assert_close(&decoded.rms_peaks, &original.rms_peaks);
+        assert_eq!(decoded.channel_mode, ChannelMode::Mono);
+        assert!(decoded.channels.is_empty());
+        assert!(decoded.bands.is_none());
+        assert_eq!(decoded.normalization_factor, 0.5);
+        assert_eq!(decoded.channel_normalization_factor, 0.75);
+        assert!((decoded.duration_secs - original.duration_secs).abs() < 1e-9);
+        assert_eq!(decoded.sample_rate, 44100);
+    }
+
+    #[test]
+    fn test_per_channel_round_trip() {
+        let left = buffers(&[(-1.0, 1.0, 0.7), (-0.5, 0.5, 0.3), (0.0, 0.1, 0.05)]);
+        let right = buffers(&[(0.0, 0.0, 0.0), (-0.1, 0.2, 0.1), (-0.3, 0.3, 0.2)]);
+        let original = waveform(ChannelMode::PerChannel, vec![left.clone(), right.clone()]);
+
+        let bytes = encode_dat(&original);
+        assert_eq!(i32::from_le_bytes(bytes[20..24].try_into().unwrap()), 2);
+
+        let decoded = decode_dat(&bytes).unwrap();
+        assert_eq!(decoded.channels.len(), 2);
+        assert_close(&decoded.channels[0].min_peaks, &left.min_peaks);
+        assert_close(&decoded.channels[1].rms_peaks, &right.rms_peaks);
+        assert_close(&decoded.channels[1].peaks, &right.peaks);
+        assert_close(&decoded.max_peaks, &original.max_peaks);
+    }
+
+    #[test]
+    fn test_band_round_trip() {
+        let mut original = waveform(ChannelMode::Mono, Vec::new());
+        original.bands = Some(BandEnergies {
+            low: vec![0.9, 0.1, 0.0],
+            mid: vec![0.2, 0.05, 0.0],
+            high: vec![0.01, 0.3, 0.0],
+        });
+
+        let decoded = decode_dat(&encode_dat(&original)).unwrap();
+        let bands = decoded.bands.unwrap();
+        let expected = original.bands.unwrap();
+        assert_close(&bands.low, &expected.low);
+        assert_close(&bands.mid, &expected.mid);
+        assert_close(&bands.high, &expected.high);
+    }
+
+    #[test]
+    fn test_rejects_other_generator_version() {
+        let mut bytes = encode_dat(&waveform(ChannelMode::Mono, Vec::new()));
+        let info = bytes.windows(4).position(|w| w == TAG_INFO).unwrap();
+        bytes[info + 8..info + 12].copy_from_slice(&(GENERATOR_VERSION + 1).to_le_bytes());
+
+        assert!(decode_dat(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_rejects_truncated_file() {
+        let bytes = encode_dat(&waveform(ChannelMode::Mono, Vec::new()));
+        assert!(decode_dat(&bytes[..bytes.len() - 3]).is_err());
+        assert!(decode_dat(&bytes[..10]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_implausible_channel_count() {
+        let mut bytes = encode_dat(&waveform(ChannelMode::Mono, Vec::new()));
+        bytes[20..24].copy_from_slice(&i32::MAX.to_le_bytes());
+        assert!(decode_dat(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_sidecar_name_includes_hash_and_version() {
+        let path = sidecar_path(
+            Path::new("/data/audio/abc123.aac"),
+            0xdead_beef,
+            ChannelMode::Mono,
+        );
+        assert_eq!(
+            path,
+            PathBuf::from(format!(
+                "/data/audio/abc123.peaks-00000000deadbeef-v{}-mono.dat",
+                GENERATOR_VERSION
+            ))
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use specta::Type;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use tokio::io::{AsyncBufReadExt, BufReader};
@@ -13,6 +15,32 @@ lazy_static! {
     )
     .unwrap();
     static ref PROGRESS_REGEX: Regex = Regex::new(r"\[download\]\s+(\d+(?:\.\d+)?)").unwrap();
+    static ref PLAYLIST_ID_REGEX: Regex =
+        Regex::new(r"(?:youtube\.com|youtu\.be)/.*[?&]list=([a-zA-Z0-9_-]+)").unwrap();
+    static ref CHANNEL_URL_REGEX: Regex = Regex::new(
+        r"youtube\.com/(@[\w.-]+|channel/[\w-]+|c/[\w.-]+|user/[\w.-]+)(?:/(videos|streams|shorts))?"
+    )
+    .unwrap();
+    static ref VIDEO_ID_REGEX: Regex = Regex::new(r"^[a-zA-Z0-9_-]{11}$").unwrap();
+}
+
+/// A video listed by a playlist or channel
+#[derive(Clone, Debug, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistEntry {
+    pub video_id: String,
+    pub title: String,
+    /// Missing for some entries, e.g. upcoming premieres
+    pub duration_secs: Option<f64>,
+    pub url: String,
+}
+
+/// Videos of a playlist or channel, in listing order
+#[derive(Clone, Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistInfo {
+    pub title: String,
+    pub entries: Vec<PlaylistEntry>,
 }
 
 pub fn extract_video_id(url: &str) -> Option<String> {
@@ -22,6 +50,87 @@ pub fn extract_video_id(url: &str) -> Option<String> {
         .map(|m| m.as_str().to_string())
 }
 
+/// The URL to list when `url` points at a playlist or a channel.
+///
+/// Channel URLs without a tab list the channel's uploads.
+pub fn playlist_url(url: &str) -> Option<String> {
+    if let Some(caps) = PLAYLIST_ID_REGEX.captures(url) {
+        let list_id = &caps[1];
+        return Some(format!("https://www.youtube.com/playlist?list={}", list_id));
+    }
+    CHANNEL_URL_REGEX.captures(url).map(|caps| {
+        let tab = caps.get(2).map_or("videos", |m| m.as_str());
+        format!("https://www.youtube.com/{}/{}", &caps[1], tab)
+    })
+}
+
+/// List the videos of a playlist or channel without downloading anything
+pub async fn expand_playlist(url: &str, qjs_path: Option<PathBuf>) -> Result<PlaylistInfo, String> {
+    let list_url =
+        playlist_url(url).ok_or_else(|| "Not a YouTube playlist or channel URL".to_string())?;
+    let yt_dlp = find_yt_dlp()?;
+
+    let mut cmd = Command::new(&yt_dlp);
+    cmd.arg("--flat-playlist")
+        .arg("--dump-single-json")
+        .arg("--no-warnings")
+        .arg("--socket-timeout")
+        .arg("30");
+    add_js_runtime(&mut cmd, qjs_path);
+
+    let output = cmd
+        .arg(&list_url)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to spawn yt-dlp: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "yt-dlp failed to list {} (exit code: {}):\n{}",
+            list_url,
+            output.status.code().unwrap_or(-1),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Failed to parse playlist: {}", e))?;
+    parse_playlist(&json)
+}
+
+/// Read yt-dlp's `--flat-playlist` JSON, keeping only downloadable videos
+fn parse_playlist(json: &serde_json::Value) -> Result<PlaylistInfo, String> {
+    let entries = json["entries"]
+        .as_array()
+        .ok_or_else(|| "Playlist has no entries".to_string())?;
+
+    let entries = entries
+        .iter()
+        .filter_map(|entry| {
+            // Channel pages without a tab list their tabs as nested playlists
+            let video_id = entry["id"].as_str()?;
+            if !VIDEO_ID_REGEX.is_match(video_id) {
+                return None;
+            }
+            let title = entry["title"].as_str().unwrap_or("Unknown");
+            if title == "[Private video]" || title == "[Deleted video]" {
+                return None;
+            }
+            Some(PlaylistEntry {
+                video_id: video_id.to_string(),
+                title: title.to_string(),
+                duration_secs: entry["duration"].as_f64(),
+                url: format!("https://www.youtube.com/watch?v={}", video_id),
+            })
+        })
+        .collect();
+
+    Ok(PlaylistInfo {
+        title: json["title"].as_str().unwrap_or("Untitled").to_string(),
+        entries,
+    })
+}
+
 pub async fn fetch_oembed_metadata(url: &str) -> Result<VideoMetadata, String> {
     let video_id = extract_video_id(url).ok_or_else(|| "Invalid YouTube URL".to_string())?;
 
@@ -97,13 +206,7 @@ where
         .arg("--socket-timeout")
         .arg("30");
 
-    if let Some(qjs) = qjs_path {
-        let qjs_str = qjs.to_string_lossy().to_string();
-        eprintln!("[tubetape] Using qjs runtime: {}", qjs_str);
-        cmd.arg("--no-js-runtimes")
-            .arg("--js-runtimes")
-            .arg(format!("quickjs:{}", qjs_str));
-    }
+    add_js_runtime(&mut cmd, qjs_path);
 
     let mut child = cmd
         .arg(url)
@@ -241,6 +344,17 @@ where
     ))
 }
 
+/// Point yt-dlp at the bundled QuickJS for YouTube's JS challenges
+fn add_js_runtime(cmd: &mut Command, qjs_path: Option<PathBuf>) {
+    if let Some(qjs) = qjs_path {
+        let qjs_str = qjs.to_string_lossy().to_string();
+        eprintln!("[tubetape] Using qjs runtime: {}", qjs_str);
+        cmd.arg("--no-js-runtimes")
+            .arg("--js-runtimes")
+            .arg(format!("quickjs:{}", qjs_str));
+    }
+}
+
 fn find_yt_dlp() -> Result<String, String> {
     let candidates = [
         "yt-dlp",
@@ -260,3 +374,54 @@ fn find_yt_dlp() -> Result<String, String> {
 
     Err("yt-dlp not found. Please install it: brew install yt-dlp".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_playlist_url() {
+        assert_eq!(
+            playlist_url("https://www.youtube.com/playlist?list=PLabc_123-x").as_deref(),
+            Some("https://www.youtube.com/playlist?list=PLabc_123-x")
+        );
+        assert_eq!(
+            playlist_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PLabc").as_deref(),
+            Some("https://www.youtube.com/playlist?list=PLabc")
+        );
+        assert_eq!(
+            playlist_url("https://www.youtube.com/@SomeLabel").as_deref(),
+            Some("https://www.youtube.com/@SomeLabel/videos")
+        );
+        assert_eq!(
+            playlist_url("https://youtube.com/channel/UCabcdefghijklmnopqrstuv/streams").as_deref(),
+            Some("https://www.youtube.com/channel/UCabcdefghijklmnopqrstuv/streams")
+        );
+        assert!(playlist_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ").is_none());
+        assert!(playlist_url("https://example.com/playlist?list=PLabc").is_none());
+    }
+
+    #[test]
+    fn test_parse_playlist_keeps_downloadable_videos() {
+        let json = serde_json::json!({
+            "title": "Breaks",
+            "entries": [
+                { "id": "dQw4w9WgXcQ", "title": "First", "duration": 212.0 },
+                { "id": "aaaaaaaaaaa", "title": "[Private video]", "duration": null },
+                { "id": "UCabcdefghijklmnopqrstuv", "title": "Channel - Videos" },
+                { "id": "bbbbbbbbbbb", "title": "Premiere" },
+            ]
+        });
+
+        let playlist = parse_playlist(&json).unwrap();
+        assert_eq!(playlist.title, "Breaks");
+        assert_eq!(playlist.entries.len(), 2);
+        assert_eq!(playlist.entries[0].video_id, "dQw4w9WgXcQ");
+        assert_eq!(playlist.entries[0].duration_secs, Some(212.0));
+        assert_eq!(
+            playlist.entries[0].url,
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+        );
+        assert_eq!(playlist.entries[1].duration_secs, None);
+    }
+}
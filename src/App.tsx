@@ -1,6 +1,6 @@
-import { useState, useCallback, useEffect } from "react";
+import { useState, useCallback, useEffect, useRef } from "react";
 import { Channel } from "@tauri-apps/api/core";
-import { save } from "@tauri-apps/plugin-dialog";
+import { open, save } from "@tauri-apps/plugin-dialog";
 import "./App.css";
 
 import ProjectCombobox from "./components/ProjectCombobox";
@@ -9,14 +9,21 @@ import VideoUnfurl from "./components/VideoUnfurl";
 import Waveform from "./components/Waveform";
 import SampleWaveform from "./components/SampleWaveform";
 import ErrorDialog from "./components/ErrorDialog";
+import QueuePanel from "./components/QueuePanel";
+import InterruptedPipelines from "./components/InterruptedPipelines";
 import { getDatabase, generateSampleId, type SampleDocType, type TubetapeDatabase } from "./lib/db";
 import type { AppState, Project, AudioInfo } from "./types";
-import { commands, type VideoMetadata, type BeatInfo, type PipelineEvent, type PipelineCommand } from "./bindings";
+import { commands, type VideoMetadata, type BeatInfo, type PipelineEvent, type PipelineCommand, type SavedPipeline } from "./bindings";
 import { useAppStats } from "./hooks/useAppStats";
 import { usePyodide } from "./hooks/usePyodide";
+import { useJobQueue } from "./hooks/useJobQueue";
 
 function App() {
   const [appState, setAppState] = useState<AppState>("idle");
+  // Pipeline whose progress is on screen; the Cancel button targets it
+  const activePipelineId = useRef<string | null>(null);
+  // Running Pyodide extractions by pipeline ID, so cancelling a pipeline stops its download
+  const extractionAborts = useRef(new Map<string, AbortController>());
   const { stats, refetch: refetchStats } = useAppStats();
   const pyodide = usePyodide();
   const [metadata, setMetadata] = useState<VideoMetadata | null>(null);
@@ -32,11 +39,23 @@ function App() {
   const [currentProject, setCurrentProject] = useState<Project | null>(null);
   const [audioInfo, setAudioInfo] = useState<AudioInfo | null>(null);
   const [beatInfo, setBeatInfo] = useState<BeatInfo | null>(null);
+  // Runs left unfinished when the app last quit
+  const [interrupted, setInterrupted] = useState<SavedPipeline[]>([]);
 
   useEffect(() => {
     getDatabase().then(setDb);
   }, []);
 
+  useEffect(() => {
+    commands.listInterruptedPipelines().then((result) => {
+      if (result.status === "ok") {
+        setInterrupted(result.data);
+      } else {
+        console.debug('[App] Failed to list interrupted pipelines:', result.error);
+      }
+    });
+  }, []);
+
   useEffect(() => {
     if (!db) return;
 
@@ -95,14 +114,21 @@ function App() {
    * Run Pyodide extraction and report progress back to the pipeline.
    * Called when pipeline emits RequestExtraction event.
    */
-  const runExtraction = useCallback(async (url: string, outputPath: string): Promise<void> => {
+  const runExtraction = useCallback(async (pipelineId: string, url: string, outputPath: string): Promise<void> => {
     // Ensure Pyodide is initialized
     if (pyodide.status === 'idle' || pyodide.status === 'error') {
       await pyodide.initialize();
     }
 
+    // A retried extraction replaces the attempt the pipeline gave up on,
+    // which would otherwise keep writing to the same output file
+    extractionAborts.current.get(pipelineId)?.abort();
+    const controller = new AbortController();
+    extractionAborts.current.set(pipelineId, controller);
+
     // Run extraction with progress forwarding
-    const result = await pyodide.extractAudio(url, outputPath, {
+    const extraction = pyodide.extractAudio(url, outputPath, {
+      signal: controller.signal,
       onProgress: async (extractProgress) => {
         // Forward progress to pipeline based on phase
         if (extractProgress.phase === 'error') {
@@ -120,16 +146,29 @@ function App() {
 
         const command: PipelineCommand = {
           command: "downloadProgress",
-          data: { bytesDownloaded, totalBytes }
+          data: { bytesDownloaded, totalBytes, partialPath: downloadProgress.path ?? null }
         };
 
-        const result = await commands.pipelineNotify(command);
+        const result = await commands.pipelineNotify(pipelineId, command);
         if (result.status === "error") {
           console.debug('[App] Failed to forward download progress:', result.error);
         }
       }
     });
 
+    const result = await extraction
+      .catch((err) => {
+        // Aborted because the pipeline was cancelled, which needs no reply
+        if (controller.signal.aborted) return null;
+        throw err;
+      })
+      .finally(() => {
+        if (extractionAborts.current.get(pipelineId) === controller) {
+          extractionAborts.current.delete(pipelineId);
+        }
+      });
+    if (!result) return;
+
     // Notify pipeline that extraction completed
     const ffmpegCommands = Array.isArray(result.ffmpegCommands) ? result.ffmpegCommands : [];
     const completeCommand: PipelineCommand = {
@@ -146,10 +185,20 @@ function App() {
         }))
       }
     };
-    await commands.pipelineNotify(completeCommand);
+    await commands.pipelineNotify(pipelineId, completeCommand);
   }, [pyodide, parseDownloadSize]);
 
-  const handleUrlSubmit = useCallback(async (url: string) => {
+  const abortExtraction = useCallback((pipelineId: string) => {
+    extractionAborts.current.get(pipelineId)?.abort();
+  }, []);
+
+  const queue = useJobQueue(runExtraction, abortExtraction, refetchStats);
+
+  /**
+   * Run the pipeline for `url`, or continue the interrupted run `resumeFrom`
+   * of it, showing its progress on screen.
+   */
+  const startPipeline = useCallback(async (url: string, resumeFrom: string | null) => {
     setError(null);
     setAppState("loading-metadata");
     setAudioPath(null);
@@ -175,30 +224,36 @@ function App() {
 
     // Create pipeline event channel
     const pipelineChannel = new Channel<PipelineEvent>();
+    let cancelled = false;
+    let runPipelineId: string | null = null;
 
     pipelineChannel.onmessage = async (event) => {
       switch (event.event) {
         case "started":
-          console.log('[Pipeline] Started:', event.data.stages);
+          console.log('[Pipeline] Started:', event.data.pipelineId, event.data.stages);
+          runPipelineId = event.data.pipelineId;
+          activePipelineId.current = runPipelineId;
           break;
 
-        case "requestExtraction":
+        case "requestExtraction": {
           // Pipeline is asking us to run Pyodide extraction
           console.log('[Pipeline] Extraction requested for:', event.data.url);
-          runExtraction(event.data.url, event.data.outputPath).catch(async (err) => {
+          const { pipelineId } = event.data;
+          runExtraction(pipelineId, event.data.url, event.data.outputPath).catch(async (err) => {
             // Notify pipeline of failure
             const failCommand: PipelineCommand = {
               command: "extractionFailed",
               data: { message: err instanceof Error ? err.message : String(err) }
             };
-            await commands.pipelineNotify(failCommand);
+            await commands.pipelineNotify(pipelineId, failCommand);
           });
           break;
+        }
 
         case "progress":
           setProgress({
-            percent: event.data.overallPercent,
-            status: event.data.message
+            percent: event.data.progress.overallPercent,
+            status: event.data.progress.message
           });
           break;
 
@@ -218,28 +273,158 @@ function App() {
 
         case "completed":
           console.log('[Pipeline] Completed:', event.data);
-          setAudioPath(event.data.audioPath);
+          setAudioPath(event.data.result.audioPath);
           setProgress(null);
           setAppState("ready");
           refetchStats();
           break;
 
+        case "retrying":
+          console.warn('[Pipeline] Retrying:', event.data);
+          setProgress((prev) => ({
+            percent: prev?.percent ?? 0,
+            status: `Retrying in ${Math.round(event.data.delayMs / 1000)}s (attempt ${event.data.attempt})...`
+          }));
+          break;
+
         case "error":
           console.error('[Pipeline] Error:', event.data);
           setError(`${event.data.stage}: ${event.data.message}`);
           setAppState("error");
           break;
+
+        case "cancelled":
+          console.log('[Pipeline] Cancelled during:', event.data.stage);
+          cancelled = true;
+          if (runPipelineId) abortExtraction(runPipelineId);
+          setProgress(null);
+          setMetadata(null);
+          setAppState("idle");
+          break;
       }
     };
 
     // Start the unified pipeline - it will emit RequestExtraction for us to handle
-    const pipelineResult = await commands.runPipeline(url, pipelineChannel);
-    if (pipelineResult.status === "error") {
+    const pipelineResult = resumeFrom
+      ? await commands.resumePipeline(resumeFrom, pipelineChannel)
+      : await commands.runPipeline(url, null, null, null, pipelineChannel);
+    if (activePipelineId.current === runPipelineId) {
+      activePipelineId.current = null;
+    }
+    if (pipelineResult.status === "error" && !cancelled) {
       console.error('[App] Pipeline failed:', pipelineResult.error);
       setError(pipelineResult.error);
       setAppState("error");
     }
-  }, [refetchStats, runExtraction]);
+  }, [refetchStats, runExtraction, abortExtraction]);
+
+  const handleUrlSubmit = useCallback((url: string) => startPipeline(url, null), [startPipeline]);
+
+  const handleResumeInterrupted = useCallback((saved: SavedPipeline) => {
+    setInterrupted((prev) => prev.filter((p) => p.pipelineId !== saved.pipelineId));
+    setCurrentProject(null);
+    startPipeline(saved.url, saved.pipelineId);
+  }, [startPipeline]);
+
+  const handleDiscardInterrupted = useCallback(async (saved: SavedPipeline) => {
+    setInterrupted((prev) => prev.filter((p) => p.pipelineId !== saved.pipelineId));
+    const result = await commands.discardPipeline(saved.pipelineId);
+    if (result.status === "error") {
+      console.debug('[App] Failed to discard pipeline:', result.error);
+    }
+    refetchStats();
+  }, [refetchStats]);
+
+  const handleCancel = useCallback(async () => {
+    const pipelineId = activePipelineId.current;
+    if (!pipelineId) return;
+    abortExtraction(pipelineId);
+    const result = await commands.pipelineNotify(pipelineId, { command: "cancel" });
+    if (result.status === "error") {
+      console.debug('[App] Failed to cancel pipeline:', result.error);
+    }
+  }, [abortExtraction]);
+
+  const handleImportFile = useCallback(async () => {
+    const path = await open({ multiple: false, directory: false });
+    if (typeof path !== "string") return;
+
+    setError(null);
+    setAudioPath(null);
+    setDuration(0);
+    setSamples([]);
+    setSelectedRegion(null);
+    setAudioBuffer(null);
+    setBeatInfo(null);
+    setCurrentProject(null);
+
+    const fileName = path.split(/[\\/]/).pop() ?? path;
+    setMetadata({
+      title: fileName.replace(/\.[^.]+$/, ""),
+      authorName: "Local file",
+      authorUrl: "",
+      thumbnailUrl: "",
+      videoId: "",
+    });
+    setAppState("extracting");
+    setProgress({ percent: 0, status: "Importing..." });
+
+    const pipelineChannel = new Channel<PipelineEvent>();
+    let cancelled = false;
+    let runPipelineId: string | null = null;
+
+    pipelineChannel.onmessage = (event) => {
+      switch (event.event) {
+        case "started":
+          runPipelineId = event.data.pipelineId;
+          activePipelineId.current = runPipelineId;
+          break;
+        case "progress":
+          setProgress({ percent: event.data.progress.overallPercent, status: event.data.progress.message });
+          break;
+        case "waveformComplete":
+          setDuration(event.data.durationSecs);
+          setAudioInfo({
+            sampleRate: event.data.sampleRate,
+            durationSecs: event.data.durationSecs,
+          });
+          break;
+        case "beatDetectionComplete":
+          setBeatInfo(event.data);
+          break;
+        case "completed":
+          setAudioPath(event.data.result.audioPath);
+          setProgress(null);
+          setAppState("ready");
+          refetchStats();
+          break;
+        case "error":
+          setError(`${event.data.stage}: ${event.data.message}`);
+          setAppState("error");
+          break;
+        case "cancelled":
+          cancelled = true;
+          setProgress(null);
+          setMetadata(null);
+          setAppState("idle");
+          break;
+      }
+    };
+
+    const importResult = await commands.importLocalFile(path, null, null, pipelineChannel);
+    if (activePipelineId.current === runPipelineId) {
+      activePipelineId.current = null;
+    }
+    if (importResult.status === "ok") {
+      // Samples are keyed by the content hash ID, known once the file is hashed
+      const { sourceId, title } = importResult.data;
+      setMetadata((current) => current && { ...current, title, videoId: sourceId });
+    } else if (!cancelled) {
+      console.error('[App] Import failed:', importResult.error);
+      setError(importResult.error);
+      setAppState("error");
+    }
+  }, [refetchStats]);
 
   const handleReset = useCallback(() => {
     setAppState("idle");
@@ -266,7 +451,7 @@ function App() {
     setAudioBuffer(null);
     setBeatInfo(null);
 
-    const cachedResult = await commands.checkCachedAudio(project.videoId);
+    const cachedResult = await commands.checkCachedAudio(project.videoId, null, null);
     if (cachedResult.status === "error") {
       console.error('[App] Cache check failed:', cachedResult.error);
       setError(cachedResult.error);
@@ -285,6 +470,21 @@ function App() {
         videoId: project.videoId,
       });
       setCurrentProject(project);
+
+      // Saved analysis is still valid, so there is nothing to recompute
+      if (cachedAudio.analysis) {
+        const { waveform, beatInfo } = cachedAudio.analysis;
+        setAudioPath(cachedAudio.audioPath);
+        setDuration(waveform.durationSecs);
+        setAudioInfo({
+          sampleRate: waveform.sampleRate,
+          durationSecs: waveform.durationSecs,
+        });
+        setBeatInfo(beatInfo);
+        setAppState("ready");
+        return;
+      }
+
       setAppState("extracting");
       setProgress({ percent: 0, status: "Processing audio..." });
 
@@ -294,7 +494,7 @@ function App() {
       pipelineChannel.onmessage = (event) => {
         switch (event.event) {
           case "progress": {
-            const displayPercent = event.data.overallPercent;
+            const displayPercent = event.data.progress.overallPercent;
             setProgress({ percent: displayPercent, status: "Processing audio..." });
           } break;
           case "waveformComplete":
@@ -320,12 +520,16 @@ function App() {
         }
       };
 
-      const processResult = await commands.processAudio(cachedAudio.audioPath, pipelineChannel);
+      const processResult = await commands.processAudio(cachedAudio.audioPath, null, null, pipelineChannel);
       if (processResult.status === "error") {
         console.error('[App] Process audio failed:', processResult.error);
         setError(processResult.error);
         setAppState("error");
       }
+    } else if (project.videoId.startsWith("local-")) {
+      // Imported files can't be fetched again; they have to be re-imported
+      setError("The imported audio is no longer in the library");
+      setAppState("error");
     } else {
       const url = `https://youtube.com/watch?v=${project.videoId}`;
       await handleUrlSubmit(url);
@@ -431,6 +635,12 @@ function App() {
             <div className="flex items-center gap-2 text-cyber-400 text-xs">
               <div className="w-3 h-3 border border-acid-green border-t-transparent rounded-full animate-spin" />
               <span className="truncate">{metadata.title}</span>
+              <button
+                onClick={handleCancel}
+                className="text-cyber-400 hover:text-red-400 transition-colors"
+              >
+                Cancel
+              </button>
             </div>
           )}
           {appState === "ready" && metadata && (
@@ -462,6 +672,35 @@ function App() {
               <p className="text-cyber-500 text-sm uppercase tracking-widest">
                 Paste a YouTube URL to begin
               </p>
+              <button
+                onClick={handleImportFile}
+                className="mt-2 text-xs text-cyber-400 hover:text-neon-cyan transition-colors uppercase tracking-wider"
+              >
+                or import a local file
+              </button>
+              {interrupted.length > 0 && (
+                <div className="mt-6 flex justify-center">
+                  <InterruptedPipelines
+                    pipelines={interrupted}
+                    onResume={handleResumeInterrupted}
+                    onDiscard={handleDiscardInterrupted}
+                  />
+                </div>
+              )}
+              <div className="mt-6 flex justify-center">
+                <QueuePanel
+                  jobs={queue.jobs}
+                  batches={queue.batches}
+                  onEnqueue={queue.enqueue}
+                  onExpandPlaylist={queue.expandPlaylist}
+                  onEnqueueBatch={queue.enqueueBatch}
+                  onBump={queue.bump}
+                  onPause={queue.pause}
+                  onResume={queue.resume}
+                  onRemove={queue.remove}
+                  onCancel={queue.cancel}
+                />
+              </div>
             </div>
           </div>
         )}
@@ -29,17 +29,42 @@ async extractAudio(url: string, onEvent: TAURI_CHANNEL<ExtractionEvent>) : Promi
     else return { status: "error", error: e  as any };
 }
 },
-async getWaveform(audioPath: string) : Promise<Result<WaveformData, string>> {
+async getWaveform(audioPath: string, options: WaveformOptions | null) : Promise<Result<WaveformData, string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("get_waveform", { audioPath }) };
+    return { status: "ok", data: await TAURI_INVOKE("get_waveform", { audioPath, options }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
 },
-async generateWaveformStream(audioPath: string, onEvent: TAURI_CHANNEL<WaveformEvent>) : Promise<Result<WaveformData, string>> {
+/**
+ * Peaks for a visible time range at exactly the resolution needed for `pixels` columns.
+ * The peak pyramid for a file is built on first use and kept in memory until
+ * the file changes. At most `MAX_RANGE_PIXELS` (32768) columns may be requested.
+ */
+async getWaveformRange(audioPath: string, startTime: number, endTime: number, pixels: number) : Promise<Result<WaveformRange, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_waveform_range", { audioPath, startTime, endTime, pixels }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async generateWaveformStream(audioPath: string, options: WaveformOptions | null, onEvent: TAURI_CHANNEL<WaveformEvent>) : Promise<Result<WaveformData, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("generate_waveform_stream", { audioPath, options, onEvent }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Stream spectrogram tiles covering a time range.
+ * Tiles are cached per source file and zoom level, so repeated requests are served from memory.
+ */
+async generateSpectrogram(audioPath: string, startTime: number, endTime: number, options: SpectrogramOptions | null, onEvent: TAURI_CHANNEL<SpectrogramEvent>) : Promise<Result<null, string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("generate_waveform_stream", { audioPath, onEvent }) };
+    return { status: "ok", data: await TAURI_INVOKE("generate_spectrogram", { audioPath, startTime, endTime, options, onEvent }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -53,9 +78,13 @@ async exportSample(sourcePath: string, outputPath: string, startTime: number, en
     else return { status: "error", error: e  as any };
 }
 },
-async checkCachedAudio(videoId: string) : Promise<Result<CachedAudioInfo | null, string>> {
+/**
+ * Look up stored audio for a video ID, along with its saved analysis when
+ * that is still valid for `waveform_options` and `beat_config`.
+ */
+async checkCachedAudio(videoId: string, waveformOptions: WaveformOptions | null, beatConfig: BeatDetectionConfig | null) : Promise<Result<CachedAudioInfo | null, string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("check_cached_audio", { videoId }) };
+    return { status: "ok", data: await TAURI_INVOKE("check_cached_audio", { videoId, waveformOptions, beatConfig }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -69,9 +98,45 @@ async getAppStats() : Promise<Result<AppStats, string>> {
     else return { status: "error", error: e  as any };
 }
 },
-async analyzeAudioBeats(audioPath: string) : Promise<Result<BeatInfo, string>> {
+async analyzeAudioBeats(audioPath: string, config: BeatDetectionConfig | null) : Promise<Result<BeatInfo, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("analyze_audio_beats", { audioPath, config }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Run the requested analyses over just the `[start, end)` selection of a file.
+ * Only that span is decoded, and all analyses share the one pass.
+ */
+async analyzeRegion(audioPath: string, start: number, end: number, analyses: RegionAnalysis[], beatConfig: BeatDetectionConfig | null) : Promise<Result<RegionAnalysisResult, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("analyze_region", { audioPath, start, end, analyses, beatConfig }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Rank the bars of a track by how likely they are to be exposed drum breaks.
+ */
+async findDrumBreaks(audioPath: string, options: DrumBreakOptions | null) : Promise<Result<DrumBreakCandidate[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("find_drum_breaks", { audioPath, options }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Render a region of up to ten minutes into separate harmonic and
+ * percussive WAV stems.
+ * The stem files can be passed to `export_sample` like any other source.
+ */
+async renderHpssStems(sourcePath: string, startTime: number, endTime: number) : Promise<Result<HpssStems, string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("analyze_audio_beats", { audioPath }) };
+    return { status: "ok", data: await TAURI_INVOKE("render_hpss_stems", { sourcePath, startTime, endTime }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -81,9 +146,9 @@ async analyzeAudioBeats(audioPath: string) : Promise<Result<BeatInfo, string>> {
  * Process an existing audio file (waveform + beat detection).
  * Used when audio is already downloaded (e.g., from cache).
  */
-async processAudio(audioPath: string, onEvent: TAURI_CHANNEL<PipelineEvent>) : Promise<Result<PipelineResult, string>> {
+async processAudio(audioPath: string, waveformOptions: WaveformOptions | null, beatConfig: BeatDetectionConfig | null, onEvent: TAURI_CHANNEL<PipelineEvent>) : Promise<Result<PipelineResult, string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("process_audio", { audioPath, onEvent }) };
+    return { status: "ok", data: await TAURI_INVOKE("process_audio", { audioPath, waveformOptions, beatConfig, onEvent }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -96,28 +161,199 @@ async processAudio(audioPath: string, onEvent: TAURI_CHANNEL<PipelineEvent>) : P
  * 1. Emits `RequestExtraction` for the frontend to run Pyodide/yt-dlp
  * 2. Waits for extraction progress/completion via `pipeline_notify`
  * 3. Runs FFmpeg commands for audio conversion
- * 4. Runs waveform + beat detection in parallel
+ * 4. Runs the configured analysis stages, by default waveform + beat detection
  * 5. Reports unified progress throughout
+ * 
+ * Each run gets its own pipeline ID, sent with every event, so several
+ * pipelines can run at once; a second run of a video that is still running
+ * is rejected, as both would write the same file. Runs join the job queue
+ * ahead of queued jobs and wait for its download and analysis slots like them.
+ * Recoverable failures are retried by `config.retry_policy`, or the default
+ * policy, with a `Retrying` event before each new attempt. Extraction that
+ * stalls or runs past `config.extraction_timeouts` fails with a recoverable error.
+ */
+async runPipeline(url: string, waveformOptions: WaveformOptions | null, beatConfig: BeatDetectionConfig | null, config: PipelineConfig | null, onEvent: TAURI_CHANNEL<PipelineEvent>) : Promise<Result<PipelineResult, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("run_pipeline", { url, waveformOptions, beatConfig, config, onEvent }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Pipeline runs that were interrupted before finishing, such as by the app
+ * quitting, and can be continued with `resume_pipeline`
+ */
+async listInterruptedPipelines() : Promise<Result<SavedPipeline[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("list_interrupted_pipelines") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Continue an interrupted pipeline run from its last saved stage.
+ * 
+ * The run gets a new pipeline ID, sent in its `Started` event. If it was
+ * still extracting, `RequestExtraction` is emitted again and the download
+ * picks up where it stopped; otherwise the conversion and analysis stages
+ * that had not finished are run on the extracted audio, with the config the
+ * interrupted run was started with. Like `run_pipeline`, it waits for the
+ * job queue's slots.
+ */
+async resumePipeline(pipelineId: string, onEvent: TAURI_CHANNEL<PipelineEvent>) : Promise<Result<PipelineResult, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("resume_pipeline", { pipelineId, onEvent }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Forget an interrupted pipeline run, deleting its partial download.
+ * Fails while a running pipeline writes the same file.
+ */
+async discardPipeline(pipelineId: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("discard_pipeline", { pipelineId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Import a local audio or video file into the audio store and analyze it.
+ * 
+ * The file is probed with FFmpeg and its audio stream copied or transcoded
+ * into the store under a content hash ID, so importing the same file twice
+ * reuses the first copy. The ID works like a video ID with `check_cached_audio`.
+ * Like other runs, the import can be cancelled through `pipeline_notify`.
  */
-async runPipeline(url: string, onEvent: TAURI_CHANNEL<PipelineEvent>) : Promise<Result<PipelineResult, string>> {
+async importLocalFile(path: string, waveformOptions: WaveformOptions | null, beatConfig: BeatDetectionConfig | null, onEvent: TAURI_CHANNEL<PipelineEvent>) : Promise<Result<ImportedFile, string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("run_pipeline", { url, onEvent }) };
+    return { status: "ok", data: await TAURI_INVOKE("import_local_file", { path, waveformOptions, beatConfig, onEvent }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
 },
 /**
- * Send a command to the running pipeline.
+ * Send a command to the pipeline with the given ID.
  * 
  * Used by the frontend to:
  * - Report extraction progress (`DownloadProgress`)
  * - Signal extraction completion (`ExtractionComplete`)
  * - Signal extraction failure (`ExtractionFailed`)
+ * - Stop the pipeline (`Cancel`)
+ */
+async pipelineNotify(pipelineId: string, command: PipelineCommand) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("pipeline_notify", { pipelineId, command }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Queue a pipeline for `url` to run in the background.
+ * 
+ * Returns the job ID, which is also the pipeline ID once the job starts.
+ * Events for the job arrive on `on_event` exactly as for `run_pipeline`.
+ */
+async enqueuePipeline(url: string, priority: number | null, waveformOptions: WaveformOptions | null, beatConfig: BeatDetectionConfig | null, onEvent: TAURI_CHANNEL<PipelineEvent>) : Promise<Result<string, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("enqueue_pipeline", { url, priority, waveformOptions, beatConfig, onEvent }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * List the videos of a playlist or channel URL so the user can pick which to queue
+ */
+async expandPlaylist(url: string) : Promise<Result<PlaylistInfo, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("expand_playlist", { url }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Queue the chosen videos of a playlist or channel as one batch.
+ * 
+ * Events for every job in the batch arrive on `on_event`; the pipeline ID
+ * each event carries tells them apart. Aggregate progress is part of each
+ * `QueueSnapshot`.
  */
-async pipelineNotify(command: PipelineCommand) : Promise<Result<null, string>> {
+async enqueueBatch(title: string, entries: PlaylistEntry[], waveformOptions: WaveformOptions | null, beatConfig: BeatDetectionConfig | null, onEvent: TAURI_CHANNEL<PipelineEvent>) : Promise<Result<EnqueuedBatch, string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("pipeline_notify", { command }) };
+    return { status: "ok", data: await TAURI_INVOKE("enqueue_batch", { title, entries, waveformOptions, beatConfig, onEvent }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Current jobs and limits; later changes arrive as `QueueSnapshot` events
+ */
+async getQueue() : Promise<Result<QueueSnapshot, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_queue") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Change how many jobs may download and analyse at once. Downloads are
+ * capped at one, since extractions run one at a time.
+ */
+async setQueueConfig(config: QueueConfig) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_queue_config", { config }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Move a job ahead of every other job in the queue
+ */
+async bumpJob(jobId: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("bump_job", { jobId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Hold a queued job back until it is resumed
+ */
+async pauseJob(jobId: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("pause_job", { jobId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async resumeJob(jobId: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("resume_job", { jobId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Remove a job that is not running. Running jobs are stopped with `pipeline_notify`.
+ */
+async removeJob(jobId: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("remove_job", { jobId }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -171,6 +407,14 @@ async downloadToFileWithProgress(url: string, outputPath: string, headers: Parti
     else return { status: "error", error: e  as any };
 }
 },
+/**
+ * Stop the download to `output_path`, if one is running, and delete what it wrote.
+ * Used when the extraction that started it is aborted; returns once the
+ * download has stopped, so a new one to the same path can't race its cleanup.
+ */
+async cancelDownload(outputPath: string) : Promise<null> {
+    return await TAURI_INVOKE("cancel_download", { outputPath });
+},
 async dlopenFfmpeg(command: string, args: string[]) : Promise<Result<FFmpegResult, string>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("dlopen_ffmpeg", { command, args }) };
@@ -193,9 +437,11 @@ async ffprobeCapabilities() : Promise<Result<FFmpegResult, string>> {
 
 
 export const events = __makeEvents__<{
-appNotification: AppNotification
+appNotification: AppNotification,
+queueSnapshot: QueueSnapshot
 }>({
-appNotification: "app-notification"
+appNotification: "app-notification",
+queueSnapshot: "queue-snapshot"
 })
 
 /** user-defined constants **/
@@ -209,6 +455,58 @@ appNotification: "app-notification"
  */
 export type AppNotification = { level: NotificationLevel; message: string }
 export type AppStats = { cacheSizeMb: number; memoryUsageMb: number }
+/**
+ * Per-block RMS energy in three crossover bands, aligned with the mono peaks
+ */
+export type BandEnergies = { 
+/**
+ * Below `LOW_CROSSOVER_HZ` (kicks, bass)
+ */
+low: number[]; 
+/**
+ * Between the crossovers (snares, vocals)
+ */
+mid: number[]; 
+/**
+ * Above `HIGH_CROSSOVER_HZ` (hats, cymbals)
+ */
+high: number[] }
+/**
+ * Aggregate progress of the jobs queued together as one batch
+ */
+export type BatchProgress = { batchId: string; title: string; total: number; completed: number; 
+/**
+ * Jobs that failed or were cancelled
+ */
+failed: number; 
+/**
+ * Mean progress of the batch's jobs, counting finished ones as 100
+ */
+percent: number }
+/**
+ * Configuration for beat detection
+ */
+export type BeatDetectionConfig = { 
+/**
+ * FFT buffer size (power of 2, e.g., 1024, 2048)
+ */
+bufSize: number; 
+/**
+ * Hop size between frames
+ */
+hopSize: number; 
+/**
+ * Onset detection method
+ */
+onsetMethod: OnsetMethod; 
+/**
+ * Silence threshold in dB
+ */
+silenceThreshold: number; 
+/**
+ * Onset detection threshold
+ */
+onsetThreshold: number }
 /**
  * Beat and tempo information extracted from audio
  */
@@ -228,20 +526,270 @@ beats: number[];
 /**
  * Onset positions in seconds (transients/attacks)
  */
-onsets: number[] }
-export type CachedAudioInfo = { audioPath: string; durationSecs: number; sampleRate: number }
+onsets: number[]; 
+/**
+ * Configuration the detection ran with
+ */
+config: BeatDetectionConfig }
+/**
+ * Finished analysis of a stored track, as returned by `check_cached_audio`
+ */
+export type CachedAnalysis = { beatInfo: BeatInfo; 
+/**
+ * Peaks in the requested options, including duration and sample rate
+ */
+waveform: WaveformData }
+export type CachedAudioInfo = { audioPath: string; durationSecs: number; sampleRate: number; 
+/**
+ * False when `duration_secs` is an estimate
+ */
+durationExact: boolean; 
+/**
+ * Saved waveform and beats, if still valid for the requested options,
+ * so the track can be shown without running `process_audio`
+ */
+analysis: CachedAnalysis | null }
+/**
+ * How source channels are combined before peak picking
+ */
+export type ChannelMode = 
+/**
+ * Only the averaged mono mix
+ */
+"mono" | 
+/**
+ * One set of peaks per source channel, in source order
+ */
+"perChannel" | 
+/**
+ * Mid (L+R)/2 and side (L-R)/2 peaks, in that order
+ */
+"midSide"
 /**
  * Download progress information from http.rs
  */
-export type DownloadProgress = { bytesDownloaded: number; totalBytes: number | null; percent: number }
-export type ExtractionEvent = { event: "started"; data: { videoId: string } } | { event: "progress"; data: { percent: number; status: string } } | { event: "audioInfo"; data: { sampleRate: number } } | { event: "waveformProgress"; data: { totalPeaks: number } } | { event: "waveformChunk"; data: { peaks: number[]; offset: number } } | { event: "beatInfo"; data: { bpm: number; bpmConfidence: number; beats: number[]; onsets: number[] } } | { event: "completed"; data: { audioPath: string; durationSecs: number } } | { event: "error"; data: { message: string } }
+export type DownloadProgress = { bytesDownloaded: number; totalBytes: number | null; percent: number; 
+/**
+ * File being downloaded to
+ */
+outputPath: string }
+/**
+ * A window of audio scored for how likely it is to be an exposed drum break
+ */
+export type DrumBreakCandidate = { 
+/**
+ * Window start in seconds
+ */
+start: number; 
+/**
+ * Window end in seconds
+ */
+end: number; 
+/**
+ * Combined score (0.0 - 1.0, higher is more likely a break)
+ */
+score: number; 
+/**
+ * Share of spectral energy in the percussive component (0.0 - 1.0)
+ */
+percussiveRatio: number; 
+/**
+ * Detected onsets per second within the window
+ */
+onsetDensity: number }
+/**
+ * Options for the drum-break finder
+ */
+export type DrumBreakOptions = { 
+/**
+ * Number of bars per scored window when a beat grid is available
+ */
+barsPerWindow: number; 
+/**
+ * Window length in seconds used when no beat grid could be detected
+ */
+windowSecs: number; 
+/**
+ * Maximum number of ranked candidates to return
+ */
+maxResults: number }
+/**
+ * IDs handed out for a batch of jobs
+ */
+export type EnqueuedBatch = { batchId: string; 
+/**
+ * In the order the jobs were given
+ */
+jobIds: string[] }
+export type ExtractionEvent = { event: "started"; data: { videoId: string } } | { event: "progress"; data: { percent: number; status: string } } | { event: "audioInfo"; data: { sampleRate: number } } | { event: "waveformProgress"; data: { totalPeaks: number } } | { event: "waveformChunk"; data: { peaks: number[]; minPeaks: number[]; maxPeaks: number[]; rmsPeaks: number[]; offset: number } } | { event: "beatInfo"; data: { bpm: number; bpmConfidence: number; beats: number[]; onsets: number[] } } | { event: "completed"; data: { audioPath: string; durationSecs: number } } | { event: "error"; data: { message: string } }
+/**
+ * How long a pipeline waits on the frontend's extraction before giving up on it.
+ * 
+ * The inactivity timeout restarts with every `DownloadProgress` or
+ * `InitializingProgress`, so it catches a hung worker or a reloaded webview
+ * long before the overall timeout would.
+ */
+export type ExtractionTimeouts = { 
+/**
+ * Longest the whole extraction may take, in seconds
+ */
+overallSecs: number; 
+/**
+ * Longest the frontend may go without reporting progress, in seconds
+ */
+inactivitySecs: number }
 /**
  * FFmpeg command queued by yt-dlp for later execution
  */
 export type FFmpegCommand = { id: string; command: string; args: string[]; inputPath: string | null; outputPath: string | null; status: string }
 export type FFmpegResult = { exitCode: number; wasAborted: boolean; stdout: string; stderr: string; error: string | null }
+/**
+ * How frequency bins are distributed over the rows of a tile
+ */
+export type FrequencyScale = "linear" | "log" | "mel"
+/**
+ * Paths of the rendered harmonic and percussive stem files
+ */
+export type HpssStems = { 
+/**
+ * WAV file containing the harmonic (sustained) component
+ */
+harmonicPath: string; 
+/**
+ * WAV file containing the percussive (transient) component
+ */
+percussivePath: string; 
+/**
+ * Length of the rendered region in seconds
+ */
+durationSecs: number; sampleRate: number }
 export type HttpResponse = { status: number; headers: Partial<{ [key in string]: string }>; body: string }
+/**
+ * A local file copied into the audio store and analyzed
+ */
+export type ImportedFile = { 
+/**
+ * Content hash ID, usable like a video ID with `check_cached_audio`
+ */
+sourceId: string; title: string; sourcePath: string; result: PipelineResult }
+/**
+ * Where a queued job is in its life
+ */
+export type JobStatus = 
+/**
+ * Waiting for a download slot
+ */
+"queued" | 
+/**
+ * Held back by the user until resumed
+ */
+"paused" | 
+/**
+ * Extracting, downloading or converting
+ */
+"downloading" | 
+/**
+ * Audio is ready; waiting for an analysis slot
+ */
+"waitingForAnalysis" | 
+/**
+ * Running waveform generation and beat detection
+ */
+"analyzing" | "completed" | "failed" | "cancelled"
+/**
+ * Musical key estimated from the pitch-class content of the audio
+ */
+export type KeyInfo = { 
+/**
+ * Tonic pitch class, e.g. "F#"
+ */
+tonic: string; mode: KeyMode; 
+/**
+ * Display name, e.g. "F# minor"
+ */
+name: string; 
+/**
+ * Correlation of the chroma with the winning key profile (-1.0 - 1.0)
+ */
+confidence: number; 
+/**
+ * Energy per pitch class starting at C, scaled so the strongest is 1.0
+ */
+chroma: number[] }
+export type KeyMode = "major" | "minor"
+/**
+ * Loudness measured per ITU-R BS.1770-4
+ */
+export type LoudnessInfo = { 
+/**
+ * Gated integrated loudness in LUFS, floored at `ABSOLUTE_GATE_LUFS`
+ */
+integratedLufs: number; 
+/**
+ * Highest absolute sample value in dBFS
+ */
+samplePeakDbfs: number }
 export type NotificationLevel = "info" | "warning" | "error"
+/**
+ * Per-block waveform summaries, one entry per `SAMPLES_PER_PEAK` samples
+ */
+/**
+ * Onset detection function, named as in aubio
+ */
+export type OnsetMethod = 
+/**
+ * Local energy of the spectral frame
+ */
+"energy" | 
+/**
+ * High frequency content, good for percussive onsets
+ */
+"hfc" | 
+/**
+ * Complex domain deviation
+ */
+"complex" | 
+/**
+ * Phase deviation
+ */
+"phase" | 
+/**
+ * Weighted phase deviation
+ */
+"wphase" | 
+/**
+ * Spectral difference
+ */
+"specdiff" | 
+/**
+ * Kullback-Leibler divergence
+ */
+"kl" | 
+/**
+ * Modified Kullback-Leibler divergence
+ */
+"mkl" | 
+/**
+ * Spectral flux
+ */
+"specflux"
+export type PeakBuffers = { 
+/**
+ * Absolute peak (`max(|min|, |max|)`)
+ */
+peaks: number[]; 
+/**
+ * Lowest signed sample
+ */
+minPeaks: number[]; 
+/**
+ * Highest signed sample
+ */
+maxPeaks: number[]; 
+/**
+ * Root-mean-square level
+ */
+rmsPeaks: number[] }
 /**
  * Commands sent TO the pipeline FROM the frontend/worker
  */
@@ -257,58 +805,350 @@ export type PipelineCommand =
 /**
  * Download progress update (forwarded from http.rs via worker)
  */
-{ command: "downloadProgress"; data: { bytesDownloaded: number; totalBytes: number | null } } | 
+{ command: "downloadProgress"; data: { bytesDownloaded: number; totalBytes: number | null; 
+/**
+ * File being downloaded to, saved so the download can be resumed
+ */
+partialPath: string | null } } | 
 /**
  * Initializing progress update (yt-dlp is working but no download yet)
  */
-{ command: "initializingProgress"; data: { message: string } }
+{ command: "initializingProgress"; data: { message: string } } | 
+/**
+ * Stop the pipeline at the next cancellation check
+ */
+{ command: "cancel" }
 /**
- * Unified pipeline event enum for streaming progress and results
+ * Per-run settings for which analyses a pipeline runs and how it handles
+ * failures and stalls
+ */
+export type PipelineConfig = { retryPolicy: RetryPolicy; extractionTimeouts: ExtractionTimeouts; 
+/**
+ * Analysis stages to run once the audio is converted; the waveform always runs
+ */
+analysisStages: StageName[] }
+/**
+ * Unified pipeline event enum for streaming progress and results.
+ * 
+ * Every event names the run it belongs to, so one channel can serve several.
  */
 export type PipelineEvent = 
 /**
  * Pipeline has started processing
  */
-{ event: "started"; data: { url: string; outputPath: string; stages: StageName[] } } | 
+{ event: "started"; data: { 
+/**
+ * ID to address this run in `pipeline_notify`; `None` for processing-only
+ * runs, which take no commands
+ */
+pipelineId: string | null; url: string; outputPath: string; stages: StageName[] } } | 
 /**
  * Progress update from any stage
  */
-{ event: "progress"; data: StageProgress } | 
+{ event: "progress"; data: { pipelineId: string | null; progress: StageProgress } } | 
 /**
  * Request frontend to run extraction via Pyodide worker
  * Pipeline BLOCKS until it receives ExtractionComplete or ExtractionFailed
  */
-{ event: "requestExtraction"; data: { url: string; outputPath: string } } | 
+{ event: "requestExtraction"; data: { pipelineId: string; url: string; outputPath: string } } | 
 /**
  * Waveform chunk for progressive rendering
  */
-{ event: "waveformChunk"; data: { peaks: number[]; offset: number } } | 
+{ event: "waveformChunk"; data: { pipelineId: string | null; peaks: number[]; minPeaks: number[]; maxPeaks: number[]; rmsPeaks: number[]; channels: PeakBuffers[]; bands: BandEnergies | null; offset: number } } | 
 /**
  * Waveform generation completed
  */
-{ event: "waveformComplete"; data: { peaks: number[]; durationSecs: number; sampleRate: number } } | 
+{ event: "waveformComplete"; data: { pipelineId: string | null; peaks: number[]; minPeaks: number[]; maxPeaks: number[]; rmsPeaks: number[]; channels: PeakBuffers[]; bands: BandEnergies | null; scale: WaveformScale; normalizationFactor: number; channelNormalizationFactor: number; durationSecs: number; sampleRate: number } } | 
+/**
+ * Beats found so far, sent while beat detection is still running
+ */
+{ event: "beatDetectionChunk"; data: { pipelineId: string | null; 
+/**
+ * Beat positions (seconds) detected since the previous chunk
+ */
+beats: number[]; 
+/**
+ * Current tempo estimate
+ */
+bpm: number } } | 
 /**
  * Beat detection completed
  */
-{ event: "beatDetectionComplete"; data: { bpm: number; bpmConfidence: number; beats: number[]; onsets: number[] } } | 
+{ event: "beatDetectionComplete"; data: { pipelineId: string | null; bpm: number; bpmConfidence: number; beats: number[]; onsets: number[]; 
+/**
+ * Configuration the detection ran with
+ */
+config: BeatDetectionConfig } } | 
+/**
+ * Key detection completed
+ */
+{ event: "keyDetectionComplete"; data: { pipelineId: string | null; 
+/**
+ * `None` when the audio is silent
+ */
+key: KeyInfo | null } } | 
+/**
+ * Loudness measurement completed
+ */
+{ event: "loudnessComplete"; data: { pipelineId: string | null; 
+/**
+ * `None` when the audio is silent
+ */
+loudness: LoudnessInfo | null } } | 
+/**
+ * Drum-break search completed
+ */
+{ event: "drumBreaksComplete"; data: { pipelineId: string | null; 
+/**
+ * Candidates, most likely first
+ */
+breaks: DrumBreakCandidate[] } } | 
 /**
  * All stages completed successfully
  */
-{ event: "completed"; data: PipelineResult } | 
+{ event: "completed"; data: { pipelineId: string | null; result: PipelineResult } } | 
+/**
+ * A stage failed with a recoverable error and will run again after `delay_ms`
+ */
+{ event: "retrying"; data: { pipelineId: string | null; stage: StageName; 
 /**
- * A stage failed (fail-fast)
+ * Number of the attempt about to start, 2 for the first retry
  */
-{ event: "error"; data: { stage: StageName; message: string; 
+attempt: number; delayMs: number } } | 
+/**
+ * A stage failed and will not be retried
+ */
+{ event: "error"; data: { pipelineId: string | null; stage: StageName; message: string; 
 /**
  * Whether this error might be recoverable with retry
  */
-recoverable: boolean } }
+recoverable: boolean } } | 
+/**
+ * The pipeline stopped after a `Cancel` command; partial files were removed
+ */
+{ event: "cancelled"; data: { pipelineId: string | null; 
+/**
+ * Stage that was running when the cancellation took effect
+ */
+stage: StageName } }
 /**
  * Result of the complete pipeline execution
  */
 export type PipelineResult = { audioPath: string; durationSecs: number; sampleRate: number }
 /**
- * Names of processing stages in the pipeline with associated weights
+ * State of the pipeline state machine, saved so interrupted runs can resume
+ */
+export type PipelineState = 
+/**
+ * Initial state - about to request extraction
+ */
+"initial" | 
+/**
+ * Waiting for frontend to complete extraction via Pyodide
+ */
+"awaitingExtraction" | 
+/**
+ * Extraction done, running FFmpeg commands
+ */
+"runningFFmpeg" | 
+/**
+ * FFmpeg done, running the analysis stages
+ */
+"processingAudio" | 
+/**
+ * All done
+ */
+"completed" | 
+/**
+ * Failed
+ */
+"failed" | 
+/**
+ * Stopped by a `Cancel` command
+ */
+"cancelled"
+/**
+ * A video listed by a playlist or channel
+ */
+export type PlaylistEntry = { videoId: string; title: string; 
+/**
+ * Missing for some entries, e.g. upcoming premieres
+ */
+durationSecs: number | null; url: string }
+/**
+ * Videos of a playlist or channel, in listing order
+ */
+export type PlaylistInfo = { title: string; entries: PlaylistEntry[] }
+/**
+ * How many queued pipelines may be in each phase at once
+ */
+export type QueueConfig = { 
+/**
+ * Pipelines extracting, downloading or converting; at most 1, since
+ * extractions run one at a time
+ */
+maxConcurrentDownloads: number; 
+/**
+ * Pipelines running waveform generation and beat detection
+ */
+maxConcurrentAnalysis: number }
+/**
+ * Queue contents, emitted whenever a job or the config changes
+ */
+export type QueueSnapshot = { jobs: QueuedJob[]; batches: BatchProgress[]; config: QueueConfig }
+/**
+ * A job as shown to the frontend
+ */
+export type QueuedJob = { 
+/**
+ * Also the pipeline ID for `pipeline_notify` once the job is running
+ */
+id: string; url: string; 
+/**
+ * Video title, when it was known at queue time
+ */
+title: string | null; 
+/**
+ * Batch the job was queued with, e.g. from a playlist
+ */
+batchId: string | null; 
+/**
+ * Higher runs first; equal priorities run in the order they were queued
+ */
+priority: number; status: JobStatus; 
+/**
+ * Overall pipeline progress (0-100)
+ */
+progress: number; 
+/**
+ * Why the job failed, once `status` is `Failed`
+ */
+error: string | null }
+/**
+ * Analyses `analyze_region` can run over a selection
+ */
+export type RegionAnalysis = 
+/**
+ * Tempo (BPM), beat grid and onsets
+ */
+"beats" | 
+/**
+ * Musical key
+ */
+"key" | 
+/**
+ * Integrated loudness and sample peak
+ */
+"loudness"
+/**
+ * Combined result of `analyze_region`; analyses that were not requested are null
+ */
+export type RegionAnalysisResult = { 
+/**
+ * Region start in seconds
+ */
+start: number; 
+/**
+ * Region end in seconds
+ */
+end: number; 
+/**
+ * Beat and onset positions are in seconds from the start of the file
+ */
+beats: BeatInfo | null; 
+/**
+ * Null when requested but the region is silent
+ */
+key: KeyInfo | null; loudness: LoudnessInfo | null }
+/**
+ * How a pipeline retries stages that failed with a recoverable error
+ */
+export type RetryPolicy = { 
+/**
+ * Attempts per stage, counting the first; 1 never retries
+ */
+maxAttempts: number; 
+/**
+ * Wait before the first retry, in milliseconds
+ */
+initialDelayMs: number; 
+/**
+ * Factor the wait grows by with each further retry
+ */
+backoffFactor: number; 
+/**
+ * Longest wait between attempts, in milliseconds
+ */
+maxDelayMs: number; 
+/**
+ * Stages that may be retried; extraction is retried by requesting it again
+ */
+stages: StageName[] }
+/**
+ * On-disk record of a running pipeline, rewritten as it moves through its
+ * stages and removed once it finishes, fails or is cancelled. A record that
+ * is still there at startup belongs to a run the app did not get to finish.
+ */
+export type SavedPipeline = { pipelineId: string; url: string; outputPath: string; 
+/**
+ * Last state the run entered
+ */
+state: PipelineState; 
+/**
+ * File the audio stream was being downloaded to, once downloading started
+ */
+partialPath: string | null; bytesReceived: number; totalBytes: number | null; 
+/**
+ * Extracted audio, once extraction completed
+ */
+audioPath: string | null; 
+/**
+ * Conversions still to run on `audio_path`
+ */
+ffmpegCommands: FFmpegCommand[]; waveformOptions: WaveformOptions; beatConfig: BeatDetectionConfig; 
+/**
+ * Records from before the config was saved resume with the defaults
+ */
+config?: PipelineConfig }
+export type SpectrogramEvent = { event: "started"; data: { totalTiles: number; sampleRate: number } } | { event: "tile"; data: SpectrogramTile } | { event: "completed"; data: { totalTiles: number } } | { event: "error"; data: { message: string } }
+/**
+ * Resolution and display range for spectrogram rendering
+ */
+export type SpectrogramOptions = { 
+/**
+ * Horizontal zoom: columns per second of audio
+ */
+pixelsPerSecond: number; 
+/**
+ * Number of frequency rows per tile
+ */
+height: number; 
+/**
+ * FFT window size (power of 2)
+ */
+fftSize: number; scale: FrequencyScale; 
+/**
+ * Level mapped to intensity 0
+ */
+minDb: number; 
+/**
+ * Level mapped to intensity 255
+ */
+maxDb: number }
+/**
+ * An image-ready block of spectrogram columns
+ */
+export type SpectrogramTile = { 
+/**
+ * Tile position at this zoom level (tile `n` starts at `n * width / pixelsPerSecond`)
+ */
+index: number; startTime: number; endTime: number; width: number; height: number; 
+/**
+ * Row-major 8-bit intensities; the first row is the highest frequency
+ */
+pixels: number[] }
+/**
+ * Names of processing stages in the pipeline
  */
 export type StageName = 
 /**
@@ -330,7 +1170,19 @@ export type StageName =
 /**
  * Beat/tempo analysis (indeterminate progress)
  */
-"beatDetection"
+"beatDetection" | 
+/**
+ * Musical key estimation (chunk progress)
+ */
+"keyDetection" | 
+/**
+ * Integrated loudness measurement (chunk progress)
+ */
+"loudness" | 
+/**
+ * Drum-break search over the beat grid (chunk progress)
+ */
+"drumBreaks"
 /**
  * Progress information for a specific stage
  */
@@ -344,8 +1196,88 @@ stagePercent: number;
  */
 overallPercent: number; message: string }
 export type VideoMetadata = { title: string; authorName: string; authorUrl: string; thumbnailUrl: string; videoId: string }
-export type WaveformData = { peaks: number[]; durationSecs: number; sampleRate: number }
-export type WaveformEvent = { event: "started"; data: { audioPath: string } } | { event: "audioInfo"; data: { sampleRate: number; durationSecs: number } } | { event: "progress"; data: { totalPeaks: number } } | { event: "chunk"; data: { peaks: number[]; offset: number } } | { event: "completed"; data: { peaks: number[]; durationSecs: number } } | { event: "error"; data: { message: string } }
+export type WaveformData = { 
+/**
+ * Absolute peak per block, normalized to the loudest block
+ */
+peaks: number[]; 
+/**
+ * Signed minimum per block, on the same scale as `peaks`
+ */
+minPeaks: number[]; 
+/**
+ * Signed maximum per block, on the same scale as `peaks`
+ */
+maxPeaks: number[]; 
+/**
+ * RMS level per block, on the same scale as `peaks`
+ */
+rmsPeaks: number[]; channelMode: ChannelMode; 
+/**
+ * Per-channel peaks as selected by `channel_mode`, sharing one scale
+ */
+channels: PeakBuffers[]; 
+/**
+ * Low/mid/high band energy per block, on the same scale as `peaks`
+ */
+bands: BandEnergies | null; 
+/**
+ * Units of every peak, band and channel value above
+ */
+scale: WaveformScale; 
+/**
+ * Loudest mono peak (1.0 for silence); normalized mono and band values were divided by it
+ */
+normalizationFactor: number; 
+/**
+ * Loudest channel peak; normalized channel values were divided by it
+ */
+channelNormalizationFactor: number; durationSecs: number; sampleRate: number }
+export type WaveformEvent = { event: "started"; data: { audioPath: string } } | { event: "audioInfo"; data: { sampleRate: number; durationSecs: number; durationExact: boolean } } | { event: "progress"; data: { totalPeaks: number } } | { event: "chunk"; data: { peaks: number[]; minPeaks: number[]; maxPeaks: number[]; rmsPeaks: number[]; channels: PeakBuffers[]; bands: BandEnergies | null; offset: number } } | { event: "completed"; data: { peaks: number[]; minPeaks: number[]; maxPeaks: number[]; rmsPeaks: number[]; channels: PeakBuffers[]; bands: BandEnergies | null; scale: WaveformScale; normalizationFactor: number; channelNormalizationFactor: number; durationSecs: number } } | { event: "error"; data: { message: string } }
+/**
+ * Options for waveform peak generation
+ */
+export type WaveformOptions = { channelMode: ChannelMode; 
+/**
+ * Also compute low/mid/high band energies of the mono mix
+ */
+bandEnergies: boolean; scale: WaveformScale }
+/**
+ * Peaks for one view of the waveform at the resolution the view needs
+ */
+export type WaveformRange = { startTime: number; endTime: number; 
+/**
+ * Source samples covered by each entry; 1.0 means the entries are raw samples
+ */
+samplesPerPeak: number; 
+/**
+ * Signed minimum per entry (full scale = 1.0)
+ */
+minPeaks: number[]; 
+/**
+ * Signed maximum per entry (full scale = 1.0)
+ */
+maxPeaks: number[]; 
+/**
+ * RMS level per entry
+ */
+rmsPeaks: number[] }
+/**
+ * Units of the waveform values
+ */
+export type WaveformScale = 
+/**
+ * Divided by the loudest peak so it reaches 1.0
+ */
+"normalized" | 
+/**
+ * Absolute sample values, full scale is 1.0
+ */
+"linear" | 
+/**
+ * Level of each value's magnitude in dBFS, floored at `DBFS_FLOOR`
+ */
+"dbfs"
 
 /** tauri-specta globals **/
 
@@ -0,0 +1,54 @@
+import type { PipelineState, SavedPipeline } from "../bindings";
+import { extractVideoId } from "../lib/youtube";
+
+interface InterruptedPipelinesProps {
+  pipelines: SavedPipeline[];
+  onResume: (saved: SavedPipeline) => void;
+  onDiscard: (saved: SavedPipeline) => void;
+}
+
+const STATE_LABELS: Record<PipelineState, string> = {
+  initial: "Not started",
+  awaitingExtraction: "Downloading",
+  runningFFmpeg: "Converting",
+  processingAudio: "Analyzing",
+  completed: "Done",
+  failed: "Failed",
+  cancelled: "Cancelled",
+};
+
+function describeProgress(saved: SavedPipeline): string {
+  if (saved.state !== "awaitingExtraction" || saved.bytesReceived === 0) {
+    return STATE_LABELS[saved.state];
+  }
+  const received = `${(saved.bytesReceived / 1_000_000).toFixed(1)} MB`;
+  if (saved.totalBytes === null) return `${STATE_LABELS[saved.state]} ${received}`;
+  return `${STATE_LABELS[saved.state]} ${Math.floor((saved.bytesReceived / saved.totalBytes) * 100)}%`;
+}
+
+/** Offer to resume or discard pipeline runs the app quit before finishing */
+function InterruptedPipelines({ pipelines, onResume, onDiscard }: InterruptedPipelinesProps) {
+  return (
+    <div className="w-full max-w-md text-left">
+      <p className="text-xs text-cyber-500 uppercase tracking-wider">Unfinished</p>
+      <ul className="mt-1 divide-y divide-retro-surface-light border border-retro-surface-light rounded">
+        {pipelines.map((saved) => (
+          <li key={saved.pipelineId} className="flex items-center gap-2 px-2 py-1 text-xs">
+            <span className="flex-1 truncate font-mono text-cyber-400" title={saved.url}>
+              {extractVideoId(saved.url) ?? saved.url}
+            </span>
+            <span className="text-cyber-500">{describeProgress(saved)}</span>
+            <button onClick={() => onResume(saved)} className="text-cyber-500 hover:text-neon-cyan transition-colors">
+              Resume
+            </button>
+            <button onClick={() => onDiscard(saved)} className="text-cyber-500 hover:text-red-400 transition-colors">
+              Discard
+            </button>
+          </li>
+        ))}
+      </ul>
+    </div>
+  );
+}
+
+export default InterruptedPipelines;
@@ -0,0 +1,229 @@
+import { useState, useCallback } from "react";
+import type { BatchProgress, JobStatus, PlaylistEntry, PlaylistInfo, QueuedJob } from "../bindings";
+import { isYouTubeUrl, isPlaylistUrl, extractVideoId } from "../lib/youtube";
+
+interface QueuePanelProps {
+  jobs: QueuedJob[];
+  batches: BatchProgress[];
+  onEnqueue: (url: string) => Promise<unknown>;
+  onExpandPlaylist: (url: string) => Promise<PlaylistInfo>;
+  onEnqueueBatch: (title: string, entries: PlaylistEntry[]) => Promise<unknown>;
+  onBump: (jobId: string) => void;
+  onPause: (jobId: string) => void;
+  onResume: (jobId: string) => void;
+  onRemove: (jobId: string) => void;
+  onCancel: (jobId: string) => void;
+}
+
+const STATUS_LABELS: Record<JobStatus, string> = {
+  queued: "Queued",
+  paused: "Paused",
+  downloading: "Downloading",
+  waitingForAnalysis: "Waiting",
+  analyzing: "Analyzing",
+  completed: "Done",
+  failed: "Failed",
+  cancelled: "Cancelled",
+};
+
+const RUNNING: JobStatus[] = ["downloading", "waitingForAnalysis", "analyzing"];
+
+function formatDuration(secs: number | null): string {
+  if (secs === null) return "--:--";
+  const minutes = Math.floor(secs / 60);
+  const seconds = Math.floor(secs % 60);
+  return `${minutes}:${seconds.toString().padStart(2, "0")}`;
+}
+
+function QueuePanel({
+  jobs,
+  batches,
+  onEnqueue,
+  onExpandPlaylist,
+  onEnqueueBatch,
+  onBump,
+  onPause,
+  onResume,
+  onRemove,
+  onCancel,
+}: QueuePanelProps) {
+  const [inputValue, setInputValue] = useState("");
+  const [error, setError] = useState<string | null>(null);
+  const [isExpanding, setIsExpanding] = useState(false);
+  const [playlist, setPlaylist] = useState<PlaylistInfo | null>(null);
+  const [selected, setSelected] = useState<Set<string>>(new Set());
+
+  const handleSubmit = useCallback(async (e: React.FormEvent) => {
+    e.preventDefault();
+    const url = inputValue.trim();
+    // A watch URL inside a playlist queues just that video
+    const expand = isPlaylistUrl(url) && !extractVideoId(url);
+    if (!expand && !isYouTubeUrl(url)) {
+      setError("Not a YouTube URL");
+      return;
+    }
+    setError(null);
+    try {
+      if (expand) {
+        setIsExpanding(true);
+        const info = await onExpandPlaylist(url);
+        setPlaylist(info);
+        setSelected(new Set(info.entries.map((entry) => entry.videoId)));
+      } else {
+        await onEnqueue(url);
+      }
+      setInputValue("");
+    } catch (err) {
+      setError(err instanceof Error ? err.message : String(err));
+    } finally {
+      setIsExpanding(false);
+    }
+  }, [inputValue, onEnqueue, onExpandPlaylist]);
+
+  const toggleEntry = useCallback((videoId: string) => {
+    setSelected((prev) => {
+      const next = new Set(prev);
+      if (next.has(videoId)) next.delete(videoId);
+      else next.add(videoId);
+      return next;
+    });
+  }, []);
+
+  const handleQueueSelected = useCallback(async () => {
+    if (!playlist) return;
+    const entries = playlist.entries.filter((entry) => selected.has(entry.videoId));
+    try {
+      await onEnqueueBatch(playlist.title, entries);
+      setPlaylist(null);
+    } catch (err) {
+      setError(err instanceof Error ? err.message : String(err));
+    }
+  }, [playlist, selected, onEnqueueBatch]);
+
+  return (
+    <div className="w-full max-w-md text-left">
+      <form onSubmit={handleSubmit} className="flex gap-2">
+        <input
+          type="text"
+          value={inputValue}
+          onChange={(e) => setInputValue(e.target.value)}
+          placeholder="Queue a video, playlist or channel URL..."
+          disabled={isExpanding}
+          className="flex-1 bg-retro-dark border border-retro-surface-light rounded px-2 py-1 text-xs text-cyber-100 placeholder:text-cyber-600 focus:outline-none focus:border-neon-cyan"
+        />
+        <button
+          type="submit"
+          disabled={isExpanding}
+          className="text-xs text-cyber-400 hover:text-neon-cyan transition-colors uppercase tracking-wider"
+        >
+          {isExpanding ? "Listing..." : "Queue"}
+        </button>
+      </form>
+      {error && <p className="mt-1 text-xs text-red-400">{error}</p>}
+
+      {playlist && (
+        <div className="mt-3 border border-retro-surface-light rounded">
+          <div className="flex items-center gap-2 px-2 py-1 text-xs border-b border-retro-surface-light">
+            <span className="flex-1 truncate text-cyber-100">{playlist.title}</span>
+            <button
+              onClick={() => setSelected(new Set(
+                selected.size === playlist.entries.length ? [] : playlist.entries.map((entry) => entry.videoId)
+              ))}
+              className="text-cyber-500 hover:text-neon-cyan transition-colors"
+            >
+              {selected.size === playlist.entries.length ? "None" : "All"}
+            </button>
+            <button onClick={() => setPlaylist(null)} className="text-cyber-500 hover:text-red-400 transition-colors">
+              Close
+            </button>
+          </div>
+          <ul className="max-h-60 overflow-y-auto">
+            {playlist.entries.map((entry) => (
+              <li key={entry.videoId}>
+                <label className="flex items-center gap-2 px-2 py-1 text-xs cursor-pointer hover:bg-retro-surface">
+                  <input
+                    type="checkbox"
+                    checked={selected.has(entry.videoId)}
+                    onChange={() => toggleEntry(entry.videoId)}
+                  />
+                  <span className="flex-1 truncate text-cyber-400">{entry.title}</span>
+                  <span className="font-mono text-cyber-600">{formatDuration(entry.durationSecs)}</span>
+                </label>
+              </li>
+            ))}
+          </ul>
+          <div className="px-2 py-1 border-t border-retro-surface-light text-right">
+            <button
+              onClick={handleQueueSelected}
+              disabled={selected.size === 0}
+              className="text-xs text-cyber-400 hover:text-neon-cyan transition-colors uppercase tracking-wider disabled:opacity-50"
+            >
+              Queue {selected.size} videos
+            </button>
+          </div>
+        </div>
+      )}
+
+      {batches.map((batch) => (
+        <div key={batch.batchId} className="mt-3 text-xs">
+          <div className="flex items-center gap-2">
+            <span className="flex-1 truncate text-cyber-100">{batch.title}</span>
+            <span className="text-cyber-500">
+              {batch.completed}/{batch.total}
+              {batch.failed > 0 && <span className="text-red-400"> ({batch.failed} failed)</span>}
+            </span>
+          </div>
+          <div className="mt-1 h-1 bg-retro-surface-light rounded-full overflow-hidden">
+            <div
+              className="h-full bg-acid-green transition-all duration-300"
+              style={{ width: `${batch.percent}%` }}
+            />
+          </div>
+        </div>
+      ))}
+
+      {jobs.length > 0 && (
+        <ul className="mt-3 divide-y divide-retro-surface-light border border-retro-surface-light rounded">
+          {jobs.map((job) => (
+            <li key={job.id} className="flex items-center gap-2 px-2 py-1 text-xs">
+              <span className="flex-1 truncate font-mono text-cyber-400" title={job.error ?? job.url}>
+                {job.title ?? job.url}
+              </span>
+              <span className={job.status === "failed" ? "text-red-400" : "text-cyber-500"}>
+                {RUNNING.includes(job.status)
+                  ? `${STATUS_LABELS[job.status]} ${Math.floor(job.progress)}%`
+                  : STATUS_LABELS[job.status]}
+              </span>
+              {(job.status === "queued" || job.status === "paused") && (
+                <button onClick={() => onBump(job.id)} className="text-cyber-500 hover:text-neon-cyan transition-colors">
+                  Top
+                </button>
+              )}
+              {job.status === "queued" && (
+                <button onClick={() => onPause(job.id)} className="text-cyber-500 hover:text-neon-cyan transition-colors">
+                  Pause
+                </button>
+              )}
+              {job.status === "paused" && (
+                <button onClick={() => onResume(job.id)} className="text-cyber-500 hover:text-neon-cyan transition-colors">
+                  Resume
+                </button>
+              )}
+              {RUNNING.includes(job.status) ? (
+                <button onClick={() => onCancel(job.id)} className="text-cyber-500 hover:text-red-400 transition-colors">
+                  Cancel
+                </button>
+              ) : (
+                <button onClick={() => onRemove(job.id)} className="text-cyber-500 hover:text-red-400 transition-colors">
+                  Remove
+                </button>
+              )}
+            </li>
+          ))}
+        </ul>
+      )}
+    </div>
+  );
+}
+
+export default QueuePanel;
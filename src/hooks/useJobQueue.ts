@@ -0,0 +1,111 @@
+import { useState, useEffect, useCallback } from "react";
+import { Channel } from "@tauri-apps/api/core";
+import {
+    commands,
+    events,
+    type BatchProgress,
+    type PipelineEvent,
+    type PipelineCommand,
+    type PlaylistEntry,
+    type QueuedJob,
+} from "../bindings";
+
+type RunExtraction = (pipelineId: string, url: string, outputPath: string) => Promise<void>;
+type AbortExtraction = (pipelineId: string) => void;
+
+const report = (action: string) => (result: { status: "ok" } | { status: "error"; error: string }) => {
+    if (result.status === "error") console.debug(`[Queue] Failed to ${action} job:`, result.error);
+};
+
+/**
+ * Background download queue. Jobs run without taking over the main view;
+ * extraction requests are still served by the Pyodide worker via `runExtraction`,
+ * and `abortExtraction` stops a cancelled job's extraction.
+ */
+export function useJobQueue(
+    runExtraction: RunExtraction,
+    abortExtraction: AbortExtraction,
+    onJobCompleted?: () => void
+) {
+    const [jobs, setJobs] = useState<QueuedJob[]>([]);
+    const [batches, setBatches] = useState<BatchProgress[]>([]);
+
+    useEffect(() => {
+        let disposed = false;
+        commands.getQueue().then((result) => {
+            if (disposed || result.status === "error") return;
+            setJobs(result.data.jobs);
+            setBatches(result.data.batches);
+        });
+        const unlisten = events.queueSnapshot.listen((event) => {
+            setJobs(event.payload.jobs);
+            setBatches(event.payload.batches);
+        });
+        return () => {
+            disposed = true;
+            unlisten.then((fn) => fn());
+        };
+    }, []);
+
+    /** One channel can serve many jobs: extraction requests carry their pipeline ID */
+    const jobChannel = useCallback(() => {
+        const channel = new Channel<PipelineEvent>();
+        channel.onmessage = (event) => {
+            switch (event.event) {
+                case "requestExtraction": {
+                    const { pipelineId } = event.data;
+                    runExtraction(pipelineId, event.data.url, event.data.outputPath).catch(async (err) => {
+                        const failCommand: PipelineCommand = {
+                            command: "extractionFailed",
+                            data: { message: err instanceof Error ? err.message : String(err) }
+                        };
+                        await commands.pipelineNotify(pipelineId, failCommand);
+                    });
+                    break;
+                }
+                case "completed":
+                    onJobCompleted?.();
+                    break;
+                case "retrying":
+                    console.warn('[Queue] Job retrying:', event.data);
+                    break;
+                case "error":
+                    console.error('[Queue] Job failed:', event.data);
+                    break;
+            }
+        };
+        return channel;
+    }, [runExtraction, onJobCompleted]);
+
+    const enqueue = useCallback(async (url: string) => {
+        const result = await commands.enqueuePipeline(url, null, null, null, jobChannel());
+        if (result.status === "error") throw new Error(result.error);
+        return result.data;
+    }, [jobChannel]);
+
+    const expandPlaylist = useCallback(async (url: string) => {
+        const result = await commands.expandPlaylist(url);
+        if (result.status === "error") throw new Error(result.error);
+        return result.data;
+    }, []);
+
+    const enqueueBatch = useCallback(async (title: string, entries: PlaylistEntry[]) => {
+        const result = await commands.enqueueBatch(title, entries, null, null, jobChannel());
+        if (result.status === "error") throw new Error(result.error);
+        return result.data;
+    }, [jobChannel]);
+
+    const bump = useCallback((jobId: string) => commands.bumpJob(jobId).then(report("bump")), []);
+    const pause = useCallback((jobId: string) => commands.pauseJob(jobId).then(report("pause")), []);
+    const resume = useCallback((jobId: string) => commands.resumeJob(jobId).then(report("resume")), []);
+    const remove = useCallback((jobId: string) => commands.removeJob(jobId).then(report("remove")), []);
+    const cancel = useCallback(
+        (jobId: string) => {
+            abortExtraction(jobId);
+            return commands.pipelineNotify(jobId, { command: "cancel" }).then(report("cancel"));
+        },
+        [abortExtraction]
+    );
+
+    return { jobs, batches, enqueue, expandPlaylist, enqueueBatch, bump, pause, resume, remove, cancel };
+}
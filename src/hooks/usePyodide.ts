@@ -2,19 +2,13 @@ import { useState, useCallback, useRef, useEffect } from 'react';
 import { 
   getPyodideClient, 
   type PyodideClient, 
-  type ExtractionProgress,
-  type VideoInfo,
-  type LogMessage,
-  type DownloadProgress 
+  type ExtractAudioOptions,
+  type VideoInfo
 } from '../wasm/pyodide-client';
 
-export type PyodideStatus = 'idle' | 'initializing' | 'ready' | 'error';
+export type { ExtractAudioOptions };
 
-export interface ExtractAudioOptions {
-  onProgress?: (progress: ExtractionProgress) => void;
-  onLog?: (log: LogMessage) => void;
-  onDownloadProgress?: (progress: DownloadProgress) => void;
-}
+export type PyodideStatus = 'idle' | 'initializing' | 'ready' | 'error';
 
 export interface UsePyodideResult {
   status: PyodideStatus;
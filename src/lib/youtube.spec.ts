@@ -1,5 +1,5 @@
 import { describe, it, expect } from "vitest";
-import { isYouTubeUrl, extractVideoId } from "./youtube";
+import { isYouTubeUrl, extractVideoId, isPlaylistUrl } from "./youtube";
 
 describe("isYouTubeUrl", () => {
   it("should recognize standard watch URLs", () => {
@@ -57,3 +57,20 @@ describe("extractVideoId", () => {
     expect(extractVideoId("")).toBe(null);
   });
 });
+
+describe("isPlaylistUrl", () => {
+  it("should recognize playlist URLs", () => {
+    expect(isPlaylistUrl("https://www.youtube.com/playlist?list=PLabc_123")).toBe(true);
+    expect(isPlaylistUrl("https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PLabc")).toBe(true);
+  });
+
+  it("should recognize channel URLs", () => {
+    expect(isPlaylistUrl("https://www.youtube.com/@SomeLabel")).toBe(true);
+    expect(isPlaylistUrl("https://www.youtube.com/channel/UCabcdefghijklmnopqrstuv/videos")).toBe(true);
+  });
+
+  it("should reject single videos and other sites", () => {
+    expect(isPlaylistUrl("https://www.youtube.com/watch?v=dQw4w9WgXcQ")).toBe(false);
+    expect(isPlaylistUrl("https://example.com/playlist?list=PLabc")).toBe(false);
+  });
+});
@@ -1,5 +1,8 @@
 const YOUTUBE_URL_PATTERN = /(?:youtube\.com\/(?:watch\?v=|embed\/|v\/|shorts\/)|youtu\.be\/)/;
 
+const PLAYLIST_URL_PATTERN = /(?:youtube\.com|youtu\.be)\/.*[?&]list=[a-zA-Z0-9_-]+/;
+const CHANNEL_URL_PATTERN = /youtube\.com\/(?:@[\w.-]+|channel\/[\w-]+|c\/[\w.-]+|user\/[\w.-]+)/;
+
 const VIDEO_ID_PATTERNS = [
   /youtube\.com\/watch\?v=([^&]+)/,
   /youtube\.com\/embed\/([^?]+)/,
@@ -19,3 +22,8 @@ export function extractVideoId(url: string): string | null {
   }
   return null;
 }
+
+/** Playlist or channel URLs, which expand into a list of videos */
+export function isPlaylistUrl(url: string): boolean {
+  return PLAYLIST_URL_PATTERN.test(url) || CHANNEL_URL_PATTERN.test(url);
+}
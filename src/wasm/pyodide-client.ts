@@ -33,6 +33,8 @@ export interface DownloadProgress {
   total: string;
   speed: string;
   eta: string;
+  /** File being downloaded to, when known */
+  path?: string;
 }
 
 export interface FFmpegCapabilities {
@@ -94,6 +96,14 @@ export interface ExtractionProgress {
   currentCommand?: FFmpegCommand;
 }
 
+export interface ExtractAudioOptions {
+  onProgress?: (progress: ExtractionProgress) => void;
+  onLog?: (log: LogMessage) => void;
+  onDownloadProgress?: (progress: DownloadProgress) => void;
+  /** Aborts the extraction, cancelling its downloads */
+  signal?: AbortSignal;
+}
+
 export class PyodideClient {
   private worker: Worker | null = null;
   private messageId = 0;
@@ -106,6 +116,10 @@ export class PyodideClient {
   private logListeners = new Set<(log: LogMessage) => void>();
   private progressListeners = new Set<(progress: DownloadProgress) => void>();
   private verboseMode = false;
+  /** Output paths of native downloads started by the worker and still running */
+  private activeDownloads = new Set<string>();
+  /** Settles when the last queued extraction does; extractions run one at a time */
+  private extractionQueue: Promise<unknown> = Promise.resolve();
 
   /**
    * Initialize the Pyodide worker
@@ -137,6 +151,9 @@ export class PyodideClient {
     console.log('[pyodide-client] Initializing Pyodide...');
     const result = await this.sendMessage('init') as InitResult;
     this.ffmpegCapabilities = result.ffmpegCapabilities;
+    if (this.verboseMode) {
+      await this.sendMessage('set_verbose', { enabled: true });
+    }
     console.log('[pyodide-client] Ready!');
     
     return this.ffmpegCapabilities;
@@ -162,21 +179,39 @@ export class PyodideClient {
     return result as VideoInfo;
   }
 
+  /**
+   * Download the audio of `url` to `outputPath`.
+   *
+   * Extractions run one at a time, as the worker keeps a single FFmpeg queue.
+   * Aborting `signal` stops this one: Python can't be interrupted mid-call,
+   * so the worker is terminated along with its downloads and recreated for
+   * the next extraction.
+   */
   async extractAudio(
     url: string, 
     outputPath: string,
-    options?: {
-      onProgress?: (progress: ExtractionProgress) => void;
-      onLog?: (log: LogMessage) => void;
-      onDownloadProgress?: (progress: DownloadProgress) => void;
-    }
+    options?: ExtractAudioOptions
   ): Promise<VideoInfo> {
-    if (!this.worker) {
-      throw new Error('Worker not initialized. Call init() first.');
+    const run = this.extractionQueue.then(() => this.runExtraction(url, outputPath, options));
+    this.extractionQueue = run.catch(() => undefined);
+    return run;
+  }
+
+  private async runExtraction(
+    url: string,
+    outputPath: string,
+    options?: ExtractAudioOptions
+  ): Promise<VideoInfo> {
+    const { onProgress, onLog, onDownloadProgress, signal } = options ?? {};
+    if (signal?.aborted) {
+      throw new Error('Cancelled');
     }
 
-    const { onProgress, onLog, onDownloadProgress } = options ?? {};
+    // Recreates the worker after an aborted extraction terminated it
+    await this.init();
 
+    const abort = () => void this.terminateExtraction();
+    signal?.addEventListener('abort', abort, { once: true });
     const unsubLog = onLog ? this.onLog(onLog) : undefined;
     const unsubProgress = onDownloadProgress ? this.onProgress(onDownloadProgress) : undefined;
 
@@ -201,6 +236,9 @@ export class PyodideClient {
           currentCommand: result.ffmpegCommands[0]
         });
 
+        if (signal?.aborted) {
+          throw new Error('Cancelled');
+        }
         const ffmpegResult = await this.sendMessage('execute_ffmpeg') as { commands: FFmpegCommand[] };
         
         const failedCommands = ffmpegResult.commands.filter(cmd => cmd.status === 'error');
@@ -219,12 +257,32 @@ export class PyodideClient {
       });
 
       return result.info;
+    } catch (error) {
+      throw signal?.aborted ? new Error('Cancelled') : error;
     } finally {
+      signal?.removeEventListener('abort', abort);
       unsubLog?.();
       unsubProgress?.();
     }
   }
 
+  /**
+   * Stop the running extraction: cancel its native downloads, which would
+   * otherwise keep writing, and terminate the worker running yt-dlp.
+   * The extraction only settles once its downloads are gone, so the next
+   * one never shares an output file with them.
+   */
+  private async terminateExtraction(): Promise<void> {
+    const downloads = [...this.activeDownloads].map((outputPath) =>
+      commands.cancelDownload(outputPath).catch((error) => {
+        console.debug('[pyodide-client] Failed to cancel download:', error);
+      })
+    );
+    this.activeDownloads.clear();
+    await Promise.all(downloads);
+    this.destroy();
+  }
+
   /**
    * Get currently queued FFmpeg commands
    */
@@ -352,12 +410,14 @@ export class PyodideClient {
   }
 
   private async handleTauriInvoke(id: string, command: string, args: Record<string, unknown>): Promise<void> {
-    if (!this.worker) return;
+    // Answers go to the worker that asked, never to one recreated after an abort
+    const worker = this.worker;
+    if (!worker) return;
 
     try {
       // Intercept download_to_file to use progress-enabled version
       if (command === 'download_to_file') {
-        await this.handleDownloadWithProgress(id, args);
+        await this.handleDownloadWithProgress(worker, id, args);
         return;
       }
 
@@ -394,14 +454,14 @@ export class PyodideClient {
           throw new Error(`Unknown command: ${command}`);
       }
 
-      this.worker.postMessage({
+      worker.postMessage({
         type: 'tauri_response',
         id,
         success: true,
         data: result
       });
     } catch (error) {
-      this.worker.postMessage({
+      worker.postMessage({
         type: 'tauri_response',
         id,
         success: false,
@@ -414,15 +474,13 @@ export class PyodideClient {
    * Handle download with progress reporting.
    * Creates a Tauri channel for progress and forwards events to the worker.
    */
-  private async handleDownloadWithProgress(id: string, args: Record<string, unknown>): Promise<void> {
-    if (!this.worker) return;
-
+  private async handleDownloadWithProgress(worker: Worker, id: string, args: Record<string, unknown>): Promise<void> {
     // Create progress channel (receives RustDownloadProgress from Rust)
     const progressChannel = new Channel<RustDownloadProgress>();
 
     progressChannel.onmessage = (progress: RustDownloadProgress) => {
       // Forward progress to worker (which will postMessage to listeners)
-      this.worker?.postMessage({
+      worker.postMessage({
         type: 'downloadProgress',
         data: progress
       });
@@ -434,15 +492,18 @@ export class PyodideClient {
           downloaded: `${(progress.bytesDownloaded / 1_000_000).toFixed(1)} MB`,
           total: progress.totalBytes ? `${(progress.totalBytes / 1_000_000).toFixed(1)} MB` : 'unknown',
           speed: '',
-          eta: ''
+          eta: '',
+          path: progress.outputPath
         });
       }
     };
 
+    const outputPath = args.outputPath as string;
+    this.activeDownloads.add(outputPath);
     try {
       const result = await commands.downloadToFileWithProgress(
         args.url as string,
-        args.outputPath as string,
+        outputPath,
         (args.headers || {}) as Record<string, string>,
         progressChannel
       );
@@ -451,19 +512,21 @@ export class PyodideClient {
         throw new Error(result.error);
       }
 
-      this.worker?.postMessage({
+      worker.postMessage({
         type: 'tauri_response',
         id,
         success: true,
         data: null
       });
     } catch (error) {
-      this.worker?.postMessage({
+      worker.postMessage({
         type: 'tauri_response',
         id,
         success: false,
         error: error instanceof Error ? error.message : String(error)
       });
+    } finally {
+      this.activeDownloads.delete(outputPath);
     }
   }
 